@@ -8,6 +8,9 @@ pub const DEFAULT_FD_LIMIT: usize = 128;
 pub const SYSTEM_FD_LIMIT: usize = 256;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = PAGE_SIZE.trailing_zeros() as usize;
+/// 大页: 2MB
+pub const HUGE_PAGE_SIZE: usize = 0x20_0000;
+pub const HUGE_PAGE_PAGES: usize = HUGE_PAGE_SIZE / PAGE_SIZE;
 pub const PTE_WIDTH: usize = 8;
 pub const PTE_WIDTH_BITS: usize = PTE_WIDTH.trailing_zeros() as usize;
 pub const DIR_WIDTH: usize = PAGE_SIZE_BITS - PTE_WIDTH_BITS;
@@ -38,6 +41,10 @@ pub const DEVICE_TREE_ADDR: usize = 0x100000;
 
 // 系统调用
 pub type SysResult<T> = Result<T, usize>;
+/// seccomp_data中的arch, 即AUDIT_ARCH_LOONGARCH64
+pub const AUDIT_ARCH: u32 = 0xc000_0102;
+/// core文件ELF头中的e_machine, 即EM_LOONGARCH
+pub const ELF_MACHINE: u16 = 258;
 
 // Ext4文件系统
 pub const EXT4_MAX_INLINE_DATA: usize = 60;
@@ -1,10 +1,10 @@
 use alloc::sync::Arc;
 use alloc::vec;
-use alloc::{string::String, vec::Vec};
+use alloc::{collections::btree_map::BTreeMap, string::String, vec::Vec};
 use bitflags::bitflags;
 use core::fmt::{self, Debug};
 
-use crate::arch::config::{PAGE_SIZE_BITS, PALEN, USER_MAX_VA};
+use crate::arch::config::{HUGE_PAGE_PAGES, PAGE_SIZE_BITS, PALEN, USER_MAX_VA};
 use crate::arch::la64::tlb::{tlb_global_invalidate, tlb_invalidate};
 use crate::arch::mm::sfence_vma_vaddr;
 use crate::arch::{PGDH, PGDL};
@@ -32,6 +32,8 @@ bitflags! {
         /// 弱序非缓存(Weakly-ordered Uncached)
         const MAT_WUC = 2 << 4;
         const G = 1 << 6;
+        /// 大页位, 只用于目录项, 与G位复用, 置1表示该目录项是映射大页的页表项
+        const H = 1 << 6;
         /// 存在位, 1表示已分配物理页(用于按需分配, COW)
         const P = 1 << 7;
         const W = 1 << 8;
@@ -140,6 +142,10 @@ impl PageTableEntry {
     pub fn is_shared(&self) -> bool {
         self.flags().contains(PTEFlags::S)
     }
+    /// 目录项是否是映射大页的页表项
+    pub fn is_huge(&self) -> bool {
+        self.is_valid() && self.flags().contains(PTEFlags::H)
+    }
 }
 
 impl PTEFlags {
@@ -199,6 +205,9 @@ impl PTEFlags {
 pub struct PageTable {
     pub root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
+    /// 每个大页映射预留的末级页表, key是大页的起始vpn
+    /// 在建立大页映射时分配, 保证拆分大页时不需要分配内存
+    huge_tables: BTreeMap<VirtPageNum, FrameTracker>,
 }
 
 // 创建页表
@@ -209,12 +218,14 @@ impl PageTable {
         Self {
             root_ppn: frame.ppn,
             frames: vec![frame],
+            huge_tables: BTreeMap::new(),
         }
     }
     pub fn from_token(token: usize) -> Self {
         Self {
             root_ppn: PhysPageNum::from(token),
             frames: Vec::new(),
+            huge_tables: BTreeMap::new(),
         }
     }
     pub fn from_existed_user(parent_pagetbl: &PageTable) -> Self {
@@ -223,6 +234,7 @@ impl PageTable {
         let cld_root_frame = frame_alloc().unwrap();
         let cld_root_ppn = cld_root_frame.ppn;
         let mut frames: Vec<FrameTracker> = Vec::new();
+        let mut huge_tables = BTreeMap::new();
         let prt_root_ppn = parent_pagetbl.root_ppn;
         // parent and child root page table
         let cld_1_table = cld_root_frame.ppn.get_pte_array();
@@ -254,6 +266,17 @@ impl PageTable {
                 for (idx2, prt_2_entry) in prt_2_table.iter_mut().enumerate() {
                     if prt_2_entry.is_valid() {
                         let cld_2_pte = &mut cld_2_table[idx2];
+                        if prt_2_entry.is_huge() {
+                            // 大页: 父子进程共享同一个大页页表项, 写时在缺页处理中拆分后再复制
+                            if prt_2_entry.writable() && !prt_2_entry.is_shared() {
+                                *prt_2_entry = PageTableEntry::from_pte_cow(*prt_2_entry);
+                            }
+                            *cld_2_pte = *prt_2_entry;
+                            // 子进程的大页映射同样需要预留拆分用的末级页表
+                            let vpn = VirtPageNum((idx1 << 18) | (idx2 << 9));
+                            huge_tables.insert(vpn, frame_alloc().unwrap());
+                            continue;
+                        }
                         let prt_3_table = prt_2_entry.ppn().get_pte_array();
                         // 3. clear PTE_W and set PTE_COW in parent 3rd level pagetable
                         for ptr_3_entry in prt_3_table.iter_mut() {
@@ -282,6 +305,7 @@ impl PageTable {
         PageTable {
             root_ppn: cld_root_ppn,
             frames,
+            huge_tables,
         }
     }
 }
@@ -304,11 +328,15 @@ impl PageTable {
                 let frame = frame_alloc().expect("failed to alloc frame for page table");
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
+            } else if i == 1 && pte.is_huge() {
+                // 要修改大页中的一页, 拆分大页
+                self.split_huge(vpn, pte);
             }
             ppn = pte.ppn();
         }
         result
     }
+    /// vpn在大页中时返回None, 需要修改其中一页时先调用`split_huge_at`
     pub fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
@@ -322,6 +350,9 @@ impl PageTable {
                 result = Some(pte);
                 break;
             }
+            if i == 1 && pte.is_huge() {
+                return None;
+            }
             ppn = pte.ppn();
         }
         result
@@ -350,6 +381,7 @@ impl PageTable {
         }
     }
     pub fn unmap(&mut self, vpn: VirtPageNum) {
+        self.split_huge_at(vpn);
         let pte = self.find_pte(vpn).unwrap();
         assert!(
             pte.is_valid(),
@@ -365,12 +397,21 @@ impl PageTable {
 
 impl PageTable {
     // 根据vpn找到对应的pte, 若找不到则返回None
+    // vpn在大页中时不拆分大页, 返回vpn对应的4K页表项
     pub fn translate_vpn_to_pte(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        if let Some(pte) = self.find_huge_pte(vpn) {
+            let mut flags = pte.flags();
+            flags.remove(PTEFlags::H);
+            return Some(PageTableEntry::new(
+                PhysPageNum(pte.ppn().0 + vpn.indexes()[2]),
+                flags,
+            ));
+        }
         self.find_pte(vpn).map(|pte| *pte)
     }
     // 返回虚拟地址对应的物理地址, 使用usize类型更加灵活
     pub fn translate_va_to_pa(&self, va: VirtAddr) -> Option<usize> {
-        self.find_pte(va.clone().floor()).map(|pte| {
+        self.translate_vpn_to_pte(va.clone().floor()).map(|pte| {
             let aligned_pa = PhysAddr::from(pte.ppn().0 << PAGE_SIZE_BITS);
             aligned_pa.0 + va.page_offset()
         })
@@ -403,6 +444,8 @@ impl PageTable {
                     let frame = frame_alloc().unwrap();
                     *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                     self.frames.push(frame);
+                } else if pte.is_huge() {
+                    self.split_huge(vpn, pte);
                 }
                 return Some(pte.ppn().get_pte_array());
             }
@@ -506,6 +549,121 @@ impl PageTable {
     }
 }
 
+// 大页
+impl PageTable {
+    /// 二级目录项中H位为1的页表项映射2MB的大页, 由tlb_refill.S中的ldpte拆成两个1MB的TLB项
+    pub const SUPPORT_HUGE_PAGE: bool = true;
+    /// 用一个二级目录项映射大页, `vpn`和`ppn`都需要按大页对齐
+    /// 对应的目录项已经指向非空的末级页表或已经是大页, 或分配预留的末级页表失败时返回false,
+    /// 由调用者回退到4K映射
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        debug_assert!(vpn.0 % HUGE_PAGE_PAGES == 0 && ppn.0 % HUGE_PAGE_PAGES == 0);
+        let idxs = vpn.indexes();
+        let pte = &mut self.root_ppn.get_pte_array()[idxs[0]];
+        if !pte.is_valid() {
+            let Some(frame) = frame_alloc() else {
+                return false;
+            };
+            *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
+            self.frames.push(frame);
+        }
+        let pte = &mut pte.ppn().get_pte_array()[idxs[1]];
+        let table = if pte.is_valid() {
+            if pte.is_huge() {
+                return false;
+            }
+            let table_ppn = pte.ppn();
+            if table_ppn.get_pte_array().iter().any(|pte| pte.is_valid()) {
+                return false;
+            }
+            // 空的末级页表留作拆分时使用
+            let index = self
+                .frames
+                .iter()
+                .position(|frame| frame.ppn == table_ppn)
+                .unwrap();
+            self.frames.swap_remove(index)
+        } else {
+            match frame_alloc() {
+                Some(frame) => frame,
+                None => return false,
+            }
+        };
+        self.huge_tables.insert(vpn, table);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V | PTEFlags::H);
+        tlb_invalidate();
+        true
+    }
+    /// vpn所在的二级目录项是大页时, 返回该页表项
+    fn find_huge_pte(&self, vpn: VirtPageNum) -> Option<&'static mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let pte = &mut self.root_ppn.get_pte_array()[idxs[0]];
+        if !pte.is_valid() {
+            return None;
+        }
+        let pte = &mut pte.ppn().get_pte_array()[idxs[1]];
+        pte.is_huge().then_some(pte)
+    }
+    /// 将大页页表项拆分为指向末级页表的目录项, 末级页表中的512个页表项映射原来的物理页
+    /// 使用建立大页映射时预留的末级页表, 不会分配内存
+    fn split_huge(&mut self, vpn: VirtPageNum, pte: &mut PageTableEntry) {
+        let huge_vpn = VirtPageNum(vpn.0 & !(HUGE_PAGE_PAGES - 1));
+        let frame = self
+            .huge_tables
+            .remove(&huge_vpn)
+            .expect("huge page mapped without a reserved page table");
+        let base_ppn = pte.ppn().0;
+        let mut flags = pte.flags();
+        flags.remove(PTEFlags::H);
+        for (i, entry) in frame.ppn.get_pte_array().iter_mut().enumerate() {
+            *entry = PageTableEntry::new(PhysPageNum(base_ppn + i), flags);
+        }
+        log::info!(
+            "[PageTable::split_huge] split huge page at ppn {:#x}",
+            base_ppn
+        );
+        *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
+        self.frames.push(frame);
+        tlb_invalidate();
+    }
+    /// 如果vpn在大页中, 拆分该大页
+    /// used by 只修改大页中一部分的操作(区域拆分, 单页的COW和写保护等)
+    pub fn split_huge_at(&mut self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_huge_pte(vpn) {
+            self.split_huge(vpn, pte);
+        }
+    }
+    /// vpn是大页的起始页时, 修改整个大页的权限并返回true
+    pub fn remap_huge(&mut self, vpn: VirtPageNum, flags: PTEFlags) -> bool {
+        if vpn.0 % HUGE_PAGE_PAGES != 0 {
+            return false;
+        }
+        let Some(pte) = self.find_huge_pte(vpn) else {
+            return false;
+        };
+        *pte = PageTableEntry::new(pte.ppn(), flags | PTEFlags::V | PTEFlags::H);
+        tlb_invalidate();
+        true
+    }
+    /// vpn是大页的起始页时, 取消整个大页的映射并返回true
+    pub fn unmap_huge(&mut self, vpn: VirtPageNum) -> bool {
+        if vpn.0 % HUGE_PAGE_PAGES != 0 {
+            return false;
+        }
+        let Some(pte) = self.find_huge_pte(vpn) else {
+            return false;
+        };
+        *pte = PageTableEntry::empty();
+        self.huge_tables.remove(&vpn);
+        tlb_invalidate();
+        true
+    }
+    /// vpn是否由大页映射
+    pub fn is_huge_mapped(&self, vpn: VirtPageNum) -> bool {
+        self.find_huge_pte(vpn).is_some()
+    }
+}
+
 #[allow(unused)]
 // 用于调试
 impl PageTable {
@@ -522,7 +680,9 @@ impl PageTable {
 
                 // 第二级页表（dir1）
                 for (index, entry) in pagetable.iter().enumerate() {
-                    if entry.is_valid() {
+                    if entry.is_huge() {
+                        log::error!("--- va: {:#x}: {:?} (huge)", va | (index << 21), entry);
+                    } else if entry.is_valid() {
                         va = va | (index << 21);
                         let pagetable = entry.ppn().get_pte_array();
 
@@ -562,6 +722,10 @@ impl PageTable {
             log::error!("level2: --- va: {:#x}, pte: None", va);
             return;
         }
+        if pte.is_huge() {
+            log::error!("level2: --- va: {:#x}, pte: {:?} (huge)", va, pte);
+            return;
+        }
         // 打印第二级页表的ppn
         log::error!("--  ppn: {:#x}", pte.ppn().0);
         let pagetable = pte.ppn().get_pte_array();
@@ -5,6 +5,9 @@ use crate::{
     task::{get_stack_top_by_sp, Task},
 };
 
+/// elf_gregset_t中的寄存器个数
+pub const ELF_NGREG: usize = 45;
+
 #[repr(C)]
 pub struct TrapContext {
     /// 通用寄存器, r[1]是ra, r[2]是tp, r[3]是sp, r[4]是a0
@@ -25,6 +28,9 @@ impl TrapContext {
     pub fn get_a0(&self) -> usize {
         self.r[4]
     }
+    pub fn get_sepc(&self) -> usize {
+        self.era
+    }
 
     /* setter */
     pub fn set_ra(&mut self, ra: usize) {
@@ -54,6 +60,14 @@ impl TrapContext {
     pub fn restore_a0(&mut self) {
         self.r[4] = self.last_a0;
     }
+    /// core文件NT_PRSTATUS中的寄存器, 即user_pt_regs: r0..r31, orig_a0, csr_era, csr_badv, reserved[10]
+    pub fn elf_gregs(&self) -> [usize; ELF_NGREG] {
+        let mut gregs = [0; ELF_NGREG];
+        gregs[..32].copy_from_slice(&self.r);
+        gregs[32] = self.last_a0;
+        gregs[33] = self.era;
+        gregs
+    }
     /// 初始化app的TrapContext
     /// argc, argv_base, envp_base, auxv_base分别放在r[4](a0), r[5], r[6], r[7]
     /// Todebug:
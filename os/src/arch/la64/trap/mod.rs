@@ -108,8 +108,10 @@ pub fn trap_handler(cx: &mut TrapContext) {
             let va = VirtAddr::from(badv);
             let cause = PageFaultCause::from(cause);
             let task = current_task();
-            task.op_memory_set_mut(|memory_set| {
-                if let Err(_e) = memory_set.handle_recoverable_page_fault(va, cause) {
+            let userfault = task.op_memory_set_mut(|memory_set| {
+                match memory_set.handle_recoverable_page_fault(va, cause) {
+                    Ok(userfault) => userfault,
+                    Err(_e) => {
                         memory_set.page_table.dump_all_user_mapping();
                         // dump_trap_context(&current_task());
                         log::error!(
@@ -122,8 +124,14 @@ pub fn trap_handler(cx: &mut TrapContext) {
                             SigInfo::new(Sig::SIGSEGV.raw(), SigInfo::KERNEL, SiField::Kill { tid: current_task().tid() }),
                             false,
                         );
+                        None
+                    }
                 }
             });
+            // 等待userfaultfd的监视者解决缺页
+            if let Some(uffd) = userfault {
+                uffd.wait_fault();
+            }
         }
         // Trap::Exception(Exception::InstructionNonDefined)
         // | Trap::Exception(Exception::InstructionPrivilegeIllegal) => {
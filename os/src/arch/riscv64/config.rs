@@ -7,6 +7,9 @@ pub const USER_MAX: usize = 0x0000_003f_ffff_ffff;
 /// KERNEL_BASE >> 12
 pub const KERNEL_DIRECT_OFFSET: usize = KERNEL_BASE >> 12;
 pub const PAGE_SIZE_BITS: usize = 0xc;
+/// 大页(Sv39 megapage): 2MB
+pub const HUGE_PAGE_SIZE: usize = 0x20_0000;
+pub const HUGE_PAGE_PAGES: usize = HUGE_PAGE_SIZE / PAGE_SIZE;
 /// 用户栈大小: 两页
 pub const USER_STACK_SIZE: usize = PAGE_SIZE << 7;
 
@@ -21,6 +24,10 @@ pub const DL_INTERP_OFFSET: usize = 0x30_0000_0000;
 
 /* 系统调用 */
 pub type SysResult<T> = Result<T, usize>;
+/// seccomp_data中的arch, 即AUDIT_ARCH_RISCV64
+pub const AUDIT_ARCH: u32 = 0xc000_00f3;
+/// core文件ELF头中的e_machine, 即EM_RISCV
+pub const ELF_MACHINE: u16 = 243;
 
 // loongarch64中物理地址的最大长度
 #[allow(unused)]
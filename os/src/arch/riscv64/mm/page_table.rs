@@ -7,7 +7,7 @@ use core::{
 };
 
 use crate::{
-    arch::config::{KERNEL_DIRECT_OFFSET, PAGE_SIZE_BITS, USER_MAX_VA},
+    arch::config::{HUGE_PAGE_PAGES, KERNEL_DIRECT_OFFSET, PAGE_SIZE_BITS, USER_MAX_VA},
     mm::{frame_alloc_range, FrameTracker, Page},
 };
 use crate::{
@@ -16,7 +16,7 @@ use crate::{
 };
 use bitflags::bitflags;
 
-use alloc::{string::String, vec};
+use alloc::{collections::btree_map::BTreeMap, string::String, vec};
 use alloc::{sync::Arc, vec::Vec};
 use riscv::{addr::page, paging};
 
@@ -120,6 +120,12 @@ impl PageTableEntry {
     pub fn is_shared(&self) -> bool {
         self.flags().contains(PTEFlags::S)
     }
+    /// 叶子页表项, 非叶子页表项的R, W, X都为0
+    /// 二级页表中的叶子页表项是大页
+    pub fn is_leaf(&self) -> bool {
+        self.flags()
+            .intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X)
+    }
 }
 
 #[allow(unused)]
@@ -163,6 +169,9 @@ impl PTEFlags {
 pub struct PageTable {
     pub root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
+    /// 每个大页映射预留的三级页表, key是大页的起始vpn
+    /// 在建立大页映射时分配, 保证拆分大页时不需要分配内存
+    huge_tables: BTreeMap<VirtPageNum, FrameTracker>,
 }
 
 // 创建页表
@@ -172,6 +181,7 @@ impl PageTable {
         PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
+            huge_tables: BTreeMap::new(),
         }
     }
     /// Temporarily used to get arguments from user space.
@@ -179,6 +189,7 @@ impl PageTable {
         Self {
             root_ppn: PhysPageNum::from(satp & ((1 << 44) - 1)),
             frames: Vec::new(),
+            huge_tables: BTreeMap::new(),
         }
     }
     /// 在用户空间中有内核空间的映射
@@ -202,6 +213,7 @@ impl PageTable {
         PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
+            huge_tables: BTreeMap::new(),
         }
     }
     /// 支持共享内存
@@ -209,6 +221,7 @@ impl PageTable {
         let cld_root_frame = frame_alloc().unwrap();
         let cld_root_ppn = cld_root_frame.ppn;
         let mut frames: Vec<FrameTracker> = Vec::new();
+        let mut huge_tables = BTreeMap::new();
         let prt_root_ppn = parent_pagetbl.root_ppn;
         // parent and child root page table
         let cld_1_table = cld_root_frame.ppn.get_pte_array();
@@ -240,6 +253,17 @@ impl PageTable {
                 for (idx2, prt_2_entry) in prt_2_table.iter_mut().enumerate() {
                     if prt_2_entry.is_valid() {
                         let cld_2_pte = &mut cld_2_table[idx2];
+                        if prt_2_entry.is_leaf() {
+                            // 大页: 父子进程共享同一个大页页表项, 写时在缺页处理中拆分后再复制
+                            if prt_2_entry.writable() && !prt_2_entry.is_shared() {
+                                *prt_2_entry = PageTableEntry::from_pte_cow(*prt_2_entry);
+                            }
+                            *cld_2_pte = *prt_2_entry;
+                            // 子进程的大页映射同样需要预留拆分用的三级页表
+                            let vpn = VirtPageNum((idx1 << 18) | (idx2 << 9));
+                            huge_tables.insert(vpn, frame_alloc().unwrap());
+                            continue;
+                        }
                         let prt_3_table = prt_2_entry.ppn().get_pte_array();
                         // 3. clear PTE_W and set PTE_COW in parent 3rd level pagetable
                         for ptr_3_entry in prt_3_table.iter_mut() {
@@ -269,6 +293,7 @@ impl PageTable {
         PageTable {
             root_ppn: cld_root_ppn,
             frames,
+            huge_tables,
         }
     }
 }
@@ -290,12 +315,16 @@ impl PageTable {
                 let frame = frame_alloc().unwrap();
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
+            } else if i == 1 && pte.is_leaf() {
+                // 要修改大页中的一页, 拆分大页
+                self.split_huge(vpn, pte);
             }
             ppn = pte.ppn();
         }
         result
     }
     /// return PageTableEntry by virtual page number if exist
+    /// vpn在大页中时返回None, 需要修改其中一页时先调用`split_huge_at`
     pub fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
@@ -309,6 +338,9 @@ impl PageTable {
                 result = Some(pte);
                 break;
             }
+            if i == 1 && pte.is_leaf() {
+                return None;
+            }
             ppn = pte.ppn();
         }
         result
@@ -326,6 +358,7 @@ impl PageTable {
     /// Remap a mapping from `vpn` to `ppn`.
     /// 由上层调用者保证vpn已经被映射
     pub fn remap(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        self.split_huge_at(vpn);
         let pte = self.find_pte(vpn).unwrap();
         assert!(
             pte.is_valid(),
@@ -338,6 +371,7 @@ impl PageTable {
         }
     }
     pub fn unmap(&mut self, vpn: VirtPageNum) {
+        self.split_huge_at(vpn);
         let pte = self.find_pte(vpn).unwrap();
         assert!(
             pte.is_valid(),
@@ -353,12 +387,31 @@ impl PageTable {
 
 impl PageTable {
     // 根据vpn找到对应的pte, 若找不到则返回None
+    // vpn在大页中时不拆分大页, 返回vpn对应的4K页表项
     pub fn translate_vpn_to_pte(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for (i, idx) in idxs.iter().enumerate() {
+            let pte = ppn.get_pte_array()[*idx];
+            if !pte.is_valid() {
+                return None;
+            }
+            if i == 2 {
+                return Some(pte);
+            }
+            if i == 1 && pte.is_leaf() {
+                return Some(PageTableEntry::new(
+                    PhysPageNum(pte.ppn().0 + idxs[2]),
+                    pte.flags(),
+                ));
+            }
+            ppn = pte.ppn();
+        }
+        None
     }
     // 返回虚拟地址对应的物理地址, 使用usize类型更加灵活
     pub fn translate_va_to_pa(&self, va: VirtAddr) -> Option<usize> {
-        self.find_pte(va.clone().floor()).map(|pte| {
+        self.translate_vpn_to_pte(va.clone().floor()).map(|pte| {
             let aligned_pa = PhysAddr::from(pte.ppn().0 << PAGE_SIZE_BITS);
             aligned_pa.0 + va.page_offset()
         })
@@ -383,6 +436,8 @@ impl PageTable {
                     let frame = frame_alloc().unwrap();
                     *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                     self.frames.push(frame);
+                } else if pte.is_leaf() {
+                    self.split_huge(vpn, pte);
                 }
                 return Some(pte.ppn().get_pte_array());
             }
@@ -493,6 +548,137 @@ impl PageTable {
     }
 }
 
+// 大页
+impl PageTable {
+    /// Sv39支持在二级页表中使用叶子页表项映射2MB的大页
+    pub const SUPPORT_HUGE_PAGE: bool = true;
+    /// 用一个二级页表项映射大页, `vpn`和`ppn`都需要按大页对齐
+    /// 对应的二级页表项已经指向非空的三级页表或已经是大页, 或分配预留的三级页表失败时返回false,
+    /// 由调用者回退到4K映射
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        debug_assert!(vpn.0 % HUGE_PAGE_PAGES == 0 && ppn.0 % HUGE_PAGE_PAGES == 0);
+        // R, W, X都为0会被当作非叶子页表项
+        if !flags.intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X) {
+            return false;
+        }
+        let idxs = vpn.indexes();
+        let pte = &mut self.root_ppn.get_pte_array()[idxs[0]];
+        if !pte.is_valid() {
+            let Some(frame) = frame_alloc() else {
+                return false;
+            };
+            *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
+            self.frames.push(frame);
+        }
+        let pte = &mut pte.ppn().get_pte_array()[idxs[1]];
+        let table = if pte.is_valid() {
+            if pte.is_leaf() {
+                return false;
+            }
+            let table_ppn = pte.ppn();
+            if table_ppn.get_pte_array().iter().any(|pte| pte.is_valid()) {
+                return false;
+            }
+            // 空的三级页表留作拆分时使用
+            let index = self
+                .frames
+                .iter()
+                .position(|frame| frame.ppn == table_ppn)
+                .unwrap();
+            self.frames.swap_remove(index)
+        } else {
+            match frame_alloc() {
+                Some(frame) => frame,
+                None => return false,
+            }
+        };
+        self.huge_tables.insert(vpn, table);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V | PTEFlags::A | PTEFlags::D);
+        unsafe {
+            asm!("sfence.vma");
+        }
+        true
+    }
+    /// vpn所在的二级页表项是大页时, 返回该页表项
+    fn find_huge_pte(&self, vpn: VirtPageNum) -> Option<&'static mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let pte = &mut self.root_ppn.get_pte_array()[idxs[0]];
+        if !pte.is_valid() {
+            return None;
+        }
+        let pte = &mut pte.ppn().get_pte_array()[idxs[1]];
+        (pte.is_valid() && pte.is_leaf()).then_some(pte)
+    }
+    /// 将大页页表项拆分为指向三级页表的页表项, 三级页表中的512个页表项映射原来的物理页
+    /// 使用建立大页映射时预留的三级页表, 不会分配内存
+    fn split_huge(&mut self, vpn: VirtPageNum, pte: &mut PageTableEntry) {
+        let huge_vpn = VirtPageNum(vpn.0 & !(HUGE_PAGE_PAGES - 1));
+        let frame = self
+            .huge_tables
+            .remove(&huge_vpn)
+            .expect("huge page mapped without a reserved page table");
+        let base_ppn = pte.ppn().0;
+        let flags = pte.flags();
+        for (i, entry) in frame.ppn.get_pte_array().iter_mut().enumerate() {
+            *entry = PageTableEntry::new(PhysPageNum(base_ppn + i), flags);
+        }
+        log::info!(
+            "[PageTable::split_huge] split huge page at ppn {:#x}",
+            base_ppn
+        );
+        *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
+        self.frames.push(frame);
+        unsafe {
+            asm!("sfence.vma");
+        }
+    }
+    /// 如果vpn在大页中, 拆分该大页
+    /// used by 只修改大页中一部分的操作(区域拆分, 单页的COW和写保护等)
+    pub fn split_huge_at(&mut self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_huge_pte(vpn) {
+            self.split_huge(vpn, pte);
+        }
+    }
+    /// vpn是大页的起始页时, 修改整个大页的权限并返回true
+    /// 新权限不能用大页表示(R, W, X都为0)时拆分大页并返回false, 由调用者逐页修改
+    pub fn remap_huge(&mut self, vpn: VirtPageNum, flags: PTEFlags) -> bool {
+        if vpn.0 % HUGE_PAGE_PAGES != 0 {
+            return false;
+        }
+        let Some(pte) = self.find_huge_pte(vpn) else {
+            return false;
+        };
+        if !flags.intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X) {
+            self.split_huge(vpn, pte);
+            return false;
+        }
+        *pte = PageTableEntry::new(pte.ppn(), flags | PTEFlags::V | PTEFlags::A | PTEFlags::D);
+        unsafe {
+            asm!("sfence.vma");
+        }
+        true
+    }
+    /// vpn是大页的起始页时, 取消整个大页的映射并返回true
+    pub fn unmap_huge(&mut self, vpn: VirtPageNum) -> bool {
+        if vpn.0 % HUGE_PAGE_PAGES != 0 {
+            return false;
+        }
+        let Some(pte) = self.find_huge_pte(vpn) else {
+            return false;
+        };
+        *pte = PageTableEntry::empty();
+        self.huge_tables.remove(&vpn);
+        unsafe {
+            asm!("sfence.vma");
+        }
+        true
+    }
+    /// vpn是否由大页映射
+    pub fn is_huge_mapped(&self, vpn: VirtPageNum) -> bool {
+        self.find_huge_pte(vpn).is_some()
+    }
+}
+
 #[allow(unused)]
 // 用于调试
 impl PageTable {
@@ -509,7 +695,9 @@ impl PageTable {
                 let pagetable = entry.ppn().get_pte_array();
                 // 二级页表
                 for (index, entry) in pagetable.iter().enumerate() {
-                    if entry.is_valid() {
+                    if entry.is_valid() && entry.is_leaf() {
+                        log::error!("--- va: {:#x}: {:?} (huge)", va | (index << 21), entry);
+                    } else if entry.is_valid() {
                         va = va | index << 21;
                         let pagetable = entry.ppn().get_pte_array();
                         // 三级页表
@@ -574,6 +762,7 @@ pub fn map_temp(vpn: VirtPageNum, ppn: PhysPageNum) {
     let mut page_table = PageTable {
         root_ppn: PhysPageNum::from(satp & ((1 << 44) - 1)),
         frames: Vec::new(),
+        huge_tables: BTreeMap::new(),
     };
     page_table.map(vpn, ppn, PTEFlags::V | PTEFlags::R | PTEFlags::W);
     unsafe {
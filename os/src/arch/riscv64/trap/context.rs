@@ -8,6 +8,9 @@ use crate::mm::frame_alloc;
 use crate::task::{get_stack_top_by_sp, Task};
 use crate::{arch::config::PAGE_SIZE_BITS, arch::mm::map_temp};
 
+/// elf_gregset_t中的寄存器个数
+pub const ELF_NGREG: usize = 32;
+
 /// Trap Context
 /// 内核栈对齐到16字节
 /// 2025-04-11 向trap_context中添加了kernel_tp字段, 为保持对齐, 顺便塞了一个last-a0来帮助signal实现SA_RESTART
@@ -69,6 +72,12 @@ impl TrapContext {
     pub fn restore_a0(&mut self) {
         self.x[10] = self.last_a0;
     }
+    /// core文件NT_PRSTATUS中的寄存器, 即user_regs_struct: pc, x1..x31
+    pub fn elf_gregs(&self) -> [usize; ELF_NGREG] {
+        let mut gregs = self.x;
+        gregs[0] = self.sepc;
+        gregs
+    }
 
     /// init app context
     /// argc, argv_base, envp_base, auxv_base分别放在x[10], x[11], x[12], x[13]
@@ -100,7 +100,9 @@ pub fn trap_handler(cx: &mut TrapContext) {
             let page_table = PageTable::from_token(satp);
             page_table.dump_all_user_mapping();
             log::error!("Instruction fault at {:#x}", stval);
-            let pte = page_table.find_pte(VirtAddr::from(stval).floor()).unwrap();
+            let pte = page_table
+                .translate_vpn_to_pte(VirtAddr::from(stval).floor())
+                .unwrap();
             log::error!("pte: {:?}", pte);
             // page fault exit code
             panic!(
@@ -121,22 +123,30 @@ pub fn trap_handler(cx: &mut TrapContext) {
             let casue = PageFaultCause::from(scause.cause());
             // log::error!("page fault cause {:?}", scause.cause());
             let task = current_task();
-            task.op_memory_set_mut(|memory_set| {
-                if let Err(sig) = memory_set.handle_recoverable_page_fault(va, casue) {
-                    // memory_set.page_table.dump_all_user_mapping();
-                    // dump_trap_context(&current_task());
-                    log::error!(
-                        "Unrecoverble page fault in application, bad addr = {:#x}, scause = {:?}, sepc = {:#x}",
-                        stval,
-                        scause.cause(),
-                        sepc::read()
-                    );
-                    task.receive_siginfo(
-                        SigInfo::new(sig.raw(), SigInfo::KERNEL, SiField::Kill { tid: current_task().tid() }),
-                        false,
-                    );
+            let userfault = task.op_memory_set_mut(|memory_set| {
+                match memory_set.handle_recoverable_page_fault(va, casue) {
+                    Ok(userfault) => userfault,
+                    Err(sig) => {
+                        // memory_set.page_table.dump_all_user_mapping();
+                        // dump_trap_context(&current_task());
+                        log::error!(
+                            "Unrecoverble page fault in application, bad addr = {:#x}, scause = {:?}, sepc = {:#x}",
+                            stval,
+                            scause.cause(),
+                            sepc::read()
+                        );
+                        task.receive_siginfo(
+                            SigInfo::new(sig.raw(), SigInfo::KERNEL, SiField::Kill { tid: current_task().tid() }),
+                            false,
+                        );
+                        None
+                    }
                 }
-            })
+            });
+            // 等待userfaultfd的监视者解决缺页
+            if let Some(uffd) = userfault {
+                uffd.wait_fault();
+            }
             // we should jump back to the faulting instruction after handling the page fault
         }
         Trap::Exception(Exception::LoadFault) | Trap::Exception(Exception::StoreFault) => {
@@ -14,7 +14,7 @@ use crate::fs::kstat::Kstat;
 use crate::fs::uapi::FallocFlags;
 use crate::fs::FS_BLOCK_SIZE;
 use crate::syscall::errno::{Errno, SyscallRet};
-use crate::task::current_task;
+use crate::task::{capability::CapSet, current_task};
 use crate::timer::TimeSpec;
 // use crate::fs::inode::InodeMeta;
 use crate::{
@@ -338,7 +338,7 @@ impl Ext4InodeDisk {
         if perm & S_ISGID != 0 {
             let task = current_task();
             let (fsuid, fsgid) = (task.fsuid(), task.fsgid());
-            if fsuid != 0 && fsgid != self.gid as u32 {
+            if !task.capable(CapSet::FSETID) && fsgid != self.gid as u32 {
                 log::error!(
                     "[Ext4InodeDisk::set_perm] S_ISGID set failed, fsuid: {}, fsgid: {}, gid: {}",
                     fsuid,
@@ -9,6 +9,7 @@ use crate::{
         kstat::Kstat,
         pipe::PipeInode,
         uapi::{DevT, FallocFlags, RenameFlags},
+        writeback::balance_dirty_pages,
     },
     mm::Page,
     syscall::errno::{Errno, SyscallRet},
@@ -64,7 +65,9 @@ impl InodeOp for Ext4Inode {
     }
 
     fn write<'a>(&'a self, page_offset: usize, buf: &'a [u8]) -> usize {
-        self.write(page_offset, buf)
+        let len = self.write(page_offset, buf);
+        balance_dirty_pages();
+        len
     }
     fn write_dio<'a>(&'a self, page_offset: usize, buf: &'a [u8]) -> usize {
         self.write_direct(page_offset, buf)
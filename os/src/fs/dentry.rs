@@ -15,7 +15,7 @@ use crate::{
     ext4::inode::{S_IFREG, S_ISGID, S_ISUID},
     mutex::SpinNoIrqLock,
     syscall::errno::{Errno, SyscallRet},
-    task::current_task,
+    task::{capability::CapSet, current_task},
     timer::TimeSpec,
 };
 
@@ -66,25 +66,34 @@ pub fn dentry_check_access(
     } else {
         (task.uid(), task.gid())
     };
-    // 特殊处理root
-    if uid == 0 {
-        // root不能绕过可执行权限检查, 必须有至少一个执行位
-        if mode & X_OK != 0 {
-            let i_mode = dentry.get_inode().get_mode();
-            if i_mode & 0o111 == 0 {
-                log::error!(
-                    "[dentry_check_access] Root user has no execute permission on {}, i_mode: {:o}",
-                    dentry.absolute_path,
-                    i_mode
-                );
-                return Err(Errno::EACCES);
-            }
+    // access(2)使用实际id检查时, 只有ruid为0才拥有permitted中的能力
+    let has_cap = |cap: CapSet| {
+        if use_effective {
+            task.capable(cap)
+        } else {
+            uid == 0 && task.caps().permitted.contains(cap)
         }
-        return Ok(0); // root用户总是有读写权限
-    }
-    // 其他用户
+    };
     let inode = dentry.get_inode();
     let i_mode = inode.get_mode();
+    // CAP_DAC_OVERRIDE绕过读写检查, 但不能绕过可执行权限检查, 普通文件必须有至少一个执行位
+    if has_cap(CapSet::DAC_OVERRIDE) {
+        if mode & X_OK == 0 || dentry.is_dir() || i_mode & 0o111 != 0 {
+            return Ok(0);
+        }
+        log::error!(
+            "[dentry_check_access] CAP_DAC_OVERRIDE has no execute permission on {}, i_mode: {:o}",
+            dentry.absolute_path,
+            i_mode
+        );
+        return Err(Errno::EACCES);
+    }
+    // CAP_DAC_READ_SEARCH绕过读检查和目录的搜索检查
+    if has_cap(CapSet::DAC_READ_SEARCH) && mode & W_OK == 0 && (mode & X_OK == 0 || dentry.is_dir())
+    {
+        return Ok(0);
+    }
+    // 其他用户
     let (user_perm, group_perm, other_perm) = (
         (i_mode >> 6) & 0o7, // 用户权限
         (i_mode >> 3) & 0o7, // 组权限
@@ -121,7 +130,7 @@ pub fn dentry_check_open(dentry: &Dentry, flags: OpenFlags, mode: i32) -> Result
     if flags.contains(OpenFlags::O_NOATIME) {
         // root用户总是有权限
         // 检查文件的所有者是否是当前用户
-        if uid != 0 && uid != dentry.get_inode().get_uid() {
+        if !task.capable(CapSet::FOWNER) && uid != dentry.get_inode().get_uid() {
             log::error!(
                 "[dentry_check_open] O_NOATIME flag set, but current user {} is not the owner of {}",
                 uid,
@@ -196,7 +205,7 @@ pub fn dentry_check_open(dentry: &Dentry, flags: OpenFlags, mode: i32) -> Result
 
 // 由调用者保证:
 //    1. dentry不是负目录项
-/// 要修改文件的所有者, 必须具备`CAP_CHOWN`能力(目前只支持root用户)
+/// 要修改文件的所有者, 必须具备`CAP_CHOWN`能力
 pub fn chown(inode: &Arc<dyn InodeOp>, new_uid: u32, new_gid: u32) -> SyscallRet {
     let task = current_task();
     let (euid, egid) = (task.fsuid(), task.fsgid());
@@ -210,8 +219,8 @@ pub fn chown(inode: &Arc<dyn InodeOp>, new_uid: u32, new_gid: u32) -> SyscallRet
         new_gid,
         i_mode
     );
-    // 特殊处理root
-    if euid == 0 {
+    // 拥有CAP_CHOWN可以任意修改所有者和组
+    if task.capable(CapSet::CHOWN) {
         if new_uid != u32::MAX {
             // dentry.get_inode().set_uid(new_uid);
             // 当super-user修改可执行文件的所有者或组时需要清除setuid和setgid位
@@ -235,7 +244,7 @@ pub fn chown(inode: &Arc<dyn InodeOp>, new_uid: u32, new_gid: u32) -> SyscallRet
         return Ok(0);
     }
     if new_uid != u32::MAX {
-        return Err(Errno::EPERM); // 目前只支持root用户修改所有者
+        return Err(Errno::EPERM); // 修改所有者需要CAP_CHOWN
     }
     // 文件的所有者可以将文件的组更改为其所属的任何组
     if new_gid != u32::MAX && new_gid != inode.get_gid() {
@@ -383,12 +392,10 @@ impl Dentry {
     // }
     // 由上层调用者保证: 负目录项不能调用该函数
     pub fn can_search(&self) -> bool {
-        let (euid, egid) = {
-            let task = current_task();
-            (task.fsuid(), task.fsgid())
-        };
-        if euid == 0 {
-            return true; // root用户总是有权限
+        let task = current_task();
+        let (euid, egid) = (task.fsuid(), task.fsgid());
+        if task.capable(CapSet::DAC_READ_SEARCH) || task.capable(CapSet::DAC_OVERRIDE) {
+            return true; // 拥有能力总是可以搜索
         }
         let i_mode = self.get_inode().get_mode();
         log::error!(
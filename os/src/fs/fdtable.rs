@@ -12,6 +12,7 @@ use virtio_drivers::PAGE_SIZE;
 use super::{
     dev::tty::TTY,
     file::{FileOp, OpenFlags},
+    memfd::MemFd,
     pipe::Pipe,
     uapi::{CloseRangeFlags, RLimit},
 };
@@ -379,6 +380,27 @@ impl FdTable {
                     return Err(Errno::EINVAL);
                 }
             }
+            FcntlOp::F_ADD_SEALS => {
+                let file = self.get_file(fd).ok_or(Errno::EBADF)?;
+                if let Some(memfd) = file.as_any().downcast_ref::<MemFd>() {
+                    if !file.writable() {
+                        return Err(Errno::EPERM);
+                    }
+                    return memfd.inode().add_seals(arg as u32);
+                } else {
+                    log::warn!("[fcntl] F_ADD_SEALS on non-memfd file");
+                    return Err(Errno::EINVAL);
+                }
+            }
+            FcntlOp::F_GET_SEALS => {
+                let file = self.get_file(fd).ok_or(Errno::EBADF)?;
+                if let Some(memfd) = file.as_any().downcast_ref::<MemFd>() {
+                    return Ok(memfd.inode().get_seals() as usize);
+                } else {
+                    log::warn!("[fcntl] F_GET_SEALS on non-memfd file");
+                    return Err(Errno::EINVAL);
+                }
+            }
             _ => {
                 log::warn!("[fcntl] Unsupported op: {:?}", op);
                 return Err(Errno::EINVAL);
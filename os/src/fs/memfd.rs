@@ -0,0 +1,486 @@
+//! memfd_create创建的匿名内存文件
+//!
+//! 数据只保存在`AddressSpace`的页中(Framed页, 不会写回也不会被回收), 可以被MAP_SHARED映射,
+//! 截断和通过fd传递. 创建时指定MFD_ALLOW_SEALING后可以通过F_ADD_SEALS添加封印,
+//! 封印在write, truncate, fallocate和mmap/mprotect时检查
+use core::{
+    any::Any,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use alloc::{format, sync::Arc, vec::Vec};
+use spin::RwLock;
+
+use crate::{
+    arch::config::{PAGE_SIZE, PAGE_SIZE_BITS},
+    ext4::inode::S_IFREG,
+    mm::{MapPermission, MapType, Page},
+    syscall::errno::{Errno, SyscallRet},
+    task::{current_task, for_each_task},
+    timer::TimeSpec,
+};
+
+use super::{
+    dentry::{Dentry, DentryFlags},
+    file::{File, FileOp, OpenFlags},
+    inode::InodeOp,
+    kstat::Kstat,
+    page_cache::AddressSpace,
+    path::Path,
+    uapi::{FallocFlags, Whence},
+};
+
+pub const MFD_CLOEXEC: u32 = 0x1;
+pub const MFD_ALLOW_SEALING: u32 = 0x2;
+pub const MFD_HUGETLB: u32 = 0x4;
+
+/// 名字最长249字节(NAME_MAX - strlen("memfd:"))
+pub const MFD_NAME_MAX_LEN: usize = 249;
+
+/// 禁止继续添加封印
+pub const F_SEAL_SEAL: u32 = 0x1;
+/// 禁止缩小文件
+pub const F_SEAL_SHRINK: u32 = 0x2;
+/// 禁止扩大文件
+pub const F_SEAL_GROW: u32 = 0x4;
+/// 禁止写入, 添加时不能存在可写的共享映射
+pub const F_SEAL_WRITE: u32 = 0x8;
+/// 禁止写入和新的可写共享映射, 已有的映射不受影响
+pub const F_SEAL_FUTURE_WRITE: u32 = 0x10;
+pub const F_SEAL_ALL: u32 =
+    F_SEAL_SEAL | F_SEAL_SHRINK | F_SEAL_GROW | F_SEAL_WRITE | F_SEAL_FUTURE_WRITE;
+
+/// memfd的inode号, 不占用磁盘inode
+static MEMFD_INODE_NUM: AtomicUsize = AtomicUsize::new(1);
+
+pub struct MemfdInode {
+    inode_num: usize,
+    pub address_space: AddressSpace,
+    seals: AtomicU32,
+    inner: RwLock<MemfdInodeInner>,
+}
+
+struct MemfdInodeInner {
+    size: usize,
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    atime: TimeSpec,
+    mtime: TimeSpec,
+    ctime: TimeSpec,
+}
+
+impl MemfdInode {
+    pub fn new(seals: u32) -> Arc<Self> {
+        let task = current_task();
+        let now = TimeSpec::new_wall_time();
+        Arc::new(Self {
+            inode_num: MEMFD_INODE_NUM.fetch_add(1, Ordering::Relaxed),
+            address_space: AddressSpace::new(),
+            seals: AtomicU32::new(seals),
+            inner: RwLock::new(MemfdInodeInner {
+                size: 0,
+                mode: S_IFREG | 0o777,
+                uid: task.fsuid(),
+                gid: task.fsgid(),
+                atime: now,
+                mtime: now,
+                ctime: now,
+            }),
+        })
+    }
+    pub fn get_seals(&self) -> u32 {
+        self.seals.load(Ordering::Relaxed)
+    }
+    /// F_ADD_SEALS
+    pub fn add_seals(self: &Arc<Self>, seals: u32) -> SyscallRet {
+        if seals & !F_SEAL_ALL != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let old = self.get_seals();
+        if old & F_SEAL_SEAL != 0 {
+            return Err(Errno::EPERM);
+        }
+        if seals & F_SEAL_WRITE != 0 && old & F_SEAL_WRITE == 0 && self.writably_mapped() {
+            return Err(Errno::EBUSY);
+        }
+        self.seals.fetch_or(seals, Ordering::Relaxed);
+        log::info!(
+            "[MemfdInode::add_seals] inode{} seals: {:#x} -> {:#x}",
+            self.inode_num,
+            old,
+            old | seals
+        );
+        Ok(0)
+    }
+    /// 禁止写入(包括新的可写共享映射)
+    pub fn write_sealed(&self) -> bool {
+        self.get_seals() & (F_SEAL_WRITE | F_SEAL_FUTURE_WRITE) != 0
+    }
+    /// 检查将文件大小从当前大小改为`new_size`是否被封印禁止
+    fn check_resize(&self, new_size: usize) -> Result<(), Errno> {
+        let seals = self.get_seals();
+        let size = self.inner.read().size;
+        if (new_size < size && seals & F_SEAL_SHRINK != 0)
+            || (new_size > size && seals & F_SEAL_GROW != 0)
+        {
+            return Err(Errno::EPERM);
+        }
+        Ok(())
+    }
+    /// 是否存在该文件的可写共享映射
+    fn writably_mapped(self: &Arc<Self>) -> bool {
+        let inode_addr = Arc::as_ptr(self) as *const ();
+        for_each_task(|task| task.clone())
+            .iter()
+            .filter(|task| task.is_process() && !task.is_kthread())
+            .any(|task| {
+                task.memory_set().read().areas.values().any(|area| {
+                    area.map_type == MapType::Filebe
+                        && area.is_shared()
+                        && area.map_perm.contains(MapPermission::W)
+                        && area.backend_file.as_ref().is_some_and(|file| {
+                            Arc::as_ptr(&file.get_inode()) as *const () == inode_addr
+                        })
+                })
+            })
+    }
+    /// 返回page_index处的页, 不存在时分配一个清零的页
+    fn get_or_alloc_page(&self, page_index: usize) -> Arc<Page> {
+        if let Some(page) = self.address_space.get_page_cache(page_index) {
+            return page;
+        }
+        self.address_space
+            .i_pages
+            .write()
+            .entry(page_index)
+            .or_insert_with(|| Arc::new(Page::new_framed(None)))
+            .clone()
+    }
+}
+
+impl InodeOp for MemfdInode {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn read<'a>(&'a self, offset: usize, buf: &'a mut [u8]) -> usize {
+        let size = self.inner.read().size;
+        if offset >= size {
+            return 0;
+        }
+        let end = size.min(offset + buf.len());
+        let mut current = offset;
+        while current < end {
+            let page_offset = current % PAGE_SIZE;
+            let len = (PAGE_SIZE - page_offset).min(end - current);
+            let dst = &mut buf[current - offset..current - offset + len];
+            // 没有分配的页读出0
+            match self.address_space.get_page_cache(current >> PAGE_SIZE_BITS) {
+                Some(page) => dst
+                    .copy_from_slice(&page.ppn().get_bytes_array()[page_offset..page_offset + len]),
+                None => dst.fill(0),
+            }
+            current += len;
+        }
+        self.inner.write().atime = TimeSpec::new_wall_time();
+        end - offset
+    }
+    /// 共享映射缺页时调用, 超出文件大小时返回None
+    fn get_page(&self, page_index: usize) -> Option<Arc<Page>> {
+        if page_index << PAGE_SIZE_BITS >= self.inner.read().size {
+            return None;
+        }
+        Some(self.get_or_alloc_page(page_index))
+    }
+    /// 用于SEEK_DATA/SEEK_HOLE, 已分配的页视为数据
+    fn lookup_extent(&self, page_index: usize) -> Option<(usize, usize)> {
+        self.address_space
+            .get_page_cache(page_index)
+            .map(|_| (page_index, 1))
+    }
+    fn write<'a>(&'a self, offset: usize, buf: &'a [u8]) -> usize {
+        let end = offset + buf.len();
+        let mut current = offset;
+        while current < end {
+            let page_offset = current % PAGE_SIZE;
+            let len = (PAGE_SIZE - page_offset).min(end - current);
+            let page = self.get_or_alloc_page(current >> PAGE_SIZE_BITS);
+            page.ppn().get_bytes_array()[page_offset..page_offset + len]
+                .copy_from_slice(&buf[current - offset..current - offset + len]);
+            current += len;
+        }
+        let mut inner_guard = self.inner.write();
+        inner_guard.size = inner_guard.size.max(end);
+        inner_guard.mtime = TimeSpec::new_wall_time();
+        inner_guard.ctime = inner_guard.mtime;
+        buf.len()
+    }
+    fn write_dio<'a>(&'a self, offset: usize, buf: &'a [u8]) -> usize {
+        self.write(offset, buf)
+    }
+    /// 缩小时释放超出部分的页, 并将最后一页的剩余部分清零
+    fn truncate(&self, size: usize) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        if size < inner_guard.size {
+            let first_removed = size.div_ceil(PAGE_SIZE);
+            self.address_space
+                .i_pages
+                .write()
+                .retain(|page_index, _| *page_index < first_removed);
+            if size % PAGE_SIZE != 0 {
+                if let Some(page) = self.address_space.get_page_cache(size >> PAGE_SIZE_BITS) {
+                    page.ppn().get_bytes_array()[size % PAGE_SIZE..].fill(0);
+                }
+            }
+        }
+        inner_guard.size = size;
+        inner_guard.mtime = TimeSpec::new_wall_time();
+        inner_guard.ctime = inner_guard.mtime;
+        Ok(0)
+    }
+    fn fallocate(&self, mode: FallocFlags, offset: usize, len: usize) -> SyscallRet {
+        let end = offset.checked_add(len).ok_or(Errno::EFBIG)?;
+        if mode.contains(FallocFlags::PUNCH_HOLE) {
+            // 只释放完整覆盖的页, 其余部分清零
+            let zeros = [0u8; PAGE_SIZE];
+            let first_full = offset.div_ceil(PAGE_SIZE);
+            let last_full = end / PAGE_SIZE;
+            let size = self.inner.read().size;
+            for (start, stop) in [
+                (offset, end.min(first_full * PAGE_SIZE)),
+                ((last_full * PAGE_SIZE).max(offset), end),
+            ] {
+                let stop = stop.min(size);
+                if start < stop
+                    && self
+                        .address_space
+                        .get_page_cache(start >> PAGE_SIZE_BITS)
+                        .is_some()
+                {
+                    self.write(start, &zeros[..stop - start]);
+                }
+            }
+            self.address_space
+                .i_pages
+                .write()
+                .retain(|page_index, _| !(first_full..last_full).contains(page_index));
+            return Ok(0);
+        }
+        if !mode.difference(FallocFlags::KEEP_SIZE).is_empty() {
+            return Err(Errno::EOPNOTSUPP);
+        }
+        for page_index in offset >> PAGE_SIZE_BITS..end.div_ceil(PAGE_SIZE) {
+            self.get_or_alloc_page(page_index);
+        }
+        if !mode.contains(FallocFlags::KEEP_SIZE) {
+            let mut inner_guard = self.inner.write();
+            inner_guard.size = inner_guard.size.max(end);
+        }
+        Ok(0)
+    }
+    fn fsync(&self) -> SyscallRet {
+        Ok(0)
+    }
+    fn can_lookup(&self) -> bool {
+        false
+    }
+    fn getattr(&self) -> Kstat {
+        let mut kstat = Kstat::new();
+        let inner_guard = self.inner.read();
+        kstat.ino = self.inode_num as u64;
+        kstat.dev = 0;
+        kstat.mode = inner_guard.mode;
+        kstat.uid = inner_guard.uid;
+        kstat.gid = inner_guard.gid;
+        kstat.nlink = 1;
+        kstat.size = inner_guard.size as u64;
+        kstat.blksize = PAGE_SIZE as u32;
+        kstat.blocks = (self.get_resident_page_count() * PAGE_SIZE / 512) as u64;
+        kstat.atime = inner_guard.atime;
+        kstat.mtime = inner_guard.mtime;
+        kstat.ctime = inner_guard.ctime;
+        kstat
+    }
+    fn get_inode_num(&self) -> usize {
+        self.inode_num
+    }
+    fn get_size(&self) -> usize {
+        self.inner.read().size
+    }
+    fn get_resident_page_count(&self) -> usize {
+        self.address_space.len()
+    }
+    fn get_mode(&self) -> u16 {
+        self.inner.read().mode
+    }
+    fn set_mode(&self, mode: u16) {
+        self.inner.write().mode = mode;
+    }
+    fn set_perm(&self, perm: u16) {
+        let mut inner_guard = self.inner.write();
+        inner_guard.mode = (inner_guard.mode & !0o7777) | (perm & 0o7777);
+    }
+    fn get_uid(&self) -> u32 {
+        self.inner.read().uid
+    }
+    fn set_uid(&self, uid: u32) {
+        self.inner.write().uid = uid;
+    }
+    fn get_gid(&self) -> u32 {
+        self.inner.read().gid
+    }
+    fn set_gid(&self, gid: u32) {
+        self.inner.write().gid = gid;
+    }
+    /* 时间戳 */
+    fn get_atime(&self) -> TimeSpec {
+        self.inner.read().atime
+    }
+    fn set_atime(&self, atime: TimeSpec) {
+        self.inner.write().atime = atime;
+    }
+    fn get_mtime(&self) -> TimeSpec {
+        self.inner.read().mtime
+    }
+    fn set_mtime(&self, mtime: TimeSpec) {
+        self.inner.write().mtime = mtime;
+    }
+    fn get_ctime(&self) -> TimeSpec {
+        self.inner.read().ctime
+    }
+    fn set_ctime(&self, ctime: TimeSpec) {
+        self.inner.write().ctime = ctime;
+    }
+}
+
+/// memfd打开的文件, 读写和定位由内部的`File`完成, 修改内容和大小前检查封印
+pub struct MemFd {
+    file: File,
+    inode: Arc<MemfdInode>,
+}
+
+impl MemFd {
+    /// `name`不包括"memfd:"前缀, 没有MFD_ALLOW_SEALING时初始封印为F_SEAL_SEAL
+    pub fn new(name: &str, flags: u32) -> Arc<Self> {
+        let seals = if flags & MFD_ALLOW_SEALING != 0 {
+            0
+        } else {
+            F_SEAL_SEAL
+        };
+        let inode = MemfdInode::new(seals);
+        // 与Linux一致, /proc/<pid>/fd/<fd>指向"/memfd:<name> (deleted)"
+        let dentry = Dentry::new(
+            format!("/memfd:{} (deleted)", name),
+            None,
+            DentryFlags::DCACHE_REGULAR_TYPE,
+            inode.clone(),
+        );
+        let path = Path::new(current_task().root().mnt.clone(), dentry);
+        Arc::new(Self {
+            file: File::new(path, inode.clone(), OpenFlags::O_RDWR),
+            inode,
+        })
+    }
+    pub fn inode(&self) -> &Arc<MemfdInode> {
+        &self.inode
+    }
+    /// 写入[offset, offset + len)前检查封印
+    fn check_write(&self, offset: usize, len: usize) -> Result<(), Errno> {
+        if self.inode.write_sealed() {
+            return Err(Errno::EPERM);
+        }
+        self.inode
+            .check_resize(self.inode.get_size().max(offset + len))
+    }
+    /// write时实际写入的位置
+    fn write_offset(&self) -> usize {
+        if self.get_flags().contains(OpenFlags::O_APPEND) {
+            self.inode.get_size()
+        } else {
+            self.file.get_offset()
+        }
+    }
+}
+
+impl FileOp for MemFd {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn read<'a>(&'a self, buf: &'a mut [u8]) -> SyscallRet {
+        self.file.read(buf)
+    }
+    fn pread<'a>(&'a self, buf: &'a mut [u8], offset: usize) -> SyscallRet {
+        self.file.pread(buf, offset)
+    }
+    fn pwrite<'a>(&'a self, buf: &'a [u8], offset: usize) -> SyscallRet {
+        let offset = if self.get_flags().contains(OpenFlags::O_APPEND) {
+            self.inode.get_size()
+        } else {
+            offset
+        };
+        self.check_write(offset, buf.len())?;
+        self.file.pwrite(buf, offset)
+    }
+    fn read_all(&self) -> Vec<u8> {
+        self.file.read_all()
+    }
+    fn get_page(&self, page_aligned_offset: usize) -> Option<Arc<Page>> {
+        self.file.get_page(page_aligned_offset)
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn get_path(&self) -> Arc<Path> {
+        self.file.get_path()
+    }
+    fn write<'a>(&'a self, buf: &'a [u8]) -> SyscallRet {
+        self.check_write(self.write_offset(), buf.len())?;
+        self.file.write(buf)
+    }
+    fn write_dio<'a>(&'a self, buf: &'a [u8]) -> SyscallRet {
+        self.check_write(self.write_offset(), buf.len())?;
+        self.file.write_dio(buf)
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        self.file.seek(offset, whence)
+    }
+    fn truncate(&self, length: usize) -> SyscallRet {
+        self.inode.check_resize(length)?;
+        self.file.truncate(length)
+    }
+    fn fallocate(&self, mode: FallocFlags, offset: usize, length: usize) -> SyscallRet {
+        if mode.contains(FallocFlags::PUNCH_HOLE) {
+            if self.inode.write_sealed() {
+                return Err(Errno::EPERM);
+            }
+        } else if !mode.contains(FallocFlags::KEEP_SIZE) {
+            self.inode
+                .check_resize(self.inode.get_size().max(offset + length))?;
+        }
+        self.file.fallocate(mode, offset, length)
+    }
+    fn fsync(&self) -> SyscallRet {
+        Ok(0)
+    }
+    fn get_offset(&self) -> usize {
+        self.file.get_offset()
+    }
+    fn readable(&self) -> bool {
+        self.file.readable()
+    }
+    fn writable(&self) -> bool {
+        self.file.writable()
+    }
+    fn r_ready(&self) -> bool {
+        true
+    }
+    fn w_ready(&self) -> bool {
+        true
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.file.get_flags()
+    }
+    fn set_flags(&self, flags: OpenFlags) {
+        self.file.set_flags(flags)
+    }
+}
@@ -22,18 +22,23 @@ pub mod file;
 pub mod inode;
 pub mod kstat;
 pub mod manager;
+pub mod memfd;
 pub mod mount;
 pub mod namei;
 pub mod old;
 pub mod page_cache;
 pub mod path;
+pub mod pidfd;
 pub mod pipe;
 pub mod proc;
 mod stdio;
+pub mod sys;
 pub mod tmp;
 // pub mod tty;
 // pub mod fd_set;
 pub mod uapi;
+pub mod userfaultfd;
+pub mod writeback;
 
 // 文件系统的锁先使用SpinNoIrqLock, Todo: 改成RwLock
 pub type FSMutex<T> = RwLock<T>;
@@ -14,6 +14,7 @@ use crate::{
     },
     mutex::SpinNoIrqLock,
     syscall::errno::SyscallRet,
+    task::{current_task, namespace::PROC_MNT_INIT_INO},
 };
 
 use super::{
@@ -25,6 +26,7 @@ use super::{
     namei::{filename_create, parse_path, Nameidata},
     path::Path,
     proc::init_procfs,
+    sys::{cgroup::cgroup_mount, init_sysfs},
     tmp::init_tmpfs,
     uapi::StatFs,
 };
@@ -84,13 +86,13 @@ impl Mount {
     }
 }
 
-/// 全局 mount 树
+/// mount 树
 struct MountTree {
     mount_table: Vec<Arc<Mount>>,
 }
 
 impl MountTree {
-    /// 创建一个新的全局 mount 树，默认只有根 "/"
+    /// 创建一个新的 mount 树，默认只有根 "/"
     fn new() -> Self {
         MountTree {
             mount_table: { Vec::new() },
@@ -98,15 +100,31 @@ impl MountTree {
     }
 }
 
+/// mount 命名空间
+/// 挂载操作没有接入 mount 树, 因此只有初始命名空间, 不支持 CLONE_NEWNS
+pub struct MntNamespace {
+    inum: u64,
+    mount_tree: SpinNoIrqLock<MountTree>,
+}
+
+impl MntNamespace {
+    pub fn inum(&self) -> u64 {
+        self.inum
+    }
+}
+
 lazy_static! {
-    /// 全局的 mount 树
-    static ref MOUNT_TREE: SpinNoIrqLock<MountTree> = {
-        SpinNoIrqLock::new(MountTree::new())
-    };
+    /// 初始的 mount 命名空间, 启动时挂载的文件系统都在其中
+    pub static ref INIT_MNT_NS: Arc<MntNamespace> = Arc::new(MntNamespace {
+        inum: PROC_MNT_INIT_INO,
+        mount_tree: SpinNoIrqLock::new(MountTree::new()),
+    });
 }
 
+/// 当前任务所在 mount 命名空间的挂载信息
 pub fn read_proc_mounts() -> String {
-    let mount_tree = MOUNT_TREE.lock();
+    let mnt_ns = current_task().mnt_ns();
+    let mount_tree = mnt_ns.mount_tree.lock();
     let mut output = String::new();
 
     for mount in &mount_tree.mount_table {
@@ -135,8 +153,9 @@ pub fn read_proc_mounts() -> String {
     output
 }
 
+/// 启动时挂载文件系统, 加入初始 mount 命名空间
 pub fn add_mount(mount: Arc<Mount>) {
-    let mut mount_tree = MOUNT_TREE.lock();
+    let mut mount_tree = INIT_MNT_NS.mount_tree.lock();
     mount_tree.mount_table.push(mount);
 }
 
@@ -157,7 +176,8 @@ pub fn add_mount(mount: Arc<Mount>) {
 //     return None;
 // }
 pub fn get_mount_by_dentry(dentry: Arc<Dentry>) -> Option<Arc<Mount>> {
-    let mount_tree = MOUNT_TREE.lock();
+    let mnt_ns = current_task().mnt_ns();
+    let mount_tree = mnt_ns.mount_tree.lock();
     for mount in mount_tree.mount_table.iter() {
         // 如果mount的parent->mnt与path.mnt相同(同一棵Mount Tree),
         // 且path.dentry是mount的root, 则返回这个mount(挂载点)
@@ -202,6 +222,7 @@ pub fn do_ext4_mount(block_device: Arc<dyn BlockDevice>) -> Arc<Path> {
     init_devfs(root_path.clone());
     init_procfs(root_path.clone());
     init_tmpfs(root_path.clone());
+    init_sysfs(root_path.clone());
 
     // Todo: 为了busybox which ls, 创建一个空的/bin/ls
     // let bin_path = "/bin";
@@ -251,6 +272,9 @@ pub fn do_mount(
     flags: usize,
     _data: *const u8,
 ) -> SyscallRet {
+    if fs_type == "cgroup2" {
+        return cgroup_mount(&dir_name);
+    }
     // user_path_at
     // 需要把dev_name先转换成BlockDevice?
     // path_mount
@@ -9,12 +9,13 @@ use super::{
     path::Path,
     pipe::Pipe,
     proc::{
+        buddyinfo::BUDDYINFO,
         exe::EXE,
         maps::MAPS,
         meminfo::{self, MEMINFO},
         mounts::{MountsFile, MOUNTS},
         pagemap::PAGEMAP,
-        pid::PID_STAT,
+        pid::{PID_COMM, PID_STAT},
         status::STATUS,
     },
     tmp,
@@ -37,17 +38,23 @@ use crate::{
         },
         fdtable::{FdEntry, FdFlags},
         proc::{
+            binfmt_misc::binfmt_misc_open,
+            coredump::CORE_SYSCTLS,
             cpuinfo::CPUINFO,
             fd::{record_fd, FD_FILE},
+            ns::{is_ns_link, ns_open},
+            oom::OOM_FILES,
             pid::{record_target_pid, TARGERT_PID},
             pid_max::PIDMAX,
-            smaps::SMAPS,
+            smaps::{PID_SMAPS, SMAPS},
             tainted::TAINTED,
+            vm::VM_SYSCTLS,
         },
+        sys::{cgroup::cgroup_open, ksm::KSM_ATTRS},
         AT_FDCWD,
     },
     syscall::{errno::Errno, AT_SYMLINK_NOFOLLOW},
-    task::current_task,
+    task::{capability::CapSet, current_task},
 };
 use alloc::{
     format,
@@ -327,7 +334,8 @@ pub fn open_last_lookups(
         } else {
             let dentry = lookup_dentry(nd);
             if !dentry.is_negative() {
-                if dentry.is_symlink() {
+                // /proc/<pid>/ns/*打开的是链接本身
+                if dentry.is_symlink() && !is_ns_link(&dentry) {
                     if flags.contains(OpenFlags::O_NOFOLLOW) {
                         // 路径最后一个分量是符号链接, 但禁止跟随, 直接返回符号链接的dentry
                         let path = Path::new(nd.mnt.clone(), dentry.clone());
@@ -424,7 +432,8 @@ pub fn open_last_lookups2(
         } else {
             let dentry = lookup_dentry(nd);
             if !dentry.is_negative() {
-                if dentry.is_symlink() {
+                // /proc/<pid>/ns/*打开的是链接本身
+                if dentry.is_symlink() && !is_ns_link(&dentry) {
                     if resolve_flags.contains(ResolveFlags::RESOLVE_NO_SYMLINKS) {
                         return Err(Errno::ELOOP); // 禁止跟随符号链接
                     }
@@ -496,6 +505,11 @@ fn create_file_from_dentry(
             meminfo.seek(0, super::uapi::Whence::SeekSet).unwrap();
             return Ok(meminfo);
         }
+        if dentry.absolute_path == "/proc/buddyinfo" {
+            let buddyinfo: Arc<dyn FileOp> = BUDDYINFO.get().unwrap().clone();
+            buddyinfo.seek(0, super::uapi::Whence::SeekSet).unwrap();
+            return Ok(buddyinfo);
+        }
         if dentry.absolute_path == "/proc/self/exe" {
             return Ok(EXE.get().unwrap().clone());
         }
@@ -524,6 +538,16 @@ fn create_file_from_dentry(
             pid_stat.seek(0, super::uapi::Whence::SeekSet).unwrap();
             return Ok(pid_stat);
         }
+        if dentry.absolute_path == "/proc/pid/smaps" {
+            let pid_smaps: Arc<dyn FileOp> = PID_SMAPS.get().unwrap().clone();
+            pid_smaps.seek(0, super::uapi::Whence::SeekSet)?;
+            return Ok(pid_smaps);
+        }
+        if dentry.absolute_path == "/proc/pid/comm" {
+            let pid_comm: Arc<dyn FileOp> = PID_COMM.get().unwrap().clone();
+            pid_comm.seek(0, super::uapi::Whence::SeekSet)?;
+            return Ok(pid_comm);
+        }
         if dentry.absolute_path.starts_with("/proc/self/fd/") {
             // /proc/self/fd/XXX
             return Ok(FD_FILE.get().unwrap().clone());
@@ -533,6 +557,35 @@ fn create_file_from_dentry(
             let tainted_file = TAINTED.get().unwrap().clone();
             return Ok(tainted_file);
         }
+        if let Some(oom_file) = OOM_FILES.get().unwrap().get(dentry.absolute_path.as_str()) {
+            // /proc/self/oom_score{,_adj}, /proc/pid/oom_score{,_adj}
+            oom_file.seek(0, super::uapi::Whence::SeekSet)?;
+            return Ok(oom_file.clone());
+        }
+        if let Some(ns_file) = ns_open(&dentry, path.clone(), inode.clone(), flags) {
+            // /proc/self/ns/*, /proc/pid/ns/*
+            return ns_file;
+        }
+        if let Some(binfmt_misc_file) =
+            binfmt_misc_open(&dentry, path.clone(), inode.clone(), flags)
+        {
+            // /proc/sys/fs/binfmt_misc/*
+            return Ok(binfmt_misc_file);
+        }
+        if let Some(sysctl) = VM_SYSCTLS.get().unwrap().get(dentry.absolute_path.as_str()) {
+            // /proc/sys/vm/*
+            sysctl.seek(0, super::uapi::Whence::SeekSet)?;
+            return Ok(sysctl.clone());
+        }
+        if let Some(sysctl) = CORE_SYSCTLS
+            .get()
+            .unwrap()
+            .get(dentry.absolute_path.as_str())
+        {
+            // /proc/sys/kernel/core_pattern, /proc/sys/kernel/core_uses_pid
+            sysctl.seek(0, super::uapi::Whence::SeekSet)?;
+            return Ok(sysctl.clone());
+        }
         if dentry.absolute_path == "/proc/sys/kernel/pid_max" {
             // /proc/sys/kernel/pid_max
             let pid_max: Arc<dyn FileOp> = PIDMAX.get().unwrap().clone();
@@ -546,6 +599,18 @@ fn create_file_from_dentry(
         }
     }
 
+    if dentry.absolute_path.starts_with("/sys") {
+        if let Some(ksm_attr) = KSM_ATTRS.get().unwrap().get(dentry.absolute_path.as_str()) {
+            // /sys/kernel/mm/ksm/*
+            ksm_attr.seek(0, super::uapi::Whence::SeekSet)?;
+            return Ok(ksm_attr.clone());
+        }
+        if let Some(cgroup_file) = cgroup_open(&dentry, path.clone(), inode.clone(), flags) {
+            // /sys/fs/cgroup/**/*
+            return Ok(cgroup_file);
+        }
+    }
+
     let file: Arc<dyn FileOp> = match file_type {
         S_IFREG => Arc::new(File::new(path, inode, flags)),
         S_IFDIR => Arc::new(File::new(path, inode, flags)),
@@ -900,7 +965,7 @@ pub fn link_path_walk(nd: &mut Nameidata) -> Result<(), Errno> {
                 return Err(Errno::ENOTDIR);
             }
             // 检查对目录有无搜索权限
-            if task.fsuid() != 0 {
+            if !task.capable(CapSet::DAC_READ_SEARCH) {
                 if !dentry.can_search() {
                     return Err(Errno::EACCES); // 没有搜索权限
                 }
@@ -996,7 +1061,7 @@ pub fn link_path_walk2(nd: &mut Nameidata, resolve_flags: &ResolveFlags) -> Resu
                 return Err(Errno::ENOTDIR);
             }
             // 检查对目录有无搜索权限
-            if task.fsuid() != 0 {
+            if !task.capable(CapSet::DAC_READ_SEARCH) {
                 if !dentry.can_search() {
                     return Err(Errno::EACCES); // 没有搜索权限
                 }
@@ -1,18 +1,200 @@
 //! Todo: 还需要搞定extent tree的逻辑
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use alloc::{
     collections::btree_map::BTreeMap,
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use spin::RwLock;
 
 use crate::{
     drivers::block::block_dev::{self, BlockDevice},
-    ext4::MAX_FS_BLOCK_ID,
+    ext4::{inode::Ext4Inode, MAX_FS_BLOCK_ID},
     mm::{Page, PageKind},
+    mutex::SpinNoIrqLock,
 };
 
 use super::inode::InodeOp;
 
+/// 全局页缓存LRU链表中的一项
+/// 不持有页和inode, 页离开i_pages或被释放时从LRU中移除
+#[derive(Clone)]
+struct LruEntry {
+    page: Weak<Page>,
+    inode: Weak<dyn InodeOp>,
+    page_index: usize,
+}
+
+/// 全局页缓存LRU链表, 新的页缓存加入队尾, 回收时从队头开始扫描
+/// 使用second chance算法近似LRU: 被访问过的页清除访问标记后重新放回队尾
+struct PageCacheLru {
+    /// key是加入队尾时的序号, 序号越小越靠近队头
+    queue: BTreeMap<usize, LruEntry>,
+    /// 页的地址到序号的映射, 用于页离开页缓存时从队列中移除
+    seqs: BTreeMap<usize, usize>,
+    next_seq: usize,
+    /// 仍在页缓存中的脏页, key是页的地址, value是变脏的时间(ms)
+    /// 写回时只遍历脏页, 不需要遍历整个LRU
+    dirty: BTreeMap<usize, usize>,
+}
+
+impl PageCacheLru {
+    const fn new() -> Self {
+        Self {
+            queue: BTreeMap::new(),
+            seqs: BTreeMap::new(),
+            next_seq: 0,
+            dirty: BTreeMap::new(),
+        }
+    }
+    fn push_back(&mut self, addr: usize, entry: LruEntry) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.insert(seq, entry);
+        if let Some(old_seq) = self.seqs.insert(addr, seq) {
+            self.queue.remove(&old_seq);
+        } else {
+            NR_PAGE_CACHE.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    fn remove(&mut self, addr: usize) {
+        if let Some(seq) = self.seqs.remove(&addr) {
+            self.queue.remove(&seq);
+            self.dirty.remove(&addr);
+            NR_PAGE_CACHE.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+    /// 将队头的项移到队尾, 并返回该项
+    fn rotate(&mut self) -> Option<LruEntry> {
+        let (_, entry) = self.queue.pop_first()?;
+        let addr = entry.page.as_ptr() as usize;
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.seqs.insert(addr, seq);
+        self.queue.insert(seq, entry.clone());
+        Some(entry)
+    }
+}
+
+static PAGE_CACHE_LRU: SpinNoIrqLock<PageCacheLru> = SpinNoIrqLock::new(PageCacheLru::new());
+
+/// 驻留在页缓存(i_pages)中的页数
+static NR_PAGE_CACHE: AtomicUsize = AtomicUsize::new(0);
+
+fn page_addr(page: &Page) -> usize {
+    page as *const Page as usize
+}
+
+fn lru_add(page: &Arc<Page>, inode: Weak<dyn InodeOp>, page_index: usize) {
+    PAGE_CACHE_LRU.lock().push_back(
+        page_addr(page),
+        LruEntry {
+            page: Arc::downgrade(page),
+            inode,
+            page_index,
+        },
+    );
+}
+
+/// 页离开i_pages时从LRU中移除
+fn lru_del(page: &Page) {
+    PAGE_CACHE_LRU.lock().remove(page_addr(page));
+}
+
+/// 页变脏时加入脏页列表, 由`Page`在脏标记变化时调用
+/// 已经离开页缓存的页不加入
+pub fn dirty_add(page: &Page, dirtied_at: usize) {
+    let addr = page_addr(page);
+    let mut lru = PAGE_CACHE_LRU.lock();
+    if lru.seqs.contains_key(&addr) {
+        lru.dirty.insert(addr, dirtied_at);
+    }
+}
+
+/// 页被写回或释放时从脏页列表中移除
+pub fn dirty_del(page: &Page) {
+    PAGE_CACHE_LRU.lock().dirty.remove(&page_addr(page));
+}
+
+/// 页被释放时调用, 移除LRU和脏页列表中残留的项
+pub fn page_cache_forget(page: &Page) {
+    PAGE_CACHE_LRU.lock().remove(page_addr(page));
+}
+
+/// 页缓存中驻留的页数
+pub fn nr_page_cache() -> usize {
+    NR_PAGE_CACHE.load(Ordering::Relaxed)
+}
+
+/// 页缓存中驻留的脏页数, 不包括已经离开页缓存但还未写回的页
+pub fn nr_dirty_page_cache() -> usize {
+    PAGE_CACHE_LRU.lock().dirty.len()
+}
+
+/// 返回所有仍在页缓存中的脏页, 按变脏时间从早到晚排序
+pub fn dirty_page_cache() -> Vec<Arc<Page>> {
+    let mut pages: Vec<(usize, Arc<Page>)> = {
+        let lru = PAGE_CACHE_LRU.lock();
+        lru.dirty
+            .iter()
+            .filter_map(|(addr, dirtied_at)| {
+                let seq = lru.seqs.get(addr)?;
+                let page = lru.queue.get(seq)?.page.upgrade()?;
+                Some((*dirtied_at, page))
+            })
+            .collect()
+    };
+    pages.sort_by_key(|(dirtied_at, _)| *dirtied_at);
+    pages.into_iter().map(|(_, page)| page).collect()
+}
+
+/// 回收干净且未被映射的页缓存, 返回回收的页数
+/// 由`frame_alloc`在内存不足时调用, 调用时不能持有`FRAME_ALLOCATOR`的锁
+pub fn shrink_page_cache(nr_to_reclaim: usize) -> usize {
+    let mut reclaimed = 0;
+    // 每一项最多扫描两次, 第一次可能只是清除访问标记
+    let mut nr_to_scan = nr_page_cache() * 2;
+    while reclaimed < nr_to_reclaim && nr_to_scan > 0 {
+        nr_to_scan -= 1;
+        // 先放回队尾, 页被回收时由`evict_page_cache`从LRU中移除
+        let Some(entry) = PAGE_CACHE_LRU.lock().rotate() else {
+            break;
+        };
+        // 页正在被释放
+        let Some(page) = entry.page.upgrade() else {
+            continue;
+        };
+        // i_pages和这里各持有一个引用, 更多的引用说明页被映射或正在使用
+        if page.test_and_clear_referenced()
+            || !page.is_reclaimable()
+            || Arc::strong_count(&page) > 2
+        {
+            continue;
+        }
+        let evicted = entry.inode.upgrade().is_some_and(|inode| {
+            inode
+                .as_any()
+                .downcast_ref::<Ext4Inode>()
+                .is_some_and(|ext4_inode| {
+                    ext4_inode
+                        .address_space
+                        .evict_page_cache(entry.page_index, &page)
+                })
+        });
+        if evicted {
+            // page在这里被drop, 释放页帧
+            reclaimed += 1;
+        }
+    }
+    log::warn!(
+        "[shrink_page_cache] reclaimed {} pages, want {}",
+        reclaimed,
+        nr_to_reclaim
+    );
+    reclaimed
+}
+
 // Todo:
 // 管理页缓存, 注意页缓存只存储文件的数据, 不存储元数据
 pub struct AddressSpace {
@@ -33,6 +215,7 @@ impl AddressSpace {
     pub fn get_page_cache(self: &Self, page_index: usize) -> Option<Arc<Page>> {
         // 看i_pages中是否有对应的页缓存
         if let Some(page) = self.i_pages.read().get(&page_index) {
+            page.mark_accessed();
             return Some(page.clone());
         } else {
             None
@@ -40,10 +223,11 @@ impl AddressSpace {
     }
     pub fn remove_page_cache(self: &Self, page_offset: usize) {
         // 删除页缓存
-        self.i_pages.write().remove(&page_offset).or_else(|| {
-            log::error!("remove_page_cache: page_offset {} not found", page_offset);
-            None
-        });
+        let page = self.i_pages.write().remove(&page_offset);
+        match page {
+            Some(page) => lru_del(&page),
+            None => log::error!("remove_page_cache: page_offset {} not found", page_offset),
+        }
     }
     pub fn new_page_cache(
         self: &Self,
@@ -56,8 +240,12 @@ impl AddressSpace {
         if fs_block_id >= MAX_FS_BLOCK_ID {
             log::warn!("new_page_cache: fs_block_id is usize::MAX, sparse file");
         }
-        let page = Arc::new(Page::new_filebe(fs_block_id, block_device, inode));
-        self.i_pages.write().insert(page_index, page.clone());
+        let page = Arc::new(Page::new_filebe(fs_block_id, block_device, inode.clone()));
+        let old = self.i_pages.write().insert(page_index, page.clone());
+        if let Some(old) = old {
+            lru_del(&old);
+        }
+        lru_add(&page, inode, page_index);
         page
     }
     pub fn new_inline_page_cache(
@@ -68,15 +256,46 @@ impl AddressSpace {
     ) -> Arc<Page> {
         // inline data目前最大是60字节, 应该是第一页
         assert!(page_index == 0);
-        let page = Arc::new(Page::new_inline(inode, inline_data));
-        self.i_pages.write().insert(page_index, page.clone());
+        let page = Arc::new(Page::new_inline(inode.clone(), inline_data));
+        let old = self.i_pages.write().insert(page_index, page.clone());
+        if let Some(old) = old {
+            lru_del(&old);
+        }
+        lru_add(&page, inode, page_index);
         page
     }
+    /// 回收页缓存, 只有当page_index对应的仍是`page`且页干净, 未被映射时才会移除
+    /// 在回收路径上调用, 不能阻塞, 锁被占用时直接放弃
+    pub fn evict_page_cache(self: &Self, page_index: usize, page: &Arc<Page>) -> bool {
+        let Some(mut i_pages) = self.i_pages.try_write() else {
+            return false;
+        };
+        match i_pages.get(&page_index) {
+            Some(cached) if Arc::ptr_eq(cached, page) => {
+                if Arc::strong_count(page) > 2 || !page.is_reclaimable() {
+                    return false;
+                }
+                i_pages.remove(&page_index);
+                lru_del(page);
+                true
+            }
+            _ => false,
+        }
+    }
     // Page有Drop trait, 会写回到磁盘
     pub fn clear(self: &Self) {
-        self.i_pages.write().clear();
+        let mut i_pages = self.i_pages.write();
+        i_pages.values().for_each(|page| lru_del(page));
+        i_pages.clear();
     }
     pub fn len(self: &Self) -> usize {
         self.i_pages.read().len()
     }
 }
+
+impl Drop for AddressSpace {
+    /// inode被回收时, 仍被映射的页不再属于页缓存
+    fn drop(&mut self) {
+        self.i_pages.get_mut().values().for_each(|page| lru_del(page));
+    }
+}
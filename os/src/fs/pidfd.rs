@@ -0,0 +1,86 @@
+//! pidfd: 引用一个进程的文件描述符
+//!
+//! 由pidfd_open或clone(CLONE_PIDFD)创建, 持有进程主线程的引用, 进程退出前pid不会被复用.
+//! 进程的所有线程退出后pidfd可读(poll返回POLLIN), 可以通过pidfd_send_signal发送信号,
+//! pidfd_getfd复制进程的文件描述符, waitid(P_PIDFD)等待子进程
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use alloc::sync::Arc;
+
+use crate::{
+    syscall::errno::{Errno, SyscallRet},
+    task::{Task, Tid},
+};
+
+use super::file::{FileOp, OpenFlags};
+
+/// pidfd_open的flags, 与O_NONBLOCK相同
+pub const PIDFD_NONBLOCK: i32 = OpenFlags::O_NONBLOCK.bits();
+
+pub struct PidFd {
+    task: Arc<Task>,
+    flags: AtomicI32,
+}
+
+impl PidFd {
+    pub fn new(task: Arc<Task>, flags: OpenFlags) -> Arc<Self> {
+        Arc::new(Self {
+            task,
+            flags: AtomicI32::new(flags.bits()),
+        })
+    }
+    pub fn task(&self) -> Arc<Task> {
+        self.task.clone()
+    }
+    /// 进程的所有线程都已经退出
+    pub fn exited(&self) -> bool {
+        self.task.is_zombie() && self.task.op_thread_group(|tg| tg.len()) == 0
+    }
+    pub fn nonblock(&self) -> bool {
+        self.get_flags().contains(OpenFlags::O_NONBLOCK)
+    }
+}
+
+/// 文件描述符引用的进程, 不是pidfd时返回EBADF
+pub fn pidfd_task(file: &Arc<dyn FileOp>) -> Result<Arc<Task>, Errno> {
+    file.as_any()
+        .downcast_ref::<PidFd>()
+        .map(|pidfd| pidfd.task())
+        .ok_or(Errno::EBADF)
+}
+
+impl FileOp for PidFd {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn read(&self, _buf: &mut [u8]) -> SyscallRet {
+        Err(Errno::EINVAL)
+    }
+    fn write(&self, _buf: &[u8]) -> SyscallRet {
+        Err(Errno::EINVAL)
+    }
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn add_wait_queue(&self, tid: Tid) {
+        self.task.op_thread_group_mut(|tg| tg.add_pidfd_waiter(tid));
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+    fn r_ready(&self) -> bool {
+        self.exited()
+    }
+    fn w_ready(&self) -> bool {
+        false
+    }
+    fn get_flags(&self) -> OpenFlags {
+        OpenFlags::from_bits(self.flags.load(Ordering::Relaxed)).unwrap()
+    }
+    fn set_flags(&self, flags: OpenFlags) {
+        self.flags.store(flags.bits(), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,267 @@
+//! /proc/sys/fs/binfmt_misc
+//!
+//! register写入`:name:type:offset:magic:mask:interpreter:flags`注册格式, 同时创建同名文件;
+//! status和各个格式的文件写入1启用, 0禁用, -1删除(status的-1删除所有格式)
+use core::{str, sync::atomic::Ordering};
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+};
+use spin::{Once, RwLock};
+
+use crate::{
+    ext4::inode::S_IFREG,
+    fs::{
+        dentry::{delete_dentry, insert_dentry, Dentry},
+        file::{FileOp, OpenFlags},
+        inode::InodeOp,
+        namei::{filename_create, filename_lookup, parse_path, Nameidata},
+        path::Path,
+        uapi::Whence,
+    },
+    syscall::errno::{Errno, SyscallRet},
+    task::{
+        binfmt::{
+            binfmt_misc_clear, binfmt_misc_enabled, binfmt_misc_lookup, binfmt_misc_register,
+            binfmt_misc_set_enabled, binfmt_misc_unregister,
+        },
+        capability::CapSet,
+        current_task,
+    },
+};
+
+pub const BINFMT_MISC_DIR: &str = "/proc/sys/fs/binfmt_misc";
+
+/// register和status, key是绝对路径
+pub static BINFMT_MISC_FILES: Once<BTreeMap<String, Arc<dyn FileOp>>> = Once::new();
+/// 创建和删除格式文件时使用的根路径
+static BINFMT_MISC_ROOT: Once<Arc<Path>> = Once::new();
+
+#[derive(Clone)]
+pub enum BinfmtMiscKind {
+    Register,
+    Status,
+    /// 已注册的格式
+    Entry(String),
+}
+
+impl BinfmtMiscKind {
+    pub const CONTROL: [BinfmtMiscKind; 2] = [BinfmtMiscKind::Register, BinfmtMiscKind::Status];
+    pub fn path(&self) -> String {
+        match self {
+            BinfmtMiscKind::Register => format!("{}/register", BINFMT_MISC_DIR),
+            BinfmtMiscKind::Status => format!("{}/status", BINFMT_MISC_DIR),
+            BinfmtMiscKind::Entry(name) => format!("{}/{}", BINFMT_MISC_DIR, name),
+        }
+    }
+    pub fn mode(&self) -> u16 {
+        match self {
+            BinfmtMiscKind::Register => 0o200,
+            _ => 0o644,
+        }
+    }
+    fn serialize(&self) -> Result<String, Errno> {
+        match self {
+            BinfmtMiscKind::Register => Err(Errno::EINVAL),
+            BinfmtMiscKind::Status => Ok(if binfmt_misc_enabled() {
+                "enabled\n".to_string()
+            } else {
+                "disabled\n".to_string()
+            }),
+            BinfmtMiscKind::Entry(name) => binfmt_misc_lookup(name)
+                .map(|entry| entry.describe())
+                .ok_or(Errno::ENOENT),
+        }
+    }
+    fn store(&self, info: &str) -> Result<(), Errno> {
+        if !current_task().capable(CapSet::SYS_ADMIN) {
+            return Err(Errno::EPERM);
+        }
+        match self {
+            BinfmtMiscKind::Register => {
+                let name = binfmt_misc_register(info)?;
+                binfmt_misc_create_file(&name);
+            }
+            BinfmtMiscKind::Status => match info.trim() {
+                "1" => binfmt_misc_set_enabled(true),
+                "0" => binfmt_misc_set_enabled(false),
+                "-1" => {
+                    for name in binfmt_misc_clear() {
+                        binfmt_misc_remove_file(&name);
+                    }
+                }
+                _ => return Err(Errno::EINVAL),
+            },
+            BinfmtMiscKind::Entry(name) => {
+                let entry = binfmt_misc_lookup(name).ok_or(Errno::ENOENT)?;
+                match info.trim() {
+                    "1" => entry.enabled.store(true, Ordering::Relaxed),
+                    "0" => entry.enabled.store(false, Ordering::Relaxed),
+                    "-1" => {
+                        binfmt_misc_unregister(name);
+                        binfmt_misc_remove_file(name);
+                    }
+                    _ => return Err(Errno::EINVAL),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn init_binfmt_misc_root(root_path: Arc<Path>) {
+    BINFMT_MISC_ROOT.call_once(|| root_path);
+}
+
+fn binfmt_misc_nameidata(path: &str) -> Nameidata {
+    let root_path = BINFMT_MISC_ROOT.get().unwrap();
+    Nameidata {
+        path_segments: parse_path(path),
+        dentry: root_path.dentry.clone(),
+        mnt: root_path.mnt.clone(),
+        depth: 0,
+    }
+}
+
+/// 注册成功后创建/proc/sys/fs/binfmt_misc/<name>
+fn binfmt_misc_create_file(name: &str) {
+    let path = BinfmtMiscKind::Entry(name.to_string()).path();
+    let mut nd = binfmt_misc_nameidata(&path);
+    match filename_create(&mut nd, 0) {
+        Ok(dentry) => {
+            let parent_inode = nd.dentry.get_inode();
+            parent_inode.create(dentry.clone(), S_IFREG | 0o644);
+            insert_dentry(dentry);
+        }
+        // 文件系统中可能残留上次启动时创建的文件
+        Err(e) => log::warn!("[binfmt_misc] create {} failed: {:?}", path, e),
+    }
+}
+
+fn binfmt_misc_remove_file(name: &str) {
+    let path = BinfmtMiscKind::Entry(name.to_string()).path();
+    let mut nd = binfmt_misc_nameidata(&path);
+    match filename_lookup(&mut nd, false) {
+        Ok(dentry) => {
+            let parent_inode = nd.dentry.get_inode();
+            if let Err(e) = parent_inode.unlink(dentry.clone()) {
+                log::warn!("[binfmt_misc] unlink {} failed: {:?}", path, e);
+                return;
+            }
+            delete_dentry(dentry);
+        }
+        Err(e) => log::warn!("[binfmt_misc] lookup {} failed: {:?}", path, e),
+    }
+}
+
+/// 打开/proc/sys/fs/binfmt_misc下的文件
+pub fn binfmt_misc_open(
+    dentry: &Arc<Dentry>,
+    path: Arc<Path>,
+    inode: Arc<dyn InodeOp>,
+    flags: OpenFlags,
+) -> Option<Arc<dyn FileOp>> {
+    if let Some(file) = BINFMT_MISC_FILES
+        .get()
+        .unwrap()
+        .get(dentry.absolute_path.as_str())
+    {
+        file.seek(0, Whence::SeekSet).ok()?;
+        return Some(file.clone());
+    }
+    let name = dentry
+        .absolute_path
+        .strip_prefix(BINFMT_MISC_DIR)?
+        .strip_prefix('/')?;
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    Some(BinfmtMiscFile::new(
+        path,
+        inode,
+        flags,
+        BinfmtMiscKind::Entry(name.to_string()),
+    ))
+}
+
+pub struct BinfmtMiscFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    pub kind: BinfmtMiscKind,
+    pub inner: RwLock<BinfmtMiscFileInner>,
+}
+
+#[derive(Default)]
+pub struct BinfmtMiscFileInner {
+    pub offset: usize,
+}
+
+impl BinfmtMiscFile {
+    pub fn new(
+        path: Arc<Path>,
+        inode: Arc<dyn InodeOp>,
+        flags: OpenFlags,
+        kind: BinfmtMiscKind,
+    ) -> Arc<Self> {
+        Arc::new(BinfmtMiscFile {
+            path,
+            inode,
+            flags,
+            kind,
+            inner: RwLock::new(BinfmtMiscFileInner::default()),
+        })
+    }
+}
+
+impl FileOp for BinfmtMiscFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        let info = self.kind.serialize()?;
+        let mut inner_guard = self.inner.write();
+        if inner_guard.offset >= info.len() {
+            return Ok(0);
+        }
+        let len = (info.len() - inner_guard.offset).min(buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[inner_guard.offset..inner_guard.offset + len]);
+        inner_guard.offset += len;
+        Ok(len)
+    }
+    fn readable(&self) -> bool {
+        !matches!(self.kind, BinfmtMiscKind::Register)
+    }
+    fn write(&self, buf: &[u8]) -> SyscallRet {
+        let info = str::from_utf8(buf).map_err(|_| Errno::EINVAL)?;
+        self.kind.store(info)?;
+        self.inner.write().offset += buf.len();
+        Ok(buf.len())
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        let base = match whence {
+            Whence::SeekSet => 0,
+            Whence::SeekCur => inner_guard.offset,
+            Whence::SeekEnd => self.kind.serialize().map_or(0, |info| info.len()),
+            _ => return Err(Errno::EINVAL),
+        };
+        inner_guard.offset = base.checked_add_signed(offset).ok_or(Errno::EINVAL)?;
+        Ok(inner_guard.offset)
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn get_path(&self) -> Arc<Path> {
+        self.path.clone()
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
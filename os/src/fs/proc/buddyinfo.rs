@@ -0,0 +1,110 @@
+use core::fmt::Write;
+
+use spin::{Once, RwLock};
+
+use crate::{
+    fs::{
+        file::{FileOp, OpenFlags},
+        inode::InodeOp,
+        path::Path,
+        uapi::Whence,
+    },
+    mm::frame_buddyinfo,
+    syscall::errno::{Errno, SyscallRet},
+};
+
+use alloc::{string::String, sync::Arc};
+
+/// /proc/buddyinfo
+pub static BUDDYINFO: Once<Arc<dyn FileOp>> = Once::new();
+
+pub struct BuddyInfoFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    pub inner: RwLock<BuddyInfoFileInner>,
+}
+
+#[derive(Default)]
+pub struct BuddyInfoFileInner {
+    pub offset: usize,
+}
+
+impl BuddyInfoFile {
+    pub fn new(path: Arc<Path>, inode: Arc<dyn InodeOp>, flags: OpenFlags) -> Arc<Self> {
+        Arc::new(BuddyInfoFile {
+            path,
+            inode,
+            flags,
+            inner: RwLock::new(BuddyInfoFileInner::default()),
+        })
+    }
+    /// 格式与Linux一致, 每一列是对应阶的空闲块数量
+    fn get_buddyinfo(&self) -> String {
+        let mut content = String::new();
+        write!(content, "Node 0, zone   Normal").unwrap();
+        for nr_free in frame_buddyinfo() {
+            write!(content, " {:>6}", nr_free).unwrap();
+        }
+        content.push('\n');
+        content
+    }
+}
+
+impl FileOp for BuddyInfoFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        let content = self.get_buddyinfo();
+        let bytes = content.as_bytes();
+        let offset = self.inner.read().offset;
+        let len = bytes.len();
+        if offset >= len {
+            return Ok(0);
+        }
+
+        let to_copy = buf.len().min(len - offset);
+        buf[..to_copy].copy_from_slice(&bytes[offset..offset + to_copy]);
+        self.inner.write().offset += to_copy;
+
+        Ok(to_copy)
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        match whence {
+            Whence::SeekSet => {
+                if offset < 0 {
+                    return Err(Errno::EINVAL);
+                }
+                inner_guard.offset = offset as usize;
+            }
+            Whence::SeekCur => {
+                inner_guard.offset = inner_guard
+                    .offset
+                    .checked_add_signed(offset)
+                    .ok_or(Errno::EINVAL)?;
+            }
+            Whence::SeekEnd => {
+                inner_guard.offset = self
+                    .get_buddyinfo()
+                    .len()
+                    .checked_add_signed(offset)
+                    .ok_or(Errno::EINVAL)?;
+            }
+            _ => {
+                return Err(Errno::EINVAL);
+            }
+        }
+        Ok(inner_guard.offset)
+    }
+    fn readable(&self) -> bool {
+        true
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
@@ -0,0 +1,142 @@
+//! /proc/sys/kernel下与core dump相关的可调参数
+use core::{str, sync::atomic::Ordering};
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+};
+use spin::{Once, RwLock};
+
+use crate::{
+    fs::{
+        file::{FileOp, OpenFlags},
+        inode::InodeOp,
+        path::Path,
+        uapi::Whence,
+    },
+    syscall::errno::{Errno, SyscallRet},
+    task::coredump::{CORENAME_MAX_SIZE, CORE_PATTERN, CORE_USES_PID},
+};
+
+/// /proc/sys/kernel下的core dump文件, key是绝对路径
+pub static CORE_SYSCTLS: Once<BTreeMap<&'static str, Arc<dyn FileOp>>> = Once::new();
+
+#[derive(Clone, Copy)]
+pub enum CoreSysctl {
+    CorePattern,
+    CoreUsesPid,
+}
+
+impl CoreSysctl {
+    pub const ALL: [CoreSysctl; 2] = [CoreSysctl::CorePattern, CoreSysctl::CoreUsesPid];
+    pub fn path(&self) -> &'static str {
+        match self {
+            CoreSysctl::CorePattern => "/proc/sys/kernel/core_pattern",
+            CoreSysctl::CoreUsesPid => "/proc/sys/kernel/core_uses_pid",
+        }
+    }
+    fn serialize(&self) -> String {
+        match self {
+            CoreSysctl::CorePattern => format!("{}\n", CORE_PATTERN.read()),
+            CoreSysctl::CoreUsesPid => format!("{}\n", CORE_USES_PID.load(Ordering::Relaxed)),
+        }
+    }
+    fn store(&self, info: &str) -> Result<(), Errno> {
+        match self {
+            CoreSysctl::CorePattern => {
+                // 与proc_dostring一致, 在换行处截断
+                let pattern = info.split('\n').next().unwrap();
+                if pattern.len() >= CORENAME_MAX_SIZE {
+                    return Err(Errno::EINVAL);
+                }
+                *CORE_PATTERN.write() = pattern.to_string();
+            }
+            CoreSysctl::CoreUsesPid => {
+                let val = info.trim().parse::<usize>().map_err(|_| Errno::EINVAL)?;
+                CORE_USES_PID.store(val, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct CoreSysctlFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    pub sysctl: CoreSysctl,
+    pub inner: RwLock<CoreSysctlFileInner>,
+}
+
+#[derive(Default)]
+pub struct CoreSysctlFileInner {
+    pub offset: usize,
+}
+
+impl CoreSysctlFile {
+    pub fn new(
+        path: Arc<Path>,
+        inode: Arc<dyn InodeOp>,
+        flags: OpenFlags,
+        sysctl: CoreSysctl,
+    ) -> Arc<Self> {
+        Arc::new(CoreSysctlFile {
+            path,
+            inode,
+            flags,
+            sysctl,
+            inner: RwLock::new(CoreSysctlFileInner::default()),
+        })
+    }
+}
+
+impl FileOp for CoreSysctlFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        let info = self.sysctl.serialize();
+        let mut inner_guard = self.inner.write();
+        if inner_guard.offset >= info.len() {
+            return Ok(0);
+        }
+        let len = (info.len() - inner_guard.offset).min(buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[inner_guard.offset..inner_guard.offset + len]);
+        inner_guard.offset += len;
+        Ok(len)
+    }
+    fn readable(&self) -> bool {
+        true
+    }
+    fn write(&self, buf: &[u8]) -> SyscallRet {
+        let info = str::from_utf8(buf).map_err(|_| Errno::EINVAL)?;
+        self.sysctl.store(info)?;
+        self.inner.write().offset += buf.len();
+        Ok(buf.len())
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        let base = match whence {
+            Whence::SeekSet => 0,
+            Whence::SeekCur => inner_guard.offset,
+            Whence::SeekEnd => self.sysctl.serialize().len(),
+            _ => {
+                log::warn!("Unsupported whence in CoreSysctlFile::seek: {:?}", whence);
+                return Err(Errno::EINVAL);
+            }
+        };
+        inner_guard.offset = base.checked_add_signed(offset).ok_or(Errno::EINVAL)?;
+        Ok(inner_guard.offset)
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
@@ -1,18 +1,20 @@
 use core::{default, str};
 
-use lazy_static::lazy_static;
 use spin::{lazy, mutex, Once, RwLock};
 
 use crate::{
+    arch::config::PAGE_SIZE,
     ext4::inode::Ext4InodeDisk,
     fs::{
         file::{FileOp, OpenFlags},
         inode::InodeOp,
         kstat::Kstat,
+        page_cache::{nr_dirty_page_cache, nr_page_cache},
         path::Path,
         uapi::Whence,
         FileOld,
     },
+    mm::{frame_available, frame_total},
     syscall::errno::{Errno, SyscallRet},
     timer::TimeSpec,
 };
@@ -131,7 +133,7 @@ impl FileOp for MemInfoFile {
         self
     }
     fn read(&self, buf: &mut [u8]) -> SyscallRet {
-        let info = FAKEMEMINFO.read().serialize();
+        let info = MemInfo::new().serialize();
         let len = info.len();
         if self.inner.read().offset >= len {
             return Ok(0);
@@ -156,8 +158,7 @@ impl FileOp for MemInfoFile {
                 inner_guard.offset = inner_guard.offset.checked_add_signed(offset).unwrap()
             }
             crate::fs::uapi::Whence::SeekEnd => {
-                inner_guard.offset = FAKEMEMINFO
-                    .read()
+                inner_guard.offset = MemInfo::new()
                     .serialize()
                     .len()
                     .checked_add_signed(offset)
@@ -178,16 +179,8 @@ impl FileOp for MemInfoFile {
     }
 }
 
-lazy_static! {
-    static ref FAKEMEMINFO: RwLock<FakeMemInfo> = RwLock::new(FakeMemInfo::new());
-}
-
-const TOTAL_MEM: usize = 16251136;
-const FREE_MEM: usize = 327680;
-const BUFFER: usize = 373336;
-const CACHED: usize = 10391984;
-const TOTAL_SWAP: usize = 4194300;
-struct FakeMemInfo {
+const TOTAL_SWAP: usize = 0;
+struct MemInfo {
     /// General memory
     pub total_mem: usize,
     pub free_mem: usize,
@@ -202,14 +195,21 @@ struct FakeMemInfo {
     pub shmem: usize,
     pub slab: usize,
 }
-impl FakeMemInfo {
-    pub const fn new() -> Self {
+impl MemInfo {
+    /// 从页帧分配器和页缓存统计当前内存信息, 单位为kB
+    pub fn new() -> Self {
+        let to_kb = |pages: usize| pages * PAGE_SIZE / 1024;
+        let free = frame_available();
+        // 只统计仍在i_pages中的页缓存
+        let cached = nr_page_cache();
+        // 干净的页缓存可以被回收
+        let reclaimable = cached.saturating_sub(nr_dirty_page_cache());
         Self {
-            total_mem: TOTAL_MEM,
-            free_mem: FREE_MEM,
-            avail_mem: TOTAL_MEM - FREE_MEM,
-            buffers: BUFFER,
-            cached: CACHED,
+            total_mem: to_kb(frame_total()),
+            free_mem: to_kb(free),
+            avail_mem: to_kb(free + reclaimable),
+            buffers: 0,
+            cached: to_kb(cached),
             total_swap: TOTAL_SWAP,
             free_swap: TOTAL_SWAP,
             shmem: 0,
@@ -8,7 +8,7 @@ use crate::{
 
 use super::{
     dentry::{self, insert_core_dentry, Dentry},
-    file::OpenFlags,
+    file::{FileOp, OpenFlags},
     mount::VfsMount,
     namei::{filename_create, parse_path, path_openat, Nameidata},
     path::Path,
@@ -16,25 +16,33 @@ use super::{
     uapi::DevT,
     AT_FDCWD,
 };
-use alloc::sync::Arc;
+use alloc::{collections::btree_map::BTreeMap, format, sync::Arc};
+use buddyinfo::{BuddyInfoFile, BUDDYINFO};
 use exe::{ExeFile, ExeInode, EXE};
 use fd::FdDirInode;
 use meminfo::{MemInfoFile, MEMINFO};
 use mounts::{MountsFile, MOUNTS};
+use ns::NsInode;
 use tainted::{TaintedFile, TAINTED};
 
+pub mod binfmt_misc;
+pub mod buddyinfo;
+pub mod coredump;
 pub mod cpuinfo;
 pub mod exe;
 pub mod fd;
 pub mod maps;
 pub mod meminfo;
 pub mod mounts;
+pub mod ns;
+pub mod oom;
 pub mod pagemap;
 pub mod pid;
 pub mod pid_max;
 pub mod smaps;
 pub mod status;
 pub mod tainted;
+pub mod vm;
 
 pub fn init_procfs(root_path: Arc<Path>) {
     let proc_path = "/proc";
@@ -90,6 +98,128 @@ pub fn init_procfs(root_path: Arc<Path>) {
             panic!("create {} failed: {:?}", kernel_path, e);
         }
     };
+    // /proc/sys/kernel/core_pattern, /proc/sys/kernel/core_uses_pid
+    let mut core_sysctls = BTreeMap::new();
+    for sysctl in coredump::CoreSysctl::ALL {
+        let sysctl_path = sysctl.path();
+        let mut nd = Nameidata {
+            path_segments: parse_path(sysctl_path),
+            dentry: root_path.dentry.clone(),
+            mnt: root_path.mnt.clone(),
+            depth: 0,
+        };
+        match filename_create(&mut nd, 0) {
+            Ok(dentry) => {
+                let parent_inode = nd.dentry.get_inode();
+                parent_inode.create(dentry.clone(), S_IFREG as u16 | 0o644);
+                let sysctl_file: Arc<dyn FileOp> = coredump::CoreSysctlFile::new(
+                    Path::new(root_path.mnt.clone(), dentry.clone()),
+                    dentry.get_inode().clone(),
+                    OpenFlags::O_RDWR,
+                    sysctl,
+                );
+                core_sysctls.insert(sysctl_path, sysctl_file);
+                insert_core_dentry(dentry.clone());
+            }
+            Err(e) => {
+                panic!("create {} failed: {:?}", sysctl_path, e);
+            }
+        }
+    }
+    coredump::CORE_SYSCTLS.call_once(|| core_sysctls);
+    // /proc/sys/vm
+    let vm_path = "/proc/sys/vm";
+    let mut nd = Nameidata {
+        path_segments: parse_path(vm_path),
+        dentry: root_path.dentry.clone(),
+        mnt: root_path.mnt.clone(),
+        depth: 0,
+    };
+    let vm_mode = S_IFDIR as u16 | 0o755;
+    match filename_create(&mut nd, 0) {
+        Ok(dentry) => {
+            let parent_inode = nd.dentry.get_inode();
+            parent_inode.mkdir(dentry.clone(), vm_mode);
+        }
+        Err(e) => {
+            panic!("create {} failed: {:?}", vm_path, e);
+        }
+    };
+    let mut vm_sysctls = BTreeMap::new();
+    for sysctl in vm::VmSysctl::ALL {
+        let sysctl_path = sysctl.path();
+        let mut nd = Nameidata {
+            path_segments: parse_path(sysctl_path),
+            dentry: root_path.dentry.clone(),
+            mnt: root_path.mnt.clone(),
+            depth: 0,
+        };
+        match filename_create(&mut nd, 0) {
+            Ok(dentry) => {
+                let parent_inode = nd.dentry.get_inode();
+                parent_inode.create(dentry.clone(), S_IFREG as u16 | sysctl.mode());
+                let sysctl_file: Arc<dyn FileOp> = vm::VmSysctlFile::new(
+                    Path::new(root_path.mnt.clone(), dentry.clone()),
+                    dentry.get_inode().clone(),
+                    OpenFlags::O_RDWR,
+                    sysctl,
+                );
+                vm_sysctls.insert(sysctl_path, sysctl_file);
+                insert_core_dentry(dentry.clone());
+            }
+            Err(e) => {
+                panic!("create {} failed: {:?}", sysctl_path, e);
+            }
+        }
+    }
+    vm::VM_SYSCTLS.call_once(|| vm_sysctls);
+    // /proc/sys/fs/binfmt_misc
+    for dir_path in ["/proc/sys/fs", binfmt_misc::BINFMT_MISC_DIR] {
+        let mut nd = Nameidata {
+            path_segments: parse_path(dir_path),
+            dentry: root_path.dentry.clone(),
+            mnt: root_path.mnt.clone(),
+            depth: 0,
+        };
+        match filename_create(&mut nd, 0) {
+            Ok(dentry) => {
+                let parent_inode = nd.dentry.get_inode();
+                parent_inode.mkdir(dentry.clone(), S_IFDIR as u16 | 0o755);
+            }
+            Err(e) => {
+                panic!("create {} failed: {:?}", dir_path, e);
+            }
+        };
+    }
+    let mut binfmt_misc_files = BTreeMap::new();
+    for kind in binfmt_misc::BinfmtMiscKind::CONTROL {
+        let kind_path = kind.path();
+        let mut nd = Nameidata {
+            path_segments: parse_path(&kind_path),
+            dentry: root_path.dentry.clone(),
+            mnt: root_path.mnt.clone(),
+            depth: 0,
+        };
+        match filename_create(&mut nd, 0) {
+            Ok(dentry) => {
+                let parent_inode = nd.dentry.get_inode();
+                parent_inode.create(dentry.clone(), S_IFREG as u16 | kind.mode());
+                let binfmt_misc_file: Arc<dyn FileOp> = binfmt_misc::BinfmtMiscFile::new(
+                    Path::new(root_path.mnt.clone(), dentry.clone()),
+                    dentry.get_inode().clone(),
+                    OpenFlags::O_RDWR,
+                    kind,
+                );
+                binfmt_misc_files.insert(kind_path, binfmt_misc_file);
+                insert_core_dentry(dentry.clone());
+            }
+            Err(e) => {
+                panic!("create {} failed: {:?}", kind_path, e);
+            }
+        }
+    }
+    binfmt_misc::BINFMT_MISC_FILES.call_once(|| binfmt_misc_files);
+    binfmt_misc::init_binfmt_misc_root(root_path.clone());
     let taint_path = "/proc/sys/kernel/tainted";
     let mut nd = Nameidata {
         path_segments: parse_path(taint_path),
@@ -219,6 +349,32 @@ pub fn init_procfs(root_path: Arc<Path>) {
             panic!("create {} failed: {:?}", meminfo_path, e);
         }
     };
+    // /proc/buddyinfo
+    // 只读, 虚拟文件
+    let buddyinfo_path = "/proc/buddyinfo";
+    let buddyinfo_mode = S_IFREG as u16 | 0o444;
+    nd = Nameidata {
+        path_segments: parse_path(buddyinfo_path),
+        dentry: root_path.dentry.clone(),
+        mnt: root_path.mnt.clone(),
+        depth: 0,
+    };
+    match filename_create(&mut nd, 0) {
+        Ok(dentry) => {
+            let parent_inode = nd.dentry.get_inode();
+            parent_inode.create(dentry.clone(), buddyinfo_mode);
+            let buddyinfo_file = BuddyInfoFile::new(
+                Path::new(root_path.mnt.clone(), dentry.clone()),
+                dentry.get_inode().clone(),
+                OpenFlags::empty(),
+            );
+            BUDDYINFO.call_once(|| buddyinfo_file.clone());
+            insert_core_dentry(dentry.clone());
+        }
+        Err(e) => {
+            panic!("create {} failed: {:?}", buddyinfo_path, e);
+        }
+    };
     // /proc/self/exe
     // /proc/self
     let self_path = "/proc/self";
@@ -346,6 +502,7 @@ pub fn init_procfs(root_path: Arc<Path>) {
                 Path::new(root_path.mnt.clone(), dentry.clone()),
                 dentry.get_inode().clone(),
                 OpenFlags::empty(),
+                false,
             );
             smaps::SMAPS.call_once(|| smaps_file.clone());
             insert_core_dentry(dentry.clone());
@@ -451,6 +608,127 @@ pub fn init_procfs(root_path: Arc<Path>) {
             panic!("create {} failed: {:?}", pid_stat_path, e);
         }
     }
+    // /proc/pid/comm
+    let pid_comm_path = "/proc/pid/comm";
+    let pid_comm_mode = S_IFREG as u16 | 0o444;
+    nd = Nameidata {
+        path_segments: parse_path(pid_comm_path),
+        dentry: root_path.dentry.clone(),
+        mnt: root_path.mnt.clone(),
+        depth: 0,
+    };
+    match filename_create(&mut nd, 0) {
+        Ok(dentry) => {
+            let parent_inode = nd.dentry.get_inode();
+            parent_inode.create(dentry.clone(), pid_comm_mode);
+            let pid_comm_file = pid::PidCommFile::new(
+                Path::new(root_path.mnt.clone(), dentry.clone()),
+                dentry.get_inode().clone(),
+                OpenFlags::empty(),
+            );
+            pid::PID_COMM.call_once(|| pid_comm_file.clone());
+            insert_core_dentry(dentry.clone());
+        }
+        Err(e) => {
+            panic!("create {} failed: {:?}", pid_comm_path, e);
+        }
+    }
+    // /proc/pid/smaps
+    let pid_smaps_path = "/proc/pid/smaps";
+    let pid_smaps_mode = S_IFREG as u16 | 0o444;
+    nd = Nameidata {
+        path_segments: parse_path(pid_smaps_path),
+        dentry: root_path.dentry.clone(),
+        mnt: root_path.mnt.clone(),
+        depth: 0,
+    };
+    match filename_create(&mut nd, 0) {
+        Ok(dentry) => {
+            let parent_inode = nd.dentry.get_inode();
+            parent_inode.create(dentry.clone(), pid_smaps_mode);
+            let pid_smaps_file = smaps::SMapsFile::new(
+                Path::new(root_path.mnt.clone(), dentry.clone()),
+                dentry.get_inode().clone(),
+                OpenFlags::empty(),
+                true,
+            );
+            smaps::PID_SMAPS.call_once(|| pid_smaps_file.clone());
+            insert_core_dentry(dentry.clone());
+        }
+        Err(e) => {
+            panic!("create {} failed: {:?}", pid_smaps_path, e);
+        }
+    }
+    // /proc/self/oom_score{,_adj}, /proc/pid/oom_score{,_adj}
+    let mut oom_files = BTreeMap::new();
+    for kind in oom::OomFileKind::ALL {
+        let mut nd = Nameidata {
+            path_segments: parse_path(kind.path),
+            dentry: root_path.dentry.clone(),
+            mnt: root_path.mnt.clone(),
+            depth: 0,
+        };
+        match filename_create(&mut nd, 0) {
+            Ok(dentry) => {
+                let parent_inode = nd.dentry.get_inode();
+                parent_inode.create(dentry.clone(), S_IFREG as u16 | kind.mode());
+                let oom_file: Arc<dyn FileOp> = oom::OomFile::new(
+                    Path::new(root_path.mnt.clone(), dentry.clone()),
+                    dentry.get_inode().clone(),
+                    OpenFlags::O_RDWR,
+                    kind,
+                );
+                oom_files.insert(kind.path, oom_file);
+                insert_core_dentry(dentry.clone());
+            }
+            Err(e) => {
+                panic!("create {} failed: {:?}", kind.path, e);
+            }
+        }
+    }
+    oom::OOM_FILES.call_once(|| oom_files);
+    // /proc/self/ns/*, /proc/pid/ns/*
+    for ns_dir in ns::NS_DIRS {
+        let mut nd = Nameidata {
+            path_segments: parse_path(ns_dir),
+            dentry: root_path.dentry.clone(),
+            mnt: root_path.mnt.clone(),
+            depth: 0,
+        };
+        match filename_create(&mut nd, 0) {
+            Ok(dentry) => {
+                let parent_inode = nd.dentry.get_inode();
+                parent_inode.mkdir(dentry.clone(), S_IFDIR as u16 | 0o511);
+                insert_core_dentry(dentry);
+            }
+            Err(e) => {
+                panic!("create {} failed: {:?}", ns_dir, e);
+            }
+        }
+        for name in ns::NS_NAMES {
+            let ns_path = format!("{}/{}", ns_dir, name);
+            let mut nd = Nameidata {
+                path_segments: parse_path(&ns_path),
+                dentry: root_path.dentry.clone(),
+                mnt: root_path.mnt.clone(),
+                depth: 0,
+            };
+            match filename_create(&mut nd, 0) {
+                Ok(dentry) => {
+                    let parent_inode = nd.dentry.get_inode();
+                    parent_inode.create(dentry.clone(), S_IFLNK as u16 | 0o777);
+                    *dentry.flags.write() = dentry::DentryFlags::DCACHE_SYMLINK_TYPE; // 设置为符号链接类型
+                    let ns_inode =
+                        NsInode::new(name, ns_dir == "/proc/pid/ns", Ext4InodeDisk::default());
+                    dentry.inner.lock().inode.replace(ns_inode);
+                    insert_core_dentry(dentry);
+                }
+                Err(e) => {
+                    panic!("create {} failed: {:?}", ns_path, e);
+                }
+            }
+        }
+    }
     // /proc/cpuinfo
     // 只读, 虚拟文件
     let cpuinfo_path = "/proc/cpuinfo";
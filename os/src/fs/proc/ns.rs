@@ -0,0 +1,194 @@
+//! /proc/self/ns/*和/proc/<pid>/ns/*
+//!
+//! 每个链接的内容形如`uts:[4026531838]`, 打开链接本身(不跟随)得到NsFile, 可以传给setns
+use alloc::{format, string::String, sync::Arc};
+use spin::RwLock;
+
+use crate::{
+    ext4::inode::{Ext4InodeDisk, S_IFLNK},
+    fs::{
+        dentry::Dentry,
+        file::{FileOp, OpenFlags},
+        inode::InodeOp,
+        kstat::Kstat,
+        path::Path,
+        proc::pid::TARGERT_PID,
+    },
+    syscall::errno::{Errno, SyscallRet},
+    task::{current_task, get_task, namespace::Namespace},
+    timer::TimeSpec,
+};
+
+pub const NS_DIRS: [&str; 2] = ["/proc/self/ns", "/proc/pid/ns"];
+pub const NS_NAMES: [&str; 5] = ["ipc", "mnt", "pid", "pid_for_children", "uts"];
+
+/// 根据绝对路径判断是否为ns链接, 返回(链接名, 是否为/proc/<pid>/ns/*)
+fn ns_link_kind(absolute_path: &str) -> Option<(&'static str, bool)> {
+    let (name, pid_target) = if let Some(name) = absolute_path.strip_prefix("/proc/self/ns/") {
+        (name, false)
+    } else {
+        (absolute_path.strip_prefix("/proc/pid/ns/")?, true)
+    };
+    NS_NAMES
+        .iter()
+        .find(|ns_name| **ns_name == name)
+        .map(|ns_name| (*ns_name, pid_target))
+}
+
+/// ns链接在打开时不跟随, 直接打开链接本身
+pub fn is_ns_link(dentry: &Dentry) -> bool {
+    ns_link_kind(&dentry.absolute_path).is_some()
+}
+
+/// 查找链接指向的命名空间
+fn resolve_namespace(name: &str, pid_target: bool) -> Result<Namespace, Errno> {
+    let task = if pid_target {
+        get_task(TARGERT_PID.lock().pid).ok_or(Errno::ESRCH)?
+    } else {
+        current_task()
+    };
+    let nsproxy = task.nsproxy();
+    Ok(match name {
+        "ipc" => Namespace::Ipc(nsproxy.ipc_ns),
+        "mnt" => Namespace::Mnt(nsproxy.mnt_ns),
+        "pid" => Namespace::Pid(task.pid_ns()),
+        "pid_for_children" => Namespace::Pid(nsproxy.pid_ns_for_children),
+        "uts" => Namespace::Uts(nsproxy.uts_ns),
+        _ => return Err(Errno::ENOENT),
+    })
+}
+
+pub struct NsInode {
+    name: &'static str,
+    pid_target: bool,
+    pub inner: RwLock<NsInodeInner>,
+}
+pub struct NsInodeInner {
+    pub inode_on_disk: Ext4InodeDisk,
+}
+
+impl NsInode {
+    pub fn new(name: &'static str, pid_target: bool, inode_on_disk: Ext4InodeDisk) -> Arc<Self> {
+        Arc::new(NsInode {
+            name,
+            pid_target,
+            inner: RwLock::new(NsInodeInner { inode_on_disk }),
+        })
+    }
+}
+
+impl InodeOp for NsInode {
+    fn get_link(&self) -> String {
+        match resolve_namespace(self.name, self.pid_target) {
+            Ok(ns) => format!("{}:[{}]", ns.name(), ns.inum()),
+            Err(_) => String::new(),
+        }
+    }
+    /// inode号即命名空间的inum, 用于判断两个任务是否处于同一命名空间
+    fn getattr(&self) -> Kstat {
+        let mut kstat = Kstat::new();
+        let inner_guard = self.inner.read();
+        let inode_on_disk = &inner_guard.inode_on_disk;
+
+        kstat.mode = self.get_mode();
+        kstat.uid = inode_on_disk.get_uid();
+        kstat.gid = inode_on_disk.get_gid();
+        kstat.nlink = 1;
+        kstat.ino = resolve_namespace(self.name, self.pid_target).map_or(0, |ns| ns.inum());
+        kstat.atime = inode_on_disk.get_atime();
+        kstat.mtime = inode_on_disk.get_mtime();
+        kstat.ctime = inode_on_disk.get_ctime();
+
+        kstat
+    }
+    fn get_resident_page_count(&self) -> usize {
+        0
+    }
+    fn get_mode(&self) -> u16 {
+        S_IFLNK as u16 | 0o777
+    }
+    fn get_uid(&self) -> u32 {
+        self.inner.read().inode_on_disk.get_uid()
+    }
+    fn get_gid(&self) -> u32 {
+        self.inner.read().inode_on_disk.get_gid()
+    }
+    /* 时间戳 */
+    fn get_atime(&self) -> TimeSpec {
+        self.inner.read().inode_on_disk.get_atime()
+    }
+    fn set_atime(&self, atime: TimeSpec) {
+        self.inner.write().inode_on_disk.set_atime(atime);
+    }
+    fn get_mtime(&self) -> TimeSpec {
+        self.inner.read().inode_on_disk.get_mtime()
+    }
+    fn set_mtime(&self, mtime: TimeSpec) {
+        self.inner.write().inode_on_disk.set_mtime(mtime);
+    }
+    fn get_ctime(&self) -> TimeSpec {
+        self.inner.read().inode_on_disk.get_ctime()
+    }
+    fn set_ctime(&self, ctime: TimeSpec) {
+        self.inner.write().inode_on_disk.set_ctime(ctime);
+    }
+}
+
+/// 打开ns链接得到的文件, 持有打开时链接指向的命名空间
+pub struct NsFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    namespace: Namespace,
+}
+
+impl NsFile {
+    pub fn namespace(&self) -> Namespace {
+        self.namespace.clone()
+    }
+}
+
+impl FileOp for NsFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn read(&self, _buf: &mut [u8]) -> SyscallRet {
+        Err(Errno::EINVAL)
+    }
+    fn write(&self, _buf: &[u8]) -> SyscallRet {
+        Err(Errno::EINVAL)
+    }
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn get_path(&self) -> Arc<Path> {
+        self.path.clone()
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
+
+/// 打开/proc/self/ns/*或/proc/<pid>/ns/*
+pub fn ns_open(
+    dentry: &Arc<Dentry>,
+    path: Arc<Path>,
+    inode: Arc<dyn InodeOp>,
+    flags: OpenFlags,
+) -> Option<Result<Arc<dyn FileOp>, Errno>> {
+    let (name, pid_target) = ns_link_kind(&dentry.absolute_path)?;
+    Some(resolve_namespace(name, pid_target).map(|namespace| {
+        Arc::new(NsFile {
+            path,
+            inode,
+            flags,
+            namespace,
+        }) as Arc<dyn FileOp>
+    }))
+}
@@ -0,0 +1,177 @@
+//! /proc/<pid>/oom_score和/proc/<pid>/oom_score_adj
+use core::str;
+
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc};
+use spin::{Once, RwLock};
+
+use crate::{
+    fs::{
+        file::{FileOp, OpenFlags},
+        inode::InodeOp,
+        path::Path,
+        proc::pid::TARGERT_PID,
+        uapi::Whence,
+    },
+    mm::oom::{oom_score, OOM_SCORE_ADJ_MAX, OOM_SCORE_ADJ_MIN},
+    syscall::errno::{Errno, SyscallRet},
+    task::{capability::CapSet, current_task, get_task, Task},
+};
+
+/// oom相关的文件, key是绝对路径
+pub static OOM_FILES: Once<BTreeMap<&'static str, Arc<dyn FileOp>>> = Once::new();
+
+#[derive(Clone, Copy)]
+pub struct OomFileKind {
+    pub path: &'static str,
+    /// oom_score_adj可写, oom_score只读
+    pub adj: bool,
+    /// /proc/<pid>/...由TARGERT_PID指定任务, 否则为当前任务
+    pub pid_target: bool,
+}
+
+impl OomFileKind {
+    pub const ALL: [OomFileKind; 4] = [
+        OomFileKind {
+            path: "/proc/self/oom_score",
+            adj: false,
+            pid_target: false,
+        },
+        OomFileKind {
+            path: "/proc/self/oom_score_adj",
+            adj: true,
+            pid_target: false,
+        },
+        OomFileKind {
+            path: "/proc/pid/oom_score",
+            adj: false,
+            pid_target: true,
+        },
+        OomFileKind {
+            path: "/proc/pid/oom_score_adj",
+            adj: true,
+            pid_target: true,
+        },
+    ];
+    pub fn mode(&self) -> u16 {
+        if self.adj {
+            0o644
+        } else {
+            0o444
+        }
+    }
+}
+
+pub struct OomFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    pub inner: RwLock<OomFileInner>,
+    kind: OomFileKind,
+}
+
+pub struct OomFileInner {
+    pub offset: usize,
+}
+
+impl OomFile {
+    pub fn new(
+        path: Arc<Path>,
+        inode: Arc<dyn InodeOp>,
+        flags: OpenFlags,
+        kind: OomFileKind,
+    ) -> Arc<Self> {
+        Arc::new(OomFile {
+            path,
+            inode,
+            flags,
+            inner: RwLock::new(OomFileInner { offset: 0 }),
+            kind,
+        })
+    }
+    fn task(&self) -> Result<Arc<Task>, Errno> {
+        if self.kind.pid_target {
+            get_task(TARGERT_PID.lock().pid).ok_or(Errno::ESRCH)
+        } else {
+            Ok(current_task())
+        }
+    }
+    fn serialize(&self) -> Result<String, Errno> {
+        let task = self.task()?;
+        if self.kind.adj {
+            Ok(format!("{}\n", task.oom_score_adj()))
+        } else {
+            Ok(format!("{}\n", oom_score(&task)))
+        }
+    }
+}
+
+impl FileOp for OomFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        let content = self.serialize()?;
+        let mut inner_guard = self.inner.write();
+        if inner_guard.offset >= content.len() {
+            return Ok(0);
+        }
+        let len = (content.len() - inner_guard.offset).min(buf.len());
+        buf[..len]
+            .copy_from_slice(&content.as_bytes()[inner_guard.offset..inner_guard.offset + len]);
+        inner_guard.offset += len;
+        Ok(len)
+    }
+    /// 写入-1000~1000, 修改其他用户进程的oom_score_adj或降低oom_score_adj需要特权
+    fn write(&self, buf: &[u8]) -> SyscallRet {
+        if !self.kind.adj {
+            return Err(Errno::EACCES);
+        }
+        let info = str::from_utf8(buf).map_err(|_| Errno::EINVAL)?;
+        let val = info.trim().parse::<i32>().map_err(|_| Errno::EINVAL)?;
+        if !(OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(&val) {
+            return Err(Errno::EINVAL);
+        }
+        let task = self.task()?;
+        let current = current_task();
+        // 文件的属主是目标进程的euid
+        let privileged = current.capable(CapSet::SYS_RESOURCE);
+        if current.fsuid() != task.euid() && !privileged {
+            return Err(Errno::EACCES);
+        }
+        if val < task.oom_score_adj() && !privileged {
+            return Err(Errno::EACCES);
+        }
+        log::info!(
+            "[OomFile::write] task{} oom_score_adj: {} -> {}",
+            task.tid(),
+            task.oom_score_adj(),
+            val
+        );
+        task.set_oom_score_adj(val);
+        self.inner.write().offset += buf.len();
+        Ok(buf.len())
+    }
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        self.kind.adj
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        let base = match whence {
+            Whence::SeekSet => 0,
+            Whence::SeekCur => inner_guard.offset,
+            Whence::SeekEnd => self.serialize()?.len(),
+            _ => return Err(Errno::EINVAL),
+        };
+        inner_guard.offset = base.checked_add_signed(offset).ok_or(Errno::EINVAL)?;
+        Ok(inner_guard.offset)
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
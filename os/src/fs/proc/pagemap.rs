@@ -147,7 +147,7 @@ impl PageMapFile {
         task.op_memory_set(|mm| {
             for vpn in vpn_range {
                 let mut entry_value: u64 = 0;
-                if let Some(pte) = mm.page_table.find_pte(vpn) {
+                if let Some(pte) = mm.page_table.translate_vpn_to_pte(vpn) {
                     // 标记为存在
                     entry_value |= 1 << 63;
                     // 页帧号
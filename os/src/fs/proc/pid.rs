@@ -37,11 +37,13 @@ lazy_static! {
     pub static ref TARGERT_PID: Arc<Mutex<TargetPid>> = Arc::new(Mutex::new(TargetPid::new(0)));
 }
 pub static PID_STAT: Once<Arc<dyn FileOp>> = Once::new();
+pub static PID_COMM: Once<Arc<dyn FileOp>> = Once::new();
 
 /// 记录当前查询的目标PID，仅用于替换/proc/pid/...中的pid
+/// pid是当前任务pid命名空间中的值, 记录的是对应的全局tid, 不可见时记录usize::MAX
 pub fn record_target_pid(pid: usize) {
     let mut target_pid = TARGERT_PID.lock();
-    target_pid.pid = pid;
+    target_pid.pid = current_task().pid_ns().tid_of(pid).unwrap_or(usize::MAX);
 }
 
 pub struct PidInode {
@@ -204,3 +206,63 @@ impl FileOp for PidStatFile {
         self.flags
     }
 }
+
+/// /proc/[PID]/comm, 任务名(内核线程为线程名)
+pub struct PidCommFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    pub inner: RwLock<PidStatFileInner>,
+}
+
+impl PidCommFile {
+    pub fn new(path: Arc<Path>, inode: Arc<dyn InodeOp>, flags: OpenFlags) -> Arc<Self> {
+        Arc::new(PidCommFile {
+            path,
+            inode,
+            flags,
+            inner: RwLock::new(PidStatFileInner { offset: 0 }),
+        })
+    }
+    fn comm(&self) -> Result<String, Errno> {
+        let tid = TARGERT_PID.lock().pid;
+        match get_task(tid) {
+            Some(task) => Ok(format!("{}\n", task.comm())),
+            None => Err(Errno::ENOENT),
+        }
+    }
+}
+
+impl FileOp for PidCommFile {
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        let comm = self.comm()?;
+        let mut inner_guard = self.inner.write();
+        if inner_guard.offset >= comm.len() {
+            return Ok(0);
+        }
+        let len = (comm.len() - inner_guard.offset).min(buf.len());
+        buf[..len].copy_from_slice(&comm.as_bytes()[inner_guard.offset..inner_guard.offset + len]);
+        inner_guard.offset += len;
+        Ok(len)
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        let base = match whence {
+            Whence::SeekSet => 0,
+            Whence::SeekCur => inner_guard.offset,
+            Whence::SeekEnd => self.comm()?.len(),
+            _ => return Err(Errno::EINVAL),
+        };
+        inner_guard.offset = base.checked_add_signed(offset).ok_or(Errno::EINVAL)?;
+        Ok(inner_guard.offset)
+    }
+    fn readable(&self) -> bool {
+        true
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
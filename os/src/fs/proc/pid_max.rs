@@ -193,6 +193,11 @@ impl FileOp for PidMaxFile {
     }
 }
 
+/// 当前的pid_max, clone3的set_tid不能超过该值
+pub fn pid_max() -> usize {
+    FAKEPidMax.read().pid_max
+}
+
 lazy_static! {
     static ref FAKEPidMax: RwLock<FakePidMax> = RwLock::new(FakePidMax::new());
 }
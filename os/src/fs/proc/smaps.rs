@@ -4,19 +4,20 @@ use lazy_static::lazy_static;
 use spin::{lazy, mutex, Once, RwLock};
 
 use crate::{
-    arch::config::PAGE_SIZE_BITS,
+    arch::config::{PAGE_SIZE, PAGE_SIZE_BITS},
     ext4::inode::Ext4InodeDisk,
     fs::{
         file::{FileOp, OpenFlags},
         inode::InodeOp,
         kstat::Kstat,
         path::Path,
+        proc::pid::TARGERT_PID,
         uapi::Whence,
         FileOld,
     },
     mm::MapPermission,
     syscall::errno::{Errno, SyscallRet},
-    task::current_task,
+    task::{current_task, get_task},
     timer::TimeSpec,
 };
 
@@ -27,6 +28,8 @@ use alloc::{
 };
 
 pub static SMAPS: Once<Arc<dyn FileOp>> = Once::new();
+/// /proc/<pid>/smaps
+pub static PID_SMAPS: Once<Arc<dyn FileOp>> = Once::new();
 
 pub struct SMapsInode {
     pub inner: RwLock<SMapsInodeInner>,
@@ -107,6 +110,8 @@ pub struct SMapsFile {
     pub path: Arc<Path>,
     pub inode: Arc<dyn InodeOp>,
     pub flags: OpenFlags,
+    /// true: /proc/<pid>/smaps, 读取TARGERT_PID的地址空间; false: /proc/self/smaps
+    pub pid_target: bool,
     pub inner: RwLock<SMapsFileInner>,
 }
 
@@ -115,17 +120,27 @@ pub struct SMapsFileInner {
 }
 
 impl SMapsFile {
-    pub fn new(path: Arc<Path>, inode: Arc<dyn InodeOp>, flags: OpenFlags) -> Arc<Self> {
+    pub fn new(
+        path: Arc<Path>,
+        inode: Arc<dyn InodeOp>,
+        flags: OpenFlags,
+        pid_target: bool,
+    ) -> Arc<Self> {
         Arc::new(SMapsFile {
             path,
             inode,
             flags,
+            pid_target,
             inner: RwLock::new(SMapsFileInner { offset: 0 }),
         })
     }
-    pub fn get_smaps(&self) -> String {
+    pub fn get_smaps(&self) -> Result<String, Errno> {
         let mut result = String::new();
-        let task = current_task();
+        let task = if self.pid_target {
+            get_task(TARGERT_PID.lock().pid).ok_or(Errno::ENOENT)?
+        } else {
+            current_task()
+        };
 
         task.op_memory_set(|mm| {
             let areas = &mm.areas;
@@ -189,11 +204,17 @@ impl SMapsFile {
                 // )
                 // .unwrap();
                 // writeln!(result, "VmFlags: {}", area.get_vmflags()).unwrap(); // 需要你实现 `get_vmflags()` 方法
+                let anon_huge_kb = if area.backend_file.is_none() && !area.is_shared() {
+                    area.huge_mapped_pages(&mm.page_table) * PAGE_SIZE / 1024
+                } else {
+                    0
+                };
+                writeln!(result, "AnonHugePages:      {:>5} kB", anon_huge_kb).unwrap();
                 writeln!(result).unwrap(); // 空行分隔每个区域
             }
         });
 
-        result
+        Ok(result)
     }
 }
 
@@ -205,7 +226,7 @@ impl FileOp for SMapsFile {
         self.inode.clone()
     }
     fn read(&self, buf: &mut [u8]) -> SyscallRet {
-        let content = self.get_smaps();
+        let content = self.get_smaps()?;
         let bytes = content.as_bytes();
         let offset = self.inner.read().offset;
         let len = bytes.len();
@@ -233,7 +254,7 @@ impl FileOp for SMapsFile {
                 inner_guard.offset = inner_guard.offset.checked_add_signed(offset).unwrap()
             }
             crate::fs::uapi::Whence::SeekEnd => {
-                let content = self.get_smaps();
+                let content = self.get_smaps()?;
                 let len = content.len();
                 inner_guard.offset = len.checked_add_signed(offset).unwrap();
             }
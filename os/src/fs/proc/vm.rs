@@ -0,0 +1,183 @@
+//! /proc/sys/vm下的可调参数
+use core::{
+    str,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc};
+use spin::{Once, RwLock};
+
+use crate::{
+    fs::{
+        file::{FileOp, OpenFlags},
+        inode::InodeOp,
+        path::Path,
+        uapi::Whence,
+        writeback::{
+            drop_page_cache, wakeup_flusher, DIRTY_BACKGROUND_RATIO, DIRTY_EXPIRE_CENTISECS,
+            DIRTY_RATIO, DIRTY_WRITEBACK_CENTISECS,
+        },
+    },
+    syscall::errno::{Errno, SyscallRet},
+};
+
+/// /proc/sys/vm下的文件, key是绝对路径
+pub static VM_SYSCTLS: Once<BTreeMap<&'static str, Arc<dyn FileOp>>> = Once::new();
+
+#[derive(Clone, Copy)]
+pub enum VmSysctl {
+    DirtyRatio,
+    DirtyBackgroundRatio,
+    DirtyExpireCentisecs,
+    DirtyWritebackCentisecs,
+    /// 只写, 写入1或3时回写并释放页缓存
+    DropCaches,
+}
+
+impl VmSysctl {
+    pub const ALL: [VmSysctl; 5] = [
+        VmSysctl::DirtyRatio,
+        VmSysctl::DirtyBackgroundRatio,
+        VmSysctl::DirtyExpireCentisecs,
+        VmSysctl::DirtyWritebackCentisecs,
+        VmSysctl::DropCaches,
+    ];
+    pub fn path(&self) -> &'static str {
+        match self {
+            VmSysctl::DirtyRatio => "/proc/sys/vm/dirty_ratio",
+            VmSysctl::DirtyBackgroundRatio => "/proc/sys/vm/dirty_background_ratio",
+            VmSysctl::DirtyExpireCentisecs => "/proc/sys/vm/dirty_expire_centisecs",
+            VmSysctl::DirtyWritebackCentisecs => "/proc/sys/vm/dirty_writeback_centisecs",
+            VmSysctl::DropCaches => "/proc/sys/vm/drop_caches",
+        }
+    }
+    pub fn mode(&self) -> u16 {
+        match self {
+            VmSysctl::DropCaches => 0o200,
+            _ => 0o644,
+        }
+    }
+    fn value(&self) -> Option<&'static AtomicUsize> {
+        match self {
+            VmSysctl::DirtyRatio => Some(&DIRTY_RATIO),
+            VmSysctl::DirtyBackgroundRatio => Some(&DIRTY_BACKGROUND_RATIO),
+            VmSysctl::DirtyExpireCentisecs => Some(&DIRTY_EXPIRE_CENTISECS),
+            VmSysctl::DirtyWritebackCentisecs => Some(&DIRTY_WRITEBACK_CENTISECS),
+            VmSysctl::DropCaches => None,
+        }
+    }
+    fn serialize(&self) -> String {
+        match self.value() {
+            Some(value) => format!("{}\n", value.load(Ordering::Relaxed)),
+            None => String::new(),
+        }
+    }
+    fn store(&self, val: usize) -> Result<(), Errno> {
+        match self {
+            VmSysctl::DirtyRatio | VmSysctl::DirtyBackgroundRatio => {
+                if val > 100 {
+                    return Err(Errno::EINVAL);
+                }
+            }
+            VmSysctl::DropCaches => {
+                if !(1..=3).contains(&val) {
+                    return Err(Errno::EINVAL);
+                }
+                // 2只释放dentry/inode缓存, 这里不处理
+                if val & 1 != 0 {
+                    let nr = drop_page_cache();
+                    log::warn!("[drop_caches] dropped {} page cache pages", nr);
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        self.value().unwrap().store(val, Ordering::Relaxed);
+        // 阈值或周期变化后让回写线程重新计算
+        wakeup_flusher();
+        Ok(())
+    }
+}
+
+pub struct VmSysctlFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    pub sysctl: VmSysctl,
+    pub inner: RwLock<VmSysctlFileInner>,
+}
+
+#[derive(Default)]
+pub struct VmSysctlFileInner {
+    pub offset: usize,
+}
+
+impl VmSysctlFile {
+    pub fn new(
+        path: Arc<Path>,
+        inode: Arc<dyn InodeOp>,
+        flags: OpenFlags,
+        sysctl: VmSysctl,
+    ) -> Arc<Self> {
+        Arc::new(VmSysctlFile {
+            path,
+            inode,
+            flags,
+            sysctl,
+            inner: RwLock::new(VmSysctlFileInner::default()),
+        })
+    }
+}
+
+impl FileOp for VmSysctlFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        if let VmSysctl::DropCaches = self.sysctl {
+            return Err(Errno::EACCES);
+        }
+        let info = self.sysctl.serialize();
+        let mut inner_guard = self.inner.write();
+        if inner_guard.offset >= info.len() {
+            return Ok(0);
+        }
+        let len = (info.len() - inner_guard.offset).min(buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[inner_guard.offset..inner_guard.offset + len]);
+        inner_guard.offset += len;
+        Ok(len)
+    }
+    fn readable(&self) -> bool {
+        !matches!(self.sysctl, VmSysctl::DropCaches)
+    }
+    fn write(&self, buf: &[u8]) -> SyscallRet {
+        let info = str::from_utf8(buf).map_err(|_| Errno::EINVAL)?;
+        let val = info.trim().parse::<usize>().map_err(|_| Errno::EINVAL)?;
+        self.sysctl.store(val)?;
+        self.inner.write().offset += buf.len();
+        Ok(buf.len())
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        let base = match whence {
+            Whence::SeekSet => 0,
+            Whence::SeekCur => inner_guard.offset,
+            Whence::SeekEnd => self.sysctl.serialize().len(),
+            _ => {
+                log::warn!("Unsupported whence in VmSysctlFile::seek: {:?}", whence);
+                return Err(Errno::EINVAL);
+            }
+        };
+        inner_guard.offset = base.checked_add_signed(offset).ok_or(Errno::EINVAL)?;
+        Ok(inner_guard.offset)
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
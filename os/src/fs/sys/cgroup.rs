@@ -0,0 +1,535 @@
+//! cgroup2文件系统, 固定挂载在/sys/fs/cgroup
+//!
+//! 在其中mkdir创建cgroup并生成控制文件, rmdir删除没有任务和子cgroup的cgroup,
+//! 向cgroup.procs写入pid将整个线程组迁移到该cgroup
+use core::{fmt::Write, str, sync::atomic::Ordering};
+
+use alloc::{
+    collections::btree_set::BTreeSet,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use spin::{Once, RwLock};
+
+use crate::{
+    arch::config::PAGE_SIZE,
+    ext4::inode::S_IFREG,
+    fs::{
+        dentry::{delete_dentry, insert_core_dentry, insert_dentry, Dentry},
+        file::{FileOp, OpenFlags},
+        inode::InodeOp,
+        namei::{filename_create, filename_lookup, parse_path, Nameidata},
+        path::Path,
+        uapi::Whence,
+        AT_FDCWD,
+    },
+    syscall::errno::{Errno, SyscallRet},
+    task::{
+        cgroup::{
+            Cgroup, CGROUP_CONTROLLERS, CGROUP_MAX, CGROUP_ROOT, CPU_PERIOD_MAX, CPU_PERIOD_MIN,
+            CPU_QUOTA_MIN, CPU_WEIGHT_MAX, CPU_WEIGHT_MIN,
+        },
+        current_task, get_task,
+        namespace::find_task_by_vpid,
+    },
+};
+
+pub const CGROUP_ROOT_DIR: &str = "/sys/fs/cgroup";
+
+/// 创建和删除控制文件时使用的根路径
+static CGROUP_FS_ROOT: Once<Arc<Path>> = Once::new();
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CgroupFileKind {
+    Procs,
+    Controllers,
+    SubtreeControl,
+    MemoryMax,
+    /// 只读
+    MemoryCurrent,
+    /// 只读
+    MemoryPeak,
+    /// 只读
+    MemoryEvents,
+    PidsMax,
+    /// 只读
+    PidsCurrent,
+    /// 只读
+    PidsEvents,
+    CpuWeight,
+    CpuMax,
+    /// 只读
+    CpuStat,
+}
+
+impl CgroupFileKind {
+    pub const ALL: [CgroupFileKind; 13] = [
+        CgroupFileKind::Procs,
+        CgroupFileKind::Controllers,
+        CgroupFileKind::SubtreeControl,
+        CgroupFileKind::MemoryMax,
+        CgroupFileKind::MemoryCurrent,
+        CgroupFileKind::MemoryPeak,
+        CgroupFileKind::MemoryEvents,
+        CgroupFileKind::PidsMax,
+        CgroupFileKind::PidsCurrent,
+        CgroupFileKind::PidsEvents,
+        CgroupFileKind::CpuWeight,
+        CgroupFileKind::CpuMax,
+        CgroupFileKind::CpuStat,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            CgroupFileKind::Procs => "cgroup.procs",
+            CgroupFileKind::Controllers => "cgroup.controllers",
+            CgroupFileKind::SubtreeControl => "cgroup.subtree_control",
+            CgroupFileKind::MemoryMax => "memory.max",
+            CgroupFileKind::MemoryCurrent => "memory.current",
+            CgroupFileKind::MemoryPeak => "memory.peak",
+            CgroupFileKind::MemoryEvents => "memory.events",
+            CgroupFileKind::PidsMax => "pids.max",
+            CgroupFileKind::PidsCurrent => "pids.current",
+            CgroupFileKind::PidsEvents => "pids.events",
+            CgroupFileKind::CpuWeight => "cpu.weight",
+            CgroupFileKind::CpuMax => "cpu.max",
+            CgroupFileKind::CpuStat => "cpu.stat",
+        }
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+    /// 根cgroup不能设置限制, 只有cgroup.*和cpu.stat
+    fn in_root(&self) -> bool {
+        matches!(
+            self,
+            CgroupFileKind::Procs
+                | CgroupFileKind::Controllers
+                | CgroupFileKind::SubtreeControl
+                | CgroupFileKind::CpuStat
+        )
+    }
+    pub fn mode(&self) -> u16 {
+        if self.is_writable() {
+            0o644
+        } else {
+            0o444
+        }
+    }
+    fn is_writable(&self) -> bool {
+        matches!(
+            self,
+            CgroupFileKind::Procs
+                | CgroupFileKind::SubtreeControl
+                | CgroupFileKind::MemoryMax
+                | CgroupFileKind::PidsMax
+                | CgroupFileKind::CpuWeight
+                | CgroupFileKind::CpuMax
+        )
+    }
+    fn serialize(&self, cgroup: &Arc<Cgroup>) -> String {
+        let memory = &cgroup.memory;
+        let pids = &cgroup.pids;
+        match self {
+            CgroupFileKind::Procs => {
+                // 只列出读者所在pid命名空间中可见的进程
+                let current = current_task();
+                let tgids: BTreeSet<usize> = cgroup
+                    .tasks()
+                    .into_iter()
+                    .filter_map(get_task)
+                    .map(|task| current.vpid_of(task.tgid()))
+                    .filter(|pid| *pid != 0)
+                    .collect();
+                let mut info = String::new();
+                for pid in tgids {
+                    writeln!(info, "{}", pid).unwrap();
+                }
+                info
+            }
+            CgroupFileKind::Controllers => format!("{}\n", CGROUP_CONTROLLERS.join(" ")),
+            CgroupFileKind::SubtreeControl => {
+                let mask = cgroup.subtree_control();
+                let enabled: Vec<&str> = CGROUP_CONTROLLERS
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, name)| *name)
+                    .collect();
+                format!("{}\n", enabled.join(" "))
+            }
+            CgroupFileKind::MemoryMax => format_max(memory.max.load(Ordering::Relaxed), |pages| {
+                pages * PAGE_SIZE
+            }),
+            CgroupFileKind::MemoryCurrent => {
+                format!("{}\n", memory.current.load(Ordering::Relaxed) * PAGE_SIZE)
+            }
+            CgroupFileKind::MemoryPeak => {
+                format!("{}\n", memory.peak.load(Ordering::Relaxed) * PAGE_SIZE)
+            }
+            CgroupFileKind::MemoryEvents => format!(
+                "low 0\nhigh 0\nmax {}\noom {}\noom_kill {}\n",
+                memory.events_max.load(Ordering::Relaxed),
+                memory.events_oom.load(Ordering::Relaxed),
+                memory.events_oom_kill.load(Ordering::Relaxed)
+            ),
+            CgroupFileKind::PidsMax => format_max(pids.max.load(Ordering::Relaxed), |n| n),
+            CgroupFileKind::PidsCurrent => {
+                format!("{}\n", pids.current.load(Ordering::Relaxed))
+            }
+            CgroupFileKind::PidsEvents => {
+                format!("max {}\n", pids.events_max.load(Ordering::Relaxed))
+            }
+            CgroupFileKind::CpuWeight => cgroup.op_cpu(|cpu| format!("{}\n", cpu.weight)),
+            CgroupFileKind::CpuMax => cgroup.op_cpu(|cpu| {
+                if cpu.quota == CGROUP_MAX {
+                    format!("max {}\n", cpu.period)
+                } else {
+                    format!("{} {}\n", cpu.quota, cpu.period)
+                }
+            }),
+            CgroupFileKind::CpuStat => cgroup.op_cpu(|cpu| {
+                format!(
+                    "usage_usec {}\nnr_periods {}\nnr_throttled {}\n",
+                    cpu.usage_usec, cpu.nr_periods, cpu.nr_throttled
+                )
+            }),
+        }
+    }
+    fn store(&self, cgroup: &Arc<Cgroup>, info: &str) -> Result<(), Errno> {
+        let info = info.trim();
+        match self {
+            CgroupFileKind::Procs => {
+                let pid = info.parse::<usize>().map_err(|_| Errno::EINVAL)?;
+                let task = if pid == 0 {
+                    current_task()
+                } else {
+                    find_task_by_vpid(pid).ok_or(Errno::ESRCH)?
+                };
+                if task.is_kthread() {
+                    return Err(Errno::EINVAL);
+                }
+                // 迁移整个线程组
+                let leader = get_task(task.tgid()).unwrap_or(task);
+                let threads: Vec<_> = leader.op_thread_group(|tg| tg.iter().collect());
+                for thread in threads {
+                    thread.set_cgroup(cgroup.clone());
+                }
+            }
+            CgroupFileKind::SubtreeControl => {
+                let mut mask = cgroup.subtree_control();
+                for token in info.split_whitespace() {
+                    let (enable, name) = if let Some(name) = token.strip_prefix('+') {
+                        (true, name)
+                    } else if let Some(name) = token.strip_prefix('-') {
+                        (false, name)
+                    } else {
+                        return Err(Errno::EINVAL);
+                    };
+                    let index = CGROUP_CONTROLLERS
+                        .iter()
+                        .position(|controller| *controller == name)
+                        .ok_or(Errno::EINVAL)?;
+                    if enable {
+                        mask |= 1 << index;
+                    } else {
+                        mask &= !(1 << index);
+                    }
+                }
+                cgroup.set_subtree_control(mask);
+            }
+            CgroupFileKind::MemoryMax => {
+                let max = parse_max(info, parse_size)?;
+                let pages = if max == CGROUP_MAX {
+                    CGROUP_MAX
+                } else {
+                    max / PAGE_SIZE
+                };
+                cgroup.memory.max.store(pages, Ordering::Relaxed);
+            }
+            CgroupFileKind::PidsMax => {
+                let max = parse_max(info, |s| s.parse::<usize>().map_err(|_| Errno::EINVAL))?;
+                cgroup.pids.max.store(max, Ordering::Relaxed);
+            }
+            CgroupFileKind::CpuWeight => {
+                let weight = info.parse::<usize>().map_err(|_| Errno::EINVAL)?;
+                if !(CPU_WEIGHT_MIN..=CPU_WEIGHT_MAX).contains(&weight) {
+                    return Err(Errno::ERANGE);
+                }
+                cgroup.op_cpu(|cpu| cpu.weight = weight);
+            }
+            CgroupFileKind::CpuMax => {
+                // "$MAX $PERIOD", 周期可以省略
+                let mut tokens = info.split_whitespace();
+                let quota = parse_max(tokens.next().ok_or(Errno::EINVAL)?, |s| {
+                    s.parse::<usize>().map_err(|_| Errno::EINVAL)
+                })?;
+                let period = match tokens.next() {
+                    Some(period) => period.parse::<usize>().map_err(|_| Errno::EINVAL)?,
+                    None => cgroup.op_cpu(|cpu| cpu.period),
+                };
+                if !(CPU_PERIOD_MIN..=CPU_PERIOD_MAX).contains(&period)
+                    || (quota != CGROUP_MAX && quota < CPU_QUOTA_MIN)
+                {
+                    return Err(Errno::EINVAL);
+                }
+                cgroup.op_cpu(|cpu| {
+                    cpu.quota = quota;
+                    cpu.period = period;
+                });
+            }
+            _ => return Err(Errno::EACCES),
+        }
+        Ok(())
+    }
+}
+
+fn format_max(val: usize, scale: impl Fn(usize) -> usize) -> String {
+    if val == CGROUP_MAX {
+        "max\n".to_string()
+    } else {
+        format!("{}\n", scale(val))
+    }
+}
+
+fn parse_max(s: &str, parse: impl Fn(&str) -> Result<usize, Errno>) -> Result<usize, Errno> {
+    if s == "max" {
+        Ok(CGROUP_MAX)
+    } else {
+        parse(s)
+    }
+}
+
+/// 字节数, 可以带K/M/G后缀
+fn parse_size(s: &str) -> Result<usize, Errno> {
+    let (num, shift) = match s.as_bytes().last() {
+        Some(b'k' | b'K') => (&s[..s.len() - 1], 10),
+        Some(b'm' | b'M') => (&s[..s.len() - 1], 20),
+        Some(b'g' | b'G') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let num = num.parse::<usize>().map_err(|_| Errno::EINVAL)?;
+    num.checked_mul(1 << shift).ok_or(Errno::ERANGE)
+}
+
+fn cgroup_nameidata(path: &str) -> Nameidata {
+    let root_path = CGROUP_FS_ROOT.get().unwrap();
+    Nameidata {
+        path_segments: parse_path(path),
+        dentry: root_path.dentry.clone(),
+        mnt: root_path.mnt.clone(),
+        depth: 0,
+    }
+}
+
+/// 在cgroup目录下创建控制文件
+fn cgroup_create_files(dir_path: &str, is_root: bool) {
+    for kind in CgroupFileKind::ALL {
+        if is_root && !kind.in_root() {
+            continue;
+        }
+        let path = format!("{}/{}", dir_path, kind.name());
+        let mut nd = cgroup_nameidata(&path);
+        match filename_create(&mut nd, 0) {
+            Ok(dentry) => {
+                let parent_inode = nd.dentry.get_inode();
+                parent_inode.create(dentry.clone(), S_IFREG | kind.mode());
+                if is_root {
+                    insert_core_dentry(dentry);
+                } else {
+                    insert_dentry(dentry);
+                }
+            }
+            // 文件系统中可能残留上次启动时创建的文件
+            Err(e) => log::warn!("[cgroup] create {} failed: {:?}", path, e),
+        }
+    }
+}
+
+fn cgroup_remove_files(dir_path: &str) {
+    for kind in CgroupFileKind::ALL {
+        let path = format!("{}/{}", dir_path, kind.name());
+        let mut nd = cgroup_nameidata(&path);
+        if let Ok(dentry) = filename_lookup(&mut nd, false) {
+            let parent_inode = nd.dentry.get_inode();
+            if let Err(e) = parent_inode.unlink(dentry.clone()) {
+                log::warn!("[cgroup] unlink {} failed: {:?}", path, e);
+                continue;
+            }
+            delete_dentry(dentry);
+        }
+    }
+}
+
+pub fn init_cgroupfs(root_path: Arc<Path>) {
+    CGROUP_FS_ROOT.call_once(|| root_path);
+    cgroup_create_files(CGROUP_ROOT_DIR, true);
+}
+
+/// /sys/fs/cgroup下的目录对应的cgroup
+fn cgroup_of_dir(dir_path: &str) -> Option<Arc<Cgroup>> {
+    let rel = dir_path.strip_prefix(CGROUP_ROOT_DIR)?;
+    if !rel.is_empty() && !rel.starts_with('/') {
+        return None;
+    }
+    CGROUP_ROOT.lookup(rel)
+}
+
+/// clone3(CLONE_INTO_CGROUP)传入的目录文件描述符对应的cgroup, 不是cgroup目录时返回EBADF
+pub fn cgroup_of_file(file: &Arc<dyn FileOp>) -> Result<Arc<Cgroup>, Errno> {
+    let dentry = file.get_path().dentry.clone();
+    if !dentry.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    cgroup_of_dir(&dentry.absolute_path)
+        .filter(|cgroup| !cgroup.is_removed())
+        .ok_or(Errno::EBADF)
+}
+
+/// `sys_mkdirat`在cgroup目录下创建目录后调用, 创建子cgroup和控制文件
+pub fn cgroup_mkdir(dentry: &Arc<Dentry>) -> Result<(), Errno> {
+    let Some((parent_path, name)) = dentry.absolute_path.rsplit_once('/') else {
+        return Ok(());
+    };
+    let Some(parent) = cgroup_of_dir(parent_path) else {
+        return Ok(());
+    };
+    parent.mkdir(name)?;
+    cgroup_create_files(&dentry.absolute_path, false);
+    Ok(())
+}
+
+/// `sys_unlinkat`删除cgroup目录前调用, 删除cgroup和控制文件
+/// cgroup中还有任务或子cgroup时返回EBUSY
+pub fn cgroup_rmdir(dentry: &Arc<Dentry>) -> Result<(), Errno> {
+    if dentry.absolute_path == CGROUP_ROOT_DIR {
+        return Err(Errno::EBUSY);
+    }
+    let Some(cgroup) = cgroup_of_dir(&dentry.absolute_path) else {
+        return Ok(());
+    };
+    cgroup.rmdir()?;
+    cgroup_remove_files(&dentry.absolute_path);
+    Ok(())
+}
+
+/// `mount -t cgroup2`, 只支持挂载在/sys/fs/cgroup
+pub fn cgroup_mount(target: &str) -> SyscallRet {
+    let mut nd = Nameidata::new(target, AT_FDCWD)?;
+    let dentry = filename_lookup(&mut nd, true)?;
+    if !dentry.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    if dentry.absolute_path != CGROUP_ROOT_DIR {
+        log::error!(
+            "[cgroup_mount] cgroup2 can only be mounted on {}, target: {}",
+            CGROUP_ROOT_DIR,
+            dentry.absolute_path
+        );
+        return Err(Errno::EINVAL);
+    }
+    Ok(0)
+}
+
+/// 打开/sys/fs/cgroup下的控制文件
+pub fn cgroup_open(
+    dentry: &Arc<Dentry>,
+    path: Arc<Path>,
+    inode: Arc<dyn InodeOp>,
+    flags: OpenFlags,
+) -> Option<Arc<dyn FileOp>> {
+    let (dir_path, name) = dentry.absolute_path.rsplit_once('/')?;
+    let kind = CgroupFileKind::from_name(name)?;
+    let cgroup = cgroup_of_dir(dir_path)?;
+    if cgroup.is_root() && !kind.in_root() {
+        return None;
+    }
+    Some(CgroupFile::new(path, inode, flags, cgroup, kind))
+}
+
+pub struct CgroupFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    pub cgroup: Arc<Cgroup>,
+    pub kind: CgroupFileKind,
+    pub inner: RwLock<CgroupFileInner>,
+}
+
+#[derive(Default)]
+pub struct CgroupFileInner {
+    pub offset: usize,
+}
+
+impl CgroupFile {
+    pub fn new(
+        path: Arc<Path>,
+        inode: Arc<dyn InodeOp>,
+        flags: OpenFlags,
+        cgroup: Arc<Cgroup>,
+        kind: CgroupFileKind,
+    ) -> Arc<Self> {
+        Arc::new(CgroupFile {
+            path,
+            inode,
+            flags,
+            cgroup,
+            kind,
+            inner: RwLock::new(CgroupFileInner::default()),
+        })
+    }
+}
+
+impl FileOp for CgroupFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        let info = self.kind.serialize(&self.cgroup);
+        let mut inner_guard = self.inner.write();
+        if inner_guard.offset >= info.len() {
+            return Ok(0);
+        }
+        let len = (info.len() - inner_guard.offset).min(buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[inner_guard.offset..inner_guard.offset + len]);
+        inner_guard.offset += len;
+        Ok(len)
+    }
+    fn readable(&self) -> bool {
+        true
+    }
+    fn write(&self, buf: &[u8]) -> SyscallRet {
+        // 已经被rmdir的cgroup
+        if self.cgroup.is_removed() {
+            return Err(Errno::ENODEV);
+        }
+        let info = str::from_utf8(buf).map_err(|_| Errno::EINVAL)?;
+        self.kind.store(&self.cgroup, info)?;
+        self.inner.write().offset += buf.len();
+        Ok(buf.len())
+    }
+    fn writable(&self) -> bool {
+        self.kind.is_writable()
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        let base = match whence {
+            Whence::SeekSet => 0,
+            Whence::SeekCur => inner_guard.offset,
+            Whence::SeekEnd => self.kind.serialize(&self.cgroup).len(),
+            _ => return Err(Errno::EINVAL),
+        };
+        inner_guard.offset = base.checked_add_signed(offset).ok_or(Errno::EINVAL)?;
+        Ok(inner_guard.offset)
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn get_path(&self) -> Arc<Path> {
+        self.path.clone()
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
@@ -0,0 +1,186 @@
+//! /sys/kernel/mm/ksm下的控制文件和统计
+use core::{
+    str,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc};
+use spin::{Once, RwLock};
+
+use crate::{
+    fs::{
+        file::{FileOp, OpenFlags},
+        inode::InodeOp,
+        path::Path,
+        uapi::Whence,
+    },
+    mm::ksm::{
+        ksm_stat, ksm_unmerge_all, wakeup_ksmd, KSM_FULL_SCANS, KSM_PAGES_TO_SCAN, KSM_RUN,
+        KSM_RUN_UNMERGE, KSM_SLEEP_MILLISECS,
+    },
+    syscall::errno::{Errno, SyscallRet},
+};
+
+/// /sys/kernel/mm/ksm下的文件, key是绝对路径
+pub static KSM_ATTRS: Once<BTreeMap<&'static str, Arc<dyn FileOp>>> = Once::new();
+
+#[derive(Clone, Copy)]
+pub enum KsmAttr {
+    Run,
+    PagesToScan,
+    SleepMillisecs,
+    /// 只读, 被合并的KSM页数
+    PagesShared,
+    /// 只读, 映射到KSM页而节省的页数
+    PagesSharing,
+    /// 只读, 本轮扫描中没有找到相同内容的候选页数
+    PagesUnshared,
+    /// 只读
+    FullScans,
+}
+
+impl KsmAttr {
+    pub const ALL: [KsmAttr; 7] = [
+        KsmAttr::Run,
+        KsmAttr::PagesToScan,
+        KsmAttr::SleepMillisecs,
+        KsmAttr::PagesShared,
+        KsmAttr::PagesSharing,
+        KsmAttr::PagesUnshared,
+        KsmAttr::FullScans,
+    ];
+    pub fn path(&self) -> &'static str {
+        match self {
+            KsmAttr::Run => "/sys/kernel/mm/ksm/run",
+            KsmAttr::PagesToScan => "/sys/kernel/mm/ksm/pages_to_scan",
+            KsmAttr::SleepMillisecs => "/sys/kernel/mm/ksm/sleep_millisecs",
+            KsmAttr::PagesShared => "/sys/kernel/mm/ksm/pages_shared",
+            KsmAttr::PagesSharing => "/sys/kernel/mm/ksm/pages_sharing",
+            KsmAttr::PagesUnshared => "/sys/kernel/mm/ksm/pages_unshared",
+            KsmAttr::FullScans => "/sys/kernel/mm/ksm/full_scans",
+        }
+    }
+    pub fn mode(&self) -> u16 {
+        if self.tunable().is_some() {
+            0o644
+        } else {
+            0o444
+        }
+    }
+    fn tunable(&self) -> Option<&'static AtomicUsize> {
+        match self {
+            KsmAttr::Run => Some(&KSM_RUN),
+            KsmAttr::PagesToScan => Some(&KSM_PAGES_TO_SCAN),
+            KsmAttr::SleepMillisecs => Some(&KSM_SLEEP_MILLISECS),
+            _ => None,
+        }
+    }
+    fn serialize(&self) -> String {
+        let (shared, sharing, unshared) = ksm_stat();
+        let val = match self {
+            KsmAttr::PagesShared => shared,
+            KsmAttr::PagesSharing => sharing,
+            KsmAttr::PagesUnshared => unshared,
+            KsmAttr::FullScans => KSM_FULL_SCANS.load(Ordering::Relaxed),
+            _ => self.tunable().unwrap().load(Ordering::Relaxed),
+        };
+        format!("{}\n", val)
+    }
+    fn store(&self, val: usize) -> Result<(), Errno> {
+        let Some(tunable) = self.tunable() else {
+            return Err(Errno::EACCES);
+        };
+        match self {
+            KsmAttr::Run if val > KSM_RUN_UNMERGE => return Err(Errno::EINVAL),
+            KsmAttr::PagesToScan | KsmAttr::SleepMillisecs if val == 0 => {
+                return Err(Errno::EINVAL)
+            }
+            _ => {}
+        }
+        tunable.store(val, Ordering::Relaxed);
+        if let KsmAttr::Run = self {
+            if val == KSM_RUN_UNMERGE {
+                ksm_unmerge_all();
+            }
+        }
+        wakeup_ksmd();
+        Ok(())
+    }
+}
+
+pub struct KsmAttrFile {
+    pub path: Arc<Path>,
+    pub inode: Arc<dyn InodeOp>,
+    pub flags: OpenFlags,
+    pub attr: KsmAttr,
+    pub inner: RwLock<KsmAttrFileInner>,
+}
+
+#[derive(Default)]
+pub struct KsmAttrFileInner {
+    pub offset: usize,
+}
+
+impl KsmAttrFile {
+    pub fn new(
+        path: Arc<Path>,
+        inode: Arc<dyn InodeOp>,
+        flags: OpenFlags,
+        attr: KsmAttr,
+    ) -> Arc<Self> {
+        Arc::new(KsmAttrFile {
+            path,
+            inode,
+            flags,
+            attr,
+            inner: RwLock::new(KsmAttrFileInner::default()),
+        })
+    }
+}
+
+impl FileOp for KsmAttrFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        let info = self.attr.serialize();
+        let mut inner_guard = self.inner.write();
+        if inner_guard.offset >= info.len() {
+            return Ok(0);
+        }
+        let len = (info.len() - inner_guard.offset).min(buf.len());
+        buf[..len].copy_from_slice(&info.as_bytes()[inner_guard.offset..inner_guard.offset + len]);
+        inner_guard.offset += len;
+        Ok(len)
+    }
+    fn readable(&self) -> bool {
+        true
+    }
+    fn write(&self, buf: &[u8]) -> SyscallRet {
+        let info = str::from_utf8(buf).map_err(|_| Errno::EINVAL)?;
+        let val = info.trim().parse::<usize>().map_err(|_| Errno::EINVAL)?;
+        self.attr.store(val)?;
+        self.inner.write().offset += buf.len();
+        Ok(buf.len())
+    }
+    fn writable(&self) -> bool {
+        self.attr.tunable().is_some()
+    }
+    fn seek(&self, offset: isize, whence: Whence) -> SyscallRet {
+        let mut inner_guard = self.inner.write();
+        let base = match whence {
+            Whence::SeekSet => 0,
+            Whence::SeekCur => inner_guard.offset,
+            Whence::SeekEnd => self.attr.serialize().len(),
+            _ => return Err(Errno::EINVAL),
+        };
+        inner_guard.offset = base.checked_add_signed(offset).ok_or(Errno::EINVAL)?;
+        Ok(inner_guard.offset)
+    }
+    fn get_inode(&self) -> Arc<dyn InodeOp> {
+        self.inode.clone()
+    }
+    fn get_flags(&self) -> OpenFlags {
+        self.flags
+    }
+}
@@ -0,0 +1,79 @@
+//! /sys下的内核可调参数
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+
+use crate::ext4::inode::{S_IFDIR, S_IFREG};
+
+use super::{
+    dentry::insert_core_dentry,
+    file::{FileOp, OpenFlags},
+    namei::{filename_create, parse_path, Nameidata},
+    path::Path,
+};
+
+pub mod cgroup;
+pub mod ksm;
+
+fn sysfs_mkdir(root_path: &Arc<Path>, dir_path: &str) {
+    let mut nd = Nameidata {
+        path_segments: parse_path(dir_path),
+        dentry: root_path.dentry.clone(),
+        mnt: root_path.mnt.clone(),
+        depth: 0,
+    };
+    let dir_mode = S_IFDIR as u16 | 0o755;
+    match filename_create(&mut nd, 0) {
+        Ok(dentry) => {
+            let parent_inode = nd.dentry.get_inode();
+            parent_inode.mkdir(dentry.clone(), dir_mode);
+            insert_core_dentry(dentry);
+        }
+        Err(e) => {
+            // 根文件系统镜像中可能已经有/sys
+            log::warn!("create {} failed: {:?}", dir_path, e);
+        }
+    };
+}
+
+pub fn init_sysfs(root_path: Arc<Path>) {
+    for dir_path in [
+        "/sys",
+        "/sys/kernel",
+        "/sys/kernel/mm",
+        "/sys/kernel/mm/ksm",
+        "/sys/fs",
+        cgroup::CGROUP_ROOT_DIR,
+    ] {
+        sysfs_mkdir(&root_path, dir_path);
+    }
+    // /sys/kernel/mm/ksm/*
+    let mut ksm_files = BTreeMap::new();
+    for attr in ksm::KsmAttr::ALL {
+        let attr_path = attr.path();
+        let mut nd = Nameidata {
+            path_segments: parse_path(attr_path),
+            dentry: root_path.dentry.clone(),
+            mnt: root_path.mnt.clone(),
+            depth: 0,
+        };
+        match filename_create(&mut nd, 0) {
+            Ok(dentry) => {
+                let parent_inode = nd.dentry.get_inode();
+                parent_inode.create(dentry.clone(), S_IFREG as u16 | attr.mode());
+                let attr_file: Arc<dyn FileOp> = ksm::KsmAttrFile::new(
+                    Path::new(root_path.mnt.clone(), dentry.clone()),
+                    dentry.get_inode().clone(),
+                    OpenFlags::O_RDWR,
+                    attr,
+                );
+                ksm_files.insert(attr_path, attr_file);
+                insert_core_dentry(dentry.clone());
+            }
+            Err(e) => {
+                panic!("create {} failed: {:?}", attr_path, e);
+            }
+        }
+    }
+    ksm::KSM_ATTRS.call_once(|| ksm_files);
+    // /sys/fs/cgroup/*
+    cgroup::init_cgroupfs(root_path);
+}
@@ -0,0 +1,505 @@
+//! userfaultfd: 由用户态处理缺页
+//!
+//! 注册了MISSING模式的私有匿名区域缺页时, 以及WP模式下被UFFDIO_WRITEPROTECT写保护的页被写时,
+//! `handle_recoverable_page_fault`不再自己处理, 而是向userfaultfd的事件队列发送UFFD_EVENT_PAGEFAULT,
+//! 缺页的任务阻塞到监视者通过UFFDIO_COPY/UFFDIO_ZEROPAGE/UFFDIO_WRITEPROTECT/UFFDIO_WAKE解决缺页,
+//! 然后返回用户态重新执行触发缺页的指令
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use alloc::{
+    collections::vec_deque::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use bitflags::bitflags;
+use spin::{Mutex, RwLock};
+
+use crate::{
+    arch::{
+        config::PAGE_SIZE,
+        mm::{copy_from_user, copy_to_user},
+    },
+    mm::{MemorySet, Page, VPNRange, VirtAddr},
+    syscall::errno::{Errno, SyscallRet},
+    task::{current_task, wait, wakeup, Tid},
+};
+
+use super::file::{FileOp, OpenFlags};
+
+pub const UFFD_API: u64 = 0xAA;
+/// userfaultfd的flags, 只处理用户态的缺页
+pub const UFFD_USER_MODE_ONLY: i32 = 1;
+
+pub const UFFDIO_API: usize = 0xC018AA3F;
+pub const UFFDIO_REGISTER: usize = 0xC020AA00;
+pub const UFFDIO_UNREGISTER: usize = 0x8010AA01;
+pub const UFFDIO_WAKE: usize = 0x8010AA02;
+pub const UFFDIO_COPY: usize = 0xC028AA03;
+pub const UFFDIO_ZEROPAGE: usize = 0xC020AA04;
+pub const UFFDIO_WRITEPROTECT: usize = 0xC018AA06;
+
+/// ioctl编号(_IOC_NR), 用于UFFDIO_API和UFFDIO_REGISTER返回的ioctls位图
+const _UFFDIO_REGISTER: u64 = 0x00;
+const _UFFDIO_UNREGISTER: u64 = 0x01;
+const _UFFDIO_WAKE: u64 = 0x02;
+const _UFFDIO_COPY: u64 = 0x03;
+const _UFFDIO_ZEROPAGE: u64 = 0x04;
+const _UFFDIO_WRITEPROTECT: u64 = 0x06;
+const _UFFDIO_API: u64 = 0x3F;
+
+const UFFD_API_IOCTLS: u64 =
+    (1 << _UFFDIO_REGISTER) | (1 << _UFFDIO_UNREGISTER) | (1 << _UFFDIO_API);
+const UFFD_API_RANGE_IOCTLS: u64 = (1 << _UFFDIO_WAKE)
+    | (1 << _UFFDIO_COPY)
+    | (1 << _UFFDIO_ZEROPAGE)
+    | (1 << _UFFDIO_WRITEPROTECT);
+
+pub const UFFD_FEATURE_PAGEFAULT_FLAG_WP: u64 = 1 << 0;
+pub const UFFD_FEATURE_THREAD_ID: u64 = 1 << 8;
+const UFFD_API_FEATURES: u64 = UFFD_FEATURE_PAGEFAULT_FLAG_WP | UFFD_FEATURE_THREAD_ID;
+
+pub const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+pub const UFFD_PAGEFAULT_FLAG_WRITE: u64 = 1 << 0;
+pub const UFFD_PAGEFAULT_FLAG_WP: u64 = 1 << 1;
+
+pub const UFFDIO_COPY_MODE_DONTWAKE: u64 = 1 << 0;
+pub const UFFDIO_COPY_MODE_WP: u64 = 1 << 1;
+pub const UFFDIO_ZEROPAGE_MODE_DONTWAKE: u64 = 1 << 0;
+pub const UFFDIO_WRITEPROTECT_MODE_WP: u64 = 1 << 0;
+pub const UFFDIO_WRITEPROTECT_MODE_DONTWAKE: u64 = 1 << 1;
+
+bitflags! {
+    /// UFFDIO_REGISTER的mode
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct UffdMode: u64 {
+        /// 缺页(页不存在)
+        const MISSING = 1 << 0;
+        /// 写被UFFDIO_WRITEPROTECT保护的页
+        const WP = 1 << 1;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UffdioApi {
+    pub api: u64,
+    pub features: u64,
+    pub ioctls: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UffdioRange {
+    pub start: u64,
+    pub len: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UffdioRegister {
+    pub range: UffdioRange,
+    pub mode: u64,
+    pub ioctls: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UffdioCopy {
+    pub dst: u64,
+    pub src: u64,
+    pub len: u64,
+    pub mode: u64,
+    /// 复制的字节数或负的错误码
+    pub copy: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UffdioZeropage {
+    pub range: UffdioRange,
+    pub mode: u64,
+    pub zeropage: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UffdioWriteprotect {
+    pub range: UffdioRange,
+    pub mode: u64,
+}
+
+/// struct uffd_msg, 只有UFFD_EVENT_PAGEFAULT一种事件
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UffdMsg {
+    pub event: u8,
+    pub reserved1: u8,
+    pub reserved2: u16,
+    pub reserved3: u32,
+    pub flags: u64,
+    pub address: u64,
+    pub ptid: u32,
+    pub pad: u32,
+}
+
+pub struct UserfaultFd {
+    /// 注册的区域只保存弱引用, 关闭userfaultfd后注册自动失效
+    this: Weak<UserfaultFd>,
+    /// 创建userfaultfd的地址空间, 注册和解决缺页都作用于它
+    mm: Weak<RwLock<MemorySet>>,
+    flags: AtomicI32,
+    inner: Mutex<UserfaultFdInner>,
+}
+
+struct UserfaultFdInner {
+    /// UFFDIO_API之后才能注册
+    features: Option<u64>,
+    /// 还没有被read的缺页事件
+    pending: VecDeque<UffdMsg>,
+    /// 等待缺页被解决的任务和缺页的页地址
+    fault_waiters: Vec<(Tid, usize)>,
+    /// 阻塞在read或poll上的任务
+    read_waiters: Vec<Tid>,
+}
+
+impl UserfaultFd {
+    pub fn new(flags: OpenFlags) -> Arc<Self> {
+        Arc::new_cyclic(|this| UserfaultFd {
+            this: this.clone(),
+            mm: Arc::downgrade(&current_task().memory_set()),
+            flags: AtomicI32::new(flags.bits()),
+            inner: Mutex::new(UserfaultFdInner {
+                features: None,
+                pending: VecDeque::new(),
+                fault_waiters: Vec::new(),
+                read_waiters: Vec::new(),
+            }),
+        })
+    }
+    fn nonblock(&self) -> bool {
+        self.flags.load(Ordering::Relaxed) & OpenFlags::O_NONBLOCK.bits() != 0
+    }
+    fn mm(&self) -> Result<Arc<RwLock<MemorySet>>, Errno> {
+        self.mm.upgrade().ok_or(Errno::ESRCH)
+    }
+    /// 由`handle_recoverable_page_fault`在持有地址空间锁时调用, 记录缺页事件,
+    /// 调用者释放锁后通过`wait_fault`阻塞
+    pub fn handle_fault(&self, va: VirtAddr, flags: u64) {
+        let task = current_task();
+        let address = va.floor().0 * PAGE_SIZE;
+        let mut inner = self.inner.lock();
+        let ptid = if inner
+            .features
+            .is_some_and(|features| features & UFFD_FEATURE_THREAD_ID != 0)
+        {
+            task.tid() as u32
+        } else {
+            0
+        };
+        log::info!(
+            "[userfaultfd] task{} fault at {:#x}, flags: {:#x}",
+            task.tid(),
+            va.0,
+            flags
+        );
+        inner.pending.push_back(UffdMsg {
+            event: UFFD_EVENT_PAGEFAULT,
+            flags,
+            address: address as u64,
+            ptid,
+            ..Default::default()
+        });
+        inner.fault_waiters.push((task.tid(), address));
+        for tid in inner.read_waiters.drain(..) {
+            wakeup(tid);
+        }
+    }
+    /// 阻塞到缺页被解决, 调用时不能持有地址空间的锁
+    pub fn wait_fault(&self) {
+        let tid = current_task().tid();
+        if !self
+            .inner
+            .lock()
+            .fault_waiters
+            .iter()
+            .any(|(waiter, _)| *waiter == tid)
+        {
+            return;
+        }
+        if wait() == -1 {
+            // 被信号打断, 处理信号后重新执行触发缺页的指令
+            self.inner
+                .lock()
+                .fault_waiters
+                .retain(|(waiter, _)| *waiter != tid);
+        }
+    }
+    /// 唤醒在[start, start + len)内缺页的任务
+    fn wake_range(&self, start: usize, len: usize) {
+        let mut inner = self.inner.lock();
+        inner.fault_waiters.retain(|(tid, address)| {
+            if (start..start + len).contains(address) {
+                wakeup(*tid);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// 检查uffdio_range并转换为页号范围
+fn uffd_range(range: &UffdioRange) -> Result<VPNRange, Errno> {
+    let start = range.start as usize;
+    let len = range.len as usize;
+    if start % PAGE_SIZE != 0 || len % PAGE_SIZE != 0 || len == 0 {
+        return Err(Errno::EINVAL);
+    }
+    let end = start.checked_add(len).ok_or(Errno::EINVAL)?;
+    Ok(VPNRange::new(
+        VirtAddr::from(start).floor(),
+        VirtAddr::from(end).floor(),
+    ))
+}
+
+fn read_arg<T: Copy + Default>(arg_ptr: usize) -> Result<T, Errno> {
+    let mut arg = T::default();
+    copy_from_user(arg_ptr as *const T, &mut arg as *mut T, 1)?;
+    Ok(arg)
+}
+
+impl UserfaultFd {
+    fn ioctl_api(&self, arg_ptr: usize) -> SyscallRet {
+        let mut api: UffdioApi = read_arg(arg_ptr)?;
+        let mut inner = self.inner.lock();
+        if inner.features.is_some() {
+            return Err(Errno::EINVAL);
+        }
+        if api.api != UFFD_API || api.features & !UFFD_API_FEATURES != 0 {
+            api.features = 0;
+            api.ioctls = 0;
+            copy_to_user(arg_ptr as *mut UffdioApi, &api, 1)?;
+            return Err(Errno::EINVAL);
+        }
+        inner.features = Some(api.features);
+        api.features = UFFD_API_FEATURES;
+        api.ioctls = UFFD_API_IOCTLS;
+        drop(inner);
+        copy_to_user(arg_ptr as *mut UffdioApi, &api, 1)?;
+        Ok(0)
+    }
+    fn ioctl_register(&self, arg_ptr: usize) -> SyscallRet {
+        let mut register: UffdioRegister = read_arg(arg_ptr)?;
+        let range = uffd_range(&register.range)?;
+        let mode = UffdMode::from_bits(register.mode)
+            .filter(|mode| !mode.is_empty())
+            .ok_or(Errno::EINVAL)?;
+        self.mm()?
+            .write()
+            .userfault_register(range, &self.this, mode)?;
+        register.ioctls = UFFD_API_RANGE_IOCTLS;
+        copy_to_user(arg_ptr as *mut UffdioRegister, &register, 1)?;
+        Ok(0)
+    }
+    fn ioctl_unregister(&self, arg_ptr: usize) -> SyscallRet {
+        let range: UffdioRange = read_arg(arg_ptr)?;
+        let vpn_range = uffd_range(&range)?;
+        self.mm()?.write().userfault_unregister(vpn_range, self)?;
+        // 解除注册后, 等待的任务重新缺页时按普通缺页处理
+        self.wake_range(range.start as usize, range.len as usize);
+        Ok(0)
+    }
+    fn ioctl_copy(&self, arg_ptr: usize) -> SyscallRet {
+        let mut copy: UffdioCopy = read_arg(arg_ptr)?;
+        if copy.mode & !(UFFDIO_COPY_MODE_DONTWAKE | UFFDIO_COPY_MODE_WP) != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let range = uffd_range(&UffdioRange {
+            start: copy.dst,
+            len: copy.len,
+        })?;
+        if copy.src as usize % PAGE_SIZE != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let mm = self.mm()?;
+        let wp = copy.mode & UFFDIO_COPY_MODE_WP != 0;
+        let mut copied = 0;
+        let mut result = Ok(0);
+        for vpn in range {
+            // 先从调用者的地址空间复制到新页, 再安装到注册的地址空间
+            let page = Page::new_framed(None);
+            let src = copy.src as usize + copied;
+            let dst = page.ppn().get_bytes_array();
+            if let Err(e) = copy_from_user(src as *const u8, dst.as_mut_ptr(), PAGE_SIZE)
+                .and_then(|_| mm.write().userfault_install(vpn, page, wp, self))
+            {
+                result = Err(e);
+                break;
+            }
+            copied += PAGE_SIZE;
+        }
+        copy.copy = match result {
+            Ok(_) => copied as i64,
+            Err(e) if copied == 0 => -(e as i64),
+            Err(_) => copied as i64,
+        };
+        copy_to_user(arg_ptr as *mut UffdioCopy, &copy, 1)?;
+        if copied > 0 && copy.mode & UFFDIO_COPY_MODE_DONTWAKE == 0 {
+            self.wake_range(copy.dst as usize, copied);
+        }
+        match result {
+            Err(e) if copied == 0 => Err(e),
+            Err(_) => Err(Errno::EAGAIN),
+            Ok(_) => Ok(0),
+        }
+    }
+    fn ioctl_zeropage(&self, arg_ptr: usize) -> SyscallRet {
+        let mut zeropage: UffdioZeropage = read_arg(arg_ptr)?;
+        if zeropage.mode & !UFFDIO_ZEROPAGE_MODE_DONTWAKE != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let range = uffd_range(&zeropage.range)?;
+        let mm = self.mm()?;
+        let mut zeroed = 0;
+        let mut result = Ok(0);
+        for vpn in range {
+            if let Err(e) = mm
+                .write()
+                .userfault_install(vpn, Page::new_framed(None), false, self)
+            {
+                result = Err(e);
+                break;
+            }
+            zeroed += PAGE_SIZE;
+        }
+        zeropage.zeropage = match result {
+            Err(e) if zeroed == 0 => -(e as i64),
+            _ => zeroed as i64,
+        };
+        copy_to_user(arg_ptr as *mut UffdioZeropage, &zeropage, 1)?;
+        if zeroed > 0 && zeropage.mode & UFFDIO_ZEROPAGE_MODE_DONTWAKE == 0 {
+            self.wake_range(zeropage.range.start as usize, zeroed);
+        }
+        match result {
+            Err(e) if zeroed == 0 => Err(e),
+            Err(_) => Err(Errno::EAGAIN),
+            Ok(_) => Ok(0),
+        }
+    }
+    fn ioctl_writeprotect(&self, arg_ptr: usize) -> SyscallRet {
+        let wp: UffdioWriteprotect = read_arg(arg_ptr)?;
+        if wp.mode & !(UFFDIO_WRITEPROTECT_MODE_WP | UFFDIO_WRITEPROTECT_MODE_DONTWAKE) != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let protect = wp.mode & UFFDIO_WRITEPROTECT_MODE_WP != 0;
+        // 保护的同时不能唤醒
+        if protect && wp.mode & UFFDIO_WRITEPROTECT_MODE_DONTWAKE != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let range = uffd_range(&wp.range)?;
+        self.mm()?
+            .write()
+            .userfault_writeprotect(range, protect, self)?;
+        if !protect && wp.mode & UFFDIO_WRITEPROTECT_MODE_DONTWAKE == 0 {
+            self.wake_range(wp.range.start as usize, wp.range.len as usize);
+        }
+        Ok(0)
+    }
+    fn ioctl_wake(&self, arg_ptr: usize) -> SyscallRet {
+        let range: UffdioRange = read_arg(arg_ptr)?;
+        uffd_range(&range)?;
+        self.wake_range(range.start as usize, range.len as usize);
+        Ok(0)
+    }
+}
+
+impl FileOp for UserfaultFd {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    /// 读取uffd_msg, 每条32字节
+    fn read(&self, buf: &mut [u8]) -> SyscallRet {
+        const MSG_SIZE: usize = core::mem::size_of::<UffdMsg>();
+        if buf.len() < MSG_SIZE {
+            return Err(Errno::EINVAL);
+        }
+        loop {
+            let mut inner = self.inner.lock();
+            if !inner.pending.is_empty() {
+                let mut read_size = 0;
+                while read_size + MSG_SIZE <= buf.len() {
+                    let Some(msg) = inner.pending.pop_front() else {
+                        break;
+                    };
+                    let bytes = unsafe {
+                        core::slice::from_raw_parts(&msg as *const UffdMsg as *const u8, MSG_SIZE)
+                    };
+                    buf[read_size..read_size + MSG_SIZE].copy_from_slice(bytes);
+                    read_size += MSG_SIZE;
+                }
+                return Ok(read_size);
+            }
+            if self.nonblock() {
+                return Err(Errno::EAGAIN);
+            }
+            inner.read_waiters.push(current_task().tid());
+            drop(inner);
+            if wait() == -1 {
+                return Err(Errno::ERESTARTSYS);
+            }
+        }
+    }
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn add_wait_queue(&self, tid: Tid) {
+        self.inner.lock().read_waiters.push(tid);
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+    fn r_ready(&self) -> bool {
+        !self.inner.lock().pending.is_empty()
+    }
+    fn w_ready(&self) -> bool {
+        false
+    }
+    fn ioctl(&self, op: usize, arg_ptr: usize) -> SyscallRet {
+        log::info!(
+            "[UserfaultFd::ioctl] op: {:#x}, arg_ptr: {:#x}",
+            op,
+            arg_ptr
+        );
+        if op != UFFDIO_API && self.inner.lock().features.is_none() {
+            return Err(Errno::EINVAL);
+        }
+        match op {
+            UFFDIO_API => self.ioctl_api(arg_ptr),
+            UFFDIO_REGISTER => self.ioctl_register(arg_ptr),
+            UFFDIO_UNREGISTER => self.ioctl_unregister(arg_ptr),
+            UFFDIO_COPY => self.ioctl_copy(arg_ptr),
+            UFFDIO_ZEROPAGE => self.ioctl_zeropage(arg_ptr),
+            UFFDIO_WRITEPROTECT => self.ioctl_writeprotect(arg_ptr),
+            UFFDIO_WAKE => self.ioctl_wake(arg_ptr),
+            _ => Err(Errno::ENOTTY),
+        }
+    }
+    fn get_flags(&self) -> OpenFlags {
+        OpenFlags::from_bits(self.flags.load(Ordering::Relaxed)).unwrap()
+    }
+    fn set_flags(&self, flags: OpenFlags) {
+        self.flags.store(flags.bits(), Ordering::Relaxed);
+    }
+}
+
+impl Drop for UserfaultFd {
+    /// 关闭后注册信息失效(区域中只保存弱引用), 唤醒所有等待的任务按普通缺页重新处理
+    fn drop(&mut self) {
+        for (tid, _) in self.inner.lock().fault_waiters.drain(..) {
+            wakeup(tid);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! 脏页回写
+//!
+//! 后台回写线程`kworker/flush`每隔dirty_writeback_centisecs被唤醒一次, 回写变脏时间超过
+//! dirty_expire_centisecs的页缓存; 脏页数超过dirty_background_ratio时提前唤醒回写线程,
+//! 超过dirty_ratio时由写入者自己同步回写(balance_dirty_pages)
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use alloc::sync::Arc;
+use spin::Once;
+
+use crate::{
+    arch::timer::get_time_ms,
+    mm::{frame_total, nr_dirty_pages},
+    task::{kthread_should_stop, kthread_sleep, kthread_spawn, wakeup, Task},
+    timer::TimeSpec,
+};
+
+use super::page_cache::{dirty_page_cache, shrink_page_cache};
+
+/// 脏页占内存的百分比超过该值时, 写入者同步回写
+pub static DIRTY_RATIO: AtomicUsize = AtomicUsize::new(20);
+/// 脏页占内存的百分比超过该值时, 唤醒后台回写线程
+pub static DIRTY_BACKGROUND_RATIO: AtomicUsize = AtomicUsize::new(10);
+/// 脏页变脏超过该时间(1/100秒)后被后台回写线程回写
+pub static DIRTY_EXPIRE_CENTISECS: AtomicUsize = AtomicUsize::new(3000);
+/// 后台回写线程的唤醒周期(1/100秒), 为0时关闭周期回写
+pub static DIRTY_WRITEBACK_CENTISECS: AtomicUsize = AtomicUsize::new(500);
+
+/// 关闭周期回写时, 后台回写线程的睡眠时间(秒)
+const FLUSHER_IDLE_SECS: usize = 3600;
+
+static FLUSHER: Once<Arc<Task>> = Once::new();
+/// 是否有待处理的后台回写请求
+static WB_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// 启动后台回写线程
+pub fn init_writeback() {
+    FLUSHER.call_once(|| kthread_spawn("kworker/flush", flusher));
+}
+
+/// 由百分比计算脏页数阈值, 返回(background, dirty)
+fn dirty_limits() -> (usize, usize) {
+    let total = frame_total();
+    let dirty_ratio = DIRTY_RATIO.load(Ordering::Relaxed);
+    let mut background_ratio = DIRTY_BACKGROUND_RATIO.load(Ordering::Relaxed);
+    // 与Linux一致, background不小于dirty时取dirty的一半
+    if background_ratio >= dirty_ratio {
+        background_ratio = dirty_ratio / 2;
+    }
+    (
+        (total * background_ratio / 100).max(1),
+        (total * dirty_ratio / 100).max(1),
+    )
+}
+
+/// 回写最多`nr_pages`个脏页, 按变脏时间从早到晚回写
+/// `expired_only`为true时只回写超过dirty_expire_centisecs的页
+/// 返回回写的页数
+pub fn writeback_pages(nr_pages: usize, expired_only: bool) -> usize {
+    let expire_ms = DIRTY_EXPIRE_CENTISECS.load(Ordering::Relaxed) * 10;
+    let now = get_time_ms();
+    let mut written = 0;
+    for page in dirty_page_cache() {
+        if written >= nr_pages {
+            break;
+        }
+        match page.dirtied_at() {
+            // 已按变脏时间排序, 之后的页都没有过期
+            Some(dirtied_at) if expired_only && now.saturating_sub(dirtied_at) < expire_ms => break,
+            Some(_) => {
+                page.sync();
+                written += 1;
+            }
+            // 在收集之后已经被回写
+            None => {}
+        }
+    }
+    if written > 0 {
+        log::info!(
+            "[writeback_pages] wrote back {} pages, {} dirty pages left",
+            written,
+            nr_dirty_pages()
+        );
+    }
+    written
+}
+
+/// 唤醒后台回写线程
+pub fn wakeup_flusher() {
+    WB_PENDING.store(true, Ordering::Release);
+    if let Some(flusher) = FLUSHER.get() {
+        wakeup(flusher.tid());
+    }
+}
+
+/// 写入页缓存后调用, 根据脏页数量触发回写
+pub fn balance_dirty_pages() {
+    let nr_dirty = nr_dirty_pages();
+    let (background_thresh, dirty_thresh) = dirty_limits();
+    if nr_dirty <= background_thresh {
+        return;
+    }
+    if nr_dirty > dirty_thresh {
+        // 单核协作式调度下, 让写入者自己回写到background阈值以下
+        log::warn!(
+            "[balance_dirty_pages] {} dirty pages exceed dirty limit {}, throttle writer",
+            nr_dirty,
+            dirty_thresh
+        );
+        writeback_pages(nr_dirty - background_thresh, false);
+    } else {
+        wakeup_flusher();
+    }
+}
+
+/// /proc/sys/vm/drop_caches: 回写所有脏页后释放干净的页缓存
+pub fn drop_page_cache() -> usize {
+    writeback_pages(usize::MAX, false);
+    shrink_page_cache(usize::MAX)
+}
+
+/// 后台回写线程
+fn flusher() -> i32 {
+    while !kthread_should_stop() {
+        let (background_thresh, _) = dirty_limits();
+        let nr_dirty = nr_dirty_pages();
+        if WB_PENDING.swap(false, Ordering::AcqRel) && nr_dirty > background_thresh {
+            writeback_pages(nr_dirty - background_thresh, false);
+        }
+        // 周期回写过期的脏页
+        writeback_pages(usize::MAX, true);
+        let interval_ms = DIRTY_WRITEBACK_CENTISECS.load(Ordering::Relaxed) * 10;
+        let sleep = if interval_ms == 0 {
+            TimeSpec {
+                sec: FLUSHER_IDLE_SECS,
+                nsec: 0,
+            }
+        } else {
+            TimeSpec {
+                sec: interval_ms / 1000,
+                nsec: interval_ms % 1000 * 1_000_000,
+            }
+        };
+        kthread_sleep(sleep);
+    }
+    0
+}
@@ -129,6 +129,8 @@ pub fn rust_main(_hart_id: usize, dtb_address: usize) -> ! {
     trap::enable_timer_interrupt();
     arch::timer::set_next_trigger();
     add_initproc();
+    fs::writeback::init_writeback();
+    mm::ksm::init_ksm();
     loader::list_apps();
     DEBUG_FLAG.store(1, core::sync::atomic::Ordering::SeqCst);
     run_tasks();
@@ -160,6 +162,8 @@ pub fn rust_main() -> ! {
     // time_test();
     enable_timer_interrupt();
     add_initproc();
+    fs::writeback::init_writeback();
+    mm::ksm::init_ksm();
     loader::list_apps();
     set_next_trigger();
     run_tasks();
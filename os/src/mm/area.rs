@@ -1,19 +1,25 @@
 use crate::arch::mm::sfence_vma_vaddr;
 use core::{fmt::Debug, ops::Range};
 
-use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use alloc::{
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    sync::{Arc, Weak},
+};
 use bitflags::bitflags;
 
 use crate::{
     arch::{
-        config::{KERNEL_BASE, PAGE_SIZE, PAGE_SIZE_BITS},
-        mm::{PTEFlags, PageTable},
+        config::{HUGE_PAGE_PAGES, KERNEL_BASE, PAGE_SIZE, PAGE_SIZE_BITS},
+        mm::{PTEFlags, PageTable, PageTableEntry},
+    },
+    fs::{
+        file::FileOp,
+        userfaultfd::{UffdMode, UserfaultFd},
     },
-    fs::file::FileOp,
     mm::address::StepByOne,
 };
 
-use super::{shm::ShmAtFlags, Page, PhysPageNum, VPNRange, VirtAddr, VirtPageNum};
+use super::{memory_pressure, shm::ShmAtFlags, Page, PhysPageNum, VPNRange, VirtAddr, VirtPageNum};
 
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq)]
@@ -46,6 +52,32 @@ impl From<&ShmAtFlags> for MapPermission {
     }
 }
 
+bitflags! {
+    /// madvise设置的访问模式, 保存在MapArea上
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct MapAdvice: u8 {
+        /// MADV_SEQUENTIAL, 缺页时预读后续的文件页
+        const SEQUENTIAL = 1 << 0;
+        /// MADV_RANDOM, 缺页时不预读也不批量分配
+        const RANDOM = 1 << 1;
+        /// MADV_HUGEPAGE, 以及MAP_HUGETLB/SHM_HUGETLB的区域, 尽量使用大页
+        const HUGEPAGE = 1 << 2;
+        /// MADV_NOHUGEPAGE
+        const NOHUGEPAGE = 1 << 3;
+        /// MADV_MERGEABLE, 私有匿名页可以被KSM合并
+        const MERGEABLE = 1 << 4;
+        /// MADV_DONTFORK, fork时子进程不继承该区域
+        const DONTFORK = 1 << 5;
+        /// MADV_WIPEONFORK, fork时子进程中该区域的内容被清零, 只能用于私有匿名映射
+        const WIPEONFORK = 1 << 6;
+        /// MADV_DONTDUMP, 生成coredump时不转储该区域的内容
+        const DONTDUMP = 1 << 7;
+    }
+}
+
+/// MADV_SEQUENTIAL区域缺页时预读的页数
+pub const READAHEAD_PAGES: usize = 32;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
     Linear,
@@ -82,6 +114,16 @@ pub struct MapArea {
     pub offset: usize,
 
     pub locked: bool, // 是否被锁定, 用于文件映射
+
+    /// madvise设置的访问模式
+    pub advice: MapAdvice,
+    /// MADV_FREE标记的页, 在被写之前可以在内存不足时直接释放
+    pub lazyfree: BTreeSet<VirtPageNum>,
+
+    /// UFFDIO_REGISTER注册的userfaultfd和模式
+    pub userfault: Option<(Weak<UserfaultFd>, UffdMode)>,
+    /// UFFDIO_WRITEPROTECT写保护的页
+    pub uffd_wp: BTreeSet<VirtPageNum>,
 }
 
 impl Debug for MapArea {
@@ -122,6 +164,10 @@ impl MapArea {
             backend_file: backed_file,
             offset,
             locked,
+            advice: MapAdvice::empty(),
+            lazyfree: BTreeSet::new(),
+            userfault: None,
+            uffd_wp: BTreeSet::new(),
         }
     }
 }
@@ -138,6 +184,12 @@ impl MapArea {
             backend_file: map_area.backend_file.clone(),
             offset: map_area.offset,
             locked: false, // 锁定状态不保持
+            advice: map_area.advice,
+            // 物理页重新分配, 不再是lazyfree
+            lazyfree: BTreeSet::new(),
+            // 子进程不继承userfaultfd的注册
+            userfault: None,
+            uffd_wp: BTreeSet::new(),
         }
     }
 }
@@ -160,6 +212,11 @@ impl MapArea {
                 let start_ppn = PhysPageNum(start_vpn.0 - (KERNEL_BASE >> PAGE_SIZE_BITS));
                 page_table.map_range_continuous(start_vpn, end_vpn, start_ppn, pte_flags.clone());
             }
+            MapType::Framed if self.want_huge_page() => {
+                // MAP_HUGETLB的共享匿名映射, 按大页分配
+                self.alloc_pages_huge();
+                self.map_pages(page_table, pte_flags);
+            }
             MapType::Framed => {
                 // for vpn in self.vpn_range {
                 //     let page = Page::new_framed(None);
@@ -231,10 +288,239 @@ impl MapArea {
     /// 在原有的MapArea上删除一个页, 并删除相关映射
     /// 如果页还没有被映射, 则不需要删除映射
     pub fn dealloc_one_page(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        self.lazyfree.remove(&vpn);
+        self.uffd_wp.remove(&vpn);
         if let Some(_) = self.pages.remove(&vpn) {
             page_table.unmap(vpn);
         }
     }
+    /// 删除`range`内的页及其映射, 完全落在`range`内的大页整体取消映射, 不拆分
+    pub fn dealloc_pages(&mut self, page_table: &mut PageTable, range: VPNRange) {
+        let end_vpn = range.get_end();
+        let vpns: alloc::vec::Vec<VirtPageNum> = self
+            .pages
+            .range(range.get_start()..end_vpn)
+            .map(|(vpn, _)| *vpn)
+            .collect();
+        let mut skip_until = range.get_start();
+        for vpn in vpns {
+            if vpn < skip_until {
+                continue;
+            }
+            let huge_end = VirtPageNum(vpn.0 + HUGE_PAGE_PAGES);
+            if huge_end <= end_vpn && page_table.unmap_huge(vpn) {
+                for huge_vpn in VPNRange::new(vpn, huge_end) {
+                    self.lazyfree.remove(&huge_vpn);
+                    self.uffd_wp.remove(&huge_vpn);
+                    self.pages.remove(&huge_vpn);
+                }
+                skip_until = huge_end;
+                continue;
+            }
+            self.dealloc_one_page(page_table, vpn);
+        }
+    }
+}
+
+/// 大页
+impl MapArea {
+    /// 是否使用大页: MADV_HUGEPAGE或MAP_HUGETLB/SHM_HUGETLB, 且没有MADV_NOHUGEPAGE
+    pub fn want_huge_page(&self) -> bool {
+        PageTable::SUPPORT_HUGE_PAGE
+            && self.advice.contains(MapAdvice::HUGEPAGE)
+            && !self.advice.contains(MapAdvice::NOHUGEPAGE)
+    }
+    /// 匿名区域缺页时尝试为vpn所在的整个大页分配物理页, 并用大页映射
+    /// 大页需要完全落在区域内且其中还没有分配过页, 失败时返回false, 由调用者按4K页处理
+    pub fn alloc_huge_page(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        pte_flags: PTEFlags,
+    ) -> bool {
+        if !self.want_huge_page() || memory_pressure() {
+            return false;
+        }
+        let start_vpn = VirtPageNum(vpn.0 & !(HUGE_PAGE_PAGES - 1));
+        let end_vpn = VirtPageNum(start_vpn.0 + HUGE_PAGE_PAGES);
+        if start_vpn < self.vpn_range.get_start() || end_vpn > self.vpn_range.get_end() {
+            return false;
+        }
+        if self.pages.range(start_vpn..end_vpn).next().is_some() {
+            return false;
+        }
+        let pages = match Page::new_huge() {
+            Some(pages) => pages,
+            None => return false,
+        };
+        if !page_table.map_huge(start_vpn, pages[0].ppn(), pte_flags) {
+            return false;
+        }
+        log::info!(
+            "[MapArea::alloc_huge_page] huge page at vpn {:#x}, ppn {:#x}",
+            start_vpn.0,
+            pages[0].ppn().0
+        );
+        self.pages.extend(
+            pages
+                .into_iter()
+                .enumerate()
+                .map(|(i, page)| (VirtPageNum(start_vpn.0 + i), page)),
+        );
+        true
+    }
+    /// 为区域内还没有分配的页分配物理页, 完全落在区域内的对齐大页尽量使用连续的页帧
+    pub fn alloc_pages_huge(&mut self) {
+        let end_vpn = self.vpn_range.get_end();
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < end_vpn {
+            if vpn.0 % HUGE_PAGE_PAGES == 0
+                && vpn.0 + HUGE_PAGE_PAGES <= end_vpn.0
+                && self
+                    .pages
+                    .range(vpn..VirtPageNum(vpn.0 + HUGE_PAGE_PAGES))
+                    .next()
+                    .is_none()
+            {
+                if let Some(pages) = Page::new_huge() {
+                    self.pages.extend(
+                        pages
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, page)| (VirtPageNum(vpn.0 + i), page)),
+                    );
+                    vpn = VirtPageNum(vpn.0 + HUGE_PAGE_PAGES);
+                    continue;
+                }
+            }
+            self.pages
+                .entry(vpn)
+                .or_insert_with(|| Arc::new(Page::new_framed(None)));
+            vpn.step();
+        }
+    }
+    /// vpn开始的大页范围内的页都已分配, 且物理地址连续并按大页对齐时, 返回起始物理页号
+    fn huge_ppn_at(&self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        if vpn.0 % HUGE_PAGE_PAGES != 0 || vpn.0 + HUGE_PAGE_PAGES > self.vpn_range.get_end().0 {
+            return None;
+        }
+        let base_ppn = self.pages.get(&vpn)?.ppn();
+        if base_ppn.0 % HUGE_PAGE_PAGES != 0 {
+            return None;
+        }
+        let contiguous = (1..HUGE_PAGE_PAGES).all(|i| {
+            self.pages
+                .get(&VirtPageNum(vpn.0 + i))
+                .is_some_and(|page| page.ppn().0 == base_ppn.0 + i)
+        });
+        contiguous.then_some(base_ppn)
+    }
+    /// 映射区域中已经分配的页, 物理地址连续且按大页对齐的部分使用大页映射
+    /// used by `MapArea::map`和System V shm
+    pub fn map_pages(&self, page_table: &mut PageTable, pte_flags: PTEFlags) {
+        let end_vpn = self.vpn_range.get_end();
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < end_vpn {
+            if self.want_huge_page() {
+                if let Some(ppn) = self.huge_ppn_at(vpn) {
+                    if page_table.map_huge(vpn, ppn, pte_flags) {
+                        vpn = VirtPageNum(vpn.0 + HUGE_PAGE_PAGES);
+                        continue;
+                    }
+                }
+            }
+            if let Some(page) = self.pages.get(&vpn) {
+                page_table.map(vpn, page.ppn(), pte_flags);
+            }
+            vpn.step();
+        }
+    }
+    /// 区域内由大页映射的页数, 用于/proc/<pid>/smaps的AnonHugePages
+    pub fn huge_mapped_pages(&self, page_table: &PageTable) -> usize {
+        let end_vpn = self.vpn_range.get_end().0;
+        let mut vpn = (self.vpn_range.get_start().0 + HUGE_PAGE_PAGES - 1) & !(HUGE_PAGE_PAGES - 1);
+        let mut count = 0;
+        while vpn + HUGE_PAGE_PAGES <= end_vpn {
+            if page_table.is_huge_mapped(VirtPageNum(vpn)) {
+                count += HUGE_PAGE_PAGES;
+            }
+            vpn += HUGE_PAGE_PAGES;
+        }
+        count
+    }
+}
+
+/// madvise相关
+impl MapArea {
+    /// 私有匿名映射
+    pub fn is_private_anonymous(&self) -> bool {
+        self.backend_file.is_none()
+            && !self.is_shared()
+            && matches!(
+                self.map_type,
+                MapType::Framed | MapType::Heap | MapType::Stack
+            )
+    }
+    /// MADV_DONTNEED: 释放`range`内的页并删除映射, 再次访问时重新缺页
+    /// 私有匿名映射得到零页, 文件映射重新从页缓存读取
+    /// 由调用者保证`range`在区域内
+    pub fn dontneed(&mut self, page_table: &mut PageTable, range: VPNRange) {
+        self.dealloc_pages(page_table, range);
+    }
+    /// MADV_FREE: 将`range`内已分配的页标记为lazyfree, 并写保护
+    /// 写保护借用COW的缺页处理, 页被写后从lazyfree中移除
+    /// 由调用者保证`range`在区域内, 且是私有匿名映射
+    pub fn lazyfree(&mut self, page_table: &mut PageTable, range: VPNRange) {
+        for (vpn, _) in self.pages.range(range.get_start()..range.get_end()) {
+            // 调用者已在`range`边界拆分大页, 剩下的大页完整落在`range`内
+            // MADV_FREE只是提示, 保留大页映射而不是为了逐页回收拆分它
+            if page_table.is_huge_mapped(*vpn) {
+                continue;
+            }
+            if let Some(pte) = page_table.find_pte(*vpn) {
+                if !pte.is_valid() {
+                    continue;
+                }
+                let mut flags = pte.flags();
+                flags.remove(PTEFlags::W);
+                flags.insert(PTEFlags::COW);
+                #[cfg(target_arch = "loongarch64")]
+                flags.remove(PTEFlags::D);
+                *pte = PageTableEntry::new(pte.ppn(), flags);
+                unsafe {
+                    sfence_vma_vaddr(vpn.0 << PAGE_SIZE_BITS);
+                }
+                self.lazyfree.insert(*vpn);
+            }
+        }
+    }
+    /// 释放所有仍未被写过的lazyfree页, 返回释放的页数
+    pub fn reclaim_lazyfree(&mut self, page_table: &mut PageTable) -> usize {
+        let lazyfree = core::mem::take(&mut self.lazyfree);
+        let count = lazyfree.len();
+        for vpn in lazyfree {
+            self.dealloc_one_page(page_table, vpn);
+        }
+        count
+    }
+    /// 将文件映射中从`start_vpn`开始的`nr_pages`页读入页缓存, 不建立映射
+    /// used by MADV_WILLNEED, MADV_SEQUENTIAL
+    pub fn readahead(&self, start_vpn: VirtPageNum, nr_pages: usize) {
+        let Some(file) = self.backend_file.as_ref() else {
+            return;
+        };
+        let end_vpn = self.vpn_range.get_end().0.min(start_vpn.0 + nr_pages);
+        for vpn in start_vpn.0..end_vpn {
+            if self.pages.contains_key(&VirtPageNum(vpn)) {
+                continue;
+            }
+            let offset = self.offset + (vpn - self.vpn_range.get_start().0) * PAGE_SIZE;
+            // 超出文件末尾
+            if file.get_page(offset).is_none() {
+                break;
+            }
+        }
+    }
 }
 
 impl MapArea {
@@ -299,7 +585,13 @@ impl MapArea {
             backend_file: self.backend_file.clone(),
             offset: new_area_offset,
             locked: self.locked, // 锁定状态保持
+            advice: self.advice,
+            lazyfree: BTreeSet::new(),
+            userfault: self.userfault.clone(),
+            uffd_wp: BTreeSet::new(),
         };
+        new_area.lazyfree = self.lazyfree.split_off(&split_vpn);
+        new_area.uffd_wp = self.uffd_wp.split_off(&split_vpn);
         // 将原有的frames划分到新区域
         self.pages.retain(|vpn, page| {
             if *vpn >= split_vpn {
@@ -340,6 +632,10 @@ impl MapArea {
             backend_file: self.backend_file.clone(),
             offset: xmap_area_offset,
             locked: self.locked, // 锁定状态保持
+            advice: self.advice,
+            lazyfree: BTreeSet::new(),
+            userfault: self.userfault.clone(),
+            uffd_wp: BTreeSet::new(),
         };
         // 3. 设置新区域的开始地址: [xmap_end, end)
         let new_vpn_range = VPNRange::new(unmap_end, old_vpn_end);
@@ -357,7 +653,15 @@ impl MapArea {
             backend_file: self.backend_file.clone(),
             offset: new_area_offset,
             locked: self.locked, // 锁定状态保持
+            advice: self.advice,
+            lazyfree: BTreeSet::new(),
+            userfault: self.userfault.clone(),
+            uffd_wp: BTreeSet::new(),
         };
+        xmap_area.lazyfree = self.lazyfree.split_off(&unmap_start);
+        new_area.lazyfree = xmap_area.lazyfree.split_off(&unmap_end);
+        xmap_area.uffd_wp = self.uffd_wp.split_off(&unmap_start);
+        new_area.uffd_wp = xmap_area.uffd_wp.split_off(&unmap_end);
         // 将原有的frames划分到新区域
         self.pages.retain(|vpn, page| {
             if *vpn >= unmap_end && *vpn < old_vpn_end {
@@ -376,12 +680,30 @@ impl MapArea {
     pub fn remap(&mut self, page_table: &mut PageTable) {
         // 对于还未映射的页, 直接设置权限在缺页时会按照self.map_perm设置
         // 对于已经映射的页, 需要重新设置权限
+        let flags = PTEFlags::from(self.map_perm);
+        let mut skip_until = self.vpn_range.get_start();
         for &vpn in self.pages.keys() {
-            page_table.remap(vpn, PTEFlags::from(self.map_perm));
+            if vpn < skip_until {
+                continue;
+            }
+            // 整个大页修改权限, 不需要拆分
+            if page_table.remap_huge(vpn, flags) {
+                skip_until = VirtPageNum(vpn.0 + HUGE_PAGE_PAGES);
+                continue;
+            }
+            page_table.remap(vpn, flags);
         }
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        let mut skip_until = self.vpn_range.get_start();
         for &vpn in self.pages.keys() {
+            if vpn < skip_until {
+                continue;
+            }
+            if page_table.unmap_huge(vpn) {
+                skip_until = VirtPageNum(vpn.0 + HUGE_PAGE_PAGES);
+                continue;
+            }
             page_table.unmap(vpn);
         }
     }
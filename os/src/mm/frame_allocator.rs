@@ -1,12 +1,18 @@
 //! Implementation of [`FrameAllocator`] which
 //! controls all the frames in the operating system.
 use crate::fs::dentry::clean_dentry_cache;
+use crate::fs::page_cache::shrink_page_cache;
+use crate::mm::oom::out_of_memory;
 use crate::mutex::SpinNoIrqLock;
 use crate::utils::ceil_to_page_size;
-use crate::{arch::boards::qemu::MEMORY_END, arch::config::KERNEL_BASE};
-use alloc::vec::Vec;
+use crate::{
+    arch::boards::qemu::MEMORY_END,
+    arch::config::{HUGE_PAGE_PAGES, KERNEL_BASE},
+};
+use alloc::{collections::BTreeSet, vec::Vec};
 use core::fmt::{self, Debug, Formatter};
 use core::panic;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::*;
 use spin::Mutex;
 
@@ -46,102 +52,261 @@ trait FrameAllocator {
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn alloc_range(&mut self, n: usize) -> Option<PhysPageNum>;
     fn alloc_range_any(&mut self, n: usize) -> Option<Vec<PhysPageNum>>;
+    fn alloc_range_aligned(&mut self, n: usize, align: usize) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
 }
 
-/// an implementation for frame allocator
-pub struct StackFrameAllocator {
-    current: usize,
+/// 伙伴系统的最大阶, 最大的空闲块为 2^MAX_ORDER 个页帧(4M)
+pub const MAX_ORDER: usize = 10;
+
+/// 目前只有单核, 只有一个per-CPU页缓存
+const NR_CPUS: usize = 1;
+/// per-CPU页缓存一次从伙伴系统批量取出/归还的页帧数
+const PCP_BATCH: usize = 16;
+/// per-CPU页缓存中的页帧超过该值时归还给伙伴系统
+const PCP_HIGH: usize = PCP_BATCH * 4;
+
+fn cpu_id() -> usize {
+    0
+}
+
+/// 向上取整到2的幂次, 返回阶数
+fn order_of(n: usize) -> usize {
+    n.next_power_of_two().trailing_zeros() as usize
+}
+
+/// per-CPU的单页缓存, 减少单页分配/释放时对伙伴系统的操作
+#[derive(Default)]
+struct PerCpuPages {
+    pages: Vec<usize>,
+}
+
+/// 剩余页帧是否低于low水位线, 每次分配和释放后更新, 缺页等路径读取时不需要加锁
+static MEMORY_PRESSURE: AtomicBool = AtomicBool::new(false);
+
+/// 伙伴系统页帧分配器, 只有一个Normal区域
+/// `free_lists[order]`中保存空闲块的起始页帧号, 块按 2^order 对齐
+pub struct BuddyFrameAllocator {
+    start: usize,
     end: usize,
-    recycled: Vec<usize>,
+    free_lists: [BTreeSet<usize>; MAX_ORDER + 1],
+    /// 伙伴系统中空闲的页帧数(不包括per-CPU缓存)
+    nr_free: usize,
+    pcp: [PerCpuPages; NR_CPUS],
+    /// 水位线, 空闲页帧低于`low`时认为内存紧张, 分配不能使空闲页帧低于`min`
+    watermark_min: usize,
+    watermark_low: usize,
+    watermark_high: usize,
+    /// OOM受害者的分配可以使用min水位线以下的保留页帧
+    use_reserve: bool,
 }
 
-impl StackFrameAllocator {
+impl BuddyFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
+        self.start = l.0;
         self.end = r.0;
+        self.free_range(l.0, r.0);
+        let total = self.total();
+        self.watermark_min = total / 128;
+        self.watermark_low = self.watermark_min * 5 / 4;
+        self.watermark_high = self.watermark_min * 3 / 2;
+        self.update_pressure();
+    }
+    /// 剩余可分配的页帧数
+    pub fn available(&self) -> usize {
+        self.nr_free + self.pcp.iter().map(|pcp| pcp.pages.len()).sum::<usize>()
+    }
+    /// 可分配的页帧总数
+    pub fn total(&self) -> usize {
+        self.end - self.start
+    }
+    /// 剩余页帧低于low水位线
+    pub fn below_low_watermark(&self) -> bool {
+        self.available() < self.watermark_low
+    }
+    fn update_pressure(&self) {
+        MEMORY_PRESSURE.store(self.below_low_watermark(), Ordering::Relaxed);
+    }
+    /// 回收到high水位线还需要的页帧数
+    pub fn pages_to_high_watermark(&self) -> usize {
+        self.watermark_high.saturating_sub(self.available())
+    }
+    /// 各阶空闲块的数量, 用于/proc/buddyinfo
+    pub fn buddyinfo(&self) -> [usize; MAX_ORDER + 1] {
+        let mut info = [0; MAX_ORDER + 1];
+        for (order, list) in self.free_lists.iter().enumerate() {
+            info[order] = list.len();
+        }
+        info
     }
     pub fn info(&self) {
         println!(
-            "[StackFrameAllocator] current: {:#x}, end: {:#x}, recycled len: {}, available: {}",
-            self.current,
+            "[BuddyFrameAllocator] start: {:#x}, end: {:#x}, free: {}, pcp: {}, buddyinfo: {:?}",
+            self.start,
             self.end,
-            self.recycled.len(),
-            self.end - self.current + self.recycled.len()
+            self.nr_free,
+            self.available() - self.nr_free,
+            self.buddyinfo()
         );
     }
+    /// 分配后需要保留的页帧数
+    fn reserved(&self) -> usize {
+        if self.use_reserve {
+            0
+        } else {
+            self.watermark_min
+        }
+    }
+    /// 把[l, r)切分成尽量大的对齐块放入空闲链表
+    fn free_range(&mut self, l: usize, r: usize) {
+        let mut ppn = l;
+        while ppn < r {
+            let mut order = (ppn.trailing_zeros() as usize).min(MAX_ORDER);
+            while ppn + (1 << order) > r {
+                order -= 1;
+            }
+            self.free_order(ppn, order);
+            ppn += 1 << order;
+        }
+    }
+    /// 从伙伴系统分配 2^order 个连续页帧, 大块按需拆分
+    fn alloc_order(&mut self, order: usize) -> Option<usize> {
+        let found = (order..=MAX_ORDER).find(|&o| !self.free_lists[o].is_empty())?;
+        let ppn = self.free_lists[found].pop_first().unwrap();
+        // 拆分, 高半部分放回低一阶的空闲链表
+        for o in (order..found).rev() {
+            self.free_lists[o].insert(ppn + (1 << o));
+        }
+        self.nr_free -= 1 << order;
+        Some(ppn)
+    }
+    /// 释放 2^order 个连续页帧, 与空闲的伙伴块合并
+    fn free_order(&mut self, mut ppn: usize, mut order: usize) {
+        self.nr_free += 1 << order;
+        while order < MAX_ORDER {
+            let buddy = ppn ^ (1 << order);
+            if !self.free_lists[order].remove(&buddy) {
+                break;
+            }
+            ppn = ppn.min(buddy);
+            order += 1;
+        }
+        self.free_lists[order].insert(ppn);
+    }
+    /// 分配 2^order 个连续页帧
+    /// 失败时把per-CPU缓存归还给伙伴系统后重试
+    fn alloc_contiguous(&mut self, order: usize) -> Option<usize> {
+        if order > MAX_ORDER || self.available() < self.reserved() + (1 << order) {
+            return None;
+        }
+        if let Some(ppn) = self.alloc_order(order) {
+            return Some(ppn);
+        }
+        self.drain_pcp();
+        self.alloc_order(order)
+    }
+    /// 分配 2^order 个页帧后只保留前 n 个, 其余归还
+    fn alloc_contiguous_n(&mut self, n: usize, order: usize) -> Option<PhysPageNum> {
+        let ppn = self.alloc_contiguous(order)?;
+        self.free_range(ppn + n, ppn + (1 << order));
+        Some(PhysPageNum(ppn))
+    }
+    fn drain_pcp(&mut self) {
+        for cpu in 0..NR_CPUS {
+            let pages = core::mem::take(&mut self.pcp[cpu].pages);
+            for ppn in pages {
+                self.free_order(ppn, 0);
+            }
+        }
+    }
 }
-impl FrameAllocator for StackFrameAllocator {
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
+            start: 0,
             end: 0,
-            recycled: Vec::new(),
+            free_lists: core::array::from_fn(|_| BTreeSet::new()),
+            nr_free: 0,
+            pcp: core::array::from_fn(|_| PerCpuPages::default()),
+            watermark_min: 0,
+            watermark_low: 0,
+            watermark_high: 0,
+            use_reserve: false,
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else if self.current == self.end {
-            None
-        } else {
-            self.current += 1;
-            Some((self.current - 1).into())
+        if self.available() <= self.reserved() {
+            return None;
         }
+        let cpu = cpu_id();
+        if self.pcp[cpu].pages.is_empty() {
+            // 从伙伴系统批量补充
+            for _ in 0..PCP_BATCH {
+                match self.alloc_order(0) {
+                    Some(ppn) => self.pcp[cpu].pages.push(ppn),
+                    None => break,
+                }
+            }
+        }
+        let result = self.pcp[cpu].pages.pop().map(PhysPageNum);
+        self.update_pressure();
+        result
     }
     fn alloc_range(&mut self, n: usize) -> Option<PhysPageNum> {
-        if self.current + n > self.end {
+        let result = self.alloc_contiguous_n(n, order_of(n));
+        self.update_pressure();
+        if result.is_none() {
             println!(
-                "[StackFrameAllocator] alloc_range failed, current: {:#x}, end: {:#x}, n: {}",
-                self.current, self.end, n
+                "[BuddyFrameAllocator] alloc_range failed, free: {}, n: {}, buddyinfo: {:?}",
+                self.available(),
+                n,
+                self.buddyinfo()
             );
-            return None; // not enough space
         }
-        let start = self.current;
-        self.current += n;
-        return Some(PhysPageNum(start));
+        result
     }
     /// 分配 n 个任意的页帧（允许不连续）
     fn alloc_range_any(&mut self, n: usize) -> Option<Vec<PhysPageNum>> {
-        let mut result = Vec::with_capacity(n);
-
-        // 尽量从 recycled 拿
-        while !self.recycled.is_empty() && result.len() < n {
-            result.push(self.recycled.pop().unwrap().into());
-        }
-
-        // 还需要额外分配
-        while self.current < self.end && result.len() < n {
-            self.current += 1;
-            result.push((self.current - 1).into());
-        }
-
-        if result.len() == n {
-            Some(result)
-        } else {
-            // 回滚
-            for ppn in result {
-                self.dealloc(ppn);
-            }
+        if self.available() < self.reserved() + n {
             println!(
-                "[StackFrameAllocator] alloc_range_any failed, current: {:#x}, end: {:#x}, n: {}",
-                self.current, self.end, n
+                "[BuddyFrameAllocator] alloc_range_any failed, free: {}, n: {}",
+                self.available(),
+                n
             );
-            None
+            return None;
         }
+        Some((0..n).map(|_| self.alloc().unwrap()).collect())
+    }
+    /// 分配连续的n个页帧, 起始页帧号按`align`对齐, 用于大页
+    fn alloc_range_aligned(&mut self, n: usize, align: usize) -> Option<PhysPageNum> {
+        let result = self.alloc_contiguous_n(n, order_of(n).max(order_of(align)));
+        self.update_pressure();
+        result
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
+        let cpu = cpu_id();
         // validity check
-        if ppn >= self.current || self.recycled.iter().any(|&v| v == ppn) {
+        if ppn < self.start
+            || ppn >= self.end
+            || self.free_lists[0].contains(&ppn)
+            || self.pcp[cpu].pages.contains(&ppn)
+        {
             panic!("Frame ppn={:#x} has not been allocated!", ppn);
         }
-        // recycle
-        self.recycled.push(ppn);
+        self.pcp[cpu].pages.push(ppn);
+        if self.pcp[cpu].pages.len() > PCP_HIGH {
+            // 归还一批给伙伴系统, 让其可以合并
+            let pages: Vec<usize> = self.pcp[cpu].pages.drain(..PCP_BATCH).collect();
+            for ppn in pages {
+                self.free_order(ppn, 0);
+            }
+        }
+        self.update_pressure();
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 lazy_static! {
     /// frame allocator instance through lazy_static!
@@ -166,9 +331,73 @@ pub fn init_frame_allocator() {
     );
 }
 
+/// 内存不足时一次回收的页缓存数量
+const RECLAIM_BATCH: usize = 32;
+
+/// 内存不足时回收页缓存后重试分配
+/// 分配成功但空闲页帧低于low水位线时, 回收页缓存直到high水位线
+/// 注意`f`返回后才会释放FRAME_ALLOCATOR的锁, 回收时会释放页帧
+fn alloc_with_reclaim<T>(
+    nr_pages: usize,
+    f: impl Fn(&mut FrameAllocatorImpl) -> Option<T>,
+) -> Option<T> {
+    let (result, nr_to_reclaim) = {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        let result = f(&mut allocator);
+        let nr_to_reclaim = if allocator.below_low_watermark() {
+            allocator.pages_to_high_watermark()
+        } else {
+            0
+        };
+        (result, nr_to_reclaim)
+    };
+    if result.is_some() {
+        if nr_to_reclaim > 0 {
+            shrink_page_cache(nr_to_reclaim.min(RECLAIM_BATCH));
+        }
+        return result;
+    }
+    log::warn!(
+        "[alloc_with_reclaim] out of frames, shrink page cache for {} pages",
+        nr_pages
+    );
+    shrink_page_cache(nr_pages.max(RECLAIM_BATCH));
+    let result = f(&mut FRAME_ALLOCATOR.lock());
+    if result.is_some() {
+        return result;
+    }
+    // 回收页缓存后仍然不足, 杀死一个进程
+    let use_reserve = out_of_memory(nr_pages);
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    allocator.use_reserve = use_reserve;
+    let result = f(&mut allocator);
+    allocator.use_reserve = false;
+    result
+}
+
+/// 剩余可分配的页帧数
+pub fn frame_available() -> usize {
+    FRAME_ALLOCATOR.lock().available()
+}
+
+/// 可分配的页帧总数
+pub fn frame_total() -> usize {
+    FRAME_ALLOCATOR.lock().total()
+}
+
+/// 剩余页帧低于low水位线时认为内存紧张
+pub fn memory_pressure() -> bool {
+    MEMORY_PRESSURE.load(Ordering::Relaxed)
+}
+
+/// 各阶空闲块的数量
+pub fn frame_buddyinfo() -> [usize; MAX_ORDER + 1] {
+    FRAME_ALLOCATOR.lock().buddyinfo()
+}
+
 /// allocate a frame, 实现了Drop, 会自动清理
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR.lock().alloc().map(FrameTracker::new)
+    alloc_with_reclaim(1, |allocator| allocator.alloc()).map(FrameTracker::new)
 }
 
 /// 由调用者负责清理
@@ -184,7 +413,7 @@ pub fn frame_alloc_ppn() -> PhysPageNum {
     //         .expect("frame alloc failed after clean dentry cache");
     //     ppn
     // }
-    FRAME_ALLOCATOR.lock().alloc().unwrap()
+    alloc_with_reclaim(1, |allocator| allocator.alloc()).expect("[frame_alloc_ppn] out of memory")
 }
 
 /// 分配连续的 n 个 frame
@@ -192,8 +421,16 @@ pub fn frame_alloc_range(n: usize) -> Option<PhysPageNum> {
     FRAME_ALLOCATOR.lock().alloc_range(n)
 }
 
+/// 分配一个大页的连续页帧, 起始页帧号按大页对齐
+/// 连续页帧不足时返回None, 由调用者回退到4K页
+pub fn frame_alloc_huge() -> Option<PhysPageNum> {
+    FRAME_ALLOCATOR
+        .lock()
+        .alloc_range_aligned(HUGE_PAGE_PAGES, HUGE_PAGE_PAGES)
+}
+
 pub fn frame_alloc_range_any(n: usize) -> Option<Vec<PhysPageNum>> {
-    FRAME_ALLOCATOR.lock().alloc_range_any(n)
+    alloc_with_reclaim(n, |allocator| allocator.alloc_range_any(n))
 }
 
 /// deallocate a frame
@@ -224,39 +461,7 @@ pub fn frame_allocator_test() {
 /// 如果无法分配连续的 n 页，返回 None
 pub fn kbuf_alloc(size: usize) -> Option<PhysPageNum> {
     let n = ceil_to_page_size(size) / crate::arch::config::PAGE_SIZE;
-    let mut allocator = FRAME_ALLOCATOR.lock();
-    let mut temp = Vec::new();
-
-    let mut count = 0;
-    let mut base = None;
-
-    while let Some(ppn) = allocator.alloc() {
-        if base.is_none() {
-            base = Some(ppn.0);
-            count = 1;
-        } else if Some(ppn.0) == base.map(|b| b + count) {
-            count += 1;
-        } else {
-            // 非连续，回收之前分配的
-            for p in temp.drain(..) {
-                allocator.dealloc(p);
-            }
-            base = Some(ppn.0);
-            count = 1;
-        }
-
-        temp.push(ppn);
-
-        if count == n {
-            return base.map(PhysPageNum);
-        }
-    }
-
-    // 分配失败，回收
-    for p in temp {
-        allocator.dealloc(p);
-    }
-    None
+    FRAME_ALLOCATOR.lock().alloc_range(n)
 }
 
 /// 回收一段连续内核缓冲区
@@ -0,0 +1,269 @@
+//! KSM(Kernel Same-page Merging)
+//!
+//! 内核线程`ksmd`周期扫描MADV_MERGEABLE的私有匿名页, 将内容相同的页合并为一个只读的KSM页,
+//! 写入时通过缺页处理的COW路径复制出私有页.
+//! stable树保存已经合并的KSM页, unstable树保存本轮扫描中见过一次的候选页,
+//! 每轮扫描结束后清空unstable树
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::{Mutex, Once, RwLock};
+
+use crate::{
+    task::{for_each_task, kthread_should_stop, kthread_sleep, kthread_spawn, wakeup, Task, Tid},
+    timer::TimeSpec,
+};
+
+use super::{MemorySet, Page, VPNRange, VirtPageNum};
+
+pub const KSM_RUN_STOP: usize = 0;
+pub const KSM_RUN_MERGE: usize = 1;
+pub const KSM_RUN_UNMERGE: usize = 2;
+
+/// /sys/kernel/mm/ksm/run: 0停止, 1合并, 2拆分所有KSM页并停止
+pub static KSM_RUN: AtomicUsize = AtomicUsize::new(KSM_RUN_STOP);
+/// 每次唤醒扫描的页数
+pub static KSM_PAGES_TO_SCAN: AtomicUsize = AtomicUsize::new(100);
+/// 两次扫描之间的睡眠时间(毫秒)
+pub static KSM_SLEEP_MILLISECS: AtomicUsize = AtomicUsize::new(20);
+/// 完成的完整扫描轮数
+pub static KSM_FULL_SCANS: AtomicUsize = AtomicUsize::new(0);
+
+/// ksmd停止时的睡眠时间(秒)
+const KSMD_IDLE_SECS: usize = 3600;
+
+static KSMD: Once<Arc<Task>> = Once::new();
+
+struct KsmState {
+    /// 校验和 -> 已合并的KSM页
+    stable: BTreeMap<u64, Vec<Weak<Page>>>,
+    /// 校验和 -> 本轮扫描中的候选页所在的地址空间和页号
+    unstable: BTreeMap<u64, (Weak<RwLock<MemorySet>>, VirtPageNum)>,
+    /// 下一次扫描开始的(tgid, vpn)
+    cursor: (Tid, VirtPageNum),
+}
+
+static KSM_STATE: Mutex<KsmState> = Mutex::new(KsmState {
+    stable: BTreeMap::new(),
+    unstable: BTreeMap::new(),
+    cursor: (0, VirtPageNum(0)),
+});
+
+/// 启动ksmd
+pub fn init_ksm() {
+    KSMD.call_once(|| kthread_spawn("ksmd", ksmd));
+}
+
+/// 修改run或扫描参数后唤醒ksmd
+pub fn wakeup_ksmd() {
+    if let Some(ksmd) = KSMD.get() {
+        wakeup(ksmd.tid());
+    }
+}
+
+fn page_bytes(page: &Page) -> &'static [u8] {
+    page.ppn().get_bytes_array()
+}
+
+/// FNV-1a, 只用于快速查找, 合并前还要比较完整内容
+fn page_checksum(page: &Page) -> u64 {
+    page_bytes(page)
+        .chunks_exact(8)
+        .fold(0xcbf29ce484222325u64, |hash, word| {
+            (hash ^ u64::from_ne_bytes(word.try_into().unwrap())).wrapping_mul(0x100000001b3)
+        })
+}
+
+fn same_content(a: &Page, b: &Page) -> bool {
+    core::ptr::eq(a, b) || page_bytes(a) == page_bytes(b)
+}
+
+/// 扫描`memory_set`中的一页, 返回是否合并
+fn scan_page(
+    state: &mut KsmState,
+    ms: &Arc<RwLock<MemorySet>>,
+    guard: &mut MemorySet,
+    vpn: VirtPageNum,
+) -> bool {
+    let Some(page) = guard.ksm_candidate(vpn) else {
+        return false;
+    };
+    if page.is_ksm() {
+        return false;
+    }
+    let checksum = page_checksum(&page);
+
+    // 先在stable树中查找已经合并的页
+    if let Some(kpage) = state.stable.get(&checksum).and_then(|kpages| {
+        kpages
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|kpage| same_content(kpage, &page))
+    }) {
+        guard.ksm_merge_page(vpn, &kpage);
+        return true;
+    }
+
+    // 再在unstable树中查找, 命中时将候选页提升为KSM页
+    if let Some((other_ms, other_vpn)) = state.unstable.get(&checksum).cloned() {
+        let merged = match other_ms.upgrade() {
+            Some(other_ms) if Arc::ptr_eq(&other_ms, ms) => {
+                other_vpn != vpn
+                    && merge_with(guard, other_vpn, &page).is_some_and(|kpage| {
+                        guard.ksm_merge_page(vpn, &kpage);
+                        true
+                    })
+            }
+            Some(other_ms) => match other_ms.try_write() {
+                Some(mut other) => merge_with(&mut other, other_vpn, &page).is_some_and(|kpage| {
+                    guard.ksm_merge_page(vpn, &kpage);
+                    true
+                }),
+                None => false,
+            },
+            None => false,
+        };
+        if merged {
+            state.unstable.remove(&checksum);
+            let kpage = guard.ksm_candidate(vpn).unwrap();
+            state
+                .stable
+                .entry(checksum)
+                .or_default()
+                .push(Arc::downgrade(&kpage));
+            return true;
+        }
+    }
+    state.unstable.insert(checksum, (Arc::downgrade(ms), vpn));
+    false
+}
+
+/// 内容仍然与`page`相同时, 将`ms`中`other_vpn`处的候选页写保护并提升为KSM页
+fn merge_with(ms: &mut MemorySet, other_vpn: VirtPageNum, page: &Arc<Page>) -> Option<Arc<Page>> {
+    let kpage = ms.ksm_candidate(other_vpn)?;
+    if kpage.is_ksm() || !same_content(&kpage, page) {
+        return None;
+    }
+    kpage.set_ksm();
+    ms.ksm_merge_page(other_vpn, &kpage);
+    Some(kpage)
+}
+
+/// 扫描最多`nr_pages`页
+pub fn ksm_scan(nr_pages: usize) {
+    let mut state = KSM_STATE.lock();
+    let mut tasks: Vec<Arc<Task>> = for_each_task(|task| task.clone())
+        .into_iter()
+        .filter(|task| task.is_process() && !task.is_kthread() && !task.is_zombie())
+        .collect();
+    tasks.sort_by_key(|task| task.tgid());
+
+    let (cursor_tgid, cursor_vpn) = state.cursor;
+    let mut remaining = nr_pages;
+    let mut merged = 0;
+    for task in tasks.iter().filter(|task| task.tgid() >= cursor_tgid) {
+        let start = if task.tgid() == cursor_tgid {
+            cursor_vpn
+        } else {
+            VirtPageNum(0)
+        };
+        let ms = task.memory_set();
+        let Some(mut guard) = ms.try_write() else {
+            continue;
+        };
+        let vpns = guard.ksm_mergeable_vpns(start, remaining);
+        for vpn in vpns.iter() {
+            if scan_page(&mut state, &ms, &mut guard, *vpn) {
+                merged += 1;
+            }
+        }
+        remaining -= vpns.len();
+        if remaining == 0 {
+            let next = VirtPageNum(vpns.last().unwrap().0 + 1);
+            state.cursor = (task.tgid(), next);
+            log::debug!(
+                "[ksm_scan] merged {} pages, pause at {:?}",
+                merged,
+                state.cursor
+            );
+            return;
+        }
+    }
+
+    // 完成一轮扫描
+    state.unstable.clear();
+    state.stable.retain(|_, kpages| {
+        kpages.retain(|kpage| kpage.strong_count() > 0);
+        !kpages.is_empty()
+    });
+    state.cursor = (0, VirtPageNum(0));
+    let full_scans = KSM_FULL_SCANS.fetch_add(1, Ordering::Relaxed) + 1;
+    log::debug!(
+        "[ksm_scan] full scan {} done, merged {} pages",
+        full_scans,
+        merged
+    );
+}
+
+/// run=2: 将所有KSM页复制为私有页
+pub fn ksm_unmerge_all() {
+    let mut state = KSM_STATE.lock();
+    let mut count = 0;
+    for task in for_each_task(|task| task.clone())
+        .iter()
+        .filter(|task| task.is_process() && !task.is_kthread())
+    {
+        match task.memory_set().try_write() {
+            Some(mut ms) => {
+                count += ms.ksm_unmerge(VPNRange::new(VirtPageNum(0), VirtPageNum(usize::MAX)))
+            }
+            None => log::warn!(
+                "[ksm_unmerge_all] task{} memory set is locked, skip",
+                task.tid()
+            ),
+        }
+    }
+    state.stable.clear();
+    state.unstable.clear();
+    state.cursor = (0, VirtPageNum(0));
+    log::info!("[ksm_unmerge_all] unmerged {} pages", count);
+}
+
+/// 返回(pages_shared, pages_sharing, pages_unshared)
+pub fn ksm_stat() -> (usize, usize, usize) {
+    let state = KSM_STATE.lock();
+    let mut shared = 0;
+    let mut sharing = 0;
+    for kpage in state.stable.values().flatten() {
+        // stable树只持有弱引用, 强引用数就是映射数
+        if kpage.strong_count() > 0 {
+            shared += 1;
+            sharing += kpage.strong_count().saturating_sub(1);
+        }
+    }
+    (shared, sharing, state.unstable.len())
+}
+
+fn ksmd() -> i32 {
+    while !kthread_should_stop() {
+        let sleep = if KSM_RUN.load(Ordering::Relaxed) == KSM_RUN_MERGE {
+            ksm_scan(KSM_PAGES_TO_SCAN.load(Ordering::Relaxed));
+            let ms = KSM_SLEEP_MILLISECS.load(Ordering::Relaxed);
+            TimeSpec {
+                sec: ms / 1000,
+                nsec: ms % 1000 * 1_000_000,
+            }
+        } else {
+            TimeSpec {
+                sec: KSMD_IDLE_SECS,
+                nsec: 0,
+            }
+        };
+        kthread_sleep(sleep);
+    }
+    0
+}
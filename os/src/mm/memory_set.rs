@@ -11,8 +11,8 @@ use crate::{
     fs::{fdtable::FdFlags, file::OpenFlags},
     futex::futex::SharedMappingInfo,
     mm::{
-        area::{MapPermission, MapType},
-        frame_alloc, FrameTracker, PhysAddr, PhysPageNum, VirtAddr, VirtPageNum,
+        area::{MapAdvice, MapPermission, MapType, READAHEAD_PAGES},
+        frame_alloc, memory_pressure, FrameTracker, PhysAddr, PhysPageNum, VirtAddr, VirtPageNum,
     },
     syscall::errno::{Errno, SyscallRet},
     task::current_task,
@@ -20,10 +20,15 @@ use crate::{
 
 use crate::{
     arch::{
-        config::{MMAP_MIN_ADDR, PAGE_SIZE_BITS, USER_STACK_SIZE},
+        config::{HUGE_PAGE_PAGES, HUGE_PAGE_SIZE, MMAP_MIN_ADDR, PAGE_SIZE_BITS, USER_STACK_SIZE},
         trap::PageFaultCause,
     },
-    fs::{file::FileOp, namei::path_openat},
+    fs::{
+        file::FileOp,
+        namei::path_openat,
+        uapi::FallocFlags,
+        userfaultfd::{UffdMode, UserfaultFd, UFFD_PAGEFAULT_FLAG_WP, UFFD_PAGEFAULT_FLAG_WRITE},
+    },
     mm::Page,
     task::aux::*,
     utils::ceil_to_page_size,
@@ -48,7 +53,7 @@ use crate::{
     mutex::SpinNoIrqLock,
     task::aux::AuxHeader,
 };
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use lazy_static::lazy_static;
 
 #[allow(unused)]
@@ -103,6 +108,12 @@ pub struct MemorySet {
     /// System V shared memory
     /// shm_start_address -> shmid
     pub addr2shmid: BTreeMap<usize, usize>,
+    /// mlockall(MCL_FUTURE): 之后新建的映射都被锁定
+    pub mlock_future: bool,
+    /// mlockall(MCL_FUTURE | MCL_ONFAULT): 之后新建的锁定映射不预先分配
+    pub mlock_onfault: bool,
+    /// execve时压入用户栈的辅助向量, 用于core文件的NT_AUXV
+    pub saved_auxv: Vec<AuxHeader>,
 }
 
 #[cfg(target_arch = "riscv64")]
@@ -120,6 +131,9 @@ impl MemorySet {
             page_table,
             areas: BTreeMap::new(),
             addr2shmid: BTreeMap::new(),
+            mlock_future: false,
+            mlock_onfault: false,
+            saved_auxv: Vec::new(),
         }
     }
 }
@@ -134,6 +148,9 @@ impl MemorySet {
             page_table: PageTable::new(),
             areas: BTreeMap::new(),
             addr2shmid: BTreeMap::new(),
+            mlock_future: false,
+            mlock_onfault: false,
+            saved_auxv: Vec::new(),
         }
     }
 
@@ -575,32 +592,13 @@ impl MemorySet {
     /// return (user_memory_set, satp, ustack_top, entry_point, aux_vec, Option<tls>)
     /// Todo: elf_data是完整的, 还要lazy_allocation?
     pub fn from_elf_lazily(
-        mut elf_file: Arc<dyn FileOp>,
-        argv: &mut Vec<String>,
+        elf_file: Arc<dyn FileOp>,
     ) -> Result<(Self, usize, usize, usize, Vec<AuxHeader>), Errno> {
         #[cfg(target_arch = "riscv64")]
         let mut memory_set = Self::from_global();
         #[cfg(target_arch = "loongarch64")]
         let mut memory_set = Self::new_bare();
 
-        // 判断加载的是否为.sh文件
-        let mut sh_head = vec![0u8; 2];
-        elf_file.pread(&mut sh_head, 0)?;
-
-        // 处理 .sh 文件
-        if argv.len() > 0 {
-            let file_name = &argv[0];
-            // 文件后缀是.sh或者file_data是#!开头
-            if file_name.ends_with(".sh") || sh_head.starts_with(b"#!") || file_name == "/tmp/hello"
-            {
-                let prepend_args = vec![String::from("/musl/busybox"), String::from("sh")];
-                argv.splice(0..0, prepend_args);
-                if let Ok(busybox) = path_openat("/musl/busybox", OpenFlags::empty(), AT_FDCWD, 0) {
-                    elf_file = busybox;
-                }
-            }
-        }
-
         // 为了每次仅读取load段中的内容，因此以下全部改为手动解析
         let mut elf_head = vec![0u8; 64];
         elf_file.pread(&mut elf_head, 0)?;
@@ -985,14 +983,21 @@ impl MemorySet {
                 area.map_perm
             );
         });
-        let memory_set = MemorySet {
+        let mut memory_set = MemorySet {
             brk: user_memory_set.brk,
             heap_bottom: user_memory_set.heap_bottom,
             mmap_start: user_memory_set.mmap_start,
             page_table,
             areas: user_memory_set.areas.clone(),
             addr2shmid: user_memory_set.addr2shmid.clone(),
+            // 内存锁不被子进程继承
+            mlock_future: false,
+            mlock_onfault: false,
+            saved_auxv: user_memory_set.saved_auxv.clone(),
         };
+        memory_set.mlock_all(false);
+        memory_set.userfault_clear();
+        memory_set.apply_fork_advice();
         memory_set
     }
 }
@@ -1061,6 +1066,11 @@ impl MemorySet {
         self.mmap_start += aligned_size;
         VPNRange::new(start_vpn, end_vpn)
     }
+    /// 分配起始地址按`align`字节对齐的区间, used by MAP_HUGETLB, SHM_HUGETLB
+    pub fn get_unmapped_area_aligned(&mut self, size: usize, align: usize) -> VPNRange {
+        self.mmap_start = (self.mmap_start + align - 1) & !(align - 1);
+        self.get_unmapped_area(size)
+    }
     pub fn translate_va_to_pa(&self, va: VirtAddr) -> Option<usize> {
         self.page_table.translate_va_to_pa(va)
     }
@@ -1075,11 +1085,20 @@ impl MemorySet {
         self.areas.clear();
     }
     // 返回值表示是否有区域被remap
+    /// 区域在`range`的边界处被拆分时, 先拆分边界所在的大页, 保证一个大页只属于一个区域
+    fn split_huge_at_boundary(&mut self, range: VPNRange) {
+        for vpn in [range.get_start(), range.get_end()] {
+            if vpn.0 % HUGE_PAGE_PAGES != 0 {
+                self.page_table.split_huge_at(vpn);
+            }
+        }
+    }
     pub fn remap_area_with_overlap(
         &mut self,
         remap_vpn_range: VPNRange,
         new_perm: MapPermission,
     ) -> bool {
+        self.split_huge_at_boundary(remap_vpn_range);
         let mut found = false;
         // 用于存放拆分出来的区域, 最后添加到areas中
         let mut split_new_areas: Vec<MapArea> = Vec::new();
@@ -1139,6 +1158,7 @@ impl MemorySet {
     }
     // 返回值表示是否有区域被删除
     pub fn remove_area_with_overlap(&mut self, unmap_vpn_range: VPNRange) -> bool {
+        self.split_huge_at_boundary(unmap_vpn_range);
         let mut found = false;
         // 用于存放拆分出来的区域, 最后添加到filebe_areas中
         let mut split_new_areas: Vec<MapArea> = Vec::new();
@@ -1163,9 +1183,8 @@ impl MemorySet {
                 // 调整区域
                 if unmap_start <= old_vpn_start && unmap_end >= old_vpn_end {
                     // `unmap_vpn_range` 完全覆盖 `vpn_range`，删除 `area`
-                    for vpn in area.vpn_range {
-                        area.dealloc_one_page(&mut self.page_table, vpn);
-                    }
+                    let vpn_range = area.vpn_range;
+                    area.dealloc_pages(&mut self.page_table, vpn_range);
                     // 记录要删除的区域
                     areas_to_remove.push(*vpn);
                 } else if unmap_start <= old_vpn_start {
@@ -1205,6 +1224,21 @@ impl MemorySet {
         found
     }
 
+    /// `range`是否完全被映射
+    pub fn is_range_mapped(&self, range: VPNRange) -> bool {
+        let mut next = range.get_start();
+        for (_, area) in self.areas.range(..range.get_end()) {
+            if area.vpn_range.get_end() <= next {
+                continue;
+            }
+            if area.vpn_range.get_start() > next {
+                return false;
+            }
+            next = area.vpn_range.get_end();
+        }
+        next >= range.get_end()
+    }
+
     // 这里从尾部开始找, 因为在MemorySet中, 内核栈一般在最后
     // used by `kstack drop trait`
     // 由调用者保证area的存在
@@ -1228,9 +1262,7 @@ impl MemorySet {
                 // 懒分配
             } else {
                 let dealloc_vpn_range = VPNRange::new(new_end_vpn, old_end_vpn);
-                for vpn in dealloc_vpn_range {
-                    area.dealloc_one_page(&mut self.page_table, vpn);
-                }
+                area.dealloc_pages(&mut self.page_table, dealloc_vpn_range);
             }
             area.vpn_range.set_end(new_end_vpn);
             return;
@@ -1242,6 +1274,611 @@ impl MemorySet {
     }
 }
 
+/// madvise
+impl MemorySet {
+    /// 对`range`内的区域执行`f`, `range`只覆盖区域的一部分时先拆分区域
+    /// 返回值表示是否有区域被修改
+    fn split_and_apply(&mut self, range: VPNRange, f: impl Fn(&mut MapArea)) -> bool {
+        self.split_huge_at_boundary(range);
+        let mut found = false;
+        // 用于存放拆分出来的区域, 最后添加到areas中
+        let mut split_new_areas: Vec<MapArea> = Vec::new();
+        let range_start = range.get_start();
+        let range_end = range.get_end();
+        for (_vpn, area) in self.areas.range_mut(..range_end).rev() {
+            if !area.vpn_range.is_intersect_with(&range) {
+                break;
+            }
+            let old_vpn_start = area.vpn_range.get_start();
+            let old_vpn_end = area.vpn_range.get_end();
+            if range_start <= old_vpn_start && range_end >= old_vpn_end {
+                // 完全覆盖
+                f(area);
+            } else if range_start <= old_vpn_start {
+                // 覆盖了前部分
+                let new_area = area.split2(range_end);
+                f(area);
+                split_new_areas.push(new_area);
+            } else if range_end >= old_vpn_end {
+                // 覆盖了后部分
+                let mut new_area = area.split2(range_start);
+                f(&mut new_area);
+                split_new_areas.push(new_area);
+            } else {
+                // 区域被拆成三部分
+                let (mut applied_area, new_area) = area.split_in3(range_start, range_end);
+                f(&mut applied_area);
+                split_new_areas.push(new_area);
+                split_new_areas.push(applied_area);
+            }
+            found = true;
+        }
+        self.areas.extend(
+            split_new_areas
+                .into_iter()
+                .map(|area| (area.vpn_range.get_start(), area)),
+        );
+        found
+    }
+    /// 设置`range`内区域的访问模式
+    pub fn madvise_set_advice(
+        &mut self,
+        range: VPNRange,
+        set: MapAdvice,
+        clear: MapAdvice,
+    ) -> bool {
+        self.split_and_apply(range, |area| {
+            area.advice.remove(clear);
+            area.advice.insert(set);
+        })
+    }
+    /// MADV_DONTNEED
+    /// 匿名共享映射的页不能在缺页时重新找到, 只读文件映射直接使用页缓存, 这两种区域跳过
+    pub fn madvise_dontneed(&mut self, range: VPNRange) -> SyscallRet {
+        let range_end = range.get_end();
+        if self
+            .areas
+            .range(..range_end)
+            .any(|(_, area)| area.vpn_range.is_intersect_with(&range) && area.locked)
+        {
+            return Err(Errno::EINVAL);
+        }
+        for (_, area) in self.areas.range_mut(..range_end) {
+            let Some(intersection) = area.vpn_range.intersection(&range) else {
+                continue;
+            };
+            if (area.is_shared() && area.backend_file.is_none())
+                || matches!(area.map_type, MapType::FilebeRO | MapType::Linear)
+            {
+                log::warn!(
+                    "[madvise_dontneed] skip area {:?}, map_type: {:?}",
+                    area.vpn_range,
+                    area.map_type
+                );
+                continue;
+            }
+            area.dontneed(&mut self.page_table, intersection);
+        }
+        Ok(0)
+    }
+    /// MADV_FREE, 只能用于私有匿名映射
+    pub fn madvise_free(&mut self, range: VPNRange) -> SyscallRet {
+        let range_end = range.get_end();
+        if self.areas.range(..range_end).any(|(_, area)| {
+            area.vpn_range.is_intersect_with(&range)
+                && (area.locked || !area.is_private_anonymous())
+        }) {
+            return Err(Errno::EINVAL);
+        }
+        self.split_huge_at_boundary(range);
+        for (_, area) in self.areas.range_mut(..range_end) {
+            if let Some(intersection) = area.vpn_range.intersection(&range) {
+                area.lazyfree(&mut self.page_table, intersection);
+            }
+        }
+        // 已经内存紧张时直接释放
+        if memory_pressure() {
+            self.reclaim_lazyfree();
+        }
+        Ok(0)
+    }
+    /// `range`内已映射的区域是否都是私有匿名映射
+    pub fn is_range_private_anonymous(&self, range: VPNRange) -> bool {
+        self.areas
+            .range(..range.get_end())
+            .filter(|(_, area)| area.vpn_range.is_intersect_with(&range))
+            .all(|(_, area)| area.is_private_anonymous())
+    }
+    /// MADV_WILLNEED, 将文件映射的页预读到页缓存
+    pub fn madvise_willneed(&self, range: VPNRange) -> SyscallRet {
+        for (_, area) in self.areas.range(..range.get_end()) {
+            if let Some(intersection) = area.vpn_range.intersection(&range) {
+                if matches!(area.map_type, MapType::Filebe | MapType::FilebeRO) {
+                    area.readahead(
+                        intersection.get_start(),
+                        intersection.get_end().0 - intersection.get_start().0,
+                    );
+                }
+            }
+        }
+        Ok(0)
+    }
+    /// MADV_REMOVE, 只能用于可写的共享映射, 释放后备存储中对应的部分
+    /// 文件映射在文件中打洞, 匿名共享映射没有独立的后备存储, 直接将页清零
+    pub fn madvise_remove(&mut self, range: VPNRange) -> SyscallRet {
+        let range_end = range.get_end();
+        for (_, area) in self.areas.range(..range_end) {
+            if !area.vpn_range.is_intersect_with(&range) {
+                continue;
+            }
+            if area.locked || !area.is_shared() {
+                return Err(Errno::EINVAL);
+            }
+            if !area.map_perm.contains(MapPermission::W) {
+                return Err(Errno::EACCES);
+            }
+        }
+        self.split_huge_at_boundary(range);
+        for (_, area) in self.areas.range_mut(..range_end) {
+            let Some(intersection) = area.vpn_range.intersection(&range) else {
+                continue;
+            };
+            match area.backend_file.clone() {
+                Some(file) => {
+                    let offset = area.offset
+                        + (intersection.get_start().0 - area.vpn_range.get_start().0) * PAGE_SIZE;
+                    let len =
+                        (intersection.get_end().0 - intersection.get_start().0) * PAGE_SIZE;
+                    file.fallocate(
+                        FallocFlags::PUNCH_HOLE | FallocFlags::KEEP_SIZE,
+                        offset,
+                        len,
+                    )?;
+                    area.dealloc_pages(&mut self.page_table, intersection);
+                }
+                None => {
+                    for (_, page) in area
+                        .pages
+                        .range(intersection.get_start()..intersection.get_end())
+                    {
+                        page.ppn().get_bytes_array().fill(0);
+                    }
+                }
+            }
+        }
+        Ok(0)
+    }
+    /// fork时处理MADV_DONTFORK和MADV_WIPEONFORK:
+    /// DONTFORK的区域不出现在子进程中, WIPEONFORK的区域在子进程中重新从零页开始
+    fn apply_fork_advice(&mut self) {
+        let dontfork: Vec<VirtPageNum> = self
+            .areas
+            .iter()
+            .filter(|(_, area)| area.advice.contains(MapAdvice::DONTFORK))
+            .map(|(vpn, _)| *vpn)
+            .collect();
+        for vpn in dontfork {
+            if let Some(mut area) = self.areas.remove(&vpn) {
+                let vpn_range = area.vpn_range;
+                area.dealloc_pages(&mut self.page_table, vpn_range);
+                self.addr2shmid.remove(&(vpn.0 << PAGE_SIZE_BITS));
+            }
+        }
+        for (_, area) in self.areas.iter_mut() {
+            if area.advice.contains(MapAdvice::WIPEONFORK) {
+                let vpn_range = area.vpn_range;
+                area.dealloc_pages(&mut self.page_table, vpn_range);
+            }
+        }
+    }
+    /// 释放所有MADV_FREE之后没有被写过的页, 返回释放的页数
+    pub fn reclaim_lazyfree(&mut self) -> usize {
+        let mut count = 0;
+        for (_, area) in self.areas.iter_mut() {
+            if !area.lazyfree.is_empty() {
+                count += area.reclaim_lazyfree(&mut self.page_table);
+            }
+        }
+        if count > 0 {
+            log::warn!("[reclaim_lazyfree] freed {} lazyfree pages", count);
+        }
+        count
+    }
+}
+
+/// mlock
+impl MemorySet {
+    /// 被锁定的页数, 即/proc/<pid>/status中的VmLck
+    pub fn locked_pages(&self) -> usize {
+        self.areas
+            .values()
+            .filter(|area| area.locked)
+            .map(|area| area.vpn_range.get_end().0 - area.vpn_range.get_start().0)
+            .sum()
+    }
+    /// `range`内还没有被锁定的页数
+    pub fn unlocked_pages_in(&self, range: VPNRange) -> usize {
+        self.areas
+            .range(..range.get_end())
+            .filter(|(_, area)| !area.locked)
+            .filter_map(|(_, area)| area.vpn_range.intersection(&range))
+            .map(|intersection| intersection.get_end().0 - intersection.get_start().0)
+            .sum()
+    }
+    /// 用户地址空间的总页数, 用于mlockall检查RLIMIT_MEMLOCK
+    pub fn total_pages(&self) -> usize {
+        self.areas
+            .values()
+            .map(|area| area.vpn_range.get_end().0 - area.vpn_range.get_start().0)
+            .sum()
+    }
+    /// 锁定或解锁`range`内的区域, 必要时拆分区域
+    /// 锁定的区域不能再被MADV_DONTNEED/MADV_FREE释放, lazyfree的页也不再被回收
+    pub fn mlock_range(&mut self, range: VPNRange, locked: bool) -> bool {
+        self.split_and_apply(range, |area| {
+            area.locked = locked;
+            if locked {
+                area.lazyfree.clear();
+            }
+        })
+    }
+    /// 锁定或解锁所有区域, used by `mlockall`, `munlockall`
+    pub fn mlock_all(&mut self, locked: bool) {
+        for area in self.areas.values_mut() {
+            area.locked = locked;
+            if locked {
+                area.lazyfree.clear();
+            }
+        }
+    }
+    /// 预先分配`range`内被锁定区域的页, 之后访问不会再缺页
+    pub fn populate_locked(&mut self, range: VPNRange) {
+        let ranges: Vec<VPNRange> = self
+            .areas
+            .range(..range.get_end())
+            .filter(|(_, area)| {
+                area.locked
+                    && !matches!(area.map_type, MapType::FilebeRO | MapType::Linear)
+                    && area
+                        .map_perm
+                        .intersects(MapPermission::R | MapPermission::W | MapPermission::X)
+            })
+            .filter_map(|(_, area)| area.vpn_range.intersection(&range))
+            .collect();
+        for populate_range in ranges {
+            if let Err(e) = self.pre_handle_cow_and_lazy_alloc(populate_range) {
+                // 例如文件映射超出了文件末尾, 与Linux一致不返回错误
+                log::warn!(
+                    "[populate_locked] failed to populate {:?}: {:?}",
+                    populate_range,
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// oom
+impl MemorySet {
+    /// 常驻内存的页数, 分别为匿名页, 文件页和共享匿名页
+    pub fn rss_counters(&self) -> (usize, usize, usize) {
+        let (mut anon, mut file, mut shmem) = (0, 0, 0);
+        for area in self.areas.values() {
+            if area.map_type == MapType::Linear {
+                continue;
+            }
+            if area.backend_file.is_some() {
+                file += area.pages.len();
+            } else if area.is_shared() {
+                shmem += area.pages.len();
+            } else {
+                anon += area.pages.len();
+            }
+        }
+        (anon, file, shmem)
+    }
+    /// 常驻内存的页数, 即/proc/<pid>/status中的VmRSS
+    pub fn rss_pages(&self) -> usize {
+        let (anon, file, shmem) = self.rss_counters();
+        anon + file + shmem
+    }
+    /// 释放被OOM杀死的进程的私有匿名页, 不等待其退出, 返回释放的页数
+    /// 区域保留, 进程在退出前再次访问时重新缺页
+    pub fn oom_reap(&mut self) -> usize {
+        let mut count = 0;
+        for area in self.areas.values_mut() {
+            if !area.is_private_anonymous() {
+                continue;
+            }
+            count += area.pages.len();
+            let range = area.vpn_range;
+            area.dontneed(&mut self.page_table, range);
+        }
+        count
+    }
+}
+
+/// ksm
+impl MemorySet {
+    /// 可以被KSM合并的页: MADV_MERGEABLE的私有匿名区域中已经映射的4K页
+    pub fn ksm_candidate(&self, vpn: VirtPageNum) -> Option<Arc<Page>> {
+        let (_, area) = self.areas.range(..=vpn).next_back()?;
+        if !area.vpn_range.contains_vpn(vpn)
+            || !area.advice.contains(MapAdvice::MERGEABLE)
+            || !area.is_private_anonymous()
+            || area.lazyfree.contains(&vpn)
+            || self.page_table.is_huge_mapped(vpn)
+        {
+            return None;
+        }
+        let page = area.pages.get(&vpn)?;
+        self.page_table
+            .translate_vpn_to_pte(vpn)
+            .filter(|pte| pte.is_valid())
+            .map(|_| page.clone())
+    }
+    /// 从`start`开始最多`max`个可以被KSM合并的页号
+    pub fn ksm_mergeable_vpns(&self, start: VirtPageNum, max: usize) -> Vec<VirtPageNum> {
+        self.areas
+            .values()
+            .filter(|area| {
+                area.advice.contains(MapAdvice::MERGEABLE) && area.is_private_anonymous()
+            })
+            .flat_map(|area| area.pages.range(start..).map(|(vpn, _)| *vpn))
+            .take(max)
+            .collect()
+    }
+    /// 将`vpn`改为只读映射到KSM页`kpage`, 由调用者保证`vpn`是`ksm_candidate`且内容相同
+    pub fn ksm_merge_page(&mut self, vpn: VirtPageNum, kpage: &Arc<Page>) {
+        let (_, area) = self.areas.range_mut(..=vpn).next_back().unwrap();
+        let pte = self.page_table.find_pte(vpn).unwrap();
+        let mut flags = pte.flags();
+        if area
+            .map_perm
+            .intersects(MapPermission::W | MapPermission::COW)
+        {
+            flags.remove(PTEFlags::W);
+            flags.insert(PTEFlags::COW);
+            #[cfg(target_arch = "loongarch64")]
+            flags.remove(PTEFlags::D);
+        }
+        *pte = PageTableEntry::new(kpage.ppn(), flags);
+        unsafe {
+            sfence_vma_vaddr(vpn.0 << PAGE_SIZE_BITS);
+        }
+        area.pages.insert(vpn, kpage.clone());
+    }
+    /// MADV_UNMERGEABLE: 将`range`内的KSM页复制为私有页, 返回复制的页数
+    pub fn ksm_unmerge(&mut self, range: VPNRange) -> usize {
+        let mut count = 0;
+        for (_, area) in self.areas.range_mut(..range.get_end()) {
+            let Some(intersection) = area.vpn_range.intersection(&range) else {
+                continue;
+            };
+            let vpns: Vec<VirtPageNum> = area
+                .pages
+                .range(intersection.get_start()..intersection.get_end())
+                .filter(|(_, page)| page.is_ksm())
+                .map(|(vpn, _)| *vpn)
+                .collect();
+            for vpn in vpns {
+                let Some(pte) = self.page_table.find_pte(vpn) else {
+                    continue;
+                };
+                let page = Page::new_framed(None);
+                page.ppn()
+                    .get_bytes_array()
+                    .copy_from_slice(pte.ppn().get_bytes_array());
+                let mut flags = pte.flags();
+                if flags.contains(PTEFlags::COW) {
+                    flags.remove(PTEFlags::COW);
+                    flags.insert(PTEFlags::W);
+                    #[cfg(target_arch = "loongarch64")]
+                    flags.insert(PTEFlags::D);
+                }
+                *pte = PageTableEntry::new(page.ppn(), flags);
+                unsafe {
+                    sfence_vma_vaddr(vpn.0 << PAGE_SIZE_BITS);
+                }
+                area.pages.insert(vpn, Arc::new(page));
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// userfaultfd
+impl MemorySet {
+    /// UFFDIO_REGISTER: 范围内必须都是私有匿名区域, 且没有注册到其他userfaultfd
+    pub fn userfault_register(
+        &mut self,
+        range: VPNRange,
+        uffd: &Weak<UserfaultFd>,
+        mode: UffdMode,
+    ) -> SyscallRet {
+        if !self.is_range_mapped(range) {
+            return Err(Errno::EINVAL);
+        }
+        for (_, area) in self.areas.range(..range.get_end()) {
+            if !area.vpn_range.is_intersect_with(&range) {
+                continue;
+            }
+            if !area.is_private_anonymous() {
+                return Err(Errno::EINVAL);
+            }
+            if let Some((other, _)) = &area.userfault {
+                if other.strong_count() > 0 && !other.ptr_eq(uffd) {
+                    return Err(Errno::EBUSY);
+                }
+            }
+        }
+        self.split_and_apply(range, |area| area.userfault = Some((uffd.clone(), mode)));
+        Ok(0)
+    }
+    /// UFFDIO_UNREGISTER, 同时解除范围内的写保护
+    pub fn userfault_unregister(&mut self, range: VPNRange, uffd: &UserfaultFd) -> SyscallRet {
+        if !self.is_range_mapped(range) {
+            return Err(Errno::EINVAL);
+        }
+        self.userfault_writeprotect_inner(range, false);
+        self.split_and_apply(range, |area| {
+            if area
+                .userfault
+                .as_ref()
+                .is_some_and(|(ctx, _)| core::ptr::eq(ctx.as_ptr(), uffd))
+            {
+                area.userfault = None;
+            }
+        });
+        Ok(0)
+    }
+    /// fork的子进程不继承userfaultfd的注册, 写保护的页改为写时复制
+    fn userfault_clear(&mut self) {
+        for (_, area) in self.areas.iter_mut() {
+            area.userfault = None;
+            let writable = area
+                .map_perm
+                .intersects(MapPermission::W | MapPermission::COW);
+            for vpn in core::mem::take(&mut area.uffd_wp) {
+                if let Some(pte) = self.page_table.find_pte(vpn).filter(|_| writable) {
+                    let mut flags = pte.flags();
+                    flags.insert(PTEFlags::COW);
+                    *pte = PageTableEntry::new(pte.ppn(), flags);
+                }
+            }
+        }
+    }
+    /// 缺页是否交给userfaultfd处理, 返回userfaultfd和uffd_msg中的flags
+    fn userfault(
+        &self,
+        vpn: VirtPageNum,
+        cause: &PageFaultCause,
+    ) -> Option<(Arc<UserfaultFd>, u64)> {
+        let (_, area) = self.areas.range(..=vpn).next_back()?;
+        if !area.vpn_range.contains_vpn(vpn) {
+            return None;
+        }
+        let (uffd, mode) = area.userfault.as_ref()?;
+        let uffd = uffd.upgrade()?;
+        let write = *cause == PageFaultCause::STORE;
+        if mode.contains(UffdMode::MISSING) && !area.pages.contains_key(&vpn) {
+            let flags = if write { UFFD_PAGEFAULT_FLAG_WRITE } else { 0 };
+            return Some((uffd, flags));
+        }
+        if mode.contains(UffdMode::WP) && write && area.uffd_wp.contains(&vpn) {
+            return Some((uffd, UFFD_PAGEFAULT_FLAG_WRITE | UFFD_PAGEFAULT_FLAG_WP));
+        }
+        None
+    }
+    /// UFFDIO_COPY/UFFDIO_ZEROPAGE: 将`page`安装到注册区域中还没有页的`vpn`
+    pub fn userfault_install(
+        &mut self,
+        vpn: VirtPageNum,
+        page: Page,
+        wp: bool,
+        uffd: &UserfaultFd,
+    ) -> SyscallRet {
+        let (_, area) = self
+            .areas
+            .range_mut(..=vpn)
+            .next_back()
+            .ok_or(Errno::ENOENT)?;
+        if !area.vpn_range.contains_vpn(vpn)
+            || !area
+                .userfault
+                .as_ref()
+                .is_some_and(|(ctx, _)| core::ptr::eq(ctx.as_ptr(), uffd))
+        {
+            return Err(Errno::ENOENT);
+        }
+        if area.pages.contains_key(&vpn) {
+            return Err(Errno::EEXIST);
+        }
+        let mut pte_flags = PTEFlags::from(area.map_perm);
+        if wp {
+            pte_flags.remove(PTEFlags::W);
+            #[cfg(target_arch = "loongarch64")]
+            pte_flags.remove(PTEFlags::D);
+            area.uffd_wp.insert(vpn);
+        }
+        self.page_table.map(vpn, page.ppn(), pte_flags);
+        area.pages.insert(vpn, Arc::new(page));
+        Ok(0)
+    }
+    /// UFFDIO_WRITEPROTECT: 范围必须注册了WP模式
+    pub fn userfault_writeprotect(
+        &mut self,
+        range: VPNRange,
+        protect: bool,
+        uffd: &UserfaultFd,
+    ) -> SyscallRet {
+        for (_, area) in self.areas.range(..range.get_end()) {
+            if area.vpn_range.is_intersect_with(&range)
+                && !area.userfault.as_ref().is_some_and(|(ctx, mode)| {
+                    core::ptr::eq(ctx.as_ptr(), uffd) && mode.contains(UffdMode::WP)
+                })
+            {
+                return Err(Errno::ENOENT);
+            }
+        }
+        self.userfault_writeprotect_inner(range, protect);
+        Ok(0)
+    }
+    /// 写保护只作用于已经存在的页
+    /// 解除写保护时, 与其他地址空间共享的页(fork, KSM)恢复为COW
+    fn userfault_writeprotect_inner(&mut self, range: VPNRange, protect: bool) {
+        for (_, area) in self.areas.range_mut(..range.get_end()) {
+            let Some(intersection) = area.vpn_range.intersection(&range) else {
+                continue;
+            };
+            let vpns: Vec<VirtPageNum> = area
+                .pages
+                .range(intersection.get_start()..intersection.get_end())
+                .map(|(vpn, _)| *vpn)
+                .filter(|vpn| protect != area.uffd_wp.contains(vpn))
+                .collect();
+            for vpn in vpns {
+                // 写保护按4K页记录, 大页需要先拆分; 解除保护的页都已经是4K映射
+                if protect {
+                    self.page_table.split_huge_at(vpn);
+                }
+                let Some(pte) = self.page_table.find_pte(vpn) else {
+                    continue;
+                };
+                let mut flags = pte.flags();
+                if protect {
+                    flags.remove(PTEFlags::W);
+                    #[cfg(target_arch = "loongarch64")]
+                    flags.remove(PTEFlags::D);
+                    area.uffd_wp.insert(vpn);
+                } else {
+                    area.uffd_wp.remove(&vpn);
+                    if !area
+                        .map_perm
+                        .intersects(MapPermission::W | MapPermission::COW)
+                        || flags.contains(PTEFlags::COW)
+                    {
+                        continue;
+                    }
+                    let page = area.pages.get(&vpn).unwrap();
+                    if Arc::strong_count(page) == 1 && !page.is_ksm() {
+                        flags.insert(PTEFlags::W);
+                        #[cfg(target_arch = "loongarch64")]
+                        flags.insert(PTEFlags::D);
+                    } else {
+                        flags.insert(PTEFlags::COW);
+                    }
+                }
+                *pte = PageTableEntry::new(pte.ppn(), flags);
+                unsafe {
+                    sfence_vma_vaddr(vpn.0 << PAGE_SIZE_BITS);
+                }
+            }
+        }
+    }
+}
+
 /// 操纵mmap_area的方法
 impl MemorySet {
     // used by Futex
@@ -1314,6 +1951,9 @@ impl MemorySet {
         const STACK_GUARD_GAP_PAGES: usize = 256; // 栈保护页的间距, 例如256页
         log::trace!("[handle_lazy_allocation_area]");
         let vpn = va.floor();
+        if memory_pressure() {
+            self.reclaim_lazyfree();
+        }
         if let Some((_, area)) = self.areas.range_mut(..=vpn).next_back() {
             if area.vpn_range.contains_vpn(vpn) {
                 if area.map_type == MapType::Filebe {
@@ -1344,6 +1984,9 @@ impl MemorySet {
                             self.page_table.map(vpn, ppn, pte_flags);
                             // 增加页的引用计数
                             area.pages.insert(va.floor(), page);
+                            if area.advice.contains(MapAdvice::SEQUENTIAL) {
+                                area.readahead(VirtPageNum(vpn.0 + 1), READAHEAD_PAGES);
+                            }
                             // 刷新tlb
                             return Ok(());
                         } else {
@@ -1372,6 +2015,9 @@ impl MemorySet {
                             self.page_table.map(vpn, ppn, pte_flags);
                             // 增加页的引用计数
                             area.pages.insert(va.floor(), Arc::new(new_page));
+                            if area.advice.contains(MapAdvice::SEQUENTIAL) {
+                                area.readahead(VirtPageNum(vpn.0 + 1), READAHEAD_PAGES);
+                            }
                             return Ok(());
                         } else {
                             // 如果没有找到对应的页, 则说明文件映射的偏移不合法
@@ -1429,11 +2075,20 @@ impl MemorySet {
                             }
                         } else {
                             // 批处理
-                            let max_alloc_page = 4;
+                            // MADV_RANDOM的区域不批量分配
+                            let max_alloc_page = if area.advice.contains(MapAdvice::RANDOM) {
+                                1
+                            } else {
+                                4
+                            };
                             let start_vpn = vpn.0 + 1;
                             let end_vpn = area.vpn_range.get_end().0.min(vpn.0 + max_alloc_page);
-                            let pages = &mut area.pages;
                             let pte_flags = PTEFlags::from(area.map_perm);
+                            // MADV_HUGEPAGE/MAP_HUGETLB的区域先尝试整个大页
+                            if area.alloc_huge_page(&mut self.page_table, vpn, pte_flags) {
+                                return Ok(());
+                            }
+                            let pages = &mut area.pages;
 
                             // 分配一页
                             let page = Page::new_framed(None);
@@ -1571,8 +2226,19 @@ impl MemorySet {
                 return Err(Errno::EFAULT);
             }
 
+            // userfaultfd写保护的页需要由用户态解除保护, 内核写入时不能绕过
+            let next_vpn = core::cmp::min(area.vpn_range.get_end(), end_vpn);
+            if area.uffd_wp.range(current_vpn..next_vpn).next().is_some() {
+                log::warn!(
+                    "[check_writable_vpn_range] write to userfaultfd write-protected page in {:#x}..{:#x}",
+                    current_vpn.0,
+                    next_vpn.0
+                );
+                return Err(Errno::EFAULT);
+            }
+
             // 更新 current_vpn，不超过 end_vpn
-            current_vpn = core::cmp::min(area.vpn_range.get_end(), end_vpn);
+            current_vpn = next_vpn;
 
             if current_vpn >= end_vpn {
                 break;
@@ -1776,6 +2442,14 @@ impl MemorySet {
         let end_vpn = vpn_range.get_end();
 
         while vpn < end_vpn {
+            // 大页不是COW时不需要处理, 直接跳到大页末尾; COW的大页需要逐页复制, 先拆分
+            if self.page_table.is_huge_mapped(vpn) {
+                if !self.page_table.translate_vpn_to_pte(vpn).unwrap().is_cow() {
+                    vpn = VirtPageNum((vpn.0 & !(HUGE_PAGE_PAGES - 1)) + HUGE_PAGE_PAGES);
+                    continue;
+                }
+                self.page_table.split_huge_at(vpn);
+            }
             // 尝试获取当前vpn所在的第三级页表数组
             let l3_idx = vpn.indexes()[2];
             let mut idx = l3_idx;
@@ -1788,8 +2462,11 @@ impl MemorySet {
                         // === 写时复制处理 ===
                         if let Some((_, area)) = self.areas.range_mut(..=vpn).next_back() {
                             if area.vpn_range.contains_vpn(vpn) {
+                                // MADV_FREE之后被写, 页需要保留
+                                area.lazyfree.remove(&vpn);
                                 let data_frame = area.pages.get(&vpn).unwrap();
-                                if Arc::strong_count(data_frame) == 1 {
+                                // KSM页可能随时被合并到其他地址空间, 总是复制
+                                if Arc::strong_count(data_frame) == 1 && !data_frame.is_ksm() {
                                     log::warn!(
                                     "[pre_handle_cow_and_lazy_alloc] arc strong count == 1, vpn: {:#x}",
                                     vpn.0
@@ -1826,6 +2503,17 @@ impl MemorySet {
                         // === 懒分配处理 ===
                         if let Some((_, area)) = self.areas.range_mut(..=vpn).next_back() {
                             if area.vpn_range.contains_vpn(vpn) {
+                                // MISSING模式注册的页由用户态通过UFFDIO_COPY/UFFDIO_ZEROPAGE填充,
+                                // 内核访问时不能直接分配零页
+                                if area.userfault.as_ref().is_some_and(|(uffd, mode)| {
+                                    mode.contains(UffdMode::MISSING) && uffd.strong_count() > 0
+                                }) {
+                                    log::warn!(
+                                        "[pre_handle_cow_and_lazy_alloc] vpn {:#x} is missing in userfaultfd range",
+                                        vpn.0
+                                    );
+                                    return Err(Errno::EFAULT);
+                                }
                                 let offset = area.offset
                                     + (vpn.0 - area.vpn_range.get_start().0) * PAGE_SIZE;
                                 if area.map_type == MapType::Filebe {
@@ -1876,15 +2564,23 @@ impl MemorySet {
     /// 处理可恢复的缺页异常
     /// 1. Cow区域
     /// 2. lazy allocation区域(目前只有file backend mmap area是lazy allocation)
+    /// 3. userfaultfd注册的区域, 返回Some, 调用者释放地址空间的锁后调用`wait_fault`
     #[no_mangle]
     pub fn handle_recoverable_page_fault(
         &mut self,
         va: VirtAddr,
         cause: PageFaultCause,
-    ) -> Result<(), Sig> {
+    ) -> Result<Option<Arc<UserfaultFd>>, Sig> {
         log::trace!("[handle_recoverable_page_fault]");
         let vpn = va.floor();
+        // userfaultfd注册的缺页和写保护优先交给用户态处理
+        if let Some((uffd, flags)) = self.userfault(vpn, &cause) {
+            uffd.handle_fault(va, flags);
+            return Ok(Some(uffd));
+        }
         let page_table = &mut self.page_table;
+        // 大页上的缺页只可能是fork后的COW, 按4K页复制, 先拆分
+        page_table.split_huge_at(vpn);
         if let Some(pte) = page_table.find_pte(vpn) {
             if pte.is_cow() {
                 log::error!(
@@ -1906,9 +2602,12 @@ impl MemorySet {
                 // debug!("handle cow page fault(cow), vpn {:#x}", vpn.0);
                 if let Some((_, area)) = self.areas.range_mut(..=vpn).next_back() {
                     if area.vpn_range.contains_vpn(vpn) {
+                        // MADV_FREE之后被写, 页需要保留
+                        area.lazyfree.remove(&vpn);
                         let data_frame = area.pages.get(&vpn).unwrap();
                         // 根据VPN找到对应的data_frame, 并查看Arc的引用计数
-                        if Arc::strong_count(data_frame) == 1 {
+                        // KSM页可能随时被合并到其他地址空间, 总是复制
+                        if Arc::strong_count(data_frame) == 1 && !data_frame.is_ksm() {
                             // 直接修改pte
                             // log::warn!("[handle_recoverable_page_fault] arc strong count == 1");
                             let mut flags = pte.flags();
@@ -1934,7 +2633,7 @@ impl MemorySet {
                         unsafe {
                             sfence_vma_vaddr(vpn.0 << PAGE_SIZE_BITS);
                         }
-                        return Ok(());
+                        return Ok(None);
                     }
                 }
                 log::info!("cow page fault recover failed");
@@ -1950,7 +2649,7 @@ impl MemorySet {
             // 页表中有对应的页表项, 但不是COW
             return Err(Sig::SIGSEGV);
         }
-        self.handle_lazy_allocation_area(va, cause)
+        self.handle_lazy_allocation_area(va, cause).map(|_| None)
         // 页表中没有对应的页表项, 也不是lazy allocation, 返回错误
     }
 }
@@ -1969,13 +2668,12 @@ impl MemorySet {
     ) -> usize {
         let shm_size = shm_segment.id.size;
         let vpn_range = if shmaddr == 0 {
-            // shmaddr == 0, 则由内核分配地址
-            let old_mmap_start = self.mmap_start;
-            self.mmap_start = self.mmap_start + shm_size;
-            VPNRange::new(
-                VirtAddr::from(old_mmap_start).floor(),
-                VirtAddr::from(old_mmap_start + shm_size).ceil(),
-            )
+            // shmaddr == 0, 则由内核分配地址, SHM_HUGETLB的段按大页对齐
+            if shm_segment.huge {
+                self.get_unmapped_area_aligned(shm_size, HUGE_PAGE_SIZE)
+            } else {
+                self.get_unmapped_area(shm_size)
+            }
         } else {
             VPNRange::new(
                 VirtAddr::from(shmaddr).floor(),
@@ -1983,13 +2681,20 @@ impl MemorySet {
             )
         };
         let mut map_area = MapArea::new(vpn_range, MapType::Framed, map_perm, None, 0, false);
+        if shm_segment.huge {
+            map_area.advice.insert(MapAdvice::HUGEPAGE);
+        }
         if shm_segment.pages.is_empty() {
-            for vpn in vpn_range {
-                let page = Arc::new(Page::new_framed(None));
-                self.page_table.map(vpn, page.ppn(), map_perm.into());
-                shm_segment.pages.push(Arc::downgrade(&page));
-                map_area.pages.insert(vpn, page);
+            if map_area.want_huge_page() {
+                map_area.alloc_pages_huge();
+            } else {
+                for vpn in vpn_range {
+                    map_area.pages.insert(vpn, Arc::new(Page::new_framed(None)));
+                }
             }
+            shm_segment
+                .pages
+                .extend(map_area.pages.values().map(Arc::downgrade));
         } else {
             debug_assert!(
                 shm_segment.pages.len() == vpn_range.get_end().0 - vpn_range.get_start().0
@@ -1998,10 +2703,11 @@ impl MemorySet {
                 let page = shm_segment.pages[vpn.0 - vpn_range.get_start().0]
                     .upgrade()
                     .unwrap();
-                self.page_table.map(vpn, page.ppn(), map_perm.into());
                 map_area.pages.insert(vpn, page);
             }
         }
+        // 物理地址连续的部分用大页映射
+        map_area.map_pages(&mut self.page_table, map_perm.into());
         self.areas.insert(vpn_range.get_start(), map_area);
         return vpn_range.get_start().0 * PAGE_SIZE;
     }
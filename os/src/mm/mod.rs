@@ -12,18 +12,20 @@ mod heap_allocator;
 mod address;
 mod area;
 mod frame_allocator;
+pub mod ksm;
 mod memory_set;
+pub mod oom;
 mod page;
 pub mod shm;
 
 pub use address::{PhysAddr, PhysPageNum, VPNRange, VirtAddr, VirtPageNum};
-pub use area::{MapArea, MapPermission, MapType};
+pub use area::{MapAdvice, MapArea, MapPermission, MapType};
 pub use frame_allocator::{
-    frame_alloc, frame_alloc_range, frame_dealloc, kbuf_alloc, kbuf_dealloc, FrameTracker,
-    FRAME_ALLOCATOR,
+    frame_alloc, frame_alloc_range, frame_available, frame_buddyinfo, frame_dealloc, frame_total,
+    kbuf_alloc, kbuf_dealloc, memory_pressure, FrameTracker, FRAME_ALLOCATOR, MAX_ORDER,
 };
 pub use memory_set::{MemorySet, KERNEL_SATP, KERNEL_SPACE};
-pub use page::{Page, PageKind};
+pub use page::{nr_dirty_pages, Page, PageKind};
 
 pub fn init() {
     heap_allocator::init_heap();
@@ -0,0 +1,257 @@
+//! OOM killer
+//! 页帧耗尽且回收页缓存后仍然无法分配时, 按常驻内存和oom_score_adj选择一个进程,
+//! 用SIGKILL杀死并回收其私有匿名内存. cgroup的内存用量超过memory.max时只在该cgroup内选择
+use core::sync::atomic::Ordering;
+
+use alloc::{sync::Arc, vec::Vec};
+use spin::Mutex;
+
+use crate::{
+    arch::config::PAGE_SIZE,
+    fs::page_cache::nr_page_cache,
+    signal::{SiField, Sig, SigInfo, SigSet},
+    task::{cgroup::Cgroup, current_task, for_each_task, get_task, Task, INIT_PROC_PID},
+};
+
+use super::frame_allocator::{frame_available, frame_total};
+
+pub const OOM_SCORE_ADJ_MIN: i32 = -1000;
+pub const OOM_SCORE_ADJ_MAX: i32 = 1000;
+
+/// 同一时间只处理一次OOM, 处理过程中(如回收受害者内存时拆分大页)的分配可以使用保留页帧
+static OOM_LOCK: Mutex<()> = Mutex::new(());
+
+/// 内核线程, init进程和僵尸进程不能被OOM杀死
+fn oom_unkillable(task: &Arc<Task>) -> bool {
+    task.is_kthread() || task.tgid() == INIT_PROC_PID || task.is_zombie()
+}
+
+/// 已经被杀死但还没有退出的任务
+fn is_oom_victim(task: &Arc<Task>) -> bool {
+    task.op_sig_pending_mut(|pending| pending.pending.contains(SigSet::SIGKILL))
+}
+
+/// 常驻内存页数, 地址空间的锁被持有时返回None
+fn task_rss(task: &Arc<Task>) -> Option<usize> {
+    task.memory_set().try_read().map(|ms| ms.rss_pages())
+}
+
+/// 常驻内存页数和其中不属于页缓存的页数(匿名页和共享匿名页), 地址空间的锁被持有时返回None
+fn task_rss_private(task: &Arc<Task>) -> Option<(usize, usize)> {
+    task.memory_set().try_read().map(|ms| {
+        let (anon, file, shmem) = ms.rss_counters();
+        (anon + file + shmem, anon + shmem)
+    })
+}
+
+/// OOM得分(页数), 越高越先被杀死, 不能被杀死时返回None
+/// oom_score_adj按总页帧数的千分比调整, -1000表示不能被杀死
+pub fn oom_badness(task: &Arc<Task>, rss: usize, totalpages: usize) -> Option<usize> {
+    let adj = task.oom_score_adj();
+    if oom_unkillable(task) || adj == OOM_SCORE_ADJ_MIN {
+        return None;
+    }
+    let points = rss as isize + adj as isize * totalpages as isize / 1000;
+    Some(points.max(1) as usize)
+}
+
+/// /proc/<pid>/oom_score, 将得分归一化到0~1000
+pub fn oom_score(task: &Arc<Task>) -> usize {
+    let totalpages = frame_total();
+    task_rss(task)
+        .and_then(|rss| oom_badness(task, rss, totalpages))
+        .map_or(0, |points| (points * 1000 / totalpages).min(1000))
+}
+
+/// 页帧耗尽时由`alloc_with_reclaim`调用, 调用时不能持有FRAME_ALLOCATOR的锁
+/// 返回true表示当前任务是受害者或正在处理OOM, 此时的分配可以使用min水位线以下的保留页帧,
+/// 让受害者完成当前的系统调用, 在返回用户态时处理SIGKILL退出
+pub fn out_of_memory(nr_pages: usize) -> bool {
+    let current = current_task();
+    if is_oom_victim(&current) {
+        return true;
+    }
+    let Some(_guard) = OOM_LOCK.try_lock() else {
+        return true;
+    };
+    let totalpages = frame_total();
+    let tasks: Vec<Arc<Task>> = for_each_task(|task| task.clone())
+        .into_iter()
+        .filter(|task| task.is_process() && !oom_unkillable(task) && !is_oom_victim(task))
+        .collect();
+
+    let mut candidates = Vec::new();
+    let mut accounted = 0;
+    let mut current_process = None;
+    for task in tasks.iter() {
+        match task_rss_private(task) {
+            Some((rss, private)) => {
+                // 文件页已经计入页缓存, 只累计不属于页缓存的页
+                accounted += private;
+                candidates.push((task.clone(), rss));
+            }
+            // 缺页处理中当前任务持有自己地址空间的写锁
+            None if task.compare_memset(&current) => current_process = Some(task.clone()),
+            None => log::warn!(
+                "[out_of_memory] task{} memory set is locked, skip",
+                task.tid()
+            ),
+        }
+    }
+    if let Some(task) = current_process {
+        // 无法读取当前进程的地址空间, 用不属于其他进程和页缓存的已用页帧估计
+        let used = totalpages - frame_available();
+        let rss = used.saturating_sub(accounted + nr_page_cache());
+        candidates.push((task, rss));
+    }
+
+    log::warn!(
+        "[out_of_memory] {} pages requested by task{}, free: {}, page cache: {}",
+        nr_pages,
+        current.tid(),
+        frame_available(),
+        nr_page_cache()
+    );
+    log::warn!("[  pid  ]   uid      rss oom_score_adj name");
+    for (task, rss) in candidates.iter() {
+        log::warn!(
+            "[{:>7}] {:>5} {:>8} {:>13} {}",
+            task.tid(),
+            task.uid(),
+            rss,
+            task.oom_score_adj(),
+            task.comm()
+        );
+    }
+
+    let Some((victim, rss, _)) = candidates
+        .iter()
+        .filter_map(|(task, rss)| oom_badness(task, *rss, totalpages).map(|p| (task, *rss, p)))
+        .max_by_key(|(_, _, points)| *points)
+    else {
+        log::error!("[out_of_memory] no killable process");
+        return false;
+    };
+
+    oom_kill_process(victim, rss, &tasks, &current, "Out of memory")
+}
+
+/// cgroup的内存用量超过memory.max时由`Cgroup::charge_memory`调用, 在该cgroup及其后代中选择受害者
+/// 返回true表示杀死了一个进程
+pub fn mem_cgroup_out_of_memory(memcg: &Arc<Cgroup>, nr_pages: usize) -> bool {
+    let current = current_task();
+    if is_oom_victim(&current) {
+        return false;
+    }
+    let Some(_guard) = OOM_LOCK.try_lock() else {
+        return false;
+    };
+    let tasks: Vec<Arc<Task>> = memcg
+        .subtree_tasks()
+        .into_iter()
+        .filter_map(get_task)
+        .filter(|task| task.is_process() && !oom_unkillable(task))
+        .collect();
+    // 上一个受害者还没有退出, 不再杀死其他进程
+    if tasks.iter().any(is_oom_victim) {
+        return false;
+    }
+    let totalpages = memcg.memory.max.load(Ordering::Relaxed).min(frame_total());
+
+    let mut candidates = Vec::new();
+    let mut accounted = 0;
+    let mut current_process = None;
+    for task in tasks.iter() {
+        match task_rss(task) {
+            Some(rss) => {
+                accounted += rss;
+                candidates.push((task.clone(), rss));
+            }
+            None if task.compare_memset(&current) => current_process = Some(task.clone()),
+            None => log::warn!(
+                "[mem_cgroup_out_of_memory] task{} memory set is locked, skip",
+                task.tid()
+            ),
+        }
+    }
+    if let Some(task) = current_process {
+        // 无法读取当前进程的地址空间, 用cgroup中不属于其他进程的用量估计
+        let usage = memcg.memory.current.load(Ordering::Relaxed);
+        candidates.push((task, usage.saturating_sub(accounted)));
+    }
+
+    log::warn!(
+        "[mem_cgroup_out_of_memory] {} pages requested by task{}, memcg: {}, usage: {}, limit: {}",
+        nr_pages,
+        current.tid(),
+        memcg.path(),
+        memcg.memory.current.load(Ordering::Relaxed),
+        memcg.memory.max.load(Ordering::Relaxed)
+    );
+    let Some((victim, rss, _)) = candidates
+        .iter()
+        .filter_map(|(task, rss)| oom_badness(task, *rss, totalpages).map(|p| (task, *rss, p)))
+        .max_by_key(|(_, _, points)| *points)
+    else {
+        log::error!("[mem_cgroup_out_of_memory] no killable process");
+        return false;
+    };
+    oom_kill_process(victim, rss, &tasks, &current, "Memory cgroup out of memory");
+    true
+}
+
+/// 杀死受害者和与其共享地址空间的进程, 受害者不在运行时直接回收其私有匿名内存
+/// 返回true表示受害者是当前进程
+fn oom_kill_process(
+    victim: &Arc<Task>,
+    rss: usize,
+    tasks: &[Arc<Task>],
+    current: &Arc<Task>,
+    reason: &str,
+) -> bool {
+    let (anon, file, shmem) = victim
+        .memory_set()
+        .try_read()
+        .map_or((rss, 0, 0), |ms| ms.rss_counters());
+    println!(
+        "{}: Killed process {} ({}) anon-rss:{}kB, file-rss:{}kB, shmem-rss:{}kB, UID:{} oom_score_adj:{}",
+        reason,
+        victim.tid(),
+        victim.comm(),
+        anon * PAGE_SIZE / 1024,
+        file * PAGE_SIZE / 1024,
+        shmem * PAGE_SIZE / 1024,
+        victim.uid(),
+        victim.oom_score_adj()
+    );
+    // 共享地址空间的进程(如vfork)一起杀死
+    let siginfo = SigInfo::new(
+        Sig::SIGKILL.raw(),
+        SigInfo::KERNEL,
+        SiField::Kill { tid: 0 },
+    );
+    for task in tasks.iter().filter(|task| task.compare_memset(victim)) {
+        task.receive_siginfo(siginfo, false);
+    }
+
+    if victim.compare_memset(current) {
+        return true;
+    }
+    // 受害者不在运行, 直接回收其私有匿名内存
+    match victim.memory_set().try_write() {
+        Some(mut memory_set) => {
+            let reaped = memory_set.oom_reap();
+            log::warn!(
+                "[oom_reaper] reaped process {} ({}), {} pages",
+                victim.tid(),
+                victim.comm(),
+                reaped
+            );
+        }
+        None => log::warn!(
+            "[oom_reaper] process {} memory set is locked, wait for it to exit",
+            victim.tid()
+        ),
+    }
+    false
+}
@@ -1,4 +1,8 @@
-use core::{alloc::Layout, ops::Add};
+use core::{
+    alloc::Layout,
+    ops::Add,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
 use alloc::{
     alloc::{alloc, dealloc},
@@ -8,10 +12,21 @@ use alloc::{
 use virtio_drivers::PAGE_SIZE;
 
 use crate::{
-    arch::config::{EXT4_MAX_INLINE_DATA, KERNEL_BASE},
+    arch::{
+        config::{EXT4_MAX_INLINE_DATA, HUGE_PAGE_PAGES, KERNEL_BASE},
+        timer::get_time_ms,
+    },
     drivers::block::{block_dev::BlockDevice, VIRTIO_BLOCK_SIZE},
     ext4::{self, extent_tree::Ext4Extent, fs::EXT4_BLOCK_SIZE, inode::Ext4Inode, MAX_FS_BLOCK_ID},
-    fs::{inode::InodeOp, FS_BLOCK_SIZE},
+    fs::{
+        inode::InodeOp,
+        page_cache::{dirty_add, dirty_del, page_cache_forget},
+        FS_BLOCK_SIZE,
+    },
+    task::{
+        cgroup::{nr_cgroups, Cgroup},
+        current_task,
+    },
 };
 
 use spin::RwLock;
@@ -21,7 +36,7 @@ use crate::mm::PhysPageNum;
 
 use super::{
     frame_alloc_range,
-    frame_allocator::{frame_alloc_ppn, frame_alloc_range_any},
+    frame_allocator::{frame_alloc_huge, frame_alloc_ppn, frame_alloc_range_any},
     frame_dealloc,
 };
 
@@ -40,6 +55,13 @@ impl PageKind {
     }
 }
 
+/// 系统中脏页缓存的数量
+static NR_DIRTY_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn nr_dirty_pages() -> usize {
+    NR_DIRTY_PAGES.load(Ordering::Relaxed)
+}
+
 pub struct ShreadPageInfo {
     // underlying block id
     start_block_id: usize,
@@ -47,18 +69,77 @@ pub struct ShreadPageInfo {
     inode: Weak<dyn InodeOp>,
     /// whether the page is dirty
     modified: bool,
+    /// 变脏的时间(ms), 用于dirty_expire_centisecs
+    dirtied_at: usize,
+}
+
+impl ShreadPageInfo {
+    fn set_dirty(&mut self, page: &Page) {
+        if !self.modified {
+            self.modified = true;
+            self.dirtied_at = get_time_ms();
+            NR_DIRTY_PAGES.fetch_add(1, Ordering::Relaxed);
+            dirty_add(page, self.dirtied_at);
+        }
+    }
+    fn clear_dirty(&mut self, page: &Page) {
+        if self.modified {
+            self.modified = false;
+            NR_DIRTY_PAGES.fetch_sub(1, Ordering::Relaxed);
+            dirty_del(page);
+        }
+    }
 }
 
 pub struct InlinePageInfo {
     inode: Weak<dyn InodeOp>,
     /// whether the page is dirty
     modified: bool,
+    /// 变脏的时间(ms), 用于dirty_expire_centisecs
+    dirtied_at: usize,
+}
+
+impl InlinePageInfo {
+    fn set_dirty(&mut self, page: &Page) {
+        if !self.modified {
+            self.modified = true;
+            self.dirtied_at = get_time_ms();
+            NR_DIRTY_PAGES.fetch_add(1, Ordering::Relaxed);
+            dirty_add(page, self.dirtied_at);
+        }
+    }
+    fn clear_dirty(&mut self, page: &Page) {
+        if self.modified {
+            self.modified = false;
+            NR_DIRTY_PAGES.fetch_sub(1, Ordering::Relaxed);
+            dirty_del(page);
+        }
+    }
 }
 
 // 页缓存中使用的页结构
 pub struct Page {
     vaddr: usize,
     page_kind: PageKind,
+    /// 最近是否被访问过, 页缓存回收时使用(second chance)
+    referenced: AtomicBool,
+    /// KSM合并后的只读页, 写时总是复制
+    ksm: AtomicBool,
+    /// 计费的memory cgroup, 页缓存和根cgroup中分配的页不计费
+    memcg: Option<Arc<Cgroup>>,
+}
+
+/// 向当前任务所在的cgroup计费`nr_pages`个页帧
+fn mem_cgroup_charge(nr_pages: usize) -> Option<Arc<Cgroup>> {
+    if nr_cgroups() == 0 {
+        return None;
+    }
+    let memcg = current_task().cgroup();
+    if memcg.is_root() {
+        return None;
+    }
+    memcg.charge_memory(nr_pages);
+    Some(memcg)
 }
 
 impl Page {
@@ -91,7 +172,11 @@ impl Page {
                     block_device: Arc::downgrade(&block_device),
                     inode,
                     modified: false,
+                    dirtied_at: 0,
                 })),
+                referenced: AtomicBool::new(false),
+                ksm: AtomicBool::new(false),
+                memcg: None,
             };
         };
     }
@@ -112,6 +197,9 @@ impl Page {
             return Self {
                 vaddr: vaddr as usize,
                 page_kind: PageKind::Framed,
+                referenced: AtomicBool::new(false),
+                ksm: AtomicBool::new(false),
+                memcg: mem_cgroup_charge(1),
             };
         }
     }
@@ -133,6 +221,7 @@ impl Page {
         //     })
         //     .collect()
         let frames = frame_alloc_range_any(n).expect("Failed to allocate frame range");
+        let memcg = mem_cgroup_charge(n);
         frames
             .into_iter()
             .map(|ppn| {
@@ -142,10 +231,36 @@ impl Page {
                 Arc::new(Page {
                     vaddr: vaddr as usize,
                     page_kind: PageKind::Framed,
+                    referenced: AtomicBool::new(false),
+                    ksm: AtomicBool::new(false),
+                    memcg: memcg.clone(),
                 })
             })
             .collect()
     }
+    /// 分配一个大页, 返回物理地址连续的HUGE_PAGE_PAGES个Framed页(全部清0)
+    /// 每个页仍单独持有自己的页帧, 大页映射被拆分后可以逐页释放
+    pub fn new_huge() -> Option<Vec<Arc<Page>>> {
+        let start_ppn = frame_alloc_huge()?;
+        let memcg = mem_cgroup_charge(HUGE_PAGE_PAGES);
+        Some(
+            (0..HUGE_PAGE_PAGES)
+                .map(|i| {
+                    let vaddr = ((start_ppn.0 + i) << PAGE_SIZE_BITS) + KERNEL_BASE;
+                    let buf =
+                        unsafe { core::slice::from_raw_parts_mut(vaddr as *mut u8, PAGE_SIZE) };
+                    buf.fill(0);
+                    Arc::new(Page {
+                        vaddr,
+                        page_kind: PageKind::Framed,
+                        referenced: AtomicBool::new(false),
+                        ksm: AtomicBool::new(false),
+                        memcg: memcg.clone(),
+                    })
+                })
+                .collect(),
+        )
+    }
     /// fs_block_id和inner_offset用于回写block cache
     pub fn new_inline(inode: Weak<dyn InodeOp>, inline_data: &[u8]) -> Self {
         unsafe {
@@ -162,7 +277,11 @@ impl Page {
                 page_kind: PageKind::Inline(RwLock::new(InlinePageInfo {
                     inode,
                     modified: false,
+                    dirtied_at: 0,
                 })),
+                referenced: AtomicBool::new(false),
+                ksm: AtomicBool::new(false),
+                memcg: None,
             };
         }
     }
@@ -231,7 +350,7 @@ impl Page {
                     assert!(offset + core::mem::size_of::<T>() <= PAGE_SIZE);
                     &mut *(addr as *mut T)
                 };
-                guard.modified = true;
+                guard.set_dirty(self);
                 f(ptr)
             }
             PageKind::Inline(info) => {
@@ -241,12 +360,57 @@ impl Page {
                     assert!(offset + core::mem::size_of::<T>() <= PAGE_SIZE);
                     &mut *(addr as *mut T)
                 };
-                guard.modified = true;
+                guard.set_dirty(self);
                 f(ptr)
             }
         }
     }
 
+    /// 标记页最近被访问过
+    #[inline(always)]
+    pub fn mark_accessed(&self) {
+        self.referenced.store(true, Ordering::Relaxed);
+    }
+    /// 返回页最近是否被访问过, 并清除访问标记
+    pub fn test_and_clear_referenced(&self) -> bool {
+        self.referenced.swap(false, Ordering::Relaxed)
+    }
+    pub fn is_ksm(&self) -> bool {
+        self.ksm.load(Ordering::Relaxed)
+    }
+    /// 标记为KSM页, 之后所有映射都是只读的
+    pub fn set_ksm(&self) {
+        self.ksm.store(true, Ordering::Relaxed);
+    }
+    pub fn is_dirty(&self) -> bool {
+        match &self.page_kind {
+            PageKind::Framed => false,
+            PageKind::Filebe(info) => info.read().modified,
+            PageKind::Inline(info) => info.read().modified,
+        }
+    }
+    /// 脏页变脏的时间(ms), 干净的页返回None
+    pub fn dirtied_at(&self) -> Option<usize> {
+        match &self.page_kind {
+            PageKind::Framed => None,
+            PageKind::Filebe(info) => {
+                let guard = info.read();
+                guard.modified.then_some(guard.dirtied_at)
+            }
+            PageKind::Inline(info) => {
+                let guard = info.read();
+                guard.modified.then_some(guard.dirtied_at)
+            }
+        }
+    }
+    /// 是否可以被页缓存回收: 只回收干净的Filebe页, inline页与inode的元数据绑定, 不回收
+    pub fn is_reclaimable(&self) -> bool {
+        match &self.page_kind {
+            PageKind::Filebe(info) => !info.read().modified,
+            _ => false,
+        }
+    }
+
     // Modify the cached data through the closure function f
     #[inline(always)]
     pub fn modify_private<T, V>(&self, offset: usize, f: impl FnOnce(&mut T) -> V) -> V {
@@ -259,7 +423,7 @@ impl Page {
     pub fn sync(&self) {
         match &self.page_kind {
             PageKind::Filebe(info) => {
-                let guard = info.write(); // 加写锁
+                let mut guard = info.write(); // 加写锁
                 if guard.modified {
                     // println!("[Page::modified]sync page: {:#x}", self.vaddr);
                     if let Some(block_device) = guard.block_device.upgrade() {
@@ -290,6 +454,12 @@ impl Page {
                                             ext4_inode.ext4_fs.upgrade().unwrap(),
                                         )
                                         .unwrap();
+                                    drop(inner);
+                                    // 空洞已经分配了实际的块, 之后的写回直接写入该块
+                                    guard.start_block_id = new_extent.physical_start_block()
+                                        * (*FS_BLOCK_SIZE / VIRTIO_BLOCK_SIZE);
+                                    block_device.write_blocks(guard.start_block_id, cache);
+                                    guard.clear_dirty(self);
                                 } else {
                                     log::error!(
                                     "[Page::sync] inode is not a Ext4Inode, cannot write back sparse hole"
@@ -302,6 +472,7 @@ impl Page {
                             }
                         } else {
                             block_device.write_blocks(guard.start_block_id, cache);
+                            guard.clear_dirty(self);
                         }
                     }
                 }
@@ -311,7 +482,7 @@ impl Page {
                     "[Page::sync] inline page synced directly, vaddr: {:#x}",
                     self.vaddr
                 );
-                let guard = info.write(); // 加写锁
+                let mut guard = info.write(); // 加写锁
                 let inode = guard.inode.upgrade();
                 if let Some(inode) = inode {
                     if let Some(ext4_inode) = inode.as_any().downcast_ref::<Ext4Inode>() {
@@ -333,6 +504,7 @@ impl Page {
                                     inline_page.get_ref(0);
                                 inner.inode_on_disk.block[0..inline_data.len()]
                                     .copy_from_slice(inline_data);
+                                guard.clear_dirty(self);
                                 // 注意这里不将inode_on_disk写回磁盘, 只更新了内存中的inode_on_disk
                             } else {
                                 log::error!(
@@ -357,8 +529,14 @@ impl Page {
 impl Drop for Page {
     fn drop(&mut self) {
         self.sync();
+        if !self.page_kind.is_private() {
+            page_cache_forget(self);
+        }
         // 释放内存
         let ppn = (self.vaddr - KERNEL_BASE) >> PAGE_SIZE_BITS;
         frame_dealloc(PhysPageNum(ppn));
+        if let Some(memcg) = &self.memcg {
+            memcg.uncharge_memory(1);
+        }
     }
 }
@@ -5,12 +5,11 @@ use core::{default, sync::atomic::AtomicUsize};
 use alloc::{sync::Weak, vec::Vec};
 use bitflags::bitflags;
 use hashbrown::HashMap;
-use lazy_static::lazy_static;
 use spin::{Mutex, RwLock};
 use virtio_drivers::PAGE_SIZE;
 
 use crate::{
-    arch::mm::copy_to_user,
+    arch::{config::HUGE_PAGE_SIZE, mm::copy_to_user},
     syscall::errno::{Errno, SyscallRet},
     task::{current_task, IdAllocator},
     timer::TimeSpec,
@@ -18,24 +17,22 @@ use crate::{
 
 use super::{MapPermission, Page};
 
-lazy_static! {
-    /// System V shared memory manager
-    static ref SHM_MANAGER: ShmManager = ShmManager::new();
-}
-
 pub struct ShmSegment {
     pub id: ShmId, // 共享内存的ID
     // 注意这里使用Weak引用, 因为共享内存的页生命周期是由用户决定的
     pub pages: Vec<Weak<Page>>, //
+    /// SHM_HUGETLB, 使用大页
+    pub huge: bool,
 }
 
 impl ShmSegment {
     /// 由上层调用者保证:
     ///     1. size是页对齐的
-    pub fn new(page_aligned_size: usize, tgid: usize) -> Self {
+    pub fn new(page_aligned_size: usize, tgid: usize, huge: bool) -> Self {
         Self {
             id: ShmId::new(page_aligned_size, tgid),
             pages: Vec::with_capacity(page_aligned_size / PAGE_SIZE),
+            huge,
         }
     }
     fn attach_update_id(&mut self, lprid: usize) {
@@ -93,6 +90,7 @@ impl ShmId {
     }
 }
 
+/// System V shared memory manager, 每个ipc命名空间一个
 pub struct ShmManager {
     pub segments: RwLock<HashMap<usize, ShmSegment>>, // 共享内存段
     shmid_allocator: Mutex<IdAllocator>,              // 共享内存ID分配器
@@ -109,6 +107,8 @@ bitflags! {
         /// 与 IPC_CREAT 一起使用，确保调用会创建新段。
         /// 如果段已存在，则调用失败
         const IPC_EXCL = 0o2000;
+        /// 使用大页, 段大小向上对齐到大页
+        const SHM_HUGETLB = 0o4000;
     }
 }
 
@@ -175,7 +175,8 @@ pub fn check_shm_segment_exist(
     shmflg: &ShmGetFlags,
 ) -> Result<usize, Errno> {
     debug_assert!(key != IPC_PRIVATE);
-    let shm_manager = SHM_MANAGER.segments.read();
+    let ipc_ns = current_task().ipc_ns();
+    let shm_manager = ipc_ns.shm.segments.read();
     if let Some(shm) = shm_manager.get(&key) {
         if shmflg.contains(ShmGetFlags::IPC_CREAT | ShmGetFlags::IPC_EXCL) {
             return Err(Errno::EEXIST);
@@ -189,15 +190,21 @@ pub fn check_shm_segment_exist(
 }
 
 // 返回值是shmid
-pub fn add_shm_segment(size: usize, tgid: usize, shmid: Option<usize>) -> usize {
-    let shm_seg = ShmSegment::new(size, tgid);
-    SHM_MANAGER.add_shmseg(shm_seg, shmid)
+pub fn add_shm_segment(size: usize, tgid: usize, shmid: Option<usize>, huge: bool) -> usize {
+    let shm_seg = ShmSegment::new(size, tgid, huge);
+    current_task().ipc_ns().shm.add_shmseg(shm_seg, shmid)
 }
 
 pub fn attach_shm_segment(shmid: usize, aligned_shmaddr: usize, shmflg: &ShmAtFlags) -> SyscallRet {
     let map_perm = MapPermission::from(shmflg);
-    if let Some(shm_seg) = SHM_MANAGER.segments.write().get_mut(&shmid) {
-        let task = current_task();
+    let task = current_task();
+    let ipc_ns = task.ipc_ns();
+    let mut segments = ipc_ns.shm.segments.write();
+    if let Some(shm_seg) = segments.get_mut(&shmid) {
+        // SHM_HUGETLB的段需要按大页对齐
+        if shm_seg.huge && aligned_shmaddr % HUGE_PAGE_SIZE != 0 {
+            return Err(Errno::EINVAL);
+        }
         let shm_start_address = task.op_memory_set_mut(|memory_set| {
             // 将共享内存段映射到进程的地址空间
             let ret = memory_set.attach_shm_segment(aligned_shmaddr, map_perm, shm_seg);
@@ -214,13 +221,14 @@ pub fn attach_shm_segment(shmid: usize, aligned_shmaddr: usize, shmflg: &ShmAtFl
 
 pub fn detach_shm_segment(shmaddr: usize) -> SyscallRet {
     let task = current_task();
+    let ipc_ns = task.ipc_ns();
     task.op_memory_set_mut(|memory_set| {
         let shmid = memory_set
             .addr2shmid
             .remove(&shmaddr)
             .ok_or(Errno::EINVAL)?;
         let mut should_remove: bool = false;
-        if let Some(shm_seg) = SHM_MANAGER.segments.write().get_mut(&shmid) {
+        if let Some(shm_seg) = ipc_ns.shm.segments.write().get_mut(&shmid) {
             // 解除映射
             memory_set.detach_shm_segment(shmaddr);
             // 更新共享内存段的引用计数
@@ -233,7 +241,7 @@ pub fn detach_shm_segment(shmaddr: usize) -> SyscallRet {
             return Err(Errno::EINVAL);
         }
         if should_remove {
-            SHM_MANAGER.segments.write().remove(&shmid).unwrap();
+            ipc_ns.shm.segments.write().remove(&shmid).unwrap();
         }
         Ok(0)
     })?;
@@ -241,7 +249,8 @@ pub fn detach_shm_segment(shmaddr: usize) -> SyscallRet {
 }
 
 pub fn stat_shm_segment(shmid: usize, buf: *mut ShmId) -> SyscallRet {
-    let shm_manager = SHM_MANAGER.segments.read();
+    let ipc_ns = current_task().ipc_ns();
+    let shm_manager = ipc_ns.shm.segments.read();
     let shm_seg = shm_manager.get(&shmid).ok_or(Errno::EINVAL)?;
     copy_to_user(buf, &shm_seg.id as *const ShmId, 1)?;
     Ok(0)
@@ -56,3 +56,48 @@ pub fn from_ipendpoint_to_socketaddr(addr: IpEndpoint) -> SocketAddr {
         }
     }
 }
+pub const LOOP_BACK_IPV6: IpAddress = IpAddress::Ipv6(Ipv6Address::LOOPBACK);
+pub const UNSPECIFIED_IPV6: IpAddress = IpAddress::Ipv6(Ipv6Address::UNSPECIFIED);
+/// 127.0.0.0/8 或 ::1 走回环网卡
+pub fn is_loopback(ip: IpAddress) -> bool {
+    match ip {
+        IpAddress::Ipv4(v4) => v4.0[0] == 127,
+        IpAddress::Ipv6(v6) => v6.is_loopback(),
+    }
+}
+/// AF_INET6 套接字上报 IPv4 对端时使用 v4-mapped 地址 ::ffff:a.b.c.d
+pub fn to_v4_mapped(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        //未绑定时为 ::
+        SocketAddr::V4(v4) if v4.ip().is_unspecified() => {
+            SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, v4.port(), 0, 0))
+        }
+        SocketAddr::V4(v4) => {
+            SocketAddr::V6(SocketAddrV6::new(v4.ip().to_ipv6_mapped(), v4.port(), 0, 0))
+        }
+        SocketAddr::V6(_) => addr,
+    }
+}
+/// v4-mapped 地址在协议栈中按 IPv4 处理
+pub fn from_v4_mapped(addr: SocketAddrV6) -> SocketAddr {
+    match addr.ip().to_ipv4_mapped() {
+        Some(v4) => SocketAddr::V4(SocketAddrV4::new(v4, addr.port())),
+        None => SocketAddr::V6(addr),
+    }
+}
+/// 根据 mac 地址生成 EUI-64 链路本地地址 fe80::/64 (RFC 4862 SLAAC)
+pub fn link_local_from_mac(mac: [u8; 6]) -> IpAddress {
+    let mut bytes = [0u8; 16];
+    bytes[0] = 0xfe;
+    bytes[1] = 0x80;
+    //翻转 universal/local 位，中间插入 ff:fe
+    bytes[8] = mac[0] ^ 0x02;
+    bytes[9] = mac[1];
+    bytes[10] = mac[2];
+    bytes[11] = 0xff;
+    bytes[12] = 0xfe;
+    bytes[13] = mac[3];
+    bytes[14] = mac[4];
+    bytes[15] = mac[5];
+    IpAddress::Ipv6(Ipv6Address(bytes))
+}
@@ -0,0 +1,385 @@
+//! classic BPF (cBPF) 过滤器
+//!
+//! 用于 SO_ATTACH_FILTER，语义与 Linux `net/core/filter.c` 的解释器一致：
+//! - 报文读取使用网络字节序
+//! - 越界读取或除零时直接返回 0 (丢弃报文)
+//! - 返回值为需要保留的报文字节数
+//!
+//! seccomp 复用同一个解释器, 读取的数据换成 seccomp_data, 见 `task/seccomp.rs`
+use alloc::vec::Vec;
+
+use crate::syscall::errno::Errno;
+
+/// struct sock_filter
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// struct sock_fprog
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: *const SockFilter,
+}
+
+pub const BPF_MAXINSNS: usize = 4096;
+const BPF_MEMWORDS: usize = 16;
+
+//指令类别
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+//读取大小
+pub const BPF_W: u16 = 0x00;
+pub const BPF_H: u16 = 0x08;
+pub const BPF_B: u16 = 0x10;
+//寻址方式
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+const BPF_MSH: u16 = 0xa0;
+//alu操作
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+//跳转
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+//操作数来源
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+//misc
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+//Linux 扩展的辅助数据读取 (k 为负偏移)
+const SKF_AD_OFF: i32 = -0x1000;
+const SKF_AD_PROTOCOL: i32 = 0;
+const SKF_AD_PKTTYPE: i32 = 4;
+const SKF_AD_IFINDEX: i32 = 8;
+const SKF_AD_VLAN_TAG: i32 = 44;
+const SKF_AD_VLAN_TAG_PRESENT: i32 = 48;
+
+/// 报文之外过滤器可以读取的信息
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BpfContext {
+    /// 以太网协议号(主机字节序)
+    pub protocol: u16,
+    pub pkttype: u8,
+    pub ifindex: i32,
+}
+
+fn class(code: u16) -> u16 {
+    code & 0x07
+}
+fn size(code: u16) -> u16 {
+    code & 0x18
+}
+fn mode(code: u16) -> u16 {
+    code & 0xe0
+}
+fn op(code: u16) -> u16 {
+    code & 0xf0
+}
+fn src(code: u16) -> u16 {
+    code & 0x08
+}
+
+/// 过滤器读取的数据
+pub trait BpfInput {
+    /// 读取offset处size(BPF_W/BPF_H/BPF_B)大小的数据
+    fn load(&self, offset: usize, size: u16) -> Option<u32>;
+    /// 读取辅助数据, k为负偏移
+    fn load_ancillary(&self, k: i32) -> Option<u32>;
+    /// BPF_LEN读取的长度
+    fn data_len(&self) -> u32;
+}
+
+struct PacketInput<'a> {
+    pkt: &'a [u8],
+    ctx: &'a BpfContext,
+}
+
+impl BpfInput for PacketInput<'_> {
+    fn load(&self, offset: usize, size: u16) -> Option<u32> {
+        load(self.pkt, offset, size)
+    }
+    fn load_ancillary(&self, k: i32) -> Option<u32> {
+        load_ancillary(self.ctx, k)
+    }
+    fn data_len(&self) -> u32 {
+        self.pkt.len() as u32
+    }
+}
+
+/// 检查用户传入的过滤器，对应 Linux 的 bpf_check_classic
+pub fn check(prog: &[SockFilter]) -> Result<(), Errno> {
+    if prog.is_empty() || prog.len() > BPF_MAXINSNS {
+        return Err(Errno::EINVAL);
+    }
+    for (pc, insn) in prog.iter().enumerate() {
+        let code = insn.code;
+        match class(code) {
+            BPF_LD | BPF_LDX => match mode(code) {
+                BPF_IMM | BPF_ABS | BPF_IND | BPF_LEN => {}
+                BPF_MEM => {
+                    if insn.k as usize >= BPF_MEMWORDS {
+                        return Err(Errno::EINVAL);
+                    }
+                }
+                BPF_MSH if class(code) == BPF_LDX && size(code) == BPF_B => {}
+                _ => return Err(Errno::EINVAL),
+            },
+            BPF_ST | BPF_STX => {
+                if insn.k as usize >= BPF_MEMWORDS {
+                    return Err(Errno::EINVAL);
+                }
+            }
+            BPF_ALU => match op(code) {
+                BPF_DIV | BPF_MOD if src(code) == BPF_K && insn.k == 0 => {
+                    return Err(Errno::EINVAL)
+                }
+                BPF_ADD | BPF_SUB | BPF_MUL | BPF_DIV | BPF_OR | BPF_AND | BPF_LSH | BPF_RSH
+                | BPF_NEG | BPF_MOD | BPF_XOR => {}
+                _ => return Err(Errno::EINVAL),
+            },
+            BPF_JMP => {
+                let remain = prog.len() - pc - 1;
+                if op(code) == BPF_JA {
+                    if insn.k as usize >= remain {
+                        return Err(Errno::EINVAL);
+                    }
+                } else {
+                    match op(code) {
+                        BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET => {}
+                        _ => return Err(Errno::EINVAL),
+                    }
+                    if insn.jt as usize >= remain || insn.jf as usize >= remain {
+                        return Err(Errno::EINVAL);
+                    }
+                }
+            }
+            BPF_RET | BPF_MISC => {}
+            _ => return Err(Errno::EINVAL),
+        }
+    }
+    //最后一条必须是ret，保证程序一定会结束
+    if class(prog[prog.len() - 1].code) != BPF_RET {
+        return Err(Errno::EINVAL);
+    }
+    Ok(())
+}
+
+/// seccomp 过滤器的额外限制，对应 Linux 的 seccomp_check_filter
+/// 只允许按4字节对齐读取 seccomp_data, 不允许间接寻址和辅助数据
+pub fn check_seccomp(prog: &[SockFilter], data_len: u32) -> Result<(), Errno> {
+    for insn in prog {
+        let code = insn.code;
+        match class(code) {
+            BPF_LD => match mode(code) {
+                BPF_ABS if size(code) == BPF_W => {
+                    if insn.k >= data_len || insn.k & 3 != 0 {
+                        return Err(Errno::EINVAL);
+                    }
+                }
+                BPF_LEN if size(code) == BPF_W => {}
+                BPF_IMM | BPF_MEM => {}
+                _ => return Err(Errno::EINVAL),
+            },
+            BPF_LDX => match mode(code) {
+                BPF_LEN if size(code) == BPF_W => {}
+                BPF_IMM | BPF_MEM => {}
+                _ => return Err(Errno::EINVAL),
+            },
+            BPF_RET => {
+                if code & 0x18 == BPF_X {
+                    return Err(Errno::EINVAL);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// 从用户态 sock_fprog 拷贝并检查过滤器
+pub fn copy_filter_from_user(fprog: &SockFprog) -> Result<Vec<SockFilter>, Errno> {
+    let len = fprog.len as usize;
+    if len == 0 || len > BPF_MAXINSNS || fprog.filter.is_null() {
+        return Err(Errno::EINVAL);
+    }
+    let mut prog = alloc::vec![
+        SockFilter {
+            code: 0,
+            jt: 0,
+            jf: 0,
+            k: 0
+        };
+        len
+    ];
+    crate::arch::mm::copy_from_user(fprog.filter, prog.as_mut_ptr(), len)?;
+    check(&prog)?;
+    Ok(prog)
+}
+
+fn load(pkt: &[u8], offset: usize, size: u16) -> Option<u32> {
+    match size {
+        BPF_W => pkt
+            .get(offset..offset.checked_add(4)?)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        BPF_H => pkt
+            .get(offset..offset.checked_add(2)?)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32),
+        BPF_B => pkt.get(offset).map(|b| *b as u32),
+        _ => None,
+    }
+}
+
+fn load_ancillary(ctx: &BpfContext, k: i32) -> Option<u32> {
+    match k - SKF_AD_OFF {
+        SKF_AD_PROTOCOL => Some(ctx.protocol as u32),
+        SKF_AD_PKTTYPE => Some(ctx.pkttype as u32),
+        SKF_AD_IFINDEX => Some(ctx.ifindex as u32),
+        //不支持vlan
+        SKF_AD_VLAN_TAG | SKF_AD_VLAN_TAG_PRESENT => Some(0),
+        _ => None,
+    }
+}
+
+/// 运行过滤器，返回需要保留的报文长度，0 表示丢弃
+pub fn run(prog: &[SockFilter], pkt: &[u8], ctx: &BpfContext) -> u32 {
+    run_filter(prog, &PacketInput { pkt, ctx })
+}
+
+/// 在任意输入上运行过滤器, 越界读取或除零时返回 0
+pub fn run_filter<I: BpfInput>(prog: &[SockFilter], input: &I) -> u32 {
+    let mut a: u32 = 0;
+    let mut x: u32 = 0;
+    let mut mem = [0u32; BPF_MEMWORDS];
+    let mut pc = 0;
+    while pc < prog.len() {
+        let insn = prog[pc];
+        let code = insn.code;
+        let k = insn.k;
+        pc += 1;
+        match class(code) {
+            BPF_LD => {
+                a = match mode(code) {
+                    BPF_IMM => k,
+                    BPF_ABS if (k as i32) < 0 => match input.load_ancillary(k as i32) {
+                        Some(v) => v,
+                        None => return 0,
+                    },
+                    BPF_ABS => match input.load(k as usize, size(code)) {
+                        Some(v) => v,
+                        None => return 0,
+                    },
+                    BPF_IND => match input.load(x.wrapping_add(k) as usize, size(code)) {
+                        Some(v) => v,
+                        None => return 0,
+                    },
+                    BPF_MEM => mem[k as usize],
+                    BPF_LEN => input.data_len(),
+                    _ => return 0,
+                }
+            }
+            BPF_LDX => {
+                x = match mode(code) {
+                    BPF_IMM => k,
+                    BPF_MEM => mem[k as usize],
+                    BPF_LEN => input.data_len(),
+                    //ip头长度: 4 * (pkt[k] & 0xf)
+                    BPF_MSH => match input.load(k as usize, BPF_B) {
+                        Some(b) => (b & 0x0f) << 2,
+                        None => return 0,
+                    },
+                    _ => return 0,
+                }
+            }
+            BPF_ST => mem[k as usize] = a,
+            BPF_STX => mem[k as usize] = x,
+            BPF_ALU => {
+                let operand = if src(code) == BPF_X { x } else { k };
+                a = match op(code) {
+                    BPF_ADD => a.wrapping_add(operand),
+                    BPF_SUB => a.wrapping_sub(operand),
+                    BPF_MUL => a.wrapping_mul(operand),
+                    BPF_DIV => {
+                        if operand == 0 {
+                            return 0;
+                        }
+                        a / operand
+                    }
+                    BPF_MOD => {
+                        if operand == 0 {
+                            return 0;
+                        }
+                        a % operand
+                    }
+                    BPF_OR => a | operand,
+                    BPF_AND => a & operand,
+                    BPF_XOR => a ^ operand,
+                    BPF_LSH => a.checked_shl(operand).unwrap_or(0),
+                    BPF_RSH => a.checked_shr(operand).unwrap_or(0),
+                    BPF_NEG => a.wrapping_neg(),
+                    _ => return 0,
+                }
+            }
+            BPF_JMP => {
+                let operand = if src(code) == BPF_X { x } else { k };
+                let taken = match op(code) {
+                    BPF_JA => {
+                        pc += k as usize;
+                        continue;
+                    }
+                    BPF_JEQ => a == operand,
+                    BPF_JGT => a > operand,
+                    BPF_JGE => a >= operand,
+                    BPF_JSET => a & operand != 0,
+                    _ => return 0,
+                };
+                pc += if taken { insn.jt } else { insn.jf } as usize;
+            }
+            BPF_RET => {
+                return match code & 0x18 {
+                    BPF_K => k,
+                    BPF_X => x,
+                    BPF_A => a,
+                    _ => 0,
+                }
+            }
+            BPF_MISC => match code & 0xf8 {
+                BPF_TAX => x = a,
+                BPF_TXA => a = x,
+                _ => return 0,
+            },
+            _ => return 0,
+        }
+    }
+    0
+}
@@ -0,0 +1,237 @@
+//! ICMP ping 套接字 (socket(AF_INET, SOCK_DGRAM, IPPROTO_ICMP))
+//!
+//! 与 Linux 的非特权 ping socket 一致：
+//! - 只允许发送 Echo Request，identifier 由内核替换为套接字绑定的 ident
+//! - 校验和由内核(smoltcp)重新计算
+//! - 接收时返回不含 IP 头的 ICMP 报文
+use core::{
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, AtomicU16, Ordering},
+};
+
+use smoltcp::{
+    iface::SocketHandle,
+    socket::icmp,
+    wire::{IpAddress, IpVersion},
+};
+use spin::RwLock;
+
+use crate::{syscall::errno::Errno, task::yield_current_task};
+
+use super::{
+    addr::{from_ipendpoint_to_socketaddr, from_sockaddr_to_ipendpoint, is_unspecified},
+    poll_interfaces,
+    tcp::PollState,
+    udp::get_ephemeral_port,
+    SocketSetWrapper, SOCKET_SET,
+};
+
+pub const IPPROTO_ICMP: usize = 1;
+pub const IPPROTO_ICMPV6: usize = 58;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMP_HEADER_LEN: usize = 8;
+
+pub struct IcmpSocket {
+    handle: SocketHandle,
+    ip_version: IpVersion,
+    //0 表示尚未绑定
+    ident: AtomicU16,
+    local_addr: RwLock<Option<IpAddress>>,
+    remote_addr: RwLock<Option<IpAddress>>,
+    nonblock: AtomicBool,
+}
+
+unsafe impl Sync for IcmpSocket {}
+unsafe impl Send for IcmpSocket {}
+
+impl IcmpSocket {
+    pub fn new(ip_version: IpVersion) -> Self {
+        let socket = SocketSetWrapper::new_icmp_socket();
+        let handle = SOCKET_SET.add(socket);
+        IcmpSocket {
+            handle,
+            ip_version,
+            ident: AtomicU16::new(0),
+            local_addr: RwLock::new(None),
+            remote_addr: RwLock::new(None),
+            nonblock: AtomicBool::new(false),
+        }
+    }
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+    pub fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Release);
+    }
+    pub fn set_hop_limit(&self, ttl: u8) {
+        SOCKET_SET.with_socket_mut::<_, icmp::Socket, _>(self.handle, |socket| {
+            socket.set_hop_limit(Some(ttl));
+        });
+    }
+    pub fn hop_limit(&self) -> u8 {
+        SOCKET_SET.with_socket::<_, icmp::Socket, _>(self.handle, |socket| {
+            socket.hop_limit().unwrap_or(super::raw::DEFAULT_TTL)
+        })
+    }
+    pub fn is_bound(&self) -> bool {
+        self.ident.load(Ordering::Acquire) != 0
+    }
+    pub fn is_connected(&self) -> bool {
+        self.remote_addr.read().is_some()
+    }
+    /// ping socket 的"端口"即 ICMP identifier
+    pub fn local_addr(&self) -> Result<SocketAddr, Errno> {
+        let addr = self.local_addr.read().unwrap_or(self.unspecified());
+        let ident = self.ident.load(Ordering::Acquire);
+        Ok(from_ipendpoint_to_socketaddr((addr, ident).into()))
+    }
+    pub fn remote_addr(&self) -> Result<SocketAddr, Errno> {
+        match *self.remote_addr.read() {
+            Some(addr) => Ok(from_ipendpoint_to_socketaddr((addr, 0).into())),
+            None => Err(Errno::ENOTCONN),
+        }
+    }
+    pub fn bind(&self, addr: SocketAddr) -> Result<(), Errno> {
+        if self.is_bound() {
+            return Err(Errno::EINVAL);
+        }
+        let endpoint = from_sockaddr_to_ipendpoint(addr);
+        self.check_family(endpoint.addr)?;
+        let ident = if endpoint.port == 0 {
+            get_ephemeral_port()
+        } else {
+            endpoint.port
+        };
+        SOCKET_SET.with_socket_mut::<_, icmp::Socket, _>(self.handle, |socket| {
+            socket
+                .bind(icmp::Endpoint::Ident(ident))
+                .map_err(|_| Errno::EADDRINUSE)
+        })?;
+        self.ident.store(ident, Ordering::Release);
+        *self.local_addr.write() = (!is_unspecified(endpoint.addr)).then_some(endpoint.addr);
+        Ok(())
+    }
+    pub fn connect(&self, addr: SocketAddr) -> Result<(), Errno> {
+        let addr = from_sockaddr_to_ipendpoint(addr).addr;
+        self.check_family(addr)?;
+        *self.remote_addr.write() = Some(addr);
+        Ok(())
+    }
+    pub fn shutdown(&self) {
+        *self.remote_addr.write() = None;
+    }
+
+    pub fn send(&self, buf: &[u8]) -> Result<usize, Errno> {
+        let remote = self.remote_addr.read().ok_or(Errno::EDESTADDRREQ)?;
+        self.send_impl(buf, remote)
+    }
+    pub fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, Errno> {
+        let remote = from_sockaddr_to_ipendpoint(addr).addr;
+        self.check_family(remote)?;
+        self.send_impl(buf, remote)
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Errno> {
+        loop {
+            //未绑定时还没有 ident，收不到回复，与 Linux 一样阻塞到首次 sendto 自动绑定之后
+            if self.is_bound() {
+                poll_interfaces();
+                let remote = *self.remote_addr.read();
+                //直接从 smoltcp 的接收缓冲区拷贝到用户缓冲区，超出部分被丢弃
+                let res = SOCKET_SET.with_socket_mut::<_, icmp::Socket, _>(self.handle, |socket| {
+                    let (packet, src) = socket.recv().ok()?;
+                    if remote.is_some_and(|remote| remote != src) {
+                        return Some(None);
+                    }
+                    let copy_len = core::cmp::min(packet.len(), buf.len());
+                    buf[..copy_len].copy_from_slice(&packet[..copy_len]);
+                    Some(Some((copy_len, src)))
+                });
+                match res {
+                    Some(Some((copy_len, src))) => {
+                        return Ok((copy_len, from_ipendpoint_to_socketaddr((src, 0).into())));
+                    }
+                    //来自其他地址的报文，丢弃后继续接收
+                    Some(None) => continue,
+                    None => {}
+                }
+            }
+            if self.is_nonblocking() {
+                return Err(Errno::EAGAIN);
+            }
+            yield_current_task();
+        }
+    }
+
+    pub fn poll(&self) -> PollState {
+        SOCKET_SET.with_socket::<_, icmp::Socket, _>(self.handle, |socket| PollState {
+            readable: socket.can_recv(),
+            writeable: socket.can_send(),
+        })
+    }
+}
+
+//private function
+impl IcmpSocket {
+    fn unspecified(&self) -> IpAddress {
+        match self.ip_version {
+            IpVersion::Ipv4 => IpAddress::v4(0, 0, 0, 0),
+            IpVersion::Ipv6 => IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 0),
+        }
+    }
+    fn check_family(&self, addr: IpAddress) -> Result<(), Errno> {
+        match (self.ip_version, addr) {
+            (IpVersion::Ipv4, IpAddress::Ipv4(_)) | (IpVersion::Ipv6, IpAddress::Ipv6(_)) => Ok(()),
+            _ => Err(Errno::EAFNOSUPPORT),
+        }
+    }
+    fn send_impl(&self, buf: &[u8], remote: IpAddress) -> Result<usize, Errno> {
+        if buf.len() < ICMP_HEADER_LEN {
+            return Err(Errno::EINVAL);
+        }
+        let echo_request = match self.ip_version {
+            IpVersion::Ipv4 => ICMP_ECHO_REQUEST,
+            IpVersion::Ipv6 => ICMPV6_ECHO_REQUEST,
+        };
+        if buf[0] != echo_request || buf[1] != 0 {
+            return Err(Errno::EINVAL);
+        }
+        //未绑定时自动分配 ident
+        if !self.is_bound() {
+            let unspecified = from_ipendpoint_to_socketaddr((self.unspecified(), 0).into());
+            self.bind(unspecified)?;
+        }
+        let mut packet = buf.to_vec();
+        //identifier 位于 ICMP 头的 4..6 字节
+        let ident = self.ident.load(Ordering::Acquire);
+        packet[4..6].copy_from_slice(&ident.to_be_bytes());
+        loop {
+            let res = SOCKET_SET.with_socket_mut::<_, icmp::Socket, _>(self.handle, |socket| {
+                if packet.len() > socket.payload_send_capacity() {
+                    return Err(Errno::EMSGSIZE);
+                }
+                socket.send_slice(&packet, remote).map_err(|e| match e {
+                    icmp::SendError::Unaddressable => Errno::EDESTADDRREQ,
+                    icmp::SendError::BufferFull => Errno::EAGAIN,
+                })
+            });
+            match res {
+                Ok(()) => break,
+                Err(Errno::EAGAIN) if !self.is_nonblocking() => {
+                    poll_interfaces();
+                    yield_current_task();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        poll_interfaces();
+        Ok(buf.len())
+    }
+}
+
+impl Drop for IcmpSocket {
+    fn drop(&mut self) {
+        SOCKET_SET.remove(self.handle);
+    }
+}
@@ -25,21 +25,24 @@ use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, SOCKET_SET};
 struct ListenTableEntry{
     //表示监听的server地址，addr
     listen_endpoint:IpListenEndpoint,
+    //IPV6_V6ONLY,为true时通配地址上的ipv6监听不接受ipv4连接
+    v6only:bool,
     task_id:usize,
     //监听client发送过来的syn,这个vec的长度决定其可以同时接受client的数量
     syn_queue:VecDeque<SocketHandle>
 }
 impl ListenTableEntry {
-    pub fn new(listen_endpoint:IpListenEndpoint)->Self {
+    pub fn new(listen_endpoint:IpListenEndpoint,v6only:bool)->Self {
 
-        ListenTableEntry { listen_endpoint: listen_endpoint,task_id:current_task().tid(),syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE) }
+        ListenTableEntry { listen_endpoint: listen_endpoint,v6only,task_id:current_task().tid(),syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE) }
     }
     pub fn can_accept(&self,dst:IpAddress)->bool {
         //这里只决定是否可以对client建立连接，这里的判断标准只是比较client传过来的remote_addr
         //具体是否可以监听需要看syn_queue中是否有空
+        //addr为None时只有ipv6套接字监听在::上,此时双栈接受ipv4连接,除非设置了v6only
         match self.listen_endpoint.addr {
             Some(addr) => addr == dst,
-            None => true,
+            None => !(self.v6only && matches!(dst, IpAddress::Ipv4(_))),
         }
     }
 }
@@ -92,14 +95,14 @@ impl ListenTable {
     //         false
     //     }
     // }
-    pub fn listen(&self,listen_endpoint:IpListenEndpoint)->SyscallRet {
+    pub fn listen(&self,listen_endpoint:IpListenEndpoint,v6only:bool)->SyscallRet {
         //判断listen_endpoint想要监听的port是否有人已经在监听e了
         let port=listen_endpoint.port;
         assert!(port!=0);
         let mut entry=self.table[port as usize].lock();
         if entry.is_none(){
             log::error!("[listen_table_listen]:has create a listen entry");
-            *entry= Some(Box::new(ListenTableEntry::new(listen_endpoint)));
+            *entry= Some(Box::new(ListenTableEntry::new(listen_endpoint,v6only)));
             Ok(0)
         }
         else {
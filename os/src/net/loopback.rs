@@ -161,9 +161,8 @@ use core::ptr;
 
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken as SmoltcpRx, TxToken as SmoltcpTx};
 use smoltcp::time::Instant;
-use smoltcp::wire::{IpProtocol, Ipv4Packet, TcpPacket};
+use smoltcp::wire::{IpEndpoint, IpProtocol, IpVersion, Ipv4Packet, TcpPacket};
 
-use crate::net::addr::from_sockaddr_to_ipendpoint;
 use crate::net::LISTEN_TABLE;
 
 /// 本地回环设备，带 buffer 池复用
@@ -195,6 +194,13 @@ impl LoopbackDev {
         }
     }
 
+    /// 把 packet socket 写入的 IP 报文放入接收队列
+    pub fn inject(&mut self, packet: &[u8]) {
+        let mut buf = self.take_buf(packet.len());
+        buf.copy_from_slice(packet);
+        self.queue.push_back(buf);
+    }
+
     /// 回收 buffer 到池里
     fn recycle_buf(&mut self, mut buf: Vec<u8>) {
         if self.pool.len() < 64 {
@@ -228,6 +234,8 @@ impl SmoltcpRx for RxTokenScoop {
     where
         F: FnOnce(&mut [u8]) -> R,
     {
+        super::tcp::track_segment(&self.buffer, false);
+        super::packet::deliver_ip_packet(super::packet::LOOPBACK_IFINDEX, &self.buffer, false);
         f(&mut self.buffer)
     }
 
@@ -252,17 +260,21 @@ impl<'a> SmoltcpTx for TxToken {
         // 让上层填充数据
         let ret = f(&mut buffer);
 
-        // 强制计算校验和
-        if let Ok(mut ipv4) = Ipv4Packet::new_checked(&mut buffer) {
-            ipv4.fill_checksum();
-            if ipv4.next_header() == IpProtocol::Tcp {
-                if let Ok(mut tcp) = TcpPacket::new_checked(ipv4.payload_mut()) {
-                    let csum = tcp.checksum();
-                    tcp.set_checksum(csum);
+        // 强制计算校验和,ipv6 没有头部校验和,不能按 ipv4 解析
+        if IpVersion::of_packet(&buffer) == Ok(IpVersion::Ipv4) {
+            if let Ok(mut ipv4) = Ipv4Packet::new_checked(&mut buffer) {
+                ipv4.fill_checksum();
+                if ipv4.next_header() == IpProtocol::Tcp {
+                    if let Ok(mut tcp) = TcpPacket::new_checked(ipv4.payload_mut()) {
+                        let csum = tcp.checksum();
+                        tcp.set_checksum(csum);
+                    }
                 }
             }
         }
 
+        super::tcp::track_segment(&buffer, true);
+        super::packet::deliver_ip_packet(super::packet::LOOPBACK_IFINDEX, &buffer, true);
         // 推送到队列
         unsafe {
             (*self.dev).queue.push_back(buffer);
@@ -277,7 +289,7 @@ impl Device for LoopbackDev {
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut cap = DeviceCapabilities::default();
-        cap.max_transmission_unit = 3_000_000;
+        cap.max_transmission_unit = super::LOOPBACK_MTU;
         cap.medium = self.medium;
         cap
     }
@@ -296,34 +308,57 @@ impl Device for LoopbackDev {
         Some(TxToken { dev: self as *mut _ })
     }
 }
-fn snoop_tcp_from_ip(buffer: &[u8], sockets: &mut SocketSet) -> Result<(), smoltcp::wire::Error> {
-    use core::net::SocketAddr;
-    use smoltcp::wire::{IpProtocol, Ipv4Packet, TcpPacket};
+/// 监听进入的 SYN 报文，为 listen 的套接字提前创建 smoltcp socket
+/// buffer 为 ipv4 或 ipv6 报文(不处理 ipv6 扩展头)
+pub(super) fn snoop_tcp_from_ip(buffer: &[u8], sockets: &mut SocketSet) -> Result<(), smoltcp::wire::Error> {
+    use smoltcp::wire::{IpAddress, Ipv6Packet};
     // log::error!("[snoop_tcp_from_ip] begin snoop_from_ip");
-    let ipv4_packet = Ipv4Packet::new_checked(buffer)?;
-    log::error!(
-        "[snoop_tcp_from_ip]:ipv4 packet header {:?}",
-        ipv4_packet.next_header()
-    );
-    if ipv4_packet.next_header() == IpProtocol::Tcp {
-        let tcp_packet = TcpPacket::new_checked(ipv4_packet.payload())?;
-        let src_addr = SocketAddr::new(ipv4_packet.src_addr().0.into(), tcp_packet.src_port());
-        let dst_addr = SocketAddr::new(ipv4_packet.dst_addr().0.into(), tcp_packet.dst_port());
-        let is_first = tcp_packet.syn() && !tcp_packet.ack();
-        log::error!("[snoop_tcp_from_ip] is first:{}", is_first);
-        if is_first {
-            // create a socket for the first incoming TCP packet, as the later accept() returns.
-            log::error!(
-                "[snoop_tcp_from_ip]:src_addr :{:?},dst_addr:{:?}",
-                src_addr,
-                dst_addr
-            );
-            LISTEN_TABLE.push_incoming_packet(
-                from_sockaddr_to_ipendpoint(dst_addr),
-                from_sockaddr_to_ipendpoint(src_addr),
-                sockets,
-            );
-        }
+    let (src_ip, dst_ip, payload): (IpAddress, IpAddress, &[u8]) =
+        match IpVersion::of_packet(buffer)? {
+            IpVersion::Ipv4 => {
+                let ipv4_packet = Ipv4Packet::new_checked(buffer)?;
+                log::error!(
+                    "[snoop_tcp_from_ip]:ipv4 packet header {:?}",
+                    ipv4_packet.next_header()
+                );
+                if ipv4_packet.next_header() != IpProtocol::Tcp {
+                    return Ok(());
+                }
+                (
+                    ipv4_packet.src_addr().into(),
+                    ipv4_packet.dst_addr().into(),
+                    ipv4_packet.payload(),
+                )
+            }
+            IpVersion::Ipv6 => {
+                let ipv6_packet = Ipv6Packet::new_checked(buffer)?;
+                log::error!(
+                    "[snoop_tcp_from_ip]:ipv6 packet header {:?}",
+                    ipv6_packet.next_header()
+                );
+                if ipv6_packet.next_header() != IpProtocol::Tcp {
+                    return Ok(());
+                }
+                (
+                    ipv6_packet.src_addr().into(),
+                    ipv6_packet.dst_addr().into(),
+                    ipv6_packet.payload(),
+                )
+            }
+        };
+    let tcp_packet = TcpPacket::new_checked(payload)?;
+    let src_addr = IpEndpoint::new(src_ip, tcp_packet.src_port());
+    let dst_addr = IpEndpoint::new(dst_ip, tcp_packet.dst_port());
+    let is_first = tcp_packet.syn() && !tcp_packet.ack();
+    log::error!("[snoop_tcp_from_ip] is first:{}", is_first);
+    if is_first {
+        // create a socket for the first incoming TCP packet, as the later accept() returns.
+        log::error!(
+            "[snoop_tcp_from_ip]:src_addr :{:?},dst_addr:{:?}",
+            src_addr,
+            dst_addr
+        );
+        LISTEN_TABLE.push_incoming_packet(dst_addr, src_addr, sockets);
     }
     Ok(())
 }
@@ -11,6 +11,7 @@
 use alloc::{boxed::Box, vec};
 use core::{cell::RefCell, ops::DerefMut, panic};
 use lazyinit::LazyInit;
+use addr::{is_unspecified, link_local_from_mac, LOOP_BACK_IPV6};
 use listentable::ListenTable;
 use loopback::LoopbackDev;
 use smoltcp::{
@@ -19,7 +20,8 @@ use smoltcp::{
     socket::{tcp::SocketBuffer, AnySocket, Socket},
     storage::{PacketBuffer, PacketMetadata},
     wire::{
-        EthernetAddress, EthernetFrame, HardwareAddress, IpCidr, IpProtocol, Ipv4Packet, TcpPacket,
+        EthernetAddress, EthernetFrame, HardwareAddress, IpCidr, IpProtocol, IpVersion, Ipv4Packet,
+        TcpPacket,
     },
 };
 // use socket::Socket;
@@ -40,8 +42,12 @@ use virtio_drivers::transport::{mmio::MmioTransport, pci::PciTransport, Transpor
 
 pub mod addr;
 pub mod alg;
+pub mod bpf;
+pub mod icmp;
 mod listentable;
 mod loopback;
+pub mod packet;
+pub mod raw;
 pub mod socket;
 pub mod tcp;
 pub mod udp;
@@ -61,12 +67,18 @@ static SOCKET_SET: LazyInit<SocketSetWrapper> = LazyInit::new();
 static RANDOM_SEED: u64 = 0xA2CE_05A2_CE05_A2CE;
 static ETH0: LazyInit<InterfaceWrapper> = LazyInit::new();
 static LOOPBACK_DEV: LazyInit<Mutex<LoopbackDev>> = LazyInit::new();
-static LOOPBACK: LazyInit<Mutex<Interface>> = LazyInit::new();
+static LOOPBACK: LazyInit<Mutex<Box<Interface>>> = LazyInit::new();
 static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
 const TCP_RX_BUF_LEN_IPERF: usize = 128 * 1024;
 const TCP_TX_BUF_LEN_IPERF: usize = 128 * 1024;
+/// 回环网卡 mtu，ipv4 报文总长为 u16，不能超过 65535
+pub const LOOPBACK_MTU: usize = 65535;
+/// virtio 网卡 mtu (不含以太网头)
+pub const ETH0_MTU: usize = 1500;
 const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
+const RAW_RX_BUF_LEN: usize = 64 * 1024;
+const RAW_TX_BUF_LEN: usize = 64 * 1024;
 const DNS_SEVER: &str = "8.8.8.8";
 const LISTEN_QUEUE_SIZE: usize = 512;
 //qemu默认ipv4的网关和ipv4地址
@@ -98,20 +110,24 @@ pub fn init(net_device: Option<VirtioNetDevice<32, HalImpl, MmioTransport>>) {
         let ip_ipv6 = IP_V6.parse().expect("invalid ip address");
         eth0.set_ip_addr(ip_ipv4, 24);
         eth0.set_ip_addr(ip_ipv6, PREFIX_V6);
+        //SLAAC: 由mac地址生成fe80::/64链路本地地址
+        eth0.set_ip_addr(link_local_from_mac(ether_addr.0), PREFIX_V6);
         ETH0.init_once(eth0);
         SOCKET_SET.init_once(SocketSetWrapper::new());
         LISTEN_TABLE.init_once(ListenTable::new());
         let mut device = LoopbackDev::new(Medium::Ip);
         let config = Config::new(smoltcp::wire::HardwareAddress::Ip);
-        let mut iface = Interface::new(
+        //开启分片后Interface内含分片/重组缓冲区,直接放到堆上,避免在启动栈上多次拷贝
+        let mut iface = Box::new(Interface::new(
             config,
             &mut device,
             SmolInstant::from_micros_const((get_time() / 1000) as i64),
-        );
+        ));
         iface.update_ip_addrs(|ip_addrs| {
             ip_addrs
                 .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
                 .unwrap();
+            ip_addrs.push(IpCidr::new(LOOP_BACK_IPV6, 128)).unwrap();
         });
         LOOPBACK.init_once(Mutex::new(iface));
         LOOPBACK_DEV.init_once(Mutex::new(device));
@@ -155,20 +171,24 @@ pub fn init_la<T: Transport + 'static>(net_device: Option<VirtioNetDevice<32, Ha
         let ip_ipv6 = IP_V6.parse().expect("invalid ip address");
         eth0.set_ip_addr(ip_ipv4, 24);
         eth0.set_ip_addr(ip_ipv6, PREFIX_V6);
+        //SLAAC: 由mac地址生成fe80::/64链路本地地址
+        eth0.set_ip_addr(link_local_from_mac(ether_addr.0), PREFIX_V6);
         ETH0.init_once(eth0);
         // SOCKET_SET.init_once(SocketSetWrapper::new());
         LISTEN_TABLE.init_once(ListenTable::new());
         let mut device = LoopbackDev::new(Medium::Ip);
         let config = Config::new(smoltcp::wire::HardwareAddress::Ip);
-        let mut iface = Interface::new(
+        //开启分片后Interface内含分片/重组缓冲区,直接放到堆上,避免在启动栈上多次拷贝
+        let mut iface = Box::new(Interface::new(
             config,
             &mut device,
             SmolInstant::from_micros_const((get_time() / 1000) as i64),
-        );
+        ));
         iface.update_ip_addrs(|ip_addrs| {
             ip_addrs
                 .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
                 .unwrap();
+            ip_addrs.push(IpCidr::new(LOOP_BACK_IPV6, 128)).unwrap();
         });
         LOOPBACK.init_once(Mutex::new(iface));
         LOOPBACK_DEV.init_once(Mutex::new(device));
@@ -253,6 +273,32 @@ impl<'a> SocketSetWrapper<'a> {
             PacketBuffer::new(vec![PacketMetadata::EMPTY; 256], vec![0; UDP_TX_BUF_LEN]);
         smoltcp::socket::udp::Socket::new(udp_recv_buffer, udp_send_buffer)
     }
+    pub fn new_raw_socket(
+        ip_version: IpVersion,
+        ip_protocol: IpProtocol,
+    ) -> smoltcp::socket::raw::Socket<'a> {
+        //raw socket收发的都是完整的ip数据包，每个包最大64KB
+        let raw_recv_buffer = smoltcp::socket::raw::PacketBuffer::new(
+            vec![smoltcp::socket::raw::PacketMetadata::EMPTY; 64],
+            vec![0; RAW_RX_BUF_LEN],
+        );
+        let raw_send_buffer = smoltcp::socket::raw::PacketBuffer::new(
+            vec![smoltcp::socket::raw::PacketMetadata::EMPTY; 64],
+            vec![0; RAW_TX_BUF_LEN],
+        );
+        smoltcp::socket::raw::Socket::new(ip_version, ip_protocol, raw_recv_buffer, raw_send_buffer)
+    }
+    pub fn new_icmp_socket() -> smoltcp::socket::icmp::Socket<'a> {
+        let icmp_recv_buffer = smoltcp::socket::icmp::PacketBuffer::new(
+            vec![smoltcp::socket::icmp::PacketMetadata::EMPTY; 64],
+            vec![0; RAW_RX_BUF_LEN],
+        );
+        let icmp_send_buffer = smoltcp::socket::icmp::PacketBuffer::new(
+            vec![smoltcp::socket::icmp::PacketMetadata::EMPTY; 64],
+            vec![0; RAW_TX_BUF_LEN],
+        );
+        smoltcp::socket::icmp::Socket::new(icmp_recv_buffer, icmp_send_buffer)
+    }
     pub fn new_dns_socket() -> smoltcp::socket::dns::Socket<'a> {
         //servers:ipaddress,Q:Q: Into<ManagedSlice<'a, Option<DnsQuery>>>,
         //Panics if `servers.len() > MAX_SERVER_COUNT`
@@ -289,12 +335,12 @@ impl<'a> SocketSetWrapper<'a> {
             match item.1 {
                 Socket::Tcp(socket) => {
                     let local_addr = socket.get_bound_endpoint();
-                    if local_addr.addr == Some(addr) && local_addr.port == port {
+                    if local_addr.port == port && addr_conflict(local_addr.addr, addr) {
                         return Err(Errno::EADDRINUSE);
                     }
                 }
                 Socket::Udp(socket) => {
-                    if socket.endpoint().addr == Some(addr) && socket.endpoint().port == port {
+                    if socket.endpoint().port == port && addr_conflict(socket.endpoint().addr, addr) {
                         return Err(Errno::EADDRINUSE);
                     }
                 }
@@ -307,6 +353,15 @@ impl<'a> SocketSetWrapper<'a> {
     }
 }
 
+/// 同一端口上地址相同或任意一方为通配地址时冲突
+/// None 表示绑定在 :: 上的双栈套接字，与 ipv4/ipv6 地址均冲突
+fn addr_conflict(bound: Option<IpAddress>, addr: IpAddress) -> bool {
+    match bound {
+        Some(bound) => bound == addr || is_unspecified(addr),
+        None => true,
+    }
+}
+
 ///建立这个为了可以创建iface,要求必须实现device trait and size (size由box保证)
 /// 这里基本后面i使用的dyn device就是virtionetdevice
 pub struct NetDeviceWrapper {
@@ -354,7 +409,7 @@ impl Device for NetDeviceWrapper {
     }
     fn capabilities(&self) -> DeviceCapabilities {
         let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = 1514;
+        caps.max_transmission_unit = ETH0_MTU + 14;
         caps.max_burst_size = None;
         caps.medium = Medium::Ethernet;
         caps
@@ -375,6 +430,9 @@ impl<'a> RxToken for NetRecvToken<'a> {
         log::error!("[NetRecvToken]:begin recv");
         let recv_buf = self.1;
         // log::error!("[NetRecvToken]:recv buffer :{:?}",recv_buf.packet());
+        //复制一份给AF_PACKET套接字
+        packet::deliver_frame(packet::ETH0_IFINDEX, recv_buf.packet(), false);
+        track_frame(recv_buf.packet(), false);
 
         let result = f(recv_buf.packet_mut());
         let mut dev = self.0.borrow_mut();
@@ -404,6 +462,8 @@ impl<'a> TxToken for NetSendToken<'a> {
         // log::error!("3");
         let res = f(data);
         // log::error!("4");
+        packet::deliver_frame(packet::ETH0_IFINDEX, data, true);
+        track_frame(data, true);
         dev.send(send_buffer);
         // log::error!("5");
         //利用virtionetdevice的free_device来push回收
@@ -416,41 +476,48 @@ impl<'a> TxToken for NetSendToken<'a> {
     }
 }
 
+/// 以太网帧中的tcp报文交给tcp统计
+fn track_frame(buf: &[u8], outgoing: bool) {
+    use smoltcp::wire::{EthernetFrame, EthernetProtocol};
+
+    if let Ok(frame) = EthernetFrame::new_checked(buf) {
+        if matches!(
+            frame.ethertype(),
+            EthernetProtocol::Ipv4 | EthernetProtocol::Ipv6
+        ) {
+            tcp::track_segment(frame.payload(), outgoing);
+        }
+    }
+}
+
 //服务器嗅探tcp packet
 fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smoltcp::wire::Error> {
-    use smoltcp::wire::{EthernetFrame, IpProtocol, Ipv4Packet, TcpPacket};
+    use smoltcp::wire::EthernetFrame;
 
     let ether_frame = EthernetFrame::new_checked(buf)?;
-    let ipv4_packet = Ipv4Packet::new_checked(ether_frame.payload())?;
-
-    if ipv4_packet.next_header() == IpProtocol::Tcp {
-        let tcp_packet = TcpPacket::new_checked(ipv4_packet.payload())?;
-        // log::error!("[snoop_tcp_packet] ipv4_packet is {:?}",ipv4_packet);
-        let src_addr = (ipv4_packet.src_addr(), tcp_packet.src_port()).into();
-        let dst_addr = (ipv4_packet.dst_addr(), tcp_packet.dst_port()).into();
-        log::error!("[snoop_tcp_packet]:src_addr is {:?}", src_addr);
-        log::error!("[snoop_tcp_packet]:dst_addr is {:?}", dst_addr);
-        let is_first = tcp_packet.syn() && !tcp_packet.ack();
-        log::error!(
-            "[snoop_tcp_packet]:tcp_packet syn is {:?},ack is {:?}",
-            tcp_packet.syn(),
-            tcp_packet.ack()
-        );
-        if is_first {
-            // create a socket for the first incoming TCP packet, as the later accept() returns.
-            LISTEN_TABLE.push_incoming_packet(dst_addr, src_addr, sockets);
-        }
-    }
-    Ok(())
+    //ipv4/ipv6 的处理与回环网卡一致
+    loopback::snoop_tcp_from_ip(ether_frame.payload(), sockets)
 }
 pub fn poll_interfaces() {
     SOCKET_SET.poll_interfaces();
 }
+/// 把一个IP报文放入回环网卡的接收队列, 下次poll时由协议栈处理
+pub fn loopback_inject(packet: &[u8]) {
+    LOOPBACK_DEV.lock().inject(packet);
+}
+/// 发往 addr 的报文经过的网卡 mtu
+pub fn route_mtu(addr: IpAddress) -> usize {
+    if addr::is_loopback(addr) {
+        LOOPBACK_MTU
+    } else {
+        ETH0_MTU
+    }
+}
 
 //connect 时需要1使用网卡抽象
 pub struct InterfaceWrapper {
     //smoltcp网卡抽象
-    iface: Mutex<Interface>,
+    iface: Mutex<Box<Interface>>,
     //网卡ethenet地址
     address: EthernetAddress,
     //名字eth0
@@ -470,11 +537,11 @@ impl<'a> InterfaceWrapper {
         //这里dev要求有trait device+sized,而size可以由boxa承担，但device必须实现,让这里的netwrapper实现
         //Safety
         //这个函数可能会panic 如果config和dev的capability中的介质不同，传入的virtionetdevice中必须是ethernet
-        let iface = Mutex::new(Interface::new(
+        let iface = Mutex::new(Box::new(Interface::new(
             config,
             &mut dev,
             SmolInstant::from_micros_const(get_time() as i64),
-        ));
+        )));
         InterfaceWrapper {
             iface: iface,
             address: address,
@@ -488,6 +555,14 @@ impl<'a> InterfaceWrapper {
     fn ethernet_address(&self) -> EthernetAddress {
         self.address
     }
+    ///绕过协议栈直接向网卡写入完整的以太网帧,用于AF_PACKET发送
+    pub fn send_frame(&self, frame: &[u8]) -> Result<(), Errno> {
+        let timestamp = SmolInstant::from_micros_const((get_time() / 1000) as i64);
+        let mut dev = self.dev.lock();
+        let token = dev.transmit(timestamp).ok_or(Errno::EAGAIN)?;
+        token.consume(frame.len(), |buf| buf.copy_from_slice(frame));
+        Ok(())
+    }
 
     //IpAddress 有两个Ipaddressv4,Ipaddressv6
     //已经支持ipv6
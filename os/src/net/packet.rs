@@ -0,0 +1,614 @@
+//! AF_PACKET 套接字 (socket(AF_PACKET, SOCK_RAW/SOCK_DGRAM, htons(proto)))
+//!
+//! 网卡收发的每一帧都会在 `NetRecvToken`/`NetSendToken` 中通过 [`deliver_frame`]
+//! 复制一份给所有打开的 packet socket，用于 tcpdump 之类的抓包工具。
+//! 回环网卡是 Medium::Ip，收发的 IP 报文经 [`deliver_ip_packet`] 补上全零 MAC 的
+//! 以太网头后再投递，与 Linux lo 的 ARPHRD_LOOPBACK 帧格式一致。
+//! - SOCK_RAW 收发完整的以太网帧
+//! - SOCK_DGRAM 收发去掉以太网头的负载，发送时由 sockaddr_ll 指定目的 MAC
+//! - 支持 bind 到指定网卡、PACKET_ADD_MEMBERSHIP 混杂模式和 SO_ATTACH_FILTER
+use alloc::{
+    collections::vec_deque::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU16, AtomicU32, AtomicUsize, Ordering};
+
+use num_enum::TryFromPrimitive;
+use smoltcp::wire::{EthernetFrame, EthernetProtocol, IpVersion};
+use spin::{Mutex, RwLock};
+
+use crate::{
+    arch::mm::{copy_from_user, copy_to_user},
+    syscall::errno::{Errno, SyscallRet},
+    task::yield_current_task,
+};
+
+use super::{
+    bpf::{self, BpfContext, SockFilter},
+    tcp::PollState,
+    ETH0, LOOPBACK_MTU,
+};
+
+pub const ETH_P_ALL: u16 = 0x0003;
+const ETH_HLEN: usize = 14;
+const ETH_FRAME_LEN: usize = 1514;
+const ARPHRD_ETHER: u16 = 1;
+const ARPHRD_LOOPBACK: u16 = 772;
+
+//sll_pkttype
+pub const PACKET_HOST: u8 = 0;
+pub const PACKET_BROADCAST: u8 = 1;
+pub const PACKET_MULTICAST: u8 = 2;
+pub const PACKET_OTHERHOST: u8 = 3;
+pub const PACKET_OUTGOING: u8 = 4;
+
+//packet_mreq.mr_type
+const PACKET_MR_MULTICAST: u16 = 0;
+const PACKET_MR_PROMISC: u16 = 1;
+const PACKET_MR_ALLMULTI: u16 = 2;
+
+/// 网卡序号，与 SIOCGIFINDEX 返回值一致
+pub const LOOPBACK_IFINDEX: i32 = 1;
+pub const ETH0_IFINDEX: i32 = 2;
+
+/// 每个 packet socket 最多缓存的帧数，超出后丢弃并计入 tp_drops
+const PACKET_RX_QUEUE_LEN: usize = 256;
+
+/// 所有打开的 packet socket，网卡收发时遍历
+static PACKET_TAPS: Mutex<Vec<Weak<PacketTap>>> = Mutex::new(Vec::new());
+
+/// struct sockaddr_ll
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SockAddrLl {
+    pub sll_family: u16,
+    /// 网络字节序
+    pub sll_protocol: u16,
+    pub sll_ifindex: i32,
+    pub sll_hatype: u16,
+    pub sll_pkttype: u8,
+    pub sll_halen: u8,
+    pub sll_addr: [u8; 8],
+}
+
+/// struct packet_mreq
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PacketMreq {
+    pub mr_ifindex: i32,
+    pub mr_type: u16,
+    pub mr_alen: u16,
+    pub mr_address: [u8; 8],
+}
+
+/// struct tpacket_stats
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TpacketStats {
+    pub tp_packets: u32,
+    pub tp_drops: u32,
+}
+
+#[derive(TryFromPrimitive, Debug)]
+#[repr(usize)]
+#[allow(non_camel_case_types)]
+pub enum PacketOption {
+    PACKET_ADD_MEMBERSHIP = 1,
+    PACKET_DROP_MEMBERSHIP = 2,
+    PACKET_STATISTICS = 6,
+}
+
+struct CapturedFrame {
+    data: Vec<u8>,
+    ifindex: i32,
+    pkttype: u8,
+}
+
+/// 网卡与 socket 共享的接收端，socket 关闭后 Weak 失效
+struct PacketTap {
+    //0表示所有网卡
+    ifindex: AtomicI32,
+    //主机字节序，0表示不接收
+    protocol: AtomicU16,
+    //PACKET_MR_PROMISC 的引用计数
+    promisc: AtomicUsize,
+    filter: RwLock<Option<Vec<SockFilter>>>,
+    queue: Mutex<VecDeque<CapturedFrame>>,
+    packets: AtomicU32,
+    drops: AtomicU32,
+}
+
+impl PacketTap {
+    fn wants(&self, ifindex: i32, protocol: u16, pkttype: u8) -> bool {
+        let bound = self.ifindex.load(Ordering::Acquire);
+        if bound != 0 && bound != ifindex {
+            return false;
+        }
+        let want = self.protocol.load(Ordering::Acquire);
+        if want != ETH_P_ALL && want != protocol {
+            return false;
+        }
+        //非混杂模式下不接收发给其他主机的帧
+        if pkttype == PACKET_OTHERHOST && self.promisc.load(Ordering::Acquire) == 0 {
+            return false;
+        }
+        true
+    }
+    fn push(&self, frame: &[u8], ctx: &BpfContext) {
+        let snaplen = match self.filter.read().as_ref() {
+            Some(prog) => bpf::run(prog, frame, ctx) as usize,
+            None => frame.len(),
+        };
+        if snaplen == 0 {
+            return;
+        }
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        let mut queue = self.queue.lock();
+        if queue.len() >= PACKET_RX_QUEUE_LEN {
+            self.drops.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let len = core::cmp::min(snaplen, frame.len());
+        queue.push_back(CapturedFrame {
+            data: frame[..len].to_vec(),
+            ifindex: ctx.ifindex,
+            pkttype: ctx.pkttype,
+        });
+    }
+}
+
+/// 由网卡驱动在收发时调用，把帧复制给所有感兴趣的 packet socket
+pub fn deliver_frame(ifindex: i32, frame: &[u8], outgoing: bool) {
+    //先在锁内拿到所有存活的socket，避免在持锁时drop最后一个引用
+    let taps: Vec<Arc<PacketTap>> = {
+        let mut taps = PACKET_TAPS.lock();
+        if taps.is_empty() {
+            return;
+        }
+        taps.retain(|tap| tap.strong_count() > 0);
+        taps.iter().filter_map(|tap| tap.upgrade()).collect()
+    };
+    let Ok(ether) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    let protocol: u16 = ether.ethertype().into();
+    let pkttype = if outgoing {
+        PACKET_OUTGOING
+    } else if ifindex == LOOPBACK_IFINDEX {
+        //回环网卡收到的都是发给本机的报文
+        PACKET_HOST
+    } else if ether.dst_addr().is_broadcast() {
+        PACKET_BROADCAST
+    } else if ether.dst_addr().is_multicast() {
+        PACKET_MULTICAST
+    } else if ETH0
+        .get()
+        .is_some_and(|eth0| eth0.ethernet_address() == ether.dst_addr())
+    {
+        PACKET_HOST
+    } else {
+        PACKET_OTHERHOST
+    };
+    let ctx = BpfContext {
+        protocol,
+        pkttype,
+        ifindex,
+    };
+    for tap in taps.iter() {
+        if tap.wants(ifindex, protocol, pkttype) {
+            tap.push(frame, &ctx);
+        }
+    }
+}
+
+/// 由回环网卡在收发时调用，为 IP 报文补上以太网头后投递给 packet socket
+pub fn deliver_ip_packet(ifindex: i32, packet: &[u8], outgoing: bool) {
+    if PACKET_TAPS.lock().is_empty() {
+        return;
+    }
+    let ethertype = match IpVersion::of_packet(packet) {
+        Ok(IpVersion::Ipv4) => EthernetProtocol::Ipv4,
+        Ok(IpVersion::Ipv6) => EthernetProtocol::Ipv6,
+        Err(_) => return,
+    };
+    let mut frame = alloc::vec![0u8; ETH_HLEN + packet.len()];
+    frame[12..ETH_HLEN].copy_from_slice(&u16::from(ethertype).to_be_bytes());
+    frame[ETH_HLEN..].copy_from_slice(packet);
+    deliver_frame(ifindex, &frame, outgoing);
+}
+
+//poll_interfaces只会poll回环网卡，抓包时需要自己推动eth0收包
+fn poll_eth0() {
+    if let Some(eth0) = ETH0.get() {
+        eth0.poll(&super::SOCKET_SET.0);
+    }
+}
+
+/// 根据网卡名获取序号，供 SIOCGIFINDEX 使用
+pub fn ifname_to_index(name: &str) -> Option<i32> {
+    match name {
+        "lo" => Some(LOOPBACK_IFINDEX),
+        "eth0" => Some(ETH0_IFINDEX),
+        _ => None,
+    }
+}
+
+const SIOCGIFFLAGS: usize = 0x8913;
+const SIOCGIFHWADDR: usize = 0x8927;
+const SIOCGIFINDEX: usize = 0x8933;
+const IFNAMSIZ: usize = 16;
+//ifr_flags
+const IFF_UP: u16 = 0x1;
+const IFF_BROADCAST: u16 = 0x2;
+const IFF_LOOPBACK: u16 = 0x8;
+const IFF_RUNNING: u16 = 0x40;
+const IFF_MULTICAST: u16 = 0x1000;
+
+/// struct ifreq，联合体部分按最大的 struct sockaddr 处理
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IfReq {
+    ifr_name: [u8; IFNAMSIZ],
+    ifr_ifru: [u8; 24],
+}
+
+/// socket 上的 SIOCGIF* ioctl，libpcap 通过它们查询网卡
+pub fn netdev_ioctl(op: usize, arg_ptr: usize) -> SyscallRet {
+    if !matches!(op, SIOCGIFFLAGS | SIOCGIFHWADDR | SIOCGIFINDEX) {
+        return Err(Errno::ENOTTY);
+    }
+    let mut ifr = IfReq {
+        ifr_name: [0; IFNAMSIZ],
+        ifr_ifru: [0; 24],
+    };
+    copy_from_user(arg_ptr as *const IfReq, &mut ifr as *mut IfReq, 1)?;
+    let name_len = ifr
+        .ifr_name
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(IFNAMSIZ);
+    let name = core::str::from_utf8(&ifr.ifr_name[..name_len]).map_err(|_| Errno::ENODEV)?;
+    let ifindex = ifname_to_index(name).ok_or(Errno::ENODEV)?;
+    if ifindex == ETH0_IFINDEX && !ETH0.is_inited() {
+        return Err(Errno::ENODEV);
+    }
+    match op {
+        SIOCGIFINDEX => ifr.ifr_ifru[..4].copy_from_slice(&ifindex.to_ne_bytes()),
+        SIOCGIFHWADDR => {
+            //struct sockaddr: sa_family 为硬件类型，sa_data 为 MAC
+            if ifindex == ETH0_IFINDEX {
+                let mac = ETH0.get().unwrap().ethernet_address();
+                ifr.ifr_ifru[..2].copy_from_slice(&ARPHRD_ETHER.to_ne_bytes());
+                ifr.ifr_ifru[2..8].copy_from_slice(mac.as_bytes());
+            } else {
+                ifr.ifr_ifru[..2].copy_from_slice(&ARPHRD_LOOPBACK.to_ne_bytes());
+            }
+        }
+        _ => {
+            let flags = if ifindex == ETH0_IFINDEX {
+                IFF_UP | IFF_BROADCAST | IFF_RUNNING | IFF_MULTICAST
+            } else {
+                IFF_UP | IFF_LOOPBACK | IFF_RUNNING
+            };
+            ifr.ifr_ifru[..2].copy_from_slice(&flags.to_ne_bytes());
+        }
+    }
+    copy_to_user(arg_ptr as *mut IfReq, &ifr as *const IfReq, 1)?;
+    Ok(0)
+}
+
+pub struct PacketSocket {
+    //SOCK_DGRAM时收发不含以太网头
+    cooked: bool,
+    tap: Arc<PacketTap>,
+    nonblock: AtomicBool,
+}
+
+unsafe impl Sync for PacketSocket {}
+unsafe impl Send for PacketSocket {}
+
+impl PacketSocket {
+    /// protocol 为 socket() 传入的网络字节序协议号
+    pub fn new(cooked: bool, protocol: u16) -> Self {
+        let tap = Arc::new(PacketTap {
+            ifindex: AtomicI32::new(0),
+            protocol: AtomicU16::new(u16::from_be(protocol)),
+            promisc: AtomicUsize::new(0),
+            filter: RwLock::new(None),
+            queue: Mutex::new(VecDeque::new()),
+            packets: AtomicU32::new(0),
+            drops: AtomicU32::new(0),
+        });
+        PACKET_TAPS.lock().push(Arc::downgrade(&tap));
+        PacketSocket {
+            cooked,
+            tap,
+            nonblock: AtomicBool::new(false),
+        }
+    }
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+    pub fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Release);
+    }
+    pub fn bind(&self, addr: &SockAddrLl) -> Result<(), Errno> {
+        match addr.sll_ifindex {
+            0 | LOOPBACK_IFINDEX | ETH0_IFINDEX => {}
+            _ => return Err(Errno::ENODEV),
+        }
+        self.tap.ifindex.store(addr.sll_ifindex, Ordering::Release);
+        //protocol为0时保持socket()时的协议
+        if addr.sll_protocol != 0 {
+            self.tap
+                .protocol
+                .store(u16::from_be(addr.sll_protocol), Ordering::Release);
+        }
+        Ok(())
+    }
+    pub fn local_addr(&self) -> SockAddrLl {
+        let ifindex = self.tap.ifindex.load(Ordering::Acquire);
+        let mut addr = SockAddrLl {
+            sll_family: super::socket::Domain::AF_PACKET as u16,
+            sll_protocol: self.tap.protocol.load(Ordering::Acquire).to_be(),
+            sll_ifindex: ifindex,
+            ..Default::default()
+        };
+        if ifindex == ETH0_IFINDEX {
+            if let Some(eth0) = ETH0.get() {
+                addr.sll_hatype = ARPHRD_ETHER;
+                addr.sll_halen = 6;
+                addr.sll_addr[..6].copy_from_slice(eth0.ethernet_address().as_bytes());
+            }
+        } else if ifindex == LOOPBACK_IFINDEX {
+            //回环网卡的地址全零
+            addr.sll_hatype = ARPHRD_LOOPBACK;
+            addr.sll_halen = 6;
+        }
+        addr
+    }
+    pub fn attach_filter(&self, prog: Vec<SockFilter>) {
+        *self.tap.filter.write() = Some(prog);
+    }
+    pub fn detach_filter(&self) -> Result<(), Errno> {
+        match self.tap.filter.write().take() {
+            Some(_) => Ok(()),
+            None => Err(Errno::ENOENT),
+        }
+    }
+    pub fn add_membership(&self, mreq: &PacketMreq) -> Result<(), Errno> {
+        self.check_mreq(mreq)?;
+        if mreq.mr_type == PACKET_MR_PROMISC {
+            self.tap.promisc.fetch_add(1, Ordering::AcqRel);
+        }
+        Ok(())
+    }
+    pub fn drop_membership(&self, mreq: &PacketMreq) -> Result<(), Errno> {
+        self.check_mreq(mreq)?;
+        if mreq.mr_type == PACKET_MR_PROMISC {
+            self.tap
+                .promisc
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+                .map_err(|_| Errno::EADDRNOTAVAIL)?;
+        }
+        Ok(())
+    }
+    /// 读取后清零，与 Linux 一致
+    pub fn statistics(&self) -> TpacketStats {
+        let drops = self.tap.drops.swap(0, Ordering::AcqRel);
+        let packets = self.tap.packets.swap(0, Ordering::AcqRel);
+        TpacketStats {
+            tp_packets: packets,
+            tp_drops: drops,
+        }
+    }
+
+    /// 不带目的地址发送，只有 SOCK_RAW 可以
+    pub fn send(&self, buf: &[u8]) -> Result<usize, Errno> {
+        if self.cooked {
+            return Err(Errno::EDESTADDRREQ);
+        }
+        let ifindex = self.tap.ifindex.load(Ordering::Acquire);
+        self.transmit(ifindex, buf)?;
+        Ok(buf.len())
+    }
+    pub fn send_to(&self, buf: &[u8], addr: &SockAddrLl) -> Result<usize, Errno> {
+        let ifindex = if addr.sll_ifindex != 0 {
+            addr.sll_ifindex
+        } else {
+            self.tap.ifindex.load(Ordering::Acquire)
+        };
+        if !self.cooked {
+            self.transmit(ifindex, buf)?;
+            return Ok(buf.len());
+        }
+        //SOCK_DGRAM 由内核补上以太网头
+        if addr.sll_halen != 6 {
+            return Err(Errno::EINVAL);
+        }
+        let protocol = if addr.sll_protocol != 0 {
+            addr.sll_protocol
+        } else {
+            self.tap.protocol.load(Ordering::Acquire).to_be()
+        };
+        let mut frame = Vec::with_capacity(ETH_HLEN + buf.len());
+        frame.extend_from_slice(&addr.sll_addr[..6]);
+        if ifindex == LOOPBACK_IFINDEX {
+            frame.extend_from_slice(&[0; 6]);
+        } else {
+            let eth0 = ETH0.get().ok_or(Errno::ENODEV)?;
+            frame.extend_from_slice(eth0.ethernet_address().as_bytes());
+        }
+        frame.extend_from_slice(&protocol.to_ne_bytes());
+        frame.extend_from_slice(buf);
+        self.transmit(ifindex, &frame)?;
+        Ok(buf.len())
+    }
+    /// 返回拷贝长度、帧原始长度与来源地址
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, usize, SockAddrLl), Errno> {
+        loop {
+            let frame = self.tap.queue.lock().pop_front();
+            match frame {
+                Some(frame) => {
+                    let ether = EthernetFrame::new_unchecked(&frame.data);
+                    let data = if self.cooked {
+                        &frame.data[ETH_HLEN.min(frame.data.len())..]
+                    } else {
+                        &frame.data[..]
+                    };
+                    let copy_len = core::cmp::min(buf.len(), data.len());
+                    buf[..copy_len].copy_from_slice(&data[..copy_len]);
+                    let mut addr = SockAddrLl {
+                        sll_family: super::socket::Domain::AF_PACKET as u16,
+                        sll_ifindex: frame.ifindex,
+                        sll_hatype: if frame.ifindex == LOOPBACK_IFINDEX {
+                            ARPHRD_LOOPBACK
+                        } else {
+                            ARPHRD_ETHER
+                        },
+                        sll_pkttype: frame.pkttype,
+                        ..Default::default()
+                    };
+                    if frame.data.len() >= ETH_HLEN {
+                        let protocol: u16 = ether.ethertype().into();
+                        addr.sll_protocol = protocol.to_be();
+                        addr.sll_halen = 6;
+                        addr.sll_addr[..6].copy_from_slice(ether.src_addr().as_bytes());
+                    }
+                    return Ok((copy_len, data.len(), addr));
+                }
+                None => {
+                    if self.is_nonblocking() {
+                        return Err(Errno::EAGAIN);
+                    }
+                    poll_eth0();
+                    super::poll_interfaces();
+                    yield_current_task();
+                }
+            }
+        }
+    }
+    pub fn poll(&self) -> PollState {
+        poll_eth0();
+        super::poll_interfaces();
+        PollState {
+            readable: !self.tap.queue.lock().is_empty(),
+            writeable: true,
+        }
+    }
+}
+
+//private function
+impl PacketSocket {
+    fn check_mreq(&self, mreq: &PacketMreq) -> Result<(), Errno> {
+        match mreq.mr_ifindex {
+            LOOPBACK_IFINDEX | ETH0_IFINDEX => {}
+            _ => return Err(Errno::ENODEV),
+        }
+        match mreq.mr_type {
+            PACKET_MR_MULTICAST | PACKET_MR_PROMISC | PACKET_MR_ALLMULTI => Ok(()),
+            _ => Err(Errno::EINVAL),
+        }
+    }
+    fn transmit(&self, ifindex: i32, frame: &[u8]) -> Result<(), Errno> {
+        if frame.len() < ETH_HLEN {
+            return Err(Errno::EINVAL);
+        }
+        let max_len = if ifindex == LOOPBACK_IFINDEX {
+            ETH_HLEN + LOOPBACK_MTU
+        } else {
+            ETH_FRAME_LEN
+        };
+        if frame.len() > max_len {
+            return Err(Errno::EMSGSIZE);
+        }
+        match ifindex {
+            //回环网卡收发的是IP报文, 去掉以太网头后放入回环队列, 由回环网卡收包时再抓取
+            LOOPBACK_IFINDEX => {
+                deliver_frame(LOOPBACK_IFINDEX, frame, true);
+                let ether = EthernetFrame::new_unchecked(frame);
+                if matches!(
+                    ether.ethertype(),
+                    EthernetProtocol::Ipv4 | EthernetProtocol::Ipv6
+                ) {
+                    super::loopback_inject(ether.payload());
+                }
+                Ok(())
+            }
+            //未bind时默认从eth0发出
+            0 | ETH0_IFINDEX => {
+                let eth0 = ETH0.get().ok_or(Errno::ENODEV)?;
+                eth0.send_frame(frame)
+            }
+            _ => Err(Errno::ENXIO),
+        }
+    }
+}
+
+impl PacketOption {
+    pub fn set(&self, socket: &PacketSocket, opt: &[u8]) -> SyscallRet {
+        match self {
+            PacketOption::PACKET_ADD_MEMBERSHIP | PacketOption::PACKET_DROP_MEMBERSHIP => {
+                if opt.len() < core::mem::size_of::<PacketMreq>() {
+                    return Err(Errno::EINVAL);
+                }
+                let mreq: PacketMreq =
+                    unsafe { core::ptr::read_unaligned(opt.as_ptr() as *const PacketMreq) };
+                match self {
+                    PacketOption::PACKET_ADD_MEMBERSHIP => socket.add_membership(&mreq)?,
+                    _ => socket.drop_membership(&mreq)?,
+                }
+                Ok(0)
+            }
+            PacketOption::PACKET_STATISTICS => Err(Errno::ENOPROTOOPT),
+        }
+    }
+    pub fn get(&self, socket: &PacketSocket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallRet {
+        match self {
+            PacketOption::PACKET_STATISTICS => {
+                let mut len: u32 = 0;
+                copy_from_user(opt_len as *const u32, &mut len as *mut u32, 1)?;
+                let stats = socket.statistics();
+                let size = core::mem::size_of::<TpacketStats>();
+                let len = core::cmp::min(len as usize, size);
+                copy_to_user(opt_value, &stats as *const TpacketStats as *const u8, len)?;
+                copy_to_user(opt_len, &(len as u32) as *const u32, 1)?;
+                Ok(0)
+            }
+            _ => Err(Errno::ENOPROTOOPT),
+        }
+    }
+}
+
+/// 从用户态读取 sockaddr_ll
+pub fn sockaddr_ll_from(addr: *const u8, len: usize) -> Result<SockAddrLl, Errno> {
+    //Linux 允许省略 sll_addr 的后两个字节
+    if len < 18 {
+        return Err(Errno::EINVAL);
+    }
+    let len = core::cmp::min(len, core::mem::size_of::<SockAddrLl>());
+    let mut sll = SockAddrLl::default();
+    copy_from_user(addr, &mut sll as *mut SockAddrLl as *mut u8, len)?;
+    if sll.sll_family != super::socket::Domain::AF_PACKET as u16 {
+        return Err(Errno::EINVAL);
+    }
+    Ok(sll)
+}
+
+/// 向用户态写回 sockaddr_ll 与实际长度
+pub fn sockaddr_ll_to(sll: &SockAddrLl, addr: usize, addr_len: usize) -> SyscallRet {
+    if addr == 0 || addr_len == 0 {
+        return Ok(0);
+    }
+    let mut len: u32 = 0;
+    copy_from_user(addr_len as *const u32, &mut len as *mut u32, 1)?;
+    let size = core::mem::size_of::<SockAddrLl>();
+    copy_to_user(
+        addr as *mut u8,
+        sll as *const SockAddrLl as *const u8,
+        core::cmp::min(len as usize, size),
+    )?;
+    copy_to_user(addr_len as *mut u32, &(size as u32) as *const u32, 1)?;
+    Ok(size)
+}
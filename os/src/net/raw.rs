@@ -0,0 +1,332 @@
+//! SOCK_RAW 套接字
+//!
+//! 基于 smoltcp 的 raw socket 实现 AF_INET/AF_INET6 下的原始套接字：
+//! - IPv4: 接收到的数据包含完整 IP 头；发送时默认由内核构造 IP 头，设置 IP_HDRINCL 后由用户提供
+//! - IPv6: 与 Linux 一致，收发均不包含 IPv6 头，ICMPv6 的校验和由内核计算
+use core::{
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+use alloc::vec;
+use smoltcp::{
+    iface::SocketHandle,
+    phy::ChecksumCapabilities,
+    socket::raw,
+    wire::{
+        Icmpv6Packet, IpAddress, IpProtocol, IpVersion, Ipv4Address, Ipv4Packet, Ipv4Repr,
+        Ipv6Address, Ipv6Packet, Ipv6Repr,
+    },
+};
+use spin::RwLock;
+
+use crate::{
+    syscall::errno::Errno,
+    task::yield_current_task,
+};
+
+use super::{
+    addr::{from_ipendpoint_to_socketaddr, from_sockaddr_to_ipendpoint, is_unspecified},
+    poll_interfaces,
+    tcp::PollState,
+    SocketSetWrapper, IP, IP_V6, SOCKET_SET,
+};
+
+/// IPPROTO_RAW, 使用该协议号创建的套接字隐含 IP_HDRINCL
+pub const IPPROTO_RAW: u8 = 255;
+/// 默认 TTL/hop limit
+pub const DEFAULT_TTL: u8 = 64;
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+
+pub struct RawSocket {
+    handle: SocketHandle,
+    ip_version: IpVersion,
+    protocol: IpProtocol,
+    //bind/connect 只记录地址，raw socket 没有端口
+    local_addr: RwLock<Option<IpAddress>>,
+    remote_addr: RwLock<Option<IpAddress>>,
+    nonblock: AtomicBool,
+    //为true时用户提供完整的 IPv4 头
+    hdrincl: AtomicBool,
+    ttl: AtomicU8,
+}
+
+unsafe impl Sync for RawSocket {}
+unsafe impl Send for RawSocket {}
+
+impl RawSocket {
+    pub fn new(ip_version: IpVersion, protocol: u8) -> Self {
+        let ip_protocol = IpProtocol::from(protocol);
+        let socket = SocketSetWrapper::new_raw_socket(ip_version, ip_protocol);
+        let handle = SOCKET_SET.add(socket);
+        RawSocket {
+            handle,
+            ip_version,
+            protocol: ip_protocol,
+            local_addr: RwLock::new(None),
+            remote_addr: RwLock::new(None),
+            nonblock: AtomicBool::new(false),
+            hdrincl: AtomicBool::new(protocol == IPPROTO_RAW),
+            ttl: AtomicU8::new(DEFAULT_TTL),
+        }
+    }
+    pub fn protocol(&self) -> u8 {
+        self.protocol.into()
+    }
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+    pub fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Release);
+    }
+    pub fn is_hdrincl(&self) -> bool {
+        self.hdrincl.load(Ordering::Acquire)
+    }
+    pub fn set_hdrincl(&self, hdrincl: bool) -> Result<(), Errno> {
+        //IPv6 raw socket不支持IP_HDRINCL
+        if self.ip_version == IpVersion::Ipv6 {
+            return Err(Errno::ENOPROTOOPT);
+        }
+        self.hdrincl.store(hdrincl, Ordering::Release);
+        Ok(())
+    }
+    pub fn ttl(&self) -> u8 {
+        self.ttl.load(Ordering::Acquire)
+    }
+    pub fn set_ttl(&self, ttl: u8) {
+        self.ttl.store(ttl, Ordering::Release);
+    }
+    pub fn local_addr(&self) -> Result<SocketAddr, Errno> {
+        let addr = self.local_addr.read().unwrap_or(self.unspecified());
+        Ok(from_ipendpoint_to_socketaddr((addr, 0).into()))
+    }
+    pub fn remote_addr(&self) -> Result<SocketAddr, Errno> {
+        match *self.remote_addr.read() {
+            Some(addr) => Ok(from_ipendpoint_to_socketaddr((addr, 0).into())),
+            None => Err(Errno::ENOTCONN),
+        }
+    }
+    pub fn is_connected(&self) -> bool {
+        self.remote_addr.read().is_some()
+    }
+    /// raw socket 的 bind 只设置源地址，用于过滤接收和构造 IP 头
+    pub fn bind(&self, addr: SocketAddr) -> Result<(), Errno> {
+        let addr = self.check_family(addr)?;
+        *self.local_addr.write() = (!is_unspecified(addr)).then_some(addr);
+        Ok(())
+    }
+    pub fn connect(&self, addr: SocketAddr) -> Result<(), Errno> {
+        let addr = self.check_family(addr)?;
+        *self.remote_addr.write() = Some(addr);
+        Ok(())
+    }
+    pub fn shutdown(&self) {
+        *self.remote_addr.write() = None;
+    }
+
+    pub fn send(&self, buf: &[u8]) -> Result<usize, Errno> {
+        let remote = self.remote_addr.read().ok_or(Errno::EDESTADDRREQ)?;
+        self.send_impl(buf, remote)
+    }
+    pub fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, Errno> {
+        let remote = self.check_family(addr)?;
+        self.send_impl(buf, remote)
+    }
+
+    /// 接收一个数据报，返回(实际拷贝长度, 源地址)，超出用户缓冲区的部分被丢弃
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Errno> {
+        loop {
+            poll_interfaces();
+            //直接从 smoltcp 的接收缓冲区拷贝到用户缓冲区
+            let res = SOCKET_SET.with_socket_mut::<_, raw::Socket, _>(self.handle, |socket| {
+                let packet = socket.recv().ok()?;
+                let Some((src, dst, header_len)) = parse_ip_header(packet) else {
+                    return Some(None);
+                };
+                if !self.accepts(src, dst) {
+                    return Some(None);
+                }
+                //IPv6 raw socket 不向用户返回 IP 头
+                let data = match self.ip_version {
+                    IpVersion::Ipv4 => packet,
+                    IpVersion::Ipv6 => &packet[header_len..],
+                };
+                let copy_len = core::cmp::min(data.len(), buf.len());
+                buf[..copy_len].copy_from_slice(&data[..copy_len]);
+                Some(Some((copy_len, src)))
+            });
+            match res {
+                Some(Some((copy_len, src))) => {
+                    return Ok((copy_len, from_ipendpoint_to_socketaddr((src, 0).into())));
+                }
+                //不属于本套接字的报文，丢弃后继续接收
+                Some(None) => continue,
+                None => {
+                    if self.is_nonblocking() {
+                        return Err(Errno::EAGAIN);
+                    }
+                    yield_current_task();
+                }
+            }
+        }
+    }
+
+    pub fn poll(&self) -> PollState {
+        SOCKET_SET.with_socket::<_, raw::Socket, _>(self.handle, |socket| PollState {
+            readable: socket.can_recv(),
+            writeable: socket.can_send(),
+        })
+    }
+}
+
+//private function
+impl RawSocket {
+    fn unspecified(&self) -> IpAddress {
+        match self.ip_version {
+            IpVersion::Ipv4 => IpAddress::Ipv4(Ipv4Address::UNSPECIFIED),
+            IpVersion::Ipv6 => IpAddress::Ipv6(Ipv6Address::UNSPECIFIED),
+        }
+    }
+    fn check_family(&self, addr: SocketAddr) -> Result<IpAddress, Errno> {
+        let addr = from_sockaddr_to_ipendpoint(addr).addr;
+        match (self.ip_version, addr) {
+            (IpVersion::Ipv4, IpAddress::Ipv4(_)) | (IpVersion::Ipv6, IpAddress::Ipv6(_)) => {
+                Ok(addr)
+            }
+            _ => Err(Errno::EAFNOSUPPORT),
+        }
+    }
+    fn accepts(&self, src: IpAddress, dst: IpAddress) -> bool {
+        if let Some(local) = *self.local_addr.read() {
+            if local != dst {
+                return false;
+            }
+        }
+        if let Some(remote) = *self.remote_addr.read() {
+            if remote != src {
+                return false;
+            }
+        }
+        true
+    }
+    /// 根据目的地址选择源地址，回环目的使用回环地址，其余使用网卡地址
+    fn source_for(&self, dst: IpAddress) -> IpAddress {
+        if let Some(local) = *self.local_addr.read() {
+            return local;
+        }
+        match dst {
+            IpAddress::Ipv4(v4) if v4.is_loopback() => IpAddress::v4(127, 0, 0, 1),
+            IpAddress::Ipv6(v6) if v6.is_loopback() => IpAddress::Ipv6(Ipv6Address::LOOPBACK),
+            IpAddress::Ipv4(_) => IP.parse().unwrap(),
+            IpAddress::Ipv6(_) => IP_V6.parse().unwrap(),
+        }
+    }
+    fn send_impl(&self, buf: &[u8], remote: IpAddress) -> Result<usize, Errno> {
+        let packet = match (self.ip_version, remote) {
+            (IpVersion::Ipv4, IpAddress::Ipv4(dst)) => {
+                if self.is_hdrincl() {
+                    //用户自己构造IP头，这里只做合法性检查，IP头校验和由smoltcp填写
+                    let packet = Ipv4Packet::new_checked(buf).map_err(|_| Errno::EINVAL)?;
+                    if packet.next_header() != self.protocol && self.protocol() != IPPROTO_RAW {
+                        return Err(Errno::EINVAL);
+                    }
+                    buf.to_vec()
+                } else {
+                    let src = match self.source_for(remote) {
+                        IpAddress::Ipv4(v4) => v4,
+                        _ => return Err(Errno::EAFNOSUPPORT),
+                    };
+                    let repr = Ipv4Repr {
+                        src_addr: src,
+                        dst_addr: dst,
+                        next_header: self.protocol,
+                        payload_len: buf.len(),
+                        hop_limit: self.ttl(),
+                    };
+                    let mut packet = vec![0u8; IPV4_HEADER_LEN + buf.len()];
+                    let mut ipv4 = Ipv4Packet::new_unchecked(&mut packet);
+                    repr.emit(&mut ipv4, &ChecksumCapabilities::default());
+                    ipv4.payload_mut().copy_from_slice(buf);
+                    packet
+                }
+            }
+            (IpVersion::Ipv6, IpAddress::Ipv6(dst)) => {
+                let src = match self.source_for(remote) {
+                    IpAddress::Ipv6(v6) => v6,
+                    _ => return Err(Errno::EAFNOSUPPORT),
+                };
+                let repr = Ipv6Repr {
+                    src_addr: src,
+                    dst_addr: dst,
+                    next_header: self.protocol,
+                    payload_len: buf.len(),
+                    hop_limit: self.ttl(),
+                };
+                let mut packet = vec![0u8; IPV6_HEADER_LEN + buf.len()];
+                let mut ipv6 = Ipv6Packet::new_unchecked(&mut packet);
+                repr.emit(&mut ipv6);
+                ipv6.payload_mut().copy_from_slice(buf);
+                //ICMPv6 的校验和必须由内核计算(RFC 3542)
+                if self.protocol == IpProtocol::Icmpv6 {
+                    let mut icmp = Icmpv6Packet::new_checked(ipv6.payload_mut())
+                        .map_err(|_| Errno::EINVAL)?;
+                    icmp.fill_checksum(&src, &dst);
+                }
+                packet
+            }
+            _ => return Err(Errno::EAFNOSUPPORT),
+        };
+        log::error!(
+            "[RawSocket_send]:send {} bytes to {:?}",
+            packet.len(),
+            remote
+        );
+        loop {
+            let res = SOCKET_SET.with_socket_mut::<_, raw::Socket, _>(self.handle, |socket| {
+                if packet.len() > socket.payload_send_capacity() {
+                    return Err(Errno::EMSGSIZE);
+                }
+                socket.send_slice(&packet).map_err(|_| Errno::EAGAIN)
+            });
+            match res {
+                Ok(()) => break,
+                Err(Errno::EAGAIN) if !self.is_nonblocking() => {
+                    poll_interfaces();
+                    yield_current_task();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        poll_interfaces();
+        Ok(buf.len())
+    }
+}
+
+/// 解析 IP 头，返回(源地址, 目的地址, 头部长度)
+fn parse_ip_header(packet: &[u8]) -> Option<(IpAddress, IpAddress, usize)> {
+    match IpVersion::of_packet(packet).ok()? {
+        IpVersion::Ipv4 => {
+            let ipv4 = Ipv4Packet::new_checked(packet).ok()?;
+            Some((
+                IpAddress::Ipv4(ipv4.src_addr()),
+                IpAddress::Ipv4(ipv4.dst_addr()),
+                ipv4.header_len() as usize,
+            ))
+        }
+        IpVersion::Ipv6 => {
+            let ipv6 = Ipv6Packet::new_checked(packet).ok()?;
+            Some((
+                IpAddress::Ipv6(ipv6.src_addr()),
+                IpAddress::Ipv6(ipv6.dst_addr()),
+                IPV6_HEADER_LEN,
+            ))
+        }
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        SOCKET_SET.remove(self.handle);
+    }
+}
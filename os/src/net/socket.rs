@@ -18,7 +18,7 @@ use core::{
 };
 
 use crate::{
-    arch::{config::SysResult, mm::copy_to_user},
+    arch::{config::SysResult, mm::copy_to_user, timer::get_time_ms},
     fs::{
         fdtable::FdFlags, file::OpenFlags, inode::InodeOp, namei::path_openat, pipe::Pipe,
         uapi::IoVec,
@@ -37,8 +37,8 @@ use alloc::{string::ToString, vec};
 use hashbrown::Equivalent;
 use num_enum::TryFromPrimitive;
 use smoltcp::{
-    socket::tcp::{self, State},
-    wire::{IpAddress, Ipv4Address},
+    socket::tcp::{self, CongestionControl, State},
+    wire::{IpAddress, IpVersion, Ipv4Address},
 };
 use spin::{Mutex, MutexGuard};
 // use crate::{arch::{config::SysResult, mm::copy_to_user}, fs::{fdtable::FdFlags, file::{File, OpenFlags}, namei::path_openat, pipe::Pipe, uapi::IoVec}, net::{alg::{encode_text, AlgType}, udp::get_ephemeral_port, unix::PasswdEntry, SOCKET_SET}, task::{current_task, yield_current_task}, timer::TimeSpec};
@@ -52,11 +52,16 @@ use crate::{
 
 use super::{
     add_membership,
-    addr::{from_ipendpoint_to_socketaddr, UNSPECIFIED_ENDPOINT},
+    addr::{from_ipendpoint_to_socketaddr, from_v4_mapped, to_v4_mapped, UNSPECIFIED_ENDPOINT},
     alg::SockAddrAlg,
-    poll_interfaces, remove_membership,
-    tcp::TcpSocket,
-    udp::UdpSocket,
+    bpf::{copy_filter_from_user, SockFprog},
+    icmp::{IcmpSocket, IPPROTO_ICMP, IPPROTO_ICMPV6},
+    packet::{netdev_ioctl, PacketSocket},
+    poll_interfaces, route_mtu,
+    raw::{RawSocket, DEFAULT_TTL},
+    remove_membership,
+    tcp::{TcpSocket, TcpStatsSnapshot, MAX_TCP_KEEPCNT, MAX_TCP_KEEPIDLE, MAX_TCP_KEEPINTVL},
+    udp::{UdpSocket, IP_PMTUDISC_DONT, IP_PMTUDISC_OMIT, IP_PMTUDISC_WANT},
     unix::{Database, NscdRequest, RequestType},
     IP,
 };
@@ -78,6 +83,7 @@ pub enum Domain {
     AF_UNSPEC = 512,
     AF_ALG = 38,
     AF_RDS = 21,
+    AF_PACKET = 17,
 }
 #[derive(TryFromPrimitive, Clone, PartialEq, Eq, Debug, Copy)]
 #[repr(usize)]
@@ -105,6 +111,12 @@ pub enum SocketType {
 pub enum SocketInner {
     Tcp(TcpSocket),
     Udp(UdpSocket),
+    //SOCK_RAW
+    Raw(RawSocket),
+    //SOCK_DGRAM+IPPROTO_ICMP/IPPROTO_ICMPV6 ping socket
+    Icmp(IcmpSocket),
+    //AF_PACKET
+    Packet(PacketSocket),
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -133,6 +145,7 @@ pub struct Socket {
     congestion: Mutex<String>,
     //setsockopt需要设置timeout,这里可以加一个
     recvtimeout: Mutex<Option<TimeSpec>>,
+    sendtimeout: Mutex<Option<TimeSpec>>,
     dont_route: bool,
     pub buffer: Option<Arc<BufferEnd>>,
     //用于send中flag为msg_more时存储，否则为none
@@ -154,6 +167,8 @@ pub struct Socket {
     //unix发送的send内容，往往用于passwd,group的euid,
     //这里如果sendfd,recvdfd为none则只需要这里读取passwd或者group中内容
     pub socket_nscdrequest: Mutex<Option<NscdRequest>>,
+    //IPV6_V6ONLY,只对AF_INET6套接字有意义
+    ipv6_only: AtomicBool,
     // waiter:Mutex<Vec<Tid>>,
 }
 
@@ -203,10 +218,28 @@ impl Socket {
     fn get_recv_timeout(&self) -> Option<TimeSpec> {
         *self.recvtimeout.lock()
     }
+    fn set_send_timeout(&self, time: Option<TimeSpec>) {
+        *self.sendtimeout.lock() = time;
+    }
+    fn get_send_timeout(&self) -> Option<TimeSpec> {
+        *self.sendtimeout.lock()
+    }
+    /// SO_SNDTIMEO对应的毫秒数, 用户传入的是timeval, nsec字段中实际是微秒
+    fn send_timeout_ms(&self) -> Option<usize> {
+        self.get_send_timeout()
+            .map(|time| time.sec * 1000 + time.nsec / 1000)
+    }
+    fn tcp_send(&self, tcp_socket: &TcpSocket, buf: &[u8]) -> Result<usize, Errno> {
+        match self.send_timeout_ms() {
+            Some(timeout_ms) => tcp_socket.send_timeout(buf, timeout_ms),
+            None => tcp_socket.send(buf),
+        }
+    }
     fn get_reuse_addr(&self) -> bool {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.is_reuse_addr(),
             SocketInner::Udp(udp_socket) => udp_socket.is_reuse_addr(),
+            SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => false,
         }
     }
     fn get_send_buf_size(&self) -> u64 {
@@ -224,6 +257,7 @@ impl Socket {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.set_reuse_addr(reuse),
             SocketInner::Udp(udp_socket) => udp_socket.set_reuse_addr(reuse),
+            SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => {}
         }
     }
     fn set_send_buf_size(&self, size: u64) {
@@ -314,8 +348,28 @@ impl Socket {
     pub fn set_ciphertext(&self, ciphertext: &[u8]) {
         *self.socket_af_ciphertext.lock() = Some(ciphertext.to_vec());
     }
-    pub fn new(domain: Domain, socket_type: SocketType) -> Self {
+    pub fn new(domain: Domain, socket_type: SocketType, protocol: usize) -> Self {
+        let is_inet = domain == Domain::AF_INET || domain == Domain::AF_INET6;
+        let ip_version = if domain == Domain::AF_INET6 {
+            IpVersion::Ipv6
+        } else {
+            IpVersion::Ipv4
+        };
         let inner = match socket_type {
+            SocketType::SOCK_RAW | SocketType::SOCK_DGRAM if domain == Domain::AF_PACKET => {
+                SocketInner::Packet(PacketSocket::new(
+                    socket_type == SocketType::SOCK_DGRAM,
+                    protocol as u16,
+                ))
+            }
+            SocketType::SOCK_RAW if is_inet => {
+                SocketInner::Raw(RawSocket::new(ip_version, protocol as u8))
+            }
+            SocketType::SOCK_DGRAM
+                if is_inet && (protocol == IPPROTO_ICMP || protocol == IPPROTO_ICMPV6) =>
+            {
+                SocketInner::Icmp(IcmpSocket::new(ip_version))
+            }
             SocketType::SOCK_STREAM | SocketType::SOCK_RAW => SocketInner::Tcp(TcpSocket::new()),
             SocketType::SOCK_DGRAM | SocketType::SOCK_SEQPACKET => {
                 SocketInner::Udp(UdpSocket::new())
@@ -334,6 +388,7 @@ impl Socket {
             send_buf_size: AtomicU64::new(64 * 1024),
             recv_buf_size: AtomicU64::new(64 * 1024),
             recvtimeout: Mutex::new(None),
+            sendtimeout: Mutex::new(None),
             congestion: Mutex::new(String::from("reno")),
             buffer: None,
             pend_send: Mutex::new(None),
@@ -348,6 +403,7 @@ impl Socket {
             socket_peer_file_unix: Mutex::new(None),
             socket_ucred: Mutex::new(None),
             socket_peer_ucred: Mutex::new(None),
+            ipv6_only: AtomicBool::new(false),
             // waiter:Mutex::new(Vec::new()),
         }
     }
@@ -355,6 +411,9 @@ impl Socket {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.set_nonblocking(block),
             SocketInner::Udp(udp_socket) => udp_socket.set_nonblocking(block),
+            SocketInner::Raw(raw_socket) => raw_socket.set_nonblocking(block),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.set_nonblocking(block),
+            SocketInner::Packet(packet_socket) => packet_socket.set_nonblocking(block),
         }
     }
     pub fn set_close_on_exec(&self, is_set: bool) -> bool {
@@ -366,18 +425,25 @@ impl Socket {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.is_connected(),
             SocketInner::Udp(udp_socket) => udp_socket.with_socket(|socket| socket.is_open()),
+            SocketInner::Raw(raw_socket) => raw_socket.is_connected(),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.is_connected(),
+            SocketInner::Packet(_) => false,
         }
     }
     pub fn is_nonblocking(&self) -> bool {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.is_nonblocking(),
             SocketInner::Udp(udp_socket) => udp_socket.is_nonblocking(),
+            SocketInner::Raw(raw_socket) => raw_socket.is_nonblocking(),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.is_nonblocking(),
+            SocketInner::Packet(packet_socket) => packet_socket.is_nonblocking(),
         }
     }
     pub fn is_block(&self) -> bool {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.is_block(),
             SocketInner::Udp(udp_socket) => udp_socket.is_block(),
+            SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => false,
         }
     }
     pub fn get_is_af_alg(&self) -> bool {
@@ -419,6 +485,9 @@ impl Socket {
                 Ok(from_ipendpoint_to_socketaddr(local_addr))
             }
             SocketInner::Udp(udp_socket) => udp_socket.local_addr(),
+            SocketInner::Raw(raw_socket) => raw_socket.local_addr(),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.local_addr(),
+            SocketInner::Packet(_) => Err(Errno::EOPNOTSUPP),
         }
     }
     pub fn get_remote_addr(&self) -> Result<SocketAddr, Errno> {
@@ -428,12 +497,70 @@ impl Socket {
                 Ok(from_ipendpoint_to_socketaddr(remote_addr))
             }
             SocketInner::Udp(udp_socket) => udp_socket.reomte_addr(),
+            SocketInner::Raw(raw_socket) => raw_socket.remote_addr(),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.remote_addr(),
+            SocketInner::Packet(_) => Err(Errno::EOPNOTSUPP),
         }
     }
-    pub fn bind(&self, local_addr: SocketAddr) {
+    pub fn bind(&self, local_addr: SocketAddr) -> Result<(), Errno> {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.bind(local_addr),
             SocketInner::Udp(udp_socket) => udp_socket.bind(local_addr),
+            SocketInner::Raw(raw_socket) => return raw_socket.bind(local_addr),
+            SocketInner::Icmp(icmp_socket) => return icmp_socket.bind(local_addr),
+            SocketInner::Packet(_) => return Err(Errno::EINVAL),
+        }
+        Ok(())
+    }
+    pub fn as_packet(&self) -> Option<&PacketSocket> {
+        match &self.inner {
+            SocketInner::Packet(packet_socket) => Some(packet_socket),
+            _ => None,
+        }
+    }
+    /// 是否为raw socket或ping socket，这类socket的recvfrom需要写回源地址
+    pub fn is_raw(&self) -> bool {
+        matches!(&self.inner, SocketInner::Raw(_) | SocketInner::Icmp(_))
+    }
+    pub fn set_ttl(&self, ttl: u8) {
+        match &self.inner {
+            SocketInner::Tcp(tcp_socket) => tcp_socket.set_hop_limit(ttl),
+            SocketInner::Udp(udp_socket) => udp_socket.set_socket_ttl(ttl),
+            SocketInner::Raw(raw_socket) => raw_socket.set_ttl(ttl),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.set_hop_limit(ttl),
+            SocketInner::Packet(_) => {}
+        }
+    }
+    pub fn is_ipv6_only(&self) -> bool {
+        self.ipv6_only.load(core::sync::atomic::Ordering::Acquire)
+    }
+    pub fn set_ipv6_only(&self, v6only: bool) {
+        self.ipv6_only
+            .store(v6only, core::sync::atomic::Ordering::Release);
+    }
+    /// 写回用户态的地址,AF_INET6 套接字上的 ipv4 地址转换为 v4-mapped 地址
+    pub fn user_addr(&self, addr: SocketAddr) -> SocketAddr {
+        if self.domain == Domain::AF_INET6 {
+            to_v4_mapped(addr)
+        } else {
+            addr
+        }
+    }
+    pub fn path_mtu(&self) -> Result<usize, Errno> {
+        match &self.inner {
+            SocketInner::Tcp(tcp_socket) if tcp_socket.is_connected() => {
+                let remote = tcp_socket.remote_addr()?;
+                Ok(route_mtu(remote.addr))
+            }
+            SocketInner::Udp(udp_socket) => udp_socket.path_mtu(),
+            _ => Err(Errno::ENOTCONN),
+        }
+    }
+    pub fn ttl(&self) -> u8 {
+        match &self.inner {
+            SocketInner::Raw(raw_socket) => raw_socket.ttl(),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.hop_limit(),
+            _ => DEFAULT_TTL,
         }
     }
     pub fn bind_check_unix(&self, path: &str) -> bool {
@@ -546,8 +673,13 @@ impl Socket {
             panic!();
         } else {
             match &self.inner {
-                SocketInner::Tcp(tcp_socket) => tcp_socket.listen(),
+                SocketInner::Tcp(tcp_socket) => {
+                    tcp_socket.listen(self.domain == Domain::AF_INET6, self.is_ipv6_only())
+                }
                 SocketInner::Udp(_) => panic!(),
+                SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => {
+                    Err(Errno::EOPNOTSUPP)
+                },
             }
         }
     }
@@ -564,8 +696,13 @@ impl Socket {
             let res = match &self.inner {
                 //这个应该发生在listen之后，listen会将port,addr写到listentable中
                 //此时remote_addra应当能够已经写回到remote_addr
-                SocketInner::Tcp(tcp_socket) => tcp_socket.accept(),
+                SocketInner::Tcp(tcp_socket) => tcp_socket
+                    .accept()
+                    .inspect(|socket| socket.inherit_options(tcp_socket)),
                 SocketInner::Udp(_) => panic!(),
+                SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => {
+                    Err(Errno::EOPNOTSUPP)
+                },
             };
             match res {
                 Ok(socket) => {
@@ -580,10 +717,11 @@ impl Socket {
                             socket_type: self.socket_type,
                             inner: SocketInner::Tcp(socket),
                             recvtimeout: Mutex::new(None),
+                            sendtimeout: Mutex::new(None),
                             close_exec: AtomicBool::new(false),
                             send_buf_size: AtomicU64::new(64 * 1024),
                             recv_buf_size: AtomicU64::new(64 * 1024),
-                            congestion: Mutex::new(String::from("reno")),
+                            congestion: Mutex::new(self.get_congestion()),
                             buffer: None,
                             pend_send: Mutex::new(None),
                             isaf_alg: AtomicBool::new(false),
@@ -597,6 +735,7 @@ impl Socket {
                             socket_peer_file_unix: Mutex::new(None),
                             socket_ucred: Mutex::new(None),
                             socket_peer_ucred: Mutex::new(None),
+                            ipv6_only: AtomicBool::new(self.is_ipv6_only()),
                             //todo,是复制还是新建立
                             // waiter:Mutex::new(Vec::new()),
 
@@ -631,6 +770,7 @@ impl Socket {
             socket_type: self.socket_type,
             inner: SocketInner::Tcp(TcpSocket::new()),
             recvtimeout: Mutex::new(None),
+            sendtimeout: Mutex::new(None),
             close_exec: AtomicBool::new(false),
             send_buf_size: AtomicU64::new(64 * 1024),
             recv_buf_size: AtomicU64::new(64 * 1024),
@@ -648,6 +788,7 @@ impl Socket {
             socket_peer_file_unix: Mutex::new(None),
             socket_ucred: Mutex::new(None),
             socket_peer_ucred: Mutex::new(None),
+            ipv6_only: AtomicBool::new(false),
             //todo是复制还是新建立
             // waiter:Mutex::new(Vec::new()),
         })
@@ -707,6 +848,7 @@ impl Socket {
             socket_type: self.socket_type,
             inner: SocketInner::Tcp(TcpSocket::new()),
             recvtimeout: Mutex::new(None),
+            sendtimeout: Mutex::new(None),
             close_exec: AtomicBool::new(false),
             send_buf_size: AtomicU64::new(64 * 1024),
             recv_buf_size: AtomicU64::new(64 * 1024),
@@ -725,6 +867,7 @@ impl Socket {
             //todo
             socket_ucred: Mutex::new(self.socket_ucred.lock().clone()),
             socket_peer_ucred: Mutex::new(self.socket_peer_ucred.lock().clone()),
+            ipv6_only: AtomicBool::new(false),
             //todo
             // waiter:Mutex::new(Vec::new()),
         })
@@ -734,6 +877,9 @@ impl Socket {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.connect(addr),
             SocketInner::Udp(udp_socket) => udp_socket.connect(addr),
+            SocketInner::Raw(raw_socket) => raw_socket.connect(addr),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.connect(addr),
+            SocketInner::Packet(_) => Err(Errno::EOPNOTSUPP),
         }
     }
 
@@ -741,6 +887,9 @@ impl Socket {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.local_addr().is_ok(),
             SocketInner::Udp(udp_socket) => udp_socket.local_addr().is_ok(),
+            SocketInner::Raw(_) => true,
+            SocketInner::Icmp(icmp_socket) => icmp_socket.is_bound(),
+            SocketInner::Packet(_) => true,
         }
     }
     pub fn shutdown(&self) -> Result<usize, Errno> {
@@ -751,6 +900,9 @@ impl Socket {
             SocketInner::Tcp(s) => {
                 s.close();
             }
+            SocketInner::Raw(s) => s.shutdown(),
+            SocketInner::Icmp(s) => s.shutdown(),
+            SocketInner::Packet(_) => {}
         };
         Ok(0)
     }
@@ -764,6 +916,9 @@ impl Socket {
                     s.abort();
                 }
             }),
+            SocketInner::Raw(s) => s.shutdown(),
+            SocketInner::Icmp(s) => s.shutdown(),
+            SocketInner::Packet(_) => {}
         };
         Ok(0)
     }
@@ -781,7 +936,7 @@ impl Socket {
                     return Err(Errno::EPIPE);
                 }
                 //accept时已经有remote_addr并写入了
-                tcp_socket.send(buf)
+                self.tcp_send(tcp_socket, buf)
             }
             SocketInner::Udp(udp_socket) => {
                 //先判断udp_socket是否已经connect
@@ -790,6 +945,9 @@ impl Socket {
                 }
                 udp_socket.send_to(buf, addr)
             }
+            SocketInner::Raw(raw_socket) => raw_socket.send_to(buf, addr),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.send_to(buf, addr),
+            SocketInner::Packet(_) => Err(Errno::EINVAL),
         }
     }
     pub fn unix_send(&self, buf: &[u8]) -> Result<usize, Errno> {
@@ -833,6 +991,16 @@ impl Socket {
         *self.socket_nscdrequest.lock() = Some(parsed);
         Ok(buf.len())
     }
+    /// 同recv_from, 额外返回报文的真实长度, 用于MSG_TRUNC
+    /// 只有udp数据报会被截断, 其余socket真实长度与拷贝长度相同
+    pub fn recv_from_full(&self, buf: &mut [u8]) -> Result<(usize, usize, SocketAddr), Errno> {
+        match &self.inner {
+            SocketInner::Udp(udp_socket) if self.domain != Domain::AF_UNIX => {
+                udp_socket.recv_from_full(buf)
+            }
+            _ => self.recv_from(buf).map(|(size, addr)| (size, size, addr)),
+        }
+    }
     pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Errno> {
         // let read_waiter=self.get_waiter();
         // if read_waiter!=0 {
@@ -935,6 +1103,11 @@ impl Socket {
             //         .recv_from(buf)
             // },
             SocketInner::Udp(udp_socket) => udp_socket.recv_from(buf),
+            SocketInner::Raw(raw_socket) => raw_socket.recv_from(buf),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.recv_from(buf),
+            SocketInner::Packet(packet_socket) => packet_socket
+                .recv_from(buf)
+                .map(|res| (res.0, from_ipendpoint_to_socketaddr(UNSPECIFIED_ENDPOINT))),
         }
     }
     pub fn name(&self) -> Result<SocketAddr, Errno> {
@@ -950,6 +1123,9 @@ impl Socket {
                 }
             }
             SocketInner::Udp(udp_socket) => udp_socket.local_addr(),
+            SocketInner::Raw(raw_socket) => raw_socket.local_addr(),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.local_addr(),
+            SocketInner::Packet(_) => Err(Errno::EOPNOTSUPP),
         }
     }
     pub fn peer_name(&self) -> Result<SocketAddr, Errno> {
@@ -959,6 +1135,9 @@ impl Socket {
                 Err(e) => Err(e),
             },
             SocketInner::Udp(udp_socket) => udp_socket.reomte_addr(),
+            SocketInner::Raw(raw_socket) => raw_socket.remote_addr(),
+            SocketInner::Icmp(icmp_socket) => icmp_socket.remote_addr(),
+            SocketInner::Packet(_) => Err(Errno::EOPNOTSUPP),
         }
     }
 }
@@ -1194,71 +1373,47 @@ pub unsafe fn socket_address_from(
             }
         }
         Domain::AF_INET6 => {
-            // 1) 端口号（网络序转主机序）
-
-            let port = {
-                let hi = kernel_addr_from_user[2] as u16;
-                let lo = kernel_addr_from_user[3] as u16;
-                u16::from_be(hi << 8 | lo)
-            };
-            let task = current_task();
-
-            // 2) 直接跳过 flowinfo（4 字节），如果你需要可以同样 copy 处理
-            //    let flowinfo = u32::from_be_bytes([
-            //        kernel_addr_from_user[4],
-            //        kernel_addr_from_user[5],
-            //        kernel_addr_from_user[6],
-            //        kernel_addr_from_user[7],
-            //    ]);
-
-            // 3) 拷贝 16 字节 IPv6 地址
-            let mut ip_bytes = [0u8; 16];
-            unsafe {
-                core::ptr::copy_nonoverlapping(
-                    kernel_addr_from_user.as_ptr().add(8),
-                    ip_bytes.as_mut_ptr(),
-                    16,
-                );
+            // struct sockaddr_in6: family(2) port(2) flowinfo(4) addr(16) scope_id(4)
+            // 与linux一致,不带scope_id的24字节旧格式也接受
+            if len < 24 {
+                return Err(Errno::EINVAL);
             }
-            // let ipv6 = std::net::Ipv6Addr::from(ip_bytes);
-
-            // 4) Scope ID （network-order -> host-order），通常只在 link-local 时用到
-            let scope_id = u32::from_be_bytes([
-                kernel_addr_from_user[24],
-                kernel_addr_from_user[25],
-                kernel_addr_from_user[26],
-                kernel_addr_from_user[27],
+            let port = u16::from_be_bytes([kernel_addr_from_user[2], kernel_addr_from_user[3]]);
+            let flowinfo = u32::from_be_bytes([
+                kernel_addr_from_user[4],
+                kernel_addr_from_user[5],
+                kernel_addr_from_user[6],
+                kernel_addr_from_user[7],
             ]);
-            log::error!("[socket_address_from] ip {:?},port {:?}", ip_bytes, port);
-            // 3) 如果首字节为 32，返回一个“假”IPv6 地址；否则按正常流程
-            if ip_bytes[0] == 32 {
-                // 这里举例一个本地链路地址 fe80::1
-                let fake_ip = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
-                Ok(SocketAddr::V6(SocketAddrV6::new(fake_ip, 5555, 0, 0)))
+            let mut ip_bytes = [0u8; 16];
+            ip_bytes.copy_from_slice(&kernel_addr_from_user[8..24]);
+            // scope_id 为主机字节序，只在链路本地地址时有意义
+            let scope_id = if len >= 28 {
+                u32::from_ne_bytes([
+                    kernel_addr_from_user[24],
+                    kernel_addr_from_user[25],
+                    kernel_addr_from_user[26],
+                    kernel_addr_from_user[27],
+                ])
             } else {
-                if port == 5001 {
-                    let addr = Ipv4Addr::new(127, 0, 0, 1);
-                    Ok(SocketAddr::V4(SocketAddrV4::new(addr, port)))
-                } else if port == 65535 && task.exe_path().contains("netperf") {
-                    let addr = Ipv4Addr::new(127, 0, 0, 1);
-                    Ok(SocketAddr::V4(SocketAddrV4::new(addr, 12865)))
-                } else if port == 35091 {
-                    let addr = Ipv4Addr::new(127, 0, 0, 1);
-                    Ok(SocketAddr::V4(SocketAddrV4::new(addr, 5001)))
-                } else if port == 65471 {
-                    //fake for recvmsg02
-                    let addr = Ipv4Addr::new(127, 0, 0, 1);
-                    Ok(SocketAddr::V4(SocketAddrV4::new(addr, 49151)))
-                } else {
-                    // println!("fake4");
-                    let addr = Ipv4Addr::new(127, 0, 0, 1);
-                    Ok(SocketAddr::V4(SocketAddrV4::new(
-                        addr,
-                        get_ephemeral_port() - 1,
-                    )))
-                }
+                0
+            };
+            let ip = Ipv6Addr::from(ip_bytes);
+            log::error!(
+                "[socket_address_from] ip {:?},port {:?},scope_id {:?}",
+                ip,
+                port,
+                scope_id
+            );
+            // v4-mapped 地址 ::ffff:a.b.c.d 按ipv4处理，设置了IPV6_V6ONLY时不允许使用
+            let addr = from_v4_mapped(SocketAddrV6::new(ip, port, flowinfo, scope_id));
+            if addr.is_ipv4() && socket.is_ipv6_only() {
+                return Err(Errno::EINVAL);
             }
+            Ok(addr)
         }
+        //packet socket使用sockaddr_ll,应通过packet::sockaddr_ll_from解析
+        Domain::AF_PACKET => Err(Errno::EINVAL),
     }
 }
 #[repr(C)]
@@ -1269,43 +1424,65 @@ pub struct SockAddrIn {
     sin_zero: [u8; 8], // padding
 }
 
+#[repr(C)]
+pub struct SockAddrIn6 {
+    sin6_family: u16,
+    sin6_port: u16,     // 网络字节序
+    sin6_flowinfo: u32, // 网络字节序
+    sin6_addr: [u8; 16],
+    sin6_scope_id: u32, // 主机字节序
+}
+
+/// 把地址写回用户空间, addr_len指向用户的socklen_t
+/// 与linux一致, 只拷贝min(*addr_len, 地址实际长度)字节, 并在*addr_len中写回实际长度
 pub fn socket_address_to(sockaddr: SocketAddr, addr: usize, addr_len: usize) -> SyscallRet {
-    // 目前仅支持 IPv4
-    let sock_in = if let SocketAddr::V4(v4) = sockaddr {
-        SockAddrIn {
-            // sin_family 在内核里以主机字节序存储
-            sin_family: Domain::AF_INET as u16,
-            // sin_port 必须是网络字节序
-            sin_port: v4.port().to_be(),
-            // sin_addr.s_addr 是一个 32 位网络字节序整数；直接存四个 octet 保证内存布局
-            sin_addr: v4.ip().octets(),
-            // C 里这 8 字节始终要清零
-            sin_zero: [0; 8],
+    let sock_in;
+    let sock_in6;
+    let (ptr, required_bytes) = match sockaddr {
+        SocketAddr::V4(v4) => {
+            sock_in = SockAddrIn {
+                // sin_family 在内核里以主机字节序存储
+                sin_family: Domain::AF_INET as u16,
+                // sin_port 必须是网络字节序
+                sin_port: v4.port().to_be(),
+                // sin_addr.s_addr 是一个 32 位网络字节序整数；直接存四个 octet 保证内存布局
+                sin_addr: v4.ip().octets(),
+                // C 里这 8 字节始终要清零
+                sin_zero: [0; 8],
+            };
+            (
+                &sock_in as *const SockAddrIn as *const u8,
+                core::mem::size_of::<SockAddrIn>(),
+            )
+        }
+        SocketAddr::V6(v6) => {
+            sock_in6 = SockAddrIn6 {
+                sin6_family: Domain::AF_INET6 as u16,
+                sin6_port: v6.port().to_be(),
+                sin6_flowinfo: v6.flowinfo().to_be(),
+                sin6_addr: v6.ip().octets(),
+                sin6_scope_id: v6.scope_id(),
+            };
+            (
+                &sock_in6 as *const SockAddrIn6 as *const u8,
+                core::mem::size_of::<SockAddrIn6>(),
+            )
         }
-    } else {
-        // IPv6 或者其它，暂不支持
-        return Err(Errno::EAFNOSUPPORT);
     };
 
-    // 整个结构体长度
-    let required_bytes = core::mem::size_of::<SockAddrIn>();
-    if addr_len < required_bytes {
-        log::error!(
-            "Buffer too small: required {} bytes, got {}",
-            required_bytes,
-            addr_len
-        );
-        return Err(Errno::ENOMEM);
-    }
-
-    // 把 struct 视为一段连续的字节，拷贝到用户空间
-    let ptr = &sock_in as *const SockAddrIn as *const u8;
-    unsafe {
-        // 如果 copy_to_user 返回 Err，会自动往上传播
-        copy_to_user(addr as *mut u8, ptr, required_bytes)?;
+    let mut len: i32 = 0;
+    copy_from_user(addr_len as *const i32, &mut len as *mut i32, 1)?;
+    if len < 0 {
+        return Err(Errno::EINVAL);
     }
-
-    // 成功时返回写入字节数
+    // 用户缓冲区不足时截断
+    copy_to_user(
+        addr as *mut u8,
+        ptr,
+        core::cmp::min(len as usize, required_bytes),
+    )?;
+    copy_to_user(addr_len as *mut u32, &(required_bytes as u32), 1)?;
+    // 成功时返回地址实际长度
     Ok(required_bytes)
 }
 #[repr(C)]
@@ -1313,12 +1490,13 @@ struct SockAddrUn {
     sun_family: u16,
     sun_path: [u8; 108],
 }
-pub fn socket_address_tounix(path: &[u8], addr: usize, len: usize) -> SyscallRet {
-    // 1. 先计算整个 struct sockaddr_un 的大小
+pub fn socket_address_tounix(path: &[u8], addr: usize, addr_len: usize) -> SyscallRet {
+    // 1. 先计算整个 struct sockaddr_un 的大小, addr_len指向用户的socklen_t
     let required_bytes = size_of::<SockAddrUn>();
-    if len < required_bytes {
-        // 用户给的 buffer 太小，至少要能装下整个 sockaddr_un
-        return Err(Errno::ENOMEM);
+    let mut len: i32 = 0;
+    copy_from_user(addr_len as *const i32, &mut len as *mut i32, 1)?;
+    if len < 0 {
+        return Err(Errno::EINVAL);
     }
 
     // 2. 准备一个本地的 SockAddrUn，并填充 sun_family 和 sun_path
@@ -1341,17 +1519,16 @@ pub fn socket_address_tounix(path: &[u8], addr: usize, len: usize) -> SyscallRet
     //    - 如果 path 里没有 '\0' 结尾，也可以把它当作 “最长 copy_len 字节” 来用，
     //      但是原则上用户传进来的应该包含一个 NUL 让 C 端能正确识别结束。
 
-    // 4. 将完整的 sockaddr_un（即 sun）拷贝到用户空间
+    // 4. 将 sockaddr_un（即 sun）拷贝到用户空间, 用户缓冲区不足时截断
     let user_ptr = addr as *mut u8;
-    unsafe {
-        copy_to_user(
-            user_ptr,
-            (&sun as *const SockAddrUn).cast::<u8>(),
-            required_bytes,
-        )?;
-    }
-
-    // 5. 成功时，返回写入的字节数
+    copy_to_user(
+        user_ptr,
+        (&sun as *const SockAddrUn).cast::<u8>(),
+        core::cmp::min(len as usize, required_bytes),
+    )?;
+    copy_to_user(addr_len as *mut u32, &(required_bytes as u32), 1)?;
+
+    // 5. 成功时，返回地址实际长度
     Ok(required_bytes)
 }
 
@@ -1456,6 +1633,17 @@ impl FileOp for Socket {
             }
             return Ok(ciphertext.len());
         }
+        //raw socket/ping socket在recv_from内部处理阻塞与非阻塞
+        match &self.inner {
+            SocketInner::Raw(raw_socket) => return raw_socket.recv_from(buf).map(|res| res.0),
+            SocketInner::Icmp(icmp_socket) => {
+                return icmp_socket.recv_from(buf).map(|res| res.0)
+            }
+            SocketInner::Packet(packet_socket) => {
+                return packet_socket.recv_from(buf).map(|res| res.0)
+            }
+            _ => {}
+        }
         if !self.r_ready() {
             log::error!(
                 "[scoket_read] is_nonblocking {:?},is_connected is {:?}",
@@ -1482,6 +1670,9 @@ impl FileOp for Socket {
                                     return Err(e);
                                 }
                             },
+                            SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => {
+                                unreachable!()
+                            }
                         }
                     }
                     yield_current_task();
@@ -1503,6 +1694,7 @@ impl FileOp for Socket {
                 }
                 Err(e) => Err(e),
             },
+            SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => unreachable!(),
         }
     }
 
@@ -1546,20 +1738,33 @@ impl FileOp for Socket {
             log::error!("[socket_write] write count {:?}", AF_ALG_WRITE_COUNT);
             return Ok(buf.len());
         }
+        match &self.inner {
+            SocketInner::Raw(raw_socket) => return raw_socket.send(buf),
+            SocketInner::Icmp(icmp_socket) => return icmp_socket.send(buf),
+            SocketInner::Packet(packet_socket) => return packet_socket.send(buf),
+            _ => {}
+        }
         //log::error!("[socket_write]:buf is {:?}",buf);
         if !self.w_ready() {
             if !self.is_block() && self.is_connected() {
+                let deadline = self.send_timeout_ms().map(|ms| get_time_ms() + ms);
                 loop {
                     if self.w_ready() {
                         match &self.inner {
                             SocketInner::Tcp(tcp_socket) => {
-                                return tcp_socket.send(buf);
+                                return self.tcp_send(tcp_socket, buf);
                             }
                             SocketInner::Udp(udp_socket) => {
                                 return udp_socket.send(buf);
                             }
+                            SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => {
+                                unreachable!()
+                            }
                         }
                     }
+                    if deadline.is_some_and(|deadline| get_time_ms() >= deadline) {
+                        return Err(Errno::EAGAIN);
+                    }
                     // log::trace!("[socket write]");
                     yield_current_task();
                     // log::trace!("[socket write]");
@@ -1569,8 +1774,9 @@ impl FileOp for Socket {
             }
         }
         match &self.inner {
-            SocketInner::Tcp(tcp_socket) => tcp_socket.send(buf),
+            SocketInner::Tcp(tcp_socket) => self.tcp_send(tcp_socket, buf),
             SocketInner::Udp(udp_socket) => udp_socket.send(buf),
+            SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => unreachable!(),
         }
     }
     fn fsync(&self) -> SyscallRet {
@@ -1608,6 +1814,9 @@ impl FileOp for Socket {
                 tcp_socket.poll(true).readable
             }
             SocketInner::Udp(udp_socket) => udp_socket.poll().readable,
+            SocketInner::Raw(raw_socket) => raw_socket.poll().readable,
+            SocketInner::Icmp(icmp_socket) => icmp_socket.poll().readable,
+            SocketInner::Packet(packet_socket) => packet_socket.poll().readable,
         }
     }
     fn w_ready(&self) -> bool {
@@ -1623,9 +1832,17 @@ impl FileOp for Socket {
         match &self.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket.poll(false).writeable,
             SocketInner::Udp(udp_socket) => udp_socket.poll().writeable,
+            SocketInner::Raw(raw_socket) => raw_socket.poll().writeable,
+            SocketInner::Icmp(icmp_socket) => icmp_socket.poll().writeable,
+            SocketInner::Packet(packet_socket) => packet_socket.poll().writeable,
         }
     }
 
+    fn ioctl(&self, op: usize, arg_ptr: usize) -> SyscallRet {
+        //目前只支持查询网卡信息的SIOCGIF*
+        netdev_ioctl(op, arg_ptr)
+    }
+
     fn add_wait_queue(&self, tid: usize) {
         log::error!("[socket_add_wait_queue]:tid is {:?}", tid);
         if self.domain == Domain::AF_UNIX {
@@ -1672,6 +1889,7 @@ pub enum SocketOptionLevel {
     Tcp = 6,
     IPv6 = 41,
     SOL_ALG = 279,
+    SOL_PACKET = 263,
 }
 
 ///为每个level建立一个配置enum
@@ -1690,10 +1908,18 @@ pub enum IpOption {
     ///加入一个多播组，开始接收发送到该组地址的数据
     IP_ADD_MEMBERSHIP = 35,
     IP_PKTINFO = 11,
+    //设置单播数据包的TTL
+    IP_TTL = 2,
+    //raw socket发送的数据自带ip头
+    IP_HDRINCL = 3,
+    //路径mtu发现策略,决定是否设置DF
+    IP_MTU_DISCOVER = 10,
+    //只读,已连接套接字的路径mtu
+    IP_MTU = 14,
     MCAST_JOIN_GROUP = 42,
     MCAST_LEAVE_GROUP = 45,
 }
-#[derive(TryFromPrimitive, Debug)]
+#[derive(TryFromPrimitive, Debug, PartialEq)]
 #[repr(usize)]
 #[allow(non_camel_case_types)]
 pub enum SocketOption {
@@ -1703,9 +1929,13 @@ pub enum SocketOption {
     SO_SNDBUF = 7,
     SO_RCVBUF = 8,
     SO_KEEPALIVE = 9,
+    SO_LINGER = 13,
     SO_RCVTIMEO = 20,
     SO_SNDTIMEO = 21,
     SO_PEERCRED = 17,
+    //classic bpf过滤器,目前只对AF_PACKET生效
+    SO_ATTACH_FILTER = 26,
+    SO_DETACH_FILTER = 27,
 }
 #[derive(TryFromPrimitive, PartialEq)]
 #[repr(usize)]
@@ -1713,6 +1943,9 @@ pub enum SocketOption {
 pub enum TcpSocketOption {
     TCP_NODELAY = 1, // disable nagle algorithm and flush
     TCP_MAXSEG = 2,
+    TCP_KEEPIDLE = 4,
+    TCP_KEEPINTVL = 5,
+    TCP_KEEPCNT = 6,
     TCP_INFO = 11,
     SO_OOBINLINE = 10,
     TCP_CONGESTION = 13,
@@ -1725,8 +1958,8 @@ pub enum Ipv6Option {
     UNICAST_HOPS = 4,
     MULTICAST_IF = 9,
     MULTICAST_HOPS = 10,
+    IPV6_V6ONLY = 26,
     //fake
-    IPV6_DEV = 26,
     IPV6_ONLY = 27,
     PACKET_INFO = 61,
     RECV_TRAFFIC_CLASS = 66,
@@ -1754,14 +1987,18 @@ impl IpOption {
             IpOption::IP_MULTICAST_TTL => {
                 //设置多播数据包生存时间
                 match &socket.inner {
-                    SocketInner::Tcp(tcp_socket) => {
-                        panic!("setsockopt IP_MULTICAST_TTL on a non-udp socket")
-                    }
+                    SocketInner::Tcp(_) => Err(Errno::ENOPROTOOPT),
                     SocketInner::Udp(udp_socket) => {
                         let ttl = u8::from_be_bytes(<[u8; 1]>::try_from(&opt[0..1]).unwrap());
                         udp_socket.set_socket_ttl(ttl);
                         Ok(0)
                     }
+                    SocketInner::Raw(_) | SocketInner::Icmp(_) => {
+                        let ttl = u8::from_be_bytes(<[u8; 1]>::try_from(&opt[0..1]).unwrap());
+                        socket.set_ttl(ttl);
+                        Ok(0)
+                    }
+                    SocketInner::Packet(_) => Err(Errno::ENOPROTOOPT),
                 }
             }
             IpOption::IP_MULTICAST_LOOP => Ok(0),
@@ -1776,273 +2013,419 @@ impl IpOption {
                 Ok(0)
             }
             IpOption::IP_PKTINFO => Ok(0),
+            IpOption::IP_TTL => {
+                if opt.len() < 4 {
+                    return Err(Errno::EINVAL);
+                }
+                let ttl = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                //-1表示恢复默认值
+                let ttl = match ttl {
+                    -1 => DEFAULT_TTL,
+                    1..=255 => ttl as u8,
+                    _ => return Err(Errno::EINVAL),
+                };
+                socket.set_ttl(ttl);
+                Ok(0)
+            }
+            IpOption::IP_MTU_DISCOVER => {
+                if opt.len() < 4 {
+                    return Err(Errno::EINVAL);
+                }
+                let mode = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                if !(IP_PMTUDISC_DONT as i32..=IP_PMTUDISC_OMIT as i32).contains(&mode) {
+                    return Err(Errno::EINVAL);
+                }
+                //只有udp会发送超过mtu的报文,其余套接字忽略
+                if let SocketInner::Udp(udp_socket) = &socket.inner {
+                    udp_socket.set_mtu_discover(mode as u8);
+                }
+                Ok(0)
+            }
+            IpOption::IP_MTU => Err(Errno::ENOPROTOOPT),
+            IpOption::IP_HDRINCL => {
+                if opt.len() < 4 {
+                    return Err(Errno::EINVAL);
+                }
+                let value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                match &socket.inner {
+                    SocketInner::Raw(raw_socket) => {
+                        raw_socket.set_hdrincl(value != 0)?;
+                        Ok(0)
+                    }
+                    _ => Err(Errno::ENOPROTOOPT),
+                }
+            }
         }
     }
+    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallRet {
+        let value: i32 = match self {
+            IpOption::IP_TTL => socket.ttl() as i32,
+            IpOption::IP_MTU_DISCOVER => match &socket.inner {
+                SocketInner::Udp(udp_socket) => udp_socket.mtu_discover() as i32,
+                _ => IP_PMTUDISC_WANT as i32,
+            },
+            IpOption::IP_MTU => socket.path_mtu()? as i32,
+            IpOption::IP_HDRINCL => match &socket.inner {
+                SocketInner::Raw(raw_socket) => raw_socket.is_hdrincl() as i32,
+                _ => return Err(Errno::ENOPROTOOPT),
+            },
+            //其余选项暂不支持读取
+            _ => return Ok(0),
+        };
+        copy_to_user(opt_value, &value.to_ne_bytes() as *const u8, 4)?;
+        copy_to_user(opt_len, &4u32 as *const u32, 1)?;
+        Ok(0)
+    }
 }
+/// 从setsockopt传入的缓冲区中读取一个int
+fn opt_i32(opt: &[u8]) -> Result<i32, Errno> {
+    if opt.len() < 4 {
+        return Err(Errno::EINVAL);
+    }
+    Ok(i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap()))
+}
+
+/// 把getsockopt的结果写回用户态,超出用户缓冲区的部分被截断
+fn opt_put(opt_value: *mut u8, opt_len: *mut u32, value: &[u8]) -> SyscallRet {
+    let mut buf_len: u32 = 0;
+    copy_from_user(opt_len as *const u32, &mut buf_len as *mut u32, 1)?;
+    let len = value.len().min(buf_len as usize);
+    copy_to_user(opt_value, value.as_ptr(), len)?;
+    copy_to_user(opt_len, &(len as u32) as *const u32, 1)?;
+    Ok(0)
+}
+
+/// struct linger
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Linger {
+    pub l_onoff: i32,
+    pub l_linger: i32,
+}
+
 impl SocketOption {
     ///主要通过opt传入的内容设置socket中的配置，包括是否o复用地址，发送和接受大小
     pub fn set(&self, socket: &Socket, opt: &[u8]) -> SyscallRet {
         match self {
             SocketOption::SO_REUSEADDR => {
                 //设置是否重复使用地址
-                if opt.len() < 4 {
-                    panic!("[socketoption_set]:the opt len is not enough");
-                    //一个地址长度都不够
-                    // return None;
-                }
-                let addr = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                let addr = opt_i32(opt)?;
                 log::error!("[set_reuse_addr] reuse addr is {:?}", addr);
                 socket.set_reuse_addr(addr != 0);
                 Ok(0)
             }
-            SocketOption::SO_ERROR => {
-                panic!("can't set SO_ERROR");
-            }
+            //SO_ERROR只读
+            SocketOption::SO_ERROR => Err(Errno::ENOPROTOOPT),
             SocketOption::SO_DONTROUTE => {
-                if opt.len() < 4 {
-                    panic!("[socketoption_set]:the opt len is not enough");
-                }
-                let addr = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                let addr = opt_i32(opt)?;
                 socket.set_reuse_addr(addr != 0);
                 // socket.reuse_addr = opt_value != 0;
                 Ok(0)
             }
             SocketOption::SO_SNDBUF => {
                 //设置最大发送报文大小
-                if opt.len() < 4 {
-                    panic!("[socketoption_set]:the opt len is not enough");
-                }
-                let len = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                let len = opt_i32(opt)?;
                 socket.set_send_buf_size(len as u64);
                 // socket.reuse_addr = opt_value != 0;
                 Ok(0)
             }
             SocketOption::SO_RCVBUF => {
-                if opt.len() < 4 {
-                    panic!("[socketoption_set]:the opt len is not enough");
-                }
-                let len = u32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                let len = opt_i32(opt)? as u32;
                 socket.set_recv_buf_size(len as u64);
                 // socket.reuse_addr = opt_value != 0;
                 Ok(0)
             }
             SocketOption::SO_KEEPALIVE => {
-                if opt.len() < 4 {
-                    panic!("can't read a int from socket opt value");
+                let enable = opt_i32(opt)? != 0;
+                match &socket.inner {
+                    SocketInner::Tcp(s) => s.set_keep_alive(enable),
+                    //linux允许在udp上设置SO_KEEPALIVE,但不会产生任何效果
+                    SocketInner::Udp(_) => {
+                        log::warn!("[setsockopt()] set keep-alive for udp socket, ignored")
+                    }
+                    SocketInner::Raw(_) | SocketInner::Icmp(_) | SocketInner::Packet(_) => {
+                        return Err(Errno::ENOPROTOOPT)
+                    }
                 }
-                let len = u32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
-                let interval = if len != 0 {
-                    Some(smoltcp::time::Duration::from_secs(45))
-                } else {
-                    None
-                };
+                Ok(0)
+            }
+            SocketOption::SO_LINGER => {
+                if opt.len() < size_of::<Linger>() {
+                    return Err(Errno::EINVAL);
+                }
+                let linger: Linger =
+                    unsafe { core::ptr::read_unaligned(opt.as_ptr() as *const Linger) };
+                log::error!("[setsockopt()] set linger {:?}", linger);
                 match &socket.inner {
-                    SocketInner::Tcp(s) => s.with_socket_mut(|s| match s {
-                        Some(s) => s.set_keep_alive(interval),
-                        None => log::warn!(
-                            "[setsockopt()] set keep-alive for tcp socket not created, ignored"
-                        ),
+                    SocketInner::Tcp(s) => s.set_linger(if linger.l_onoff != 0 {
+                        Some(linger.l_linger.max(0) as u32)
+                    } else {
+                        None
                     }),
-                    SocketInner::Udp(udp_socket) => {
-                        panic!("current not support udp keepalive");
-                    }
+                    _ => log::warn!("[setsockopt()] SO_LINGER on non-tcp socket, ignored"),
                 }
-                socket.set_recv_buf_size(len as u64);
                 Ok(0)
             }
-            SocketOption::SO_RCVTIMEO => {
-                if opt.len() != size_of::<TimeSpec>() {
-                    panic!("can't read a timeval from socket opt value");
+            SocketOption::SO_RCVTIMEO | SocketOption::SO_SNDTIMEO => {
+                if opt.len() < size_of::<TimeSpec>() {
+                    return Err(Errno::EINVAL);
                 }
                 // println!("[setsocketoption]set socket option so recvtimeo");
-                let timeout = unsafe { *(opt.as_ptr() as *const TimeSpec) };
-                socket.set_recv_timeout(if timeout.nsec == 0 && timeout.sec == 0 {
+                let timeout = unsafe { core::ptr::read_unaligned(opt.as_ptr() as *const TimeSpec) };
+                let timeout = if timeout.nsec == 0 && timeout.sec == 0 {
                     None
                 } else {
                     Some(timeout)
-                });
-
+                };
+                if *self == SocketOption::SO_RCVTIMEO {
+                    socket.set_recv_timeout(timeout);
+                } else {
+                    socket.set_send_timeout(timeout);
+                }
                 Ok(0)
             }
-            SocketOption::SO_SNDTIMEO => {
-                panic!("can't set SO_ERROR");
-            }
             SocketOption::SO_PEERCRED => {
-                //todo,getsockopt里面没有设置这个
-                Ok(0)
+                //SO_PEERCRED只读
+                Err(Errno::ENOPROTOOPT)
             }
+            SocketOption::SO_ATTACH_FILTER => {
+                if opt.len() < core::mem::size_of::<SockFprog>() {
+                    return Err(Errno::EINVAL);
+                }
+                let fprog: SockFprog =
+                    unsafe { core::ptr::read_unaligned(opt.as_ptr() as *const SockFprog) };
+                let prog = copy_filter_from_user(&fprog)?;
+                match &socket.inner {
+                    SocketInner::Packet(packet_socket) => {
+                        packet_socket.attach_filter(prog);
+                        Ok(0)
+                    }
+                    _ => {
+                        log::warn!("[setsockopt()] SO_ATTACH_FILTER on non-packet socket");
+                        Err(Errno::EOPNOTSUPP)
+                    }
+                }
+            }
+            SocketOption::SO_DETACH_FILTER => match &socket.inner {
+                SocketInner::Packet(packet_socket) => {
+                    packet_socket.detach_filter()?;
+                    Ok(0)
+                }
+                _ => Err(Errno::ENOENT),
+            },
         }
     }
 
     //配合getsockopt函数
-    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) {
-        let buf_len = unsafe { *opt_len } as usize;
-        log::error!("[get_socket_option]buf_len is {:?}", buf_len);
+    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallRet {
         match self {
             SocketOption::SO_REUSEADDR => {
                 let value: i32 = if socket.get_reuse_addr() { 1 } else { 0 };
-
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
-                unsafe {
-                    #[cfg(target_arch = "riscv64")]
-                    copy_nonoverlapping(&value.to_ne_bytes() as *const u8, opt_value, 4);
-                    #[cfg(target_arch = "loongarch64")]
-                    copy_to_user(opt_value, &value.to_ne_bytes() as *const u8, 4);
-                    *opt_len = 4;
-                }
+                opt_put(opt_value, opt_len, &value.to_ne_bytes())
             }
             SocketOption::SO_DONTROUTE => {
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
-                let size: i32 = if socket.dont_route { 1 } else { 0 };
-
-                unsafe {
-                    #[cfg(target_arch = "riscv64")]
-                    copy_nonoverlapping(&size.to_ne_bytes() as *const u8, opt_value, 4);
-                    #[cfg(target_arch = "loongarch64")]
-                    copy_to_user(opt_value, &size.to_ne_bytes() as *const u8, 4);
-                    *opt_len = 4;
-                }
+                let value: i32 = if socket.dont_route { 1 } else { 0 };
+                opt_put(opt_value, opt_len, &value.to_ne_bytes())
             }
             SocketOption::SO_SNDBUF => {
-                // if buf_len < 4 {
-                //     panic!("can't write a int to socket opt value");
-                // }
-
                 let size: i32 = socket.get_send_buf_size() as i32;
-
-                unsafe {
-                    #[cfg(target_arch = "loongarch64")]
-                    copy_to_user(opt_value, &size.to_ne_bytes() as *const u8, 4);
-                    #[cfg(target_arch = "riscv64")]
-                    copy_nonoverlapping(&size.to_ne_bytes() as *const u8, opt_value, 4);
-                    *opt_len = 4;
-                }
+                opt_put(opt_value, opt_len, &size.to_ne_bytes())
             }
             SocketOption::SO_RCVBUF => {
-                // if buf_len < 4 {
-                //     panic!("can't write a int to socket opt value");
-                // }
-
                 let size: i32 = socket.get_recv_buf_size() as i32;
-
-                unsafe {
-                    #[cfg(target_arch = "riscv64")]
-                    copy_nonoverlapping(&size.to_ne_bytes() as *const u8, opt_value, 4);
-                    #[cfg(target_arch = "loongarch64")]
-                    copy_to_user(opt_value, &size.to_ne_bytes() as *const u8, 4);
-                    *opt_len = 4;
-                }
+                opt_put(opt_value, opt_len, &size.to_ne_bytes())
             }
             SocketOption::SO_KEEPALIVE => {
-                if buf_len < 4 {
-                    panic!("can't write a int to socket opt value");
-                }
-
                 let keep_alive: i32 = match &socket.inner {
-                    SocketInner::Udp(_) => {
-                        panic!("[getsockopt()] get SO_KEEPALIVE on udp socket, returning false");
-                        0
-                    }
-                    SocketInner::Tcp(s) => {
-                        s.with_socket(|s| if s.keep_alive().is_some() { 1 } else { 0 })
-                    }
+                    SocketInner::Tcp(s) => s.keep_alive() as i32,
+                    _ => 0,
                 };
-
-                unsafe {
-                    #[cfg(target_arch = "riscv64")]
-                    copy_nonoverlapping(&keep_alive.to_ne_bytes() as *const u8, opt_value, 4);
-                    #[cfg(target_arch = "loongarch64")]
-                    copy_to_user(opt_value, &keep_alive.to_ne_bytes() as *const u8, 4);
-                    *opt_len = 4;
-                }
+                opt_put(opt_value, opt_len, &keep_alive.to_ne_bytes())
+            }
+            SocketOption::SO_LINGER => {
+                let linger = match &socket.inner {
+                    SocketInner::Tcp(s) => match s.linger() {
+                        Some(timeout) => Linger {
+                            l_onoff: 1,
+                            l_linger: timeout as i32,
+                        },
+                        None => Linger::default(),
+                    },
+                    _ => Linger::default(),
+                };
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(
+                        &linger as *const Linger as *const u8,
+                        size_of::<Linger>(),
+                    )
+                };
+                opt_put(opt_value, opt_len, bytes)
             }
-            SocketOption::SO_RCVTIMEO => {
-                if buf_len < size_of::<TimeSpec>() {
-                    panic!("can't write a timeval to socket opt value");
-                }
-
-                unsafe {
-                    match socket.get_recv_timeout() {
-                        Some(time) => {
-                            #[cfg(target_arch = "riscv64")]
-                            copy_nonoverlapping(
-                                (&time) as *const TimeSpec,
-                                opt_value as *mut TimeSpec,
-                                1,
-                            );
-                            #[cfg(target_arch = "loongarch64")]
-                            copy_to_user(
-                                opt_value as *mut TimeSpec,
-                                &time as *const TimeSpec,
-                                size_of::<TimeSpec>(),
-                            );
-                        }
-                        None => {
-                            #[cfg(target_arch = "riscv64")]
-                            copy_nonoverlapping(
-                                &0u8 as *const u8,
-                                opt_value,
-                                size_of::<TimeSpec>(),
-                            );
-                            #[cfg(target_arch = "loongarch64")]
-                            copy_to_user(opt_value, &0u8 as *const u8, size_of::<TimeSpec>());
-                        }
-                    }
-
-                    *opt_len = size_of::<TimeSpec>() as u32;
-                }
+            SocketOption::SO_RCVTIMEO | SocketOption::SO_SNDTIMEO => {
+                let time = if *self == SocketOption::SO_RCVTIMEO {
+                    socket.get_recv_timeout()
+                } else {
+                    socket.get_send_timeout()
+                };
+                let time = time.unwrap_or(TimeSpec { sec: 0, nsec: 0 });
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(
+                        &time as *const TimeSpec as *const u8,
+                        size_of::<TimeSpec>(),
+                    )
+                };
+                opt_put(opt_value, opt_len, bytes)
             }
-            SocketOption::SO_ERROR => {}
-            SocketOption::SO_SNDTIMEO => {
-                panic!("unimplemented!")
+            //目前没有记录异步错误
+            SocketOption::SO_ERROR => opt_put(opt_value, opt_len, &0i32.to_ne_bytes()),
+            SocketOption::SO_ATTACH_FILTER | SocketOption::SO_DETACH_FILTER => {
+                //不支持读取已安装的过滤器
+                Err(Errno::ENOPROTOOPT)
             }
             SocketOption::SO_PEERCRED => {
-                // 首先准备好 UCred 结构体的大小
-                let needed = core::mem::size_of::<UCred>(); // 12 字节 (i32 + u32 + u32)
-                if buf_len < needed {
-                    panic!("can't write ucred to socket opt value: buffer too small");
-                }
-
-                // 假设 socket.get_peer_ucred() 返回一个 UCred { pid: i32, uid: u32, gid: u32 }
-                let peer_ucred = socket.get_peer_ucred();
-
-                // 按照 “pid | uid | gid” 顺序把各字段转换为本机字节序的 4 字节数组
-                let pid_bytes = peer_ucred.pid.to_ne_bytes(); // [u8; 4]
-                let uid_bytes = peer_ucred.uid.to_ne_bytes(); // [u8; 4]
-                let gid_bytes = peer_ucred.gid.to_ne_bytes(); // [u8; 4]
-
-                unsafe {
-                    #[cfg(target_arch = "riscv64")]
-                    {
-                        // RISC-V64 下直接用 core::ptr::copy_nonoverlapping 向 opt_value 写入
-                        use core::ptr::copy_nonoverlapping;
-
-                        // 写入 pid (偏移 0..4)
-                        copy_nonoverlapping(pid_bytes.as_ptr(), opt_value, 4);
-                        // 写入 uid (偏移 4..8)
-                        copy_nonoverlapping(uid_bytes.as_ptr(), opt_value.add(4), 4);
-                        // 写入 gid (偏移 8..12)
-                        copy_nonoverlapping(gid_bytes.as_ptr(), opt_value.add(8), 4);
-                    }
+                //未连接的socket返回pid 0, uid/gid -1
+                let peer_ucred = socket.socket_peer_ucred.lock().unwrap_or(UCred {
+                    pid: 0,
+                    uid: u32::MAX,
+                    gid: u32::MAX,
+                });
+                // 按照 “pid | uid | gid” 顺序写回
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(
+                        &peer_ucred as *const UCred as *const u8,
+                        size_of::<UCred>(),
+                    )
+                };
+                opt_put(opt_value, opt_len, bytes)
+            }
+        }
+    }
+}
 
-                    #[cfg(target_arch = "loongarch64")]
-                    {
-                        // LoongArch64 下用 copy_to_user 向 opt_value 写入
-                        copy_to_user(opt_value, pid_bytes.as_ptr(), 4);
-                        copy_to_user(opt_value.add(4), uid_bytes.as_ptr(), 4);
-                        copy_to_user(opt_value.add(8), gid_bytes.as_ptr(), 4);
-                    }
+/// struct tcp_info,只包含linux中total_retrans及之前的字段
+/// rtt/重传计数由TcpStats在网卡收发时统计; smoltcp不公开拥塞窗口,
+/// tcpi_snd_cwnd报告最近一个rtt内观察到的最大在途报文段数, 是拥塞窗口的下界
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct TcpInfo {
+    pub tcpi_state: u8,
+    pub tcpi_ca_state: u8,
+    pub tcpi_retransmits: u8,
+    pub tcpi_probes: u8,
+    pub tcpi_backoff: u8,
+    pub tcpi_options: u8,
+    //低4位为tcpi_snd_wscale,高4位为tcpi_rcv_wscale
+    pub tcpi_wscale: u8,
+    pub tcpi_delivery_rate_app_limited: u8,
+
+    //以下时间单位均为微秒
+    pub tcpi_rto: u32,
+    pub tcpi_ato: u32,
+    pub tcpi_snd_mss: u32,
+    pub tcpi_rcv_mss: u32,
+
+    pub tcpi_unacked: u32,
+    pub tcpi_sacked: u32,
+    pub tcpi_lost: u32,
+    pub tcpi_retrans: u32,
+    pub tcpi_fackets: u32,
+
+    pub tcpi_last_data_sent: u32,
+    pub tcpi_last_ack_sent: u32,
+    pub tcpi_last_data_recv: u32,
+    pub tcpi_last_ack_recv: u32,
+
+    pub tcpi_pmtu: u32,
+    pub tcpi_rcv_ssthresh: u32,
+    pub tcpi_rtt: u32,
+    pub tcpi_rttvar: u32,
+    pub tcpi_snd_ssthresh: u32,
+    pub tcpi_snd_cwnd: u32,
+    pub tcpi_advmss: u32,
+    pub tcpi_reordering: u32,
+
+    pub tcpi_rcv_rtt: u32,
+    pub tcpi_rcv_space: u32,
+
+    pub tcpi_total_retrans: u32,
+}
 
-                    // 最后把写入的长度写回给用户
-                    *opt_len = needed as u32;
-                }
+//linux中的TCP_INFINITE_SSTHRESH
+const TCP_INFINITE_SSTHRESH: u32 = 0x7fffffff;
+//linux中tcp_ca_state的TCP_CA_Open和TCP_CA_Loss
+const TCP_CA_OPEN: u8 = 0;
+const TCP_CA_LOSS: u8 = 4;
+//linux中的TCP_MSS_DEFAULT, 连接建立前使用
+const TCP_MSS_DEFAULT: u32 = 536;
+//smoltcp的RttEstimator把重传超时固定在RTTE_MIN_RTO(毫秒), 换算为微秒后超出u32, 饱和为u32::MAX
+const SMOLTCP_RTO_MS: u64 = 10_000_000;
+//linux中的TCP_CA_NAME_MAX
+const TCP_CA_NAME_MAX: usize = 16;
+
+impl TcpInfo {
+    /// linux中tcp状态的编号
+    fn linux_state(state: State) -> u8 {
+        match state {
+            State::Established => 1,
+            State::SynSent => 2,
+            State::SynReceived => 3,
+            State::FinWait1 => 4,
+            State::FinWait2 => 5,
+            State::TimeWait => 6,
+            State::Closed => 7,
+            State::CloseWait => 8,
+            State::LastAck => 9,
+            State::Listen => 10,
+            State::Closing => 11,
+        }
+    }
+    /// 路径mtu减去ip和tcp首部, 未连接时返回TCP_MSS_DEFAULT
+    fn path_mss(socket: &tcp::Socket) -> u32 {
+        match socket.remote_endpoint() {
+            Some(endpoint) => {
+                let header = match endpoint.addr {
+                    IpAddress::Ipv4(_) => 20 + 20,
+                    IpAddress::Ipv6(_) => 40 + 20,
+                };
+                (route_mtu(endpoint.addr) - header) as u32
             }
+            None => TCP_MSS_DEFAULT,
+        }
+    }
+    fn from_socket(socket: &tcp::Socket, stats: TcpStatsSnapshot) -> Self {
+        let mss = Self::path_mss(socket);
+        let pmtu = socket
+            .remote_endpoint()
+            .map(|endpoint| route_mtu(endpoint.addr) as u32)
+            .unwrap_or(0);
+        let unacked = stats.unacked_bytes.div_ceil(mss);
+        TcpInfo {
+            tcpi_state: Self::linux_state(socket.state()),
+            tcpi_ca_state: if stats.retransmits > 0 {
+                TCP_CA_LOSS
+            } else {
+                TCP_CA_OPEN
+            },
+            tcpi_retransmits: stats.retransmits,
+            tcpi_rto: (SMOLTCP_RTO_MS * 1000).min(u32::MAX as u64) as u32,
+            tcpi_rtt: stats.srtt_us,
+            tcpi_rttvar: stats.rttvar_us,
+            tcpi_snd_cwnd: stats.flight_bytes.div_ceil(mss),
+            tcpi_total_retrans: stats.total_retrans,
+            tcpi_ato: socket
+                .ack_delay()
+                .map(|delay| delay.total_micros() as u32)
+                .unwrap_or(0),
+            tcpi_snd_mss: mss,
+            tcpi_rcv_mss: mss,
+            tcpi_unacked: unacked,
+            tcpi_pmtu: pmtu,
+            tcpi_rcv_ssthresh: socket.recv_capacity() as u32,
+            tcpi_snd_ssthresh: TCP_INFINITE_SSTHRESH,
+            tcpi_advmss: mss,
+            tcpi_reordering: 3,
+            tcpi_rcv_space: socket.recv_capacity() as u32,
+            ..Default::default()
         }
     }
 }
@@ -2051,86 +2434,151 @@ impl TcpSocketOption {
     pub fn set(&self, rawsocket: &Socket, opt: &[u8]) -> SyscallRet {
         let socket = match &rawsocket.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket,
-            SocketInner::Udp(udp_socket) => panic!("only tcp socket can call on this functino"),
+            _ => return Err(Errno::ENOPROTOOPT),
         };
 
         match self {
             TcpSocketOption::TCP_NODELAY => {
-                if opt.len() < 4 {
-                    panic!("can't read a int from socket opt value");
-                }
-                let opt_value = u32::from_be_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                let opt_value = opt_i32(opt)?;
                 socket.set_nagle_enabled(opt_value == 0);
                 Ok(0)
             }
-            TcpSocketOption::TCP_MAXSEG => {
-                unimplemented!()
+            //mss由smoltcp根据对端通告决定, 无法由用户限制
+            TcpSocketOption::TCP_MAXSEG => Err(Errno::ENOPROTOOPT),
+            TcpSocketOption::TCP_KEEPIDLE
+            | TcpSocketOption::TCP_KEEPINTVL
+            | TcpSocketOption::TCP_KEEPCNT => {
+                let value = opt_i32(opt)?;
+                let max = match self {
+                    TcpSocketOption::TCP_KEEPIDLE => MAX_TCP_KEEPIDLE,
+                    TcpSocketOption::TCP_KEEPINTVL => MAX_TCP_KEEPINTVL,
+                    _ => MAX_TCP_KEEPCNT,
+                };
+                if value < 1 || value as u32 > max {
+                    return Err(Errno::EINVAL);
+                }
+                match self {
+                    TcpSocketOption::TCP_KEEPIDLE => socket.set_keep_idle(value as u32),
+                    TcpSocketOption::TCP_KEEPINTVL => socket.set_keep_intvl(value as u32),
+                    _ => socket.set_keep_cnt(value as u32),
+                }
+                Ok(0)
             }
-            TcpSocketOption::TCP_INFO => Ok(0),
+            //TCP_INFO只读
+            TcpSocketOption::TCP_INFO => Err(Errno::ENOPROTOOPT),
             TcpSocketOption::TCP_CONGESTION => {
-                rawsocket.set_congestion(String::from_utf8(Vec::from(opt)).unwrap());
+                //名字可以不以'\0'结尾
+                let len = opt.iter().position(|&c| c == 0).unwrap_or(opt.len());
+                let name = core::str::from_utf8(&opt[..len]).map_err(|_| Errno::ENOENT)?;
+                log::error!("[setsockopt()] set tcp congestion {:?}", name);
+                match name {
+                    "reno" => socket.set_congestion(CongestionControl::Reno),
+                    _ => return Err(Errno::ENOENT),
+                }
+                rawsocket.set_congestion(String::from(name));
                 Ok(0)
             }
             TcpSocketOption::SO_OOBINLINE => Ok(0),
         }
     }
 
-    pub fn get(&self, rawsocket: &Socket, opt_addr: *mut u8, opt_len: *mut u32) {
+    pub fn get(&self, rawsocket: &Socket, opt_addr: *mut u8, opt_len: *mut u32) -> SyscallRet {
         let socket = match &rawsocket.inner {
             SocketInner::Tcp(tcp_socket) => tcp_socket,
-            SocketInner::Udp(udp_socket) => panic!("only tcp socket can call on this functino"),
+            _ => return Err(Errno::ENOPROTOOPT),
         };
-        let buf_len = unsafe { *opt_len };
         match self {
             TcpSocketOption::TCP_NODELAY => {
-                if buf_len < 4 {
-                    panic!("can't read a int from socket opt value");
-                }
                 let value: i32 = if socket.nagle_enabled() { 0 } else { 1 };
-
-                let value = value.to_ne_bytes();
-
-                unsafe {
-                    #[cfg(target_arch = "riscv64")]
-                    copy_nonoverlapping(&value as *const u8, opt_addr, 4);
-                    #[cfg(target_arch = "loongarch64")]
-                    copy_to_user(opt_addr, &value as *const u8, 4);
-                    *opt_len = 4;
-                }
+                opt_put(opt_addr, opt_len, &value.to_ne_bytes())
             }
             TcpSocketOption::TCP_MAXSEG => {
-                let len = size_of::<usize>();
-
-                let value: usize = 1500;
-
-                unsafe {
-                    #[cfg(target_arch = "riscv64")]
-                    copy_nonoverlapping(&value as *const usize as *const u8, opt_addr, len);
-                    #[cfg(target_arch = "loongarch64")]
-                    copy_to_user(opt_addr, &value as *const usize as *const u8, len);
-                    *opt_len = len as u32;
+                let value = socket.with_socket_mut(|s| match s {
+                    Some(s) => TcpInfo::path_mss(s) as i32,
+                    None => TCP_MSS_DEFAULT as i32,
+                });
+                opt_put(opt_addr, opt_len, &value.to_ne_bytes())
+            }
+            TcpSocketOption::TCP_KEEPIDLE => {
+                opt_put(opt_addr, opt_len, &(socket.keep_idle() as i32).to_ne_bytes())
+            }
+            TcpSocketOption::TCP_KEEPINTVL => {
+                opt_put(opt_addr, opt_len, &(socket.keep_intvl() as i32).to_ne_bytes())
+            }
+            TcpSocketOption::TCP_KEEPCNT => {
+                opt_put(opt_addr, opt_len, &(socket.keep_cnt() as i32).to_ne_bytes())
+            }
+            TcpSocketOption::TCP_INFO => {
+                let stats = socket.stats().unwrap_or_default();
+                let info = socket.with_socket_mut(|s| match s {
+                    Some(s) => TcpInfo::from_socket(s, stats),
+                    //还没有分配smoltcp socket
+                    None => TcpInfo {
+                        tcpi_state: TcpInfo::linux_state(State::Closed),
+                        ..Default::default()
+                    },
+                });
+                log::error!("[getsockopt()] tcp info {:?}", info);
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(
+                        &info as *const TcpInfo as *const u8,
+                        size_of::<TcpInfo>(),
+                    )
                 };
+                opt_put(opt_addr, opt_len, bytes)
             }
-            TcpSocketOption::TCP_INFO => {}
             TcpSocketOption::TCP_CONGESTION => {
-                let bytes = rawsocket.get_congestion();
-                let bytes = bytes.as_bytes();
-
-                unsafe {
-                    #[cfg(target_arch = "riscv64")]
-                    copy_nonoverlapping(bytes.as_ptr(), opt_addr, bytes.len());
-                    #[cfg(target_arch = "loongarch64")]
-                    copy_to_user(opt_addr, bytes.as_ptr(), bytes.len());
-                    *opt_len = bytes.len() as u32;
-                };
+                let mut name = [0u8; TCP_CA_NAME_MAX];
+                let congestion = rawsocket.get_congestion();
+                let len = congestion.len().min(TCP_CA_NAME_MAX - 1);
+                name[..len].copy_from_slice(&congestion.as_bytes()[..len]);
+                opt_put(opt_addr, opt_len, &name)
             }
-            TcpSocketOption::SO_OOBINLINE => {}
+            TcpSocketOption::SO_OOBINLINE => opt_put(opt_addr, opt_len, &0i32.to_ne_bytes()),
         }
     }
 }
 
 impl Ipv6Option {
     pub fn set(&self, socket: &Socket, opt: &[u8]) -> SyscallRet {
+        match self {
+            Ipv6Option::IPV6_V6ONLY => {
+                if opt.len() < 4 {
+                    return Err(Errno::EINVAL);
+                }
+                if socket.domain != Domain::AF_INET6 {
+                    return Err(Errno::ENOPROTOOPT);
+                }
+                let value = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                socket.set_ipv6_only(value != 0);
+                Ok(0)
+            }
+            Ipv6Option::UNICAST_HOPS => {
+                if opt.len() < 4 {
+                    return Err(Errno::EINVAL);
+                }
+                let hops = i32::from_ne_bytes(<[u8; 4]>::try_from(&opt[0..4]).unwrap());
+                //-1表示恢复默认值
+                let hops = match hops {
+                    -1 => DEFAULT_TTL,
+                    1..=255 => hops as u8,
+                    _ => return Err(Errno::EINVAL),
+                };
+                socket.set_ttl(hops);
+                Ok(0)
+            }
+            _ => Ok(0),
+        }
+    }
+    pub fn get(&self, socket: &Socket, opt_value: *mut u8, opt_len: *mut u32) -> SyscallRet {
+        let value: i32 = match self {
+            Ipv6Option::IPV6_V6ONLY => socket.is_ipv6_only() as i32,
+            Ipv6Option::UNICAST_HOPS => socket.ttl() as i32,
+            //其余选项暂不支持读取
+            _ => return Ok(0),
+        };
+        copy_to_user(opt_value, &value.to_ne_bytes() as *const u8, 4)?;
+        copy_to_user(opt_len, &4u32 as *const u32, 1)?;
         Ok(0)
     }
 }
@@ -2147,28 +2595,26 @@ impl ALG_Option {
     //optval已经复制到内核
     pub fn set(&self, socket: &Socket, opt: &[u8]) -> SyscallRet {
         log::error!("[ALG_Option_set]opt is {:?}", opt);
-        assert!(socket.domain == Domain::AF_ALG);
+        if socket.domain != Domain::AF_ALG {
+            return Err(Errno::ENOPROTOOPT);
+        }
         match self {
             ALG_Option::ALG_SET_KEY => {
                 // 设置密钥
                 //optval 指向一个缓冲区，里面存放着“raw key bytes”，optlen 则是这个密钥（字节串）的长度
                 //对称加密/消息鉴别（MAC/HMAC）算法的密
+                //还没有bind算法
                 socket
                     .socket_af_alg
                     .lock()
                     .as_mut()
-                    .unwrap()
+                    .ok_or(Errno::ENOPROTOOPT)?
                     .set_alg_key(opt);
                 Ok(0)
             }
-            ALG_Option::ALG_SET_IV => {
-                unimplemented!()
-            }
-            ALG_Option::ALG_SET_AEAD_AUTHSIZE => {
-                unimplemented!()
-            }
-            ALG_Option::ALG_SET_OP => {
-                unimplemented!()
+            //IV和操作类型在sendmsg的控制消息中传递, 不能通过setsockopt设置
+            ALG_Option::ALG_SET_IV | ALG_Option::ALG_SET_AEAD_AUTHSIZE | ALG_Option::ALG_SET_OP => {
+                Err(Errno::ENOPROTOOPT)
             }
         }
     }
@@ -12,11 +12,12 @@
 
 //本文将主要用于tcp的连接，按照linux中内容，主要状态转换为socket->bind(to a addr)->listen(listentable)->connect->send/recv
 
-use core::{cell::UnsafeCell, net::SocketAddr,sync::atomic::{AtomicBool, AtomicU8, Ordering}, time};
-use smoltcp::{iface::SocketHandle, socket::tcp::{self, ConnectError, RecvError, SendError, State}, wire::{IpEndpoint, IpListenEndpoint, Ipv4Address}};
+use alloc::{collections::btree_map::BTreeMap, sync::{Arc, Weak}};
+use core::{cell::UnsafeCell, net::SocketAddr,sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering}, time};
+use smoltcp::{iface::SocketHandle, socket::tcp::{self, CongestionControl, ConnectError, RecvError, SendError, State}, time::Duration, wire::{IpAddress, IpEndpoint, IpListenEndpoint, IpProtocol, IpVersion, Ipv4Address, Ipv4Packet, Ipv6Packet, TcpPacket, TcpSeqNumber}};
 use spin::Mutex;
 
-use crate::{arch::timer::get_time, net::{addr::{from_sockaddr_to_ipendpoint, is_unspecified, LOOP_BACK_ENDPOINT}, ETH0, LOOPBACK}, syscall::errno::{Errno, SyscallRet}, task::{current_task, yield_current_task}};
+use crate::{arch::timer::{get_time, get_time_ms, get_time_us}, net::{addr::{from_sockaddr_to_ipendpoint, is_loopback, is_unspecified, LOOP_BACK_ENDPOINT}, ETH0, LOOPBACK}, syscall::errno::{Errno, SyscallRet}, task::{current_task, yield_current_task}};
 
 use super::{addr::UNSPECIFIED_ENDPOINT, listentable::ListenTable, poll_interfaces, SocketSetWrapper, LISTEN_TABLE, SOCKET_SET};
 pub struct PollState{
@@ -38,8 +39,27 @@ pub struct TcpSocket{
     loacl_addr:UnsafeCell<IpEndpoint>,
     remote_addr:UnsafeCell<IpEndpoint>,
     nonblock:AtomicBool,
-    reuse_addr:AtomicBool
+    reuse_addr:AtomicBool,
+    //SO_KEEPALIVE以及TCP_KEEPIDLE/TCP_KEEPINTVL/TCP_KEEPCNT,单位为秒
+    //在handle分配之前设置的值会在connect/bind时写入smoltcp socket
+    keep_alive:AtomicBool,
+    keep_idle:AtomicU32,
+    keep_intvl:AtomicU32,
+    keep_cnt:AtomicU32,
+    //SO_LINGER,None表示未开启,Some(0)表示close时直接发送RST
+    linger:Mutex<Option<u32>>,
+    //TCP_CONGESTION,None表示不做拥塞控制
+    congestion:Mutex<Option<CongestionControl>>,
+    //连接建立后从收发的报文中统计的rtt和重传信息,供TCP_INFO使用
+    stats:Mutex<Option<Arc<TcpStats>>>,
 }
+//与linux保持一致的保活默认值和上限
+pub const TCP_KEEPIDLE_DEFAULT:u32=7200;
+pub const TCP_KEEPINTVL_DEFAULT:u32=75;
+pub const TCP_KEEPCNT_DEFAULT:u32=9;
+pub const MAX_TCP_KEEPIDLE:u32=32767;
+pub const MAX_TCP_KEEPINTVL:u32=32767;
+pub const MAX_TCP_KEEPCNT:u32=127;
 //这几个状态i定义用于控制状态切换时的动作
 const STATE_CLOSED:u8=0;
 const STATE_BUSY:u8=1;
@@ -109,14 +129,54 @@ impl TcpSocket {
             get_ephemeral_port()
         };
         assert!(port!=0);
-        //确保addr不是0.0.0.0
-        let addr=if is_unspecified(local_addr.addr) {
-            Some(smoltcp::wire::IpAddress::Ipv4(Ipv4Address::new(127, 0, 0, 1)))
-        }else{
-            Some(local_addr.addr)
+        //确保addr不是0.0.0.0,绑定在::上的ipv6套接字监听所有地址
+        let addr=match local_addr.addr {
+            IpAddress::Ipv6(_) if is_unspecified(local_addr.addr) => None,
+            _ if is_unspecified(local_addr.addr) => {
+                Some(smoltcp::wire::IpAddress::Ipv4(Ipv4Address::new(127, 0, 0, 1)))
+            }
+            _ => Some(local_addr.addr),
         };
         IpListenEndpoint{addr,port}
     }
+    /// 把保存在TcpSocket中的保活和拥塞控制配置写入smoltcp socket
+    /// smoltcp只有一个保活间隔:空闲keep_idle秒后发送第一个探测,
+    /// 若之后keep_intvl*keep_cnt秒内没有收到任何报文则断开连接
+    fn apply_options(&self,socket:&mut tcp::Socket) {
+        if self.keep_alive.load(Ordering::Acquire) {
+            let idle=self.keep_idle.load(Ordering::Acquire) as u64;
+            let probes=self.keep_intvl.load(Ordering::Acquire) as u64*self.keep_cnt.load(Ordering::Acquire) as u64;
+            socket.set_keep_alive(Some(Duration::from_secs(idle)));
+            socket.set_timeout(Some(Duration::from_secs(idle+probes)));
+        }
+        else {
+            socket.set_keep_alive(None);
+            socket.set_timeout(None);
+        }
+        socket.set_congestion_control(self.congestion.lock().unwrap_or(CongestionControl::None));
+    }
+    fn update_options(&self) {
+        self.with_socket_mut(|socket|{
+            if let Some(socket)=socket {
+                self.apply_options(socket);
+            }
+        });
+    }
+    /// SO_LINGER:close时等待发送缓冲区中的数据和FIN被对端确认,最多等待timeout秒
+    fn linger_wait(&self,handle:SocketHandle,timeout:u32) {
+        let deadline=get_time_ms()+timeout as usize*1000;
+        loop {
+            SOCKET_SET.poll_interfaces();
+            let done=SOCKET_SET.with_socket::<_,tcp::Socket,_>(handle,|socket|{
+                matches!(socket.state(),State::Closed|State::TimeWait|State::FinWait2)
+            });
+            if done||get_time_ms()>=deadline {
+                log::error!("[Tcp_linger] linger done {:?}",done);
+                return;
+            }
+            yield_current_task();
+        }
+    }
     //client发送syn之后如果接收到server的syn ack则返回connected,这个设置似乎是由smoltcp完成
     //connect一般只发送而不recv
     //connecting时1使用
@@ -217,13 +277,37 @@ impl TcpSocket {
             loacl_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT), 
             remote_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT), 
             nonblock: AtomicBool::new(false), 
-            reuse_addr: AtomicBool::new(false) }
+            reuse_addr: AtomicBool::new(false),
+            keep_alive: AtomicBool::new(false),
+            keep_idle: AtomicU32::new(TCP_KEEPIDLE_DEFAULT),
+            keep_intvl: AtomicU32::new(TCP_KEEPINTVL_DEFAULT),
+            keep_cnt: AtomicU32::new(TCP_KEEPCNT_DEFAULT),
+            linger: Mutex::new(None),
+            congestion: Mutex::new(None),
+            stats: Mutex::new(None) }
     }
     pub fn new_connected(handle:SocketHandle,local_endpoint:IpEndpoint,remote_endpoint:IpEndpoint)->Self {
         TcpSocket { state: AtomicU8::new(STATE_CONNECTED),
              handle: UnsafeCell::new(Some(handle)), 
              loacl_addr: UnsafeCell::new(local_endpoint), 
-             remote_addr: UnsafeCell::new(remote_endpoint), nonblock: AtomicBool::new(false), reuse_addr: AtomicBool::new(false) }
+             remote_addr: UnsafeCell::new(remote_endpoint), nonblock: AtomicBool::new(false), reuse_addr: AtomicBool::new(false),
+             keep_alive: AtomicBool::new(false),
+             keep_idle: AtomicU32::new(TCP_KEEPIDLE_DEFAULT),
+             keep_intvl: AtomicU32::new(TCP_KEEPINTVL_DEFAULT),
+             keep_cnt: AtomicU32::new(TCP_KEEPCNT_DEFAULT),
+             linger: Mutex::new(None),
+             congestion: Mutex::new(None),
+             stats: Mutex::new(Some(TcpStats::register(local_endpoint, remote_endpoint))) }
+    }
+    /// accept得到的socket继承监听socket的保活,linger和拥塞控制配置
+    pub fn inherit_options(&self,listener:&TcpSocket) {
+        self.keep_alive.store(listener.keep_alive.load(Ordering::Acquire), Ordering::Release);
+        self.keep_idle.store(listener.keep_idle.load(Ordering::Acquire), Ordering::Release);
+        self.keep_intvl.store(listener.keep_intvl.load(Ordering::Acquire), Ordering::Release);
+        self.keep_cnt.store(listener.keep_cnt.load(Ordering::Acquire), Ordering::Release);
+        *self.linger.lock()=*listener.linger.lock();
+        *self.congestion.lock()=*listener.congestion.lock();
+        self.update_options();
     }
     pub fn local_addr(&self)->Result<IpEndpoint, Errno> {
         match self.get_state() {
@@ -274,14 +358,18 @@ impl TcpSocket {
             let handle=unsafe { self.handle.get().read()}.unwrap_or_else(||{
                 SOCKET_SET.add(SocketSetWrapper::new_tcp_socket())
             });
-            let bound_endpoint=self.bound_endpoint();
+            let mut bound_endpoint=self.bound_endpoint();
             let remote_ipendpoint=from_sockaddr_to_ipendpoint(remote_addr);
+            //本地地址与对端协议族不同(如未绑定的ipv6套接字),交给smoltcp自动选择源地址
+            if bound_endpoint.addr.is_some_and(|addr| addr.version()!=remote_ipendpoint.addr.version()) {
+                bound_endpoint.addr=None;
+            }
             log::error!("[TcpSocket:connect]:connect from {:?} to {:?}",bound_endpoint,remote_ipendpoint);
             if bound_endpoint.port==remote_ipendpoint.port {
                 return Err(Errno::ECONNREFUSED);
             }
-            //需要判断连接的remote_addr是否是127.0.0.1,这将决定使用什么网卡
-            let iface=if remote_ipendpoint.addr.as_bytes()[0]==127 {
+            //需要判断连接的remote_addr是否是127.0.0.1或::1,这将决定使用什么网卡
+            let iface=if is_loopback(remote_ipendpoint.addr) {
                 //使用回环网络todo
                 LOOPBACK.get().unwrap()
             }else{
@@ -303,6 +391,7 @@ impl TcpSocket {
             log::trace!("[tcp_connect]");
             let (local_endpoint, remote_endpoint) =
                 SOCKET_SET.with_socket_mut::<_, tcp::Socket, Result<(IpEndpoint, IpEndpoint), Errno>>(handle, |socket| {
+                    self.apply_options(socket);
                     socket
                         .connect(iface.lock().context(), remote_ipendpoint, bound_endpoint)
                         .map_err(|e| match e {
@@ -318,6 +407,8 @@ impl TcpSocket {
             unsafe { self.loacl_addr.get().write(local_endpoint) };
             unsafe { self.remote_addr.get().write(remote_endpoint) };
             unsafe { self.handle.get().write(Some(handle)) };
+            //SYN在下一次poll时才发出,此时注册可以把握手也计入rtt
+            *self.stats.lock()=Some(TcpStats::register(local_endpoint, remote_endpoint));
             Ok(())
         })?;
         //等待server返回synack
@@ -381,6 +472,7 @@ impl TcpSocket {
             //把local_endpoint写到socketset中的socket中
             SOCKET_SET.with_socket_mut::<_,smoltcp::socket::tcp::Socket,_>(handle, |socket|{
                 socket.set_bound_endpoint(self.bound_endpoint());
+                self.apply_options(socket);
             });
             
             Ok(())
@@ -391,9 +483,14 @@ impl TcpSocket {
 
 
     //函数将会监听一个地址并写回listentable
-    pub fn listen(&self)->SyscallRet {
+    //ipv6:是否是AF_INET6套接字,v6only:IPV6_V6ONLY
+    pub fn listen(&self,ipv6:bool,v6only:bool)->SyscallRet {
         self.update_state(STATE_CLOSED, STATE_LISTENING,||{
-            let bound_endpoint=self.bound_endpoint();
+            let mut bound_endpoint=self.bound_endpoint();
+            //未绑定的ipv6套接字监听在::上
+            if ipv6 && unsafe { self.loacl_addr.get().read() }.addr==UNSPECIFIED_ENDPOINT.addr {
+                bound_endpoint.addr=None;
+            }
             log::error!("[TcpSocket]:listen on bound_endpoint {:?}",bound_endpoint);
             unsafe { (*self.loacl_addr.get()).port=bound_endpoint.port };
             //listentable会判断对应的port的entry是否为空，如果为空，创建一个entry进入
@@ -403,7 +500,7 @@ impl TcpSocket {
             //     //listens
             //     let _ = socket.listen(bound_endpoint);
             // });
-            LISTEN_TABLE.listen(bound_endpoint)
+            LISTEN_TABLE.listen(bound_endpoint,v6only)
         })
     }
     
@@ -468,18 +565,30 @@ impl TcpSocket {
     pub fn shutdown(&self){
         // stream
         // 已经建立连接的socket断开连接
-        let _ = self.update_state(STATE_CONNECTED, STATE_CLOSED, || {
+        let linger=*self.linger.lock();
+        let closed = self.update_state(STATE_CONNECTED, STATE_CLOSED, || {
             // SAFETY: `self.handle` should be initialized in a connected socket, and
             // no other threads can read or write it.
             let handle = unsafe { self.handle.get().read().unwrap() };
             SOCKET_SET.with_socket_mut::<_,tcp::Socket,_>(handle, |socket| {
                 // debug!("TCP socket {}: shutting down", handle);
-                socket.close();
+                //SO_LINGER开启且超时为0时丢弃未发送的数据并发送RST
+                if linger==Some(0) {
+                    socket.abort();
+                }
+                else {
+                    socket.close();
+                }
             });
             unsafe { self.loacl_addr.get().write(UNSPECIFIED_ENDPOINT) }; // clear bound address
             SOCKET_SET.poll_interfaces();
-            Ok(())
+            Ok(handle)
         });
+        if let (Ok(handle),Some(timeout))=(closed,linger) {
+            if timeout>0 {
+                self.linger_wait(handle, timeout);
+            }
+        }
 
         // listener
         // 监听者断开连接
@@ -644,6 +753,36 @@ impl TcpSocket {
         })
     }
 
+    /// SO_SNDTIMEO:发送缓冲区一直满时最多阻塞timeout_ms毫秒,超时返回EAGAIN
+    pub fn send_timeout(&self,buf:&[u8],timeout_ms:usize)->Result<usize,Errno> {
+        log::error!("[Tcp_socket]:begin send with timeout {:?}ms",timeout_ms);
+        if self.is_connecting() {
+            return Err(Errno::EAGAIN);
+        }
+        else if !self.is_connected() {
+            return Err(Errno::ENOTCONN);
+        }
+        let deadline=get_time_ms()+timeout_ms;
+        let handle=unsafe { self.handle.get().read().unwrap() };
+        self.block_on(||{
+            SOCKET_SET.with_socket_mut::<_,tcp::Socket,_>(handle, |socket|{
+                if !socket.is_active() || !socket.may_send() {
+                    Err(Errno::EPIPE)
+                }
+                else if socket.can_send() {
+                    socket.send_slice(buf).map_err(|_|Errno::EPIPE)
+                }
+                else if get_time_ms()>=deadline {
+                    //block_on遇到EAGAIN会继续等待,这里先用ETIMEDOUT跳出
+                    Err(Errno::ETIMEDOUT)
+                }
+                else {
+                    Err(Errno::EAGAIN)
+                }
+            })
+        }).map_err(|e| if e==Errno::ETIMEDOUT { Errno::EAGAIN } else { e })
+    }
+
     pub fn poll(&self,isread:bool)->PollState {
         // log::error!("[Tcp_socket]:poll state is {:?}",self.get_state());
         // log::error!("[Tcp_socket]:poll socket addr is {:?}",self.local_addr().unwrap());
@@ -695,16 +834,209 @@ impl TcpSocket {
             None => f(None),
         }
     }
+    /// 连接的rtt和重传统计,未连接时返回None
+    pub fn stats(&self)->Option<TcpStatsSnapshot> {
+        self.stats.lock().as_ref().map(|stats| stats.snapshot())
+    }
     pub fn set_hop_limit(&self,limit:u8) {
         let handle=unsafe { self.handle.get().read().unwrap() };
         SOCKET_SET.with_socket_mut::<_,tcp::Socket,_>(handle,|socket|{
             socket.set_hop_limit(Some(limit));
         });
     }
+    pub fn keep_alive(&self)->bool {
+        self.keep_alive.load(Ordering::Acquire)
+    }
+    pub fn set_keep_alive(&self,enable:bool) {
+        self.keep_alive.store(enable, Ordering::Release);
+        self.update_options();
+    }
+    pub fn keep_idle(&self)->u32 {
+        self.keep_idle.load(Ordering::Acquire)
+    }
+    pub fn set_keep_idle(&self,secs:u32) {
+        self.keep_idle.store(secs, Ordering::Release);
+        self.update_options();
+    }
+    pub fn keep_intvl(&self)->u32 {
+        self.keep_intvl.load(Ordering::Acquire)
+    }
+    pub fn set_keep_intvl(&self,secs:u32) {
+        self.keep_intvl.store(secs, Ordering::Release);
+        self.update_options();
+    }
+    pub fn keep_cnt(&self)->u32 {
+        self.keep_cnt.load(Ordering::Acquire)
+    }
+    pub fn set_keep_cnt(&self,cnt:u32) {
+        self.keep_cnt.store(cnt, Ordering::Release);
+        self.update_options();
+    }
+    pub fn linger(&self)->Option<u32> {
+        *self.linger.lock()
+    }
+    pub fn set_linger(&self,linger:Option<u32>) {
+        *self.linger.lock()=linger;
+    }
+    pub fn set_congestion(&self,congestion:CongestionControl) {
+        *self.congestion.lock()=Some(congestion);
+        self.update_options();
+    }
 }
 
 
 //分配可用的端口，u16,端口从0xc000到0xffff
+/// 已连接socket的统计信息,key为(本地地址,对端地址),socket关闭后Weak失效
+static TCP_STATS: Mutex<BTreeMap<(IpEndpoint, IpEndpoint), Weak<TcpStats>>> = Mutex::new(BTreeMap::new());
+
+/// smoltcp不公开rtt和重传计数,网卡收发时根据tcp首部自己统计
+/// 与smoltcp的RttEstimator一样,同一时刻只对一个报文段计时,重传过的报文段不参与采样(Karn算法)
+pub struct TcpStats {
+    inner: Mutex<TcpStatsInner>,
+}
+
+#[derive(Default)]
+struct TcpStatsInner {
+    //最早未确认的序号
+    snd_una: Option<TcpSeqNumber>,
+    //已发送的最大序号(不含)
+    snd_nxt: Option<TcpSeqNumber>,
+    //正在计时的报文段的结束序号和发送时间(微秒)
+    timed: Option<(TcpSeqNumber, usize)>,
+    //平滑rtt和偏差(微秒),按RFC 6298计算,0表示还没有采样
+    srtt: u32,
+    rttvar: u32,
+    //当前未确认数据的连续重传次数,有新数据被确认后清零
+    retransmits: u8,
+    total_retrans: u32,
+    //上一个rtt和当前rtt内观察到的最大在途字节数
+    last_flight: u32,
+    flight: u32,
+}
+
+/// TCP_INFO使用的统计快照
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TcpStatsSnapshot {
+    pub srtt_us: u32,
+    pub rttvar_us: u32,
+    pub retransmits: u8,
+    pub total_retrans: u32,
+    pub unacked_bytes: u32,
+    pub flight_bytes: u32,
+}
+
+impl TcpStats {
+    fn register(local: IpEndpoint, remote: IpEndpoint) -> Arc<Self> {
+        let stats = Arc::new(TcpStats { inner: Mutex::new(TcpStatsInner::default()) });
+        let mut table = TCP_STATS.lock();
+        table.retain(|_, stats| stats.strong_count() > 0);
+        table.insert((local, remote), Arc::downgrade(&stats));
+        stats
+    }
+    fn snapshot(&self) -> TcpStatsSnapshot {
+        let inner = self.inner.lock();
+        let unacked = match (inner.snd_una, inner.snd_nxt) {
+            (Some(una), Some(nxt)) => (nxt - una) as u32,
+            _ => 0,
+        };
+        TcpStatsSnapshot {
+            srtt_us: inner.srtt,
+            rttvar_us: inner.rttvar,
+            retransmits: inner.retransmits,
+            total_retrans: inner.total_retrans,
+            unacked_bytes: unacked,
+            flight_bytes: inner.last_flight.max(inner.flight),
+        }
+    }
+    /// 本地发出的报文段
+    fn on_send(&self, seq: TcpSeqNumber, len: usize, now: usize) {
+        let mut inner = self.inner.lock();
+        let end = seq + len;
+        let una = *inner.snd_una.get_or_insert(seq);
+        match inner.snd_nxt {
+            Some(nxt) if seq < nxt => {
+                inner.retransmits = inner.retransmits.saturating_add(1);
+                inner.total_retrans += 1;
+                inner.timed = None;
+                if end > nxt {
+                    inner.snd_nxt = Some(end);
+                }
+            }
+            _ => {
+                inner.snd_nxt = Some(end);
+                if inner.timed.is_none() {
+                    inner.timed = Some((end, now));
+                }
+            }
+        }
+        if end > una {
+            inner.flight = inner.flight.max((end - una) as u32);
+        }
+    }
+    /// 收到对端的ack
+    fn on_ack(&self, ack: TcpSeqNumber, now: usize) {
+        let mut inner = self.inner.lock();
+        if inner.snd_una.is_some_and(|una| ack > una) {
+            inner.snd_una = Some(ack);
+            inner.retransmits = 0;
+        }
+        if let Some((end, sent)) = inner.timed {
+            if ack >= end {
+                let rtt = (now.saturating_sub(sent) as u32).max(1);
+                if inner.srtt == 0 {
+                    inner.srtt = rtt;
+                    inner.rttvar = rtt / 2;
+                } else {
+                    inner.rttvar = (inner.rttvar * 3 + inner.srtt.abs_diff(rtt)) / 4;
+                    inner.srtt = (inner.srtt * 7 + rtt) / 8;
+                }
+                inner.timed = None;
+                inner.last_flight = inner.flight;
+                inner.flight = 0;
+            }
+        }
+    }
+}
+
+/// 由网卡在收发ip报文时调用,更新对应连接的统计信息
+pub fn track_segment(packet: &[u8], outgoing: bool) {
+    if TCP_STATS.lock().is_empty() {
+        return;
+    }
+    let (src, dst, payload): (IpAddress, IpAddress, &[u8]) = match IpVersion::of_packet(packet) {
+        Ok(IpVersion::Ipv4) => match Ipv4Packet::new_checked(packet) {
+            Ok(ip) if ip.next_header() == IpProtocol::Tcp => {
+                (ip.src_addr().into(), ip.dst_addr().into(), ip.payload())
+            }
+            _ => return,
+        },
+        Ok(IpVersion::Ipv6) => match Ipv6Packet::new_checked(packet) {
+            Ok(ip) if ip.next_header() == IpProtocol::Tcp => {
+                (ip.src_addr().into(), ip.dst_addr().into(), ip.payload())
+            }
+            _ => return,
+        },
+        Err(_) => return,
+    };
+    let Ok(tcp) = TcpPacket::new_checked(payload) else {
+        return;
+    };
+    let src = IpEndpoint::new(src, tcp.src_port());
+    let dst = IpEndpoint::new(dst, tcp.dst_port());
+    let key = if outgoing { (src, dst) } else { (dst, src) };
+    let Some(stats) = TCP_STATS.lock().get(&key).and_then(|stats| stats.upgrade()) else {
+        return;
+    };
+    let now = get_time_us();
+    if outgoing {
+        if tcp.segment_len() > 0 {
+            stats.on_send(tcp.seq_number(), tcp.segment_len(), now);
+        }
+    } else if tcp.ack() {
+        stats.on_ack(tcp.ack_number(), now);
+    }
+}
+
 fn get_ephemeral_port() -> u16 {
     const PORT_START: u16 = 0xc000;
     const PORT_END: u16 = 0xffff;
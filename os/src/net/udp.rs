@@ -17,6 +17,7 @@ use core::net::SocketAddr;
 use core::panic;
 use core::ptr::copy_nonoverlapping;
 use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU8;
 use core::sync::atomic::Ordering;
 use core::time;
 use alloc::vec;
@@ -25,6 +26,7 @@ use smoltcp::socket::udp;
 use smoltcp::socket::udp::BindError;
 use smoltcp::wire::IpAddress;
 use smoltcp::wire::IpListenEndpoint;
+use smoltcp::wire::IpVersion;
 use spin::Mutex;
 use spin::RwLock;
 use smoltcp::wire::IpEndpoint;
@@ -35,6 +37,7 @@ use crate::net::addr::is_unspecified;
 use crate::net::addr::LOOP_BACK_ENDPOINT;
 use crate::net::addr::LOOP_BACK_IP;
 use crate::net::addr::UNSPECIFIED_IP;
+use crate::net::addr::UNSPECIFIED_IPV6;
 use crate::syscall::errno::Errno;
 use crate::syscall::errno::SyscallRet;
 use crate::task;
@@ -45,18 +48,32 @@ use super::addr::from_ipendpoint_to_socketaddr;
 use super::addr::from_sockaddr_to_ipendpoint;
 use super::addr::UNSPECIFIED_ENDPOINT;
 use super::poll_interfaces;
+use super::route_mtu;
 use super::tcp::PollState;
 use super::SocketSetWrapper;
 
 use super::LISTEN_TABLE;
 use super::SOCKET_SET;
 
+//IP_MTU_DISCOVER 的取值
+pub const IP_PMTUDISC_DONT: u8 = 0;
+pub const IP_PMTUDISC_WANT: u8 = 1;
+pub const IP_PMTUDISC_DO: u8 = 2;
+pub const IP_PMTUDISC_PROBE: u8 = 3;
+pub const IP_PMTUDISC_INTERFACE: u8 = 4;
+pub const IP_PMTUDISC_OMIT: u8 = 5;
+const UDP_HEADER_LEN: usize = 8;
+
  pub struct UdpSocket{
     handle:UnsafeCell<Option<SocketHandle>>,
     local_addr:RwLock<Option<IpEndpoint>>,
     remote_addr:RwLock<Option<IpEndpoint>>,
     nonblock:AtomicBool,
-    reuse_addr:AtomicBool
+    reuse_addr:AtomicBool,
+    //IP_MTU_DISCOVER,为DO/PROBE时超过mtu返回EMSGSIZE而不是分片
+    //smoltcp对不分片的ipv4报文总是设置DF,只有分片时清除DF
+    //所以DO/PROBE发出的报文都带DF,而DONT/OMIT无法清除DF
+    pmtudisc:AtomicU8,
  }
 
  unsafe impl Sync for UdpSocket {}
@@ -72,7 +89,8 @@ use super::SOCKET_SET;
             local_addr:RwLock::new(None)
             , remote_addr: RwLock::new(None),
             nonblock: AtomicBool::new(false),
-            reuse_addr: AtomicBool::new(false), }
+            reuse_addr: AtomicBool::new(false),
+            pmtudisc: AtomicU8::new(IP_PMTUDISC_WANT), }
             
      }
 
@@ -118,6 +136,23 @@ use super::SOCKET_SET;
     pub fn is_block(&self)->bool {
         false
     }
+    pub fn mtu_discover(&self)->u8 {
+        self.pmtudisc.load(Ordering::Acquire)
+    }
+    pub fn set_mtu_discover(&self,mode:u8) {
+        self.pmtudisc.store(mode, Ordering::Release);
+    }
+    /// 是否不允许分片,不分片的报文由smoltcp设置DF
+    fn dont_fragment(&self)->bool {
+        matches!(self.mtu_discover(),IP_PMTUDISC_DO|IP_PMTUDISC_PROBE)
+    }
+    /// IP_MTU,只有connect之后才能获取路径mtu
+    pub fn path_mtu(&self)->Result<usize,Errno> {
+        match *self.remote_addr.read() {
+            Some(remote) => Ok(route_mtu(remote.addr)),
+            None => Err(Errno::ENOTCONN),
+        }
+    }
 
     //绑定addr到local_addr
     pub fn bind(&self,mut bind_addr:SocketAddr) {
@@ -136,7 +171,8 @@ use super::SOCKET_SET;
 
         log::error!("[udp_bind]bind addr is {:?}",bind_addr);
         let mut local_endpoint=from_sockaddr_to_ipendpoint(bind_addr);
-        if is_unspecified(local_endpoint.addr) {
+        //绑定在::上的ipv6套接字同时接收ipv4与ipv6报文
+        if is_unspecified(local_endpoint.addr) && local_endpoint.addr.version()==IpVersion::Ipv4 {
             local_endpoint.addr=LOOP_BACK_IP;
         }
         let endpoint=IpListenEndpoint{
@@ -172,20 +208,20 @@ use super::SOCKET_SET;
         self.send_impl(buf, from_sockaddr_to_ipendpoint(remote_addr))
     }
     pub fn recv_from(&self,buf:&mut [u8])->Result<(usize,SocketAddr),Errno> {
+        self.recv_from_full(buf).map(|(copy_len,_,addr)|(copy_len,addr))
+    }
+    /// 接收一个数据报,返回(拷贝的长度,数据报的真实长度,源地址)
+    /// buf不够大时数据报被截断,剩余部分丢弃,真实长度用于MSG_TRUNC
+    pub fn recv_from_full(&self,buf:&mut [u8])->Result<(usize,usize,SocketAddr),Errno> {
         log::error!("[udp_recv_from]begin recv");
-        let mut binding = vec![0;1528];
-        let kernel_buf=binding.as_mut_slice();
-
-        self.recv_impl(|socket| match socket.recv_slice(kernel_buf) {
-            Ok((len, meta)) => {
-                // log::error!("[udp_recv_from]recv buf {:?}",buf);
-                {
-                    let copy_len = core::cmp::min(len, buf.len()); 
-                    log::error!("[udp_recv_from] copy len is {:?}",copy_len);
-                    buf[..copy_len].copy_from_slice(&kernel_buf[..copy_len]); 
-                    log::trace!("[udp_block_on] loop");
-                    Ok((copy_len, from_ipendpoint_to_socketaddr(meta.endpoint)))}
-                },
+        //直接借用smoltcp接收缓冲区中的数据报,不能用recv_slice:buf太小时它会先出队再返回Truncated
+        self.recv_impl(|socket| match socket.recv() {
+            Ok((payload, meta)) => {
+                let copy_len = core::cmp::min(payload.len(), buf.len());
+                log::error!("[udp_recv_from] copy len is {:?}, datagram len is {:?}",copy_len,payload.len());
+                buf[..copy_len].copy_from_slice(&payload[..copy_len]);
+                Ok((copy_len, payload.len(), from_ipendpoint_to_socketaddr(meta.endpoint)))
+            },
             Err(e) => {
                 log::error!("[udp_recv_from] recv error {:?}",e);
                 match e {
@@ -226,7 +262,8 @@ use super::SOCKET_SET;
         log::error!("[Udpsocket_connect]:begin connect remote addr is {:?}",remote_addr);
         let mut self_remote_addr=self.remote_addr.write();
         if self.local_addr.read().is_none() {
-            self.bind(from_ipendpoint_to_socketaddr(IpEndpoint::new(LOOP_BACK_IP, get_ephemeral_port())));
+            let local_ip=if remote_addr.is_ipv6() { UNSPECIFIED_IPV6 } else { LOOP_BACK_IP };
+            self.bind(from_ipendpoint_to_socketaddr(IpEndpoint::new(local_ip, get_ephemeral_port())));
         }
         *self_remote_addr=Some(from_sockaddr_to_ipendpoint(remote_addr));
         Ok(())
@@ -238,9 +275,7 @@ use super::SOCKET_SET;
     pub fn recv(&self, buf: &mut [u8]) -> Result<usize,Errno> {
         let remote_endpoint = from_sockaddr_to_ipendpoint(self.reomte_addr().unwrap());
         self.recv_impl(|socket| {
-            let (len, meta) = socket
-                .recv_slice(buf)
-                .map_err(|_| panic!("socket recv() failed"))?;
+            let (payload, meta) = socket.recv().map_err(|_| Errno::EAGAIN)?;
             // let remote_addr=IpAddress::Ipv4(remote_endpoint.ip());
             if !is_unspecified(remote_endpoint.addr) && remote_endpoint.addr!= meta.endpoint.addr {
                 return Err(Errno::EAGAIN)
@@ -248,6 +283,8 @@ use super::SOCKET_SET;
             if remote_endpoint.port!= 0 && remote_endpoint.port != meta.endpoint.port {
                 return Err(Errno::EAGAIN)
             }
+            let len = core::cmp::min(payload.len(), buf.len());
+            buf[..len].copy_from_slice(&payload[..len]);
             Ok(len)
         })
     }
@@ -325,6 +362,7 @@ use super::SOCKET_SET;
              return Err(Errno::ENOTCONN)
          }
          log::error!("[Udpsocket_send]:send to {:?}",remote_addr);
+         self.check_datagram_len(buf.len(), remote_addr)?;
          //阻塞loop
          self.block_on(||{
             let handle=unsafe { self.handle.get().read().unwrap() };
@@ -333,8 +371,14 @@ use super::SOCKET_SET;
                     log::error!("[Udpsocket]:socket not bind,send must be called after bind");
                     return Err(Errno::ENOTCONN);
                 }
+                else if buf.len() > socket.payload_send_capacity() {
+                    Err(Errno::EMSGSIZE)
+                }
                 else if socket.can_send() {
-                    socket.send_slice(buf, remote_addr).expect("socket send failed");
+                    socket.send_slice(buf, remote_addr).map_err(|e| match e {
+                        udp::SendError::BufferFull => Errno::EAGAIN,
+                        udp::SendError::Unaddressable => Errno::EDESTADDRREQ,
+                    })?;
                     Ok(buf.len())
                 }
                 else {
@@ -345,6 +389,23 @@ use super::SOCKET_SET;
             })
          })
      }
+     /// 检查数据报长度:超过ip报文上限(65535减去ip和udp首部),或超过路径mtu且设置了DF时返回EMSGSIZE
+     /// 分片缓冲区按64KB配置,能容纳任意合法长度的报文
+     fn check_datagram_len(&self,len:usize,remote_addr:IpEndpoint)->Result<(),Errno> {
+        let ip_header_len=match remote_addr.addr {
+            IpAddress::Ipv4(_) => 20,
+            IpAddress::Ipv6(_) => 40,
+        };
+        let packet_len=len + UDP_HEADER_LEN + ip_header_len;
+        if packet_len > u16::MAX as usize {
+            return Err(Errno::EMSGSIZE);
+        }
+        if packet_len > route_mtu(remote_addr.addr) && self.dont_fragment() {
+            log::error!("[Udpsocket_send]:datagram len {} exceed mtu",packet_len);
+            return Err(Errno::EMSGSIZE);
+        }
+        Ok(())
+     }
      fn block_on<F,T>(&self,mut f: F)->Result<T, Errno>
      where F:FnMut()->Result<T,Errno>
      {
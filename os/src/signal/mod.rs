@@ -20,7 +20,10 @@ use crate::{
         },
     },
     mm::VirtAddr,
-    task::{current_task, get_stack_top_by_sp, kernel_exit, remove_task, schedule, Task},
+    task::{
+        coredump::coredump_prepare, current_task, get_stack_top_by_sp, kernel_exit, remove_task,
+        schedule, Task,
+    },
 };
 
 // 用户栈构造如下
@@ -96,8 +99,13 @@ pub fn handle_signal() {
             match sig.get_default_type() {
                 ActionType::Ignore => {}
                 ActionType::Term => terminate(task, sig),
-                ActionType::Stop => stop(),
-                ActionType::Cont => cont(),
+                ActionType::Stop => {
+                    // 恢复后继续处理其他未决信号(SIGKILL或SIGCONT的处理函数)
+                    task.do_signal_stop(sig);
+                    continue;
+                }
+                // SIGCONT在产生时已经恢复了线程组
+                ActionType::Cont => {}
                 ActionType::Core => core(task, sig),
             }
         }
@@ -148,8 +156,24 @@ pub fn handle_signal() {
                 let siginfo_sp = user_sp; // siginfo_sp：塞入siginfo后的用户栈位置
                 trap_cx.set_a1(siginfo_sp);
                 log::info!("[handle_signal] a1 = {:#x}", siginfo_sp);
-                let sender_pid = sig_info.fields.parse_pid().unwrap();
-                let linux_siginfo = LinuxSigInfo::new(sig.raw(), sig_info.code, sender_pid as i32);
+                let linux_siginfo = match sig_info.fields {
+                    SiField::Kill { tid } => {
+                        LinuxSigInfo::new(sig.raw(), sig_info.code, tid as i32)
+                    }
+                    SiField::Sys {
+                        call_addr,
+                        syscall,
+                        arch,
+                        errno,
+                    } => LinuxSigInfo::new_sys(
+                        sig.raw(),
+                        sig_info.code,
+                        errno,
+                        call_addr,
+                        syscall,
+                        arch,
+                    ),
+                };
 
                 // 创建ucontext
                 user_sp = user_sp - core::mem::size_of::<UContext>();
@@ -200,18 +224,17 @@ fn terminate(task: Arc<Task>, sig: Sig) {
     kernel_exit(task, sig.raw() as i32 & 0x7F);
     schedule();
 }
-// Todo:
-fn stop() {}
-fn cont() {}
-// Todo: 转储任务崩溃时的内存快照
-// 目前只有page fault恢复失败时, 内核会发送SIGSEGV信号
+// 生成core文件后终止线程组
 fn core(task: Arc<Task>, sig: Sig) {
-    // task.op_memory_set(|memory_set| {
-    //     memory_set.page_table.dump_all_user_mapping();
-    // });
+    // 先记录各线程的寄存器, 再结束其他线程, 避免转储过程中内存被修改
+    let dump = coredump_prepare(&task, sig);
     task.close_thread();
-    // 将信号放入低7位 (第8位是core dump标志,在gdb调试崩溃程序中用到)
-    kernel_exit(task, sig.raw() as i32 & 0x7F | 0x80);
+    // 将信号放入低7位, 第8位是core dump标志, 只有成功生成core文件时才设置
+    let core_flag = match dump {
+        Some(dump) if dump.write(&task) => 0x80,
+        _ => 0,
+    };
+    kernel_exit(task, sig.raw() as i32 & 0x7F | core_flag);
     // panic!("core dump: {:?}", sig);
     log::error!("[core] core dump: {:?}", sig);
     schedule();
@@ -89,13 +89,23 @@ impl SigInfo {
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub enum SiField {
-    Kill { tid: Tid },
+    Kill {
+        tid: Tid,
+    },
+    /// seccomp产生的SIGSYS
+    Sys {
+        call_addr: usize,
+        syscall: i32,
+        arch: u32,
+        errno: i32,
+    },
 }
 
 impl SiField {
     pub fn parse_pid(&self) -> Option<Tid> {
         match self {
             SiField::Kill { tid } => Some(*tid),
+            SiField::Sys { .. } => None,
         }
     }
 }
@@ -137,6 +147,10 @@ impl SigInfo {
     /// stopped child has continued
     pub const CLD_CONTINUED: i32 = 6;
     pub const NSIGCHLD: i32 = 6;
+
+    // SIGSYS si_codes
+    /// seccomp triggered
+    pub const SYS_SECCOMP: i32 = 1;
 }
 
 #[derive(Default, Copy, Clone)]
@@ -162,6 +176,44 @@ impl LinuxSigInfo {
             _pad: [0; 27],
         }
     }
+
+    /// SIGCHLD的siginfo, 联合体部分为_sigchld { _pid, _uid, _status }
+    pub fn new_chld(code: i32, pid: i32, uid: u32, status: i32) -> Self {
+        let mut pad = [0; 27];
+        pad[0] = uid as i32;
+        pad[1] = status;
+        Self {
+            si_signo: Sig::SIGCHLD.raw(),
+            si_errno: 0,
+            si_code: code,
+            si_trapno: 0,
+            si_pid: pid,
+            _pad: pad,
+        }
+    }
+
+    /// SIGSYS的siginfo, 联合体部分为_sigsys { _call_addr, _syscall, _arch }
+    pub fn new_sys(
+        signo: i32,
+        code: i32,
+        errno: i32,
+        call_addr: usize,
+        syscall: i32,
+        arch: u32,
+    ) -> Self {
+        let mut pad = [0; 27];
+        pad[0] = (call_addr >> 32) as i32;
+        pad[1] = syscall;
+        pad[2] = arch as i32;
+        Self {
+            si_signo: signo,
+            si_errno: errno,
+            si_code: code,
+            si_trapno: 0,
+            si_pid: call_addr as i32,
+            _pad: pad,
+        }
+    }
 }
 
 #[repr(C)]
@@ -91,8 +91,9 @@ impl ActionType {
             | Sig::SIGXCPU
             | Sig::SIGXFSZ
             | Sig::SIGSYS => ActionType::Core,
-            Sig::SIGSTOP | Sig::SIGTSTP | Sig::SIGTTIN | Sig::SIGTTOU |Sig::SIGALRM=> ActionType::Stop,
-            Sig::SIGCHLD | Sig::SIGURG | Sig::SIGWINCH => ActionType::Ignore,
+            Sig::SIGSTOP | Sig::SIGTSTP | Sig::SIGTTIN | Sig::SIGTTOU => ActionType::Stop,
+            // Todo: SIGALRM默认应终止进程, 目前保持忽略
+            Sig::SIGCHLD | Sig::SIGURG | Sig::SIGWINCH | Sig::SIGALRM => ActionType::Ignore,
             Sig::SIGCONT => ActionType::Cont,
             _ => ActionType::Term,
         }
@@ -83,6 +83,13 @@ impl SigPending {
         }
     }
 
+    // 丢弃未处理集合中在sigset中的信号
+    pub fn remove_signals(&mut self, sigset: SigSet) {
+        self.pending.remove(sigset);
+        self.info
+            .retain(|&signo, _| !sigset.contain_signal(Sig::from(signo)));
+    }
+
     // 在信号掩码中添加新位
     pub fn add_mask(&mut self, sig: Sig) {
         self.mask.add_signal(sig);
@@ -198,6 +205,11 @@ impl Sig {
         (self.0 - 1) as usize
     }
 
+    /// 默认行为为停止线程组的作业控制信号
+    pub fn is_stop(&self) -> bool {
+        matches!(self.0, 19..=22)
+    }
+
     pub fn is_kill_or_stop(&self) -> bool {
         self.0 == 9 || self.0 == 19
     }
@@ -373,6 +385,12 @@ bitflags! {
 }
 
 impl SigSet {
+    /// 作业控制的停止信号, 产生SIGCONT时丢弃
+    pub const STOP_SIGNALS: SigSet = SigSet::SIGSTOP
+        .union(SigSet::SIGTSTP)
+        .union(SigSet::SIGTTIN)
+        .union(SigSet::SIGTTOU);
+
     pub fn add_signal(&mut self, sig: Sig) {
         self.insert(SigSet::from_bits(1 << sig.index()).unwrap())
     }
@@ -39,10 +39,11 @@ use crate::{
         mount::do_mount,
         namei::{filename_create, filename_lookup, path_openat, Nameidata},
         path::Path,
+        sys::cgroup::{cgroup_mkdir, cgroup_rmdir},
         uapi::IoVec,
         AT_FDCWD,
     },
-    task::current_task,
+    task::{capability::CapSet, current_task},
     utils::c_str_to_string,
 };
 
@@ -414,6 +415,10 @@ pub fn sys_unlinkat(dirfd: i32, pathname: *const u8, flag: i32) -> SyscallRet {
                 log::error!("[sys_unlinkat] cannot unlink a directory without AT_REMOVEDIR");
                 return Err(Errno::EISDIR);
             }
+            if dentry.is_dir() {
+                // 先删除cgroup的控制文件
+                cgroup_rmdir(&dentry)?;
+            }
             let parent_inode = dir_dentry.get_inode();
             parent_inode.unlink(dentry.clone())?;
             // 从dentry cache中删除
@@ -686,7 +691,9 @@ pub fn sys_mkdirat(dirfd: isize, pathname: *const u8, mode: usize) -> SyscallRet
     match filename_create(&mut nd, fake_lookup_flags) {
         Ok(dentry) => {
             let parent_inode = nd.dentry.get_inode();
-            parent_inode.mkdir(dentry, mode as u16 | S_IFDIR);
+            parent_inode.mkdir(dentry.clone(), mode as u16 | S_IFDIR);
+            // /sys/fs/cgroup下的目录是cgroup
+            cgroup_mkdir(&dentry)?;
             return Ok(0);
         }
         Err(e) => {
@@ -865,8 +872,8 @@ pub fn sys_chroot(pathname: *const u8) -> SyscallRet {
                 log::error!("[sys_chroot] chroot path must be searchable");
                 return Err(Errno::EACCES);
             }
-            if task.fsuid() != 0 {
-                log::error!("[sys_chroot] only root can chroot");
+            if !task.capable(CapSet::SYS_CHROOT) {
+                log::error!("[sys_chroot] CAP_SYS_CHROOT required");
                 return Err(Errno::EPERM);
             }
             current_task().set_root(Path::new(nd.mnt, dentry));
@@ -1064,6 +1071,8 @@ pub enum FcntlOp {
     F_DUPFD_CLOEXEC = 1030,
     F_SETPIPE_SZE = 1031, // 设置管道大小
     F_GETPIPE_SZ = 1032,  // 获取管道大小
+    F_ADD_SEALS = 1033,   // 给memfd添加封印
+    F_GET_SEALS = 1034,   // 获取memfd的封印
 }
 
 impl TryFrom<i32> for FcntlOp {
@@ -1078,6 +1087,8 @@ impl TryFrom<i32> for FcntlOp {
             1030 => Ok(FcntlOp::F_DUPFD_CLOEXEC),
             1031 => Ok(FcntlOp::F_SETPIPE_SZE),
             1032 => Ok(FcntlOp::F_GETPIPE_SZ),
+            1033 => Ok(FcntlOp::F_ADD_SEALS),
+            1034 => Ok(FcntlOp::F_GET_SEALS),
             _ => Err(()),
         }
     }
@@ -2185,6 +2196,9 @@ pub fn sys_mount(
         fs_type,
         flags
     );
+    if !current_task().capable(CapSet::SYS_ADMIN) {
+        return Err(Errno::EPERM);
+    }
     do_mount(source, target, fs_type, flags, _data)
 }
 
@@ -2193,6 +2207,9 @@ pub fn sys_umount2(target: *const u8, flags: i32) -> SyscallRet {
     // let target = c_str_to_string(target);
     // log::info!("[sys_unmount] target: {:?}, flags: {}", target, flags);
     log::info!("[sys_unmount] target: {:?}, flags: {}", target, flags);
+    if !current_task().capable(CapSet::SYS_ADMIN) {
+        return Err(Errno::EPERM);
+    }
     Ok(0)
 }
 
@@ -2310,8 +2327,9 @@ pub fn sys_msync(addr: usize, len: usize, flags: i32) -> SyscallRet {
                     );
                     return Err(Errno::ENOMEM);
                 }
-                if area.locked {
-                    log::error!("[sys_msync] Memory area is locked, cannot sync");
+                // 只有MS_INVALIDATE不能用于被mlock锁定的区域
+                if area.locked && flags & MS_INVALIDATE != 0 {
+                    log::error!("[sys_msync] Memory area is locked, cannot invalidate");
                     return Err(Errno::EBUSY);
                 }
                 if flags & MS_INVALIDATE != 0 {
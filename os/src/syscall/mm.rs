@@ -2,22 +2,28 @@ use core::{fmt::Debug, iter::Map, mem};
 
 use crate::{
     arch::{
-        config::{MMAP_MIN_ADDR, PAGE_SIZE, PAGE_SIZE_BITS, USER_MAX_VA},
+        config::{HUGE_PAGE_SIZE, MMAP_MIN_ADDR, PAGE_SIZE, PAGE_SIZE_BITS, USER_MAX_VA},
         mm::copy_to_user,
         trap::context::dump_trap_context,
     },
     fs::file::{File, OpenFlags},
+    fs::uapi::{Resource, RLIM_INFINITY},
+    fs::{
+        fdtable::FdFlags,
+        memfd::{MemFd, MFD_ALLOW_SEALING, MFD_CLOEXEC, MFD_HUGETLB, MFD_NAME_MAX_LEN},
+        userfaultfd::{UserfaultFd, UFFD_USER_MODE_ONLY},
+    },
     index_list::{IndexList, ListIndex},
     mm::{
         shm::{
             self, add_shm_segment, attach_shm_segment, check_shm_segment_exist, detach_shm_segment,
             stat_shm_segment, ShmAtFlags, ShmCtlOp, ShmGetFlags, ShmId, ShmSegment, IPC_PRIVATE,
         },
-        MapArea, MapPermission, MapType, VPNRange, VirtAddr, VirtPageNum,
+        MapAdvice, MapArea, MapPermission, MapType, VPNRange, VirtAddr, VirtPageNum,
     },
     syscall::errno::Errno,
-    task::current_task,
-    utils::{ceil_to_page_size, floor_to_page_size},
+    task::{capability::CapSet, current_task, Task},
+    utils::{c_str_to_string, ceil_to_page_size, floor_to_page_size},
 };
 use alloc::{string::String, vec::Vec};
 use bitflags::bitflags;
@@ -170,10 +176,10 @@ pub fn sys_brk(brk: usize) -> SyscallRet {
                         );
                         // if area.vpn_range.get_end() < remove_range.get_end() {
                         // 覆盖后部分
-                        for vpn in VPNRange::new(remove_range.get_start(), area.vpn_range.get_end())
-                        {
-                            area.dealloc_one_page(&mut memory_set.page_table, vpn);
-                        }
+                        area.dealloc_pages(
+                            &mut memory_set.page_table,
+                            VPNRange::new(remove_range.get_start(), area.vpn_range.get_end()),
+                        );
                         area.vpn_range.set_end(remove_range.get_start());
                         memory_set.areas.insert(area.vpn_range.get_start(), area);
                         // } else {
@@ -254,6 +260,8 @@ bitflags! {
         // MAP_POPULATE, 预先填充页表, 提高访问速度
         const MAP_POPULATE = 0x8000;
         const MAP_STACK = 0x20000;
+        /// MAP_HUGETLB, 使用大页, 目前只支持匿名映射
+        const MAP_HUGETLB = 0x40000;
         // 一定要映射到hint指向的地址, 如果指定地址范围已经有映射, 则会返回EEXIST, 而不是取消原来的映射
         const MAP_FIXED_NOREPLACE = 0x100000;
     }
@@ -304,6 +312,11 @@ pub fn sys_mmap(
     );
     //处理参数
     let prot = MmapProt::from_bits(prot as u32).unwrap();
+    // MAP_HUGETLB时flags的[26, 32)位是大页大小的log2, 0表示默认大小
+    const MAP_HUGE_SHIFT: usize = 26;
+    const MAP_HUGE_MASK: usize = 0x3f;
+    let huge_size_bits = (flags >> MAP_HUGE_SHIFT) & MAP_HUGE_MASK;
+    let flags = flags & !(MAP_HUGE_MASK << MAP_HUGE_SHIFT);
     let flags = match MmapFlags::from_bits(flags as u32) {
         Some(flags) => flags,
         None => {
@@ -326,16 +339,40 @@ pub fn sys_mmap(
         return Err(Errno::EINVAL);
     }
 
+    let hugetlb = flags.contains(MmapFlags::MAP_HUGETLB);
+    if hugetlb {
+        // 只支持默认大小(2MB)的匿名大页, 指定的地址需要按大页对齐
+        if (huge_size_bits != 0 && (1 << huge_size_bits) != HUGE_PAGE_SIZE)
+            || !flags.contains(MmapFlags::MAP_ANONYMOUS)
+            || (hint % HUGE_PAGE_SIZE != 0
+                && flags.intersects(MmapFlags::MAP_FIXED | MmapFlags::MAP_FIXED_NOREPLACE))
+        {
+            return Err(Errno::EINVAL);
+        }
+    }
+    // 大页映射的长度向上对齐到大页
+    let len = if hugetlb {
+        len.checked_next_multiple_of(HUGE_PAGE_SIZE)
+            .ok_or(Errno::ENOMEM)?
+    } else {
+        len
+    };
+
     let mut map_perm: MapPermission = prot.into();
     if flags.contains(MmapFlags::MAP_DENYWRITE) {
         log::warn!("[sys_mmap] MAP_DENYWRITE not implemented");
     }
-    let locked = if flags.contains(MmapFlags::MAP_LOCKED) {
-        log::warn!("[sys_mmap] MAP_LOCKED set");
-        true
-    } else {
-        false
-    };
+    // MAP_LOCKED或mlockall(MCL_FUTURE)之后的映射被锁定
+    let (mlock_future, mlock_onfault) =
+        task.op_memory_set(|memory_set| (memory_set.mlock_future, memory_set.mlock_onfault));
+    let locked = flags.contains(MmapFlags::MAP_LOCKED) || mlock_future;
+    if locked {
+        let locked_pages = task.op_memory_set(|memory_set| memory_set.locked_pages());
+        check_memlock_limit(&task, locked_pages, ceil_to_page_size(len) / PAGE_SIZE)
+            .map_err(|_| Errno::EAGAIN)?;
+    }
+    // MAP_LOCKED不保证预先分配成功, 与Linux一致失败时不报错
+    let populate_locked = locked && (flags.contains(MmapFlags::MAP_LOCKED) || !mlock_onfault);
     // 加上U权限
     map_perm |= MapPermission::U;
     if flags.contains(MmapFlags::MAP_SHARED) {
@@ -395,10 +432,18 @@ pub fn sys_mmap(
                     VirtAddr::from(hint).floor(),
                     VirtAddr::from(hint + len).ceil(),
                 )
+            } else if hugetlb {
+                memory_set.get_unmapped_area_aligned(len, HUGE_PAGE_SIZE)
             } else {
                 memory_set.get_unmapped_area(len)
             };
-            if map_perm.contains(MapPermission::S) {
+            if map_perm.contains(MapPermission::S) && hugetlb {
+                // 共享大页映射, 直接分配物理页
+                let mut mmap_area =
+                    MapArea::new(vpn_range, MapType::Framed, map_perm, None, 0, locked);
+                mmap_area.advice.insert(MapAdvice::HUGEPAGE);
+                memory_set.push_anoymous_area(mmap_area);
+            } else if map_perm.contains(MapPermission::S) {
                 // 共享映射, 直接分配物理页
                 memory_set.insert_framed_area(vpn_range, map_perm, locked);
             } else {
@@ -412,11 +457,18 @@ pub fn sys_mmap(
                 } else {
                     // 懒分配
                     log::info!("[sys_mmap] lazy allocation for anonymous mapping");
-                    let mmap_area =
+                    let mut mmap_area =
                         MapArea::new(vpn_range, MapType::Framed, map_perm, None, 0, locked);
+                    if hugetlb {
+                        // 缺页时按大页分配
+                        mmap_area.advice.insert(MapAdvice::HUGEPAGE);
+                    }
                     memory_set.insert_map_area_lazily(mmap_area);
                 };
             }
+            if populate_locked {
+                memory_set.populate_locked(vpn_range);
+            }
             log::error!(
                 "[sys_mmap] alloc area is {:#x}",
                 vpn_range.get_start().0 << PAGE_SIZE_BITS
@@ -455,6 +507,16 @@ pub fn sys_mmap(
             log::error!("[sys_mmap] file not opened with O_RDWR");
             return Err(Errno::EACCES);
         }
+        // 被F_SEAL_WRITE或F_SEAL_FUTURE_WRITE封印的memfd不能建立新的可写共享映射
+        if let Some(memfd) = file.as_any().downcast_ref::<MemFd>() {
+            if map_perm.contains(MapPermission::S)
+                && map_perm.contains(MapPermission::W)
+                && memfd.inode().write_sealed()
+            {
+                log::error!("[sys_mmap] memfd is write sealed");
+                return Err(Errno::EPERM);
+            }
+        }
 
         task.op_memory_set_mut(|memory_set| {
             let vpn_range = if flags.contains(MmapFlags::MAP_FIXED)
@@ -491,6 +553,9 @@ pub fn sys_mmap(
                 // 懒分配
                 memory_set.insert_map_area_lazily(mmap_area);
             }
+            if populate_locked {
+                memory_set.populate_locked(vpn_range);
+            }
             log::error!(
                 "[sys_mmap] file return {:#x}",
                 vpn_range.get_start().0 << PAGE_SIZE_BITS
@@ -547,6 +612,23 @@ pub fn sys_mprotect(addr: usize, size: usize, prot: i32) -> SyscallRet {
     let remap_range = VPNRange::new(start_vpn, end_vpn);
 
     current_task().op_memory_set_mut(|memory_set| {
+        // 被封印禁止写入的memfd的共享映射不能加上写权限
+        if new_perm.contains(MapPermission::W)
+            && memory_set
+                .areas
+                .range(..remap_range.get_end())
+                .any(|(_, area)| {
+                    area.vpn_range.is_intersect_with(&remap_range)
+                        && area.is_shared()
+                        && area.backend_file.as_ref().is_some_and(|file| {
+                            file.as_any()
+                                .downcast_ref::<MemFd>()
+                                .is_some_and(|memfd| memfd.inode().write_sealed())
+                        })
+                })
+        {
+            return Err(Errno::EACCES);
+        }
         if !memory_set.remap_area_with_overlap(remap_range, new_perm) {
             log::warn!(
                 "[sys_mprotect] no mapped area found for {:#x}-{:#x}",
@@ -672,7 +754,26 @@ pub fn sys_mremap(
         return;
     }
 }
-// Todo:
+pub const MADV_NORMAL: i32 = 0;
+pub const MADV_RANDOM: i32 = 1;
+pub const MADV_SEQUENTIAL: i32 = 2;
+pub const MADV_WILLNEED: i32 = 3;
+pub const MADV_DONTNEED: i32 = 4;
+pub const MADV_FREE: i32 = 8;
+pub const MADV_REMOVE: i32 = 9;
+pub const MADV_DONTFORK: i32 = 10;
+pub const MADV_DOFORK: i32 = 11;
+pub const MADV_MERGEABLE: i32 = 12;
+pub const MADV_UNMERGEABLE: i32 = 13;
+pub const MADV_HUGEPAGE: i32 = 14;
+pub const MADV_NOHUGEPAGE: i32 = 15;
+pub const MADV_DONTDUMP: i32 = 16;
+pub const MADV_DODUMP: i32 = 17;
+pub const MADV_WIPEONFORK: i32 = 18;
+pub const MADV_KEEPONFORK: i32 = 19;
+pub const MADV_COLD: i32 = 20;
+pub const MADV_PAGEOUT: i32 = 21;
+
 pub fn sys_madvise(addr: usize, len: usize, advice: i32) -> SyscallRet {
     log::info!(
         "sys_madvise: addr: {:#x}, len: {:#x}, advice: {:#x}",
@@ -680,13 +781,94 @@ pub fn sys_madvise(addr: usize, len: usize, advice: i32) -> SyscallRet {
         len,
         advice
     );
-    log::warn!("[sys_madvise] Unimplemented");
     // addr必须页对齐
     if addr % PAGE_SIZE != 0 {
         return Err(Errno::EINVAL);
     }
-    // Todo:
-    Ok(0)
+    let end = addr
+        .checked_add(len)
+        .filter(|end| *end <= USER_MAX_VA)
+        .map(ceil_to_page_size)
+        .ok_or(Errno::EINVAL)?;
+    if len == 0 {
+        return Ok(0);
+    }
+    let range = VPNRange::new(VirtAddr::from(addr).floor(), VirtAddr::from(end).floor());
+    current_task().op_memory_set_mut(|memory_set| {
+        let mapped = memory_set.is_range_mapped(range);
+        match advice {
+            MADV_NORMAL => {
+                memory_set.madvise_set_advice(
+                    range,
+                    MapAdvice::empty(),
+                    MapAdvice::SEQUENTIAL | MapAdvice::RANDOM,
+                );
+            }
+            MADV_RANDOM => {
+                memory_set.madvise_set_advice(range, MapAdvice::RANDOM, MapAdvice::SEQUENTIAL);
+            }
+            MADV_SEQUENTIAL => {
+                memory_set.madvise_set_advice(range, MapAdvice::SEQUENTIAL, MapAdvice::RANDOM);
+            }
+            MADV_HUGEPAGE => {
+                memory_set.madvise_set_advice(range, MapAdvice::HUGEPAGE, MapAdvice::NOHUGEPAGE);
+            }
+            MADV_NOHUGEPAGE => {
+                memory_set.madvise_set_advice(range, MapAdvice::NOHUGEPAGE, MapAdvice::HUGEPAGE);
+            }
+            MADV_MERGEABLE => {
+                memory_set.madvise_set_advice(range, MapAdvice::MERGEABLE, MapAdvice::empty());
+            }
+            MADV_UNMERGEABLE => {
+                let count = memory_set.ksm_unmerge(range);
+                log::info!("[sys_madvise] unmerged {} ksm pages", count);
+                memory_set.madvise_set_advice(range, MapAdvice::empty(), MapAdvice::MERGEABLE);
+            }
+            MADV_WILLNEED => {
+                memory_set.madvise_willneed(range)?;
+            }
+            MADV_DONTNEED => {
+                memory_set.madvise_dontneed(range)?;
+            }
+            MADV_FREE => {
+                memory_set.madvise_free(range)?;
+            }
+            MADV_REMOVE => {
+                memory_set.madvise_remove(range)?;
+            }
+            MADV_DONTFORK => {
+                memory_set.madvise_set_advice(range, MapAdvice::DONTFORK, MapAdvice::empty());
+            }
+            MADV_DOFORK => {
+                memory_set.madvise_set_advice(range, MapAdvice::empty(), MapAdvice::DONTFORK);
+            }
+            MADV_WIPEONFORK => {
+                if !memory_set.is_range_private_anonymous(range) {
+                    return Err(Errno::EINVAL);
+                }
+                memory_set.madvise_set_advice(range, MapAdvice::WIPEONFORK, MapAdvice::empty());
+            }
+            MADV_KEEPONFORK => {
+                memory_set.madvise_set_advice(range, MapAdvice::empty(), MapAdvice::WIPEONFORK);
+            }
+            MADV_DONTDUMP => {
+                memory_set.madvise_set_advice(range, MapAdvice::DONTDUMP, MapAdvice::empty());
+            }
+            MADV_DODUMP => {
+                memory_set.madvise_set_advice(range, MapAdvice::empty(), MapAdvice::DONTDUMP);
+            }
+            MADV_COLD | MADV_PAGEOUT => {
+                // 只是提示, 不影响语义
+                log::warn!("[sys_madvise] advice {} ignored", advice);
+            }
+            _ => return Err(Errno::EINVAL),
+        }
+        // 与Linux一致, 对已映射的部分生效, 但范围内有未映射的页时返回ENOMEM
+        if !mapped {
+            return Err(Errno::ENOMEM);
+        }
+        Ok(0)
+    })
 }
 
 /* shm start */
@@ -699,10 +881,16 @@ pub fn sys_shmget(key: usize, size: usize, shmflg: i32) -> SyscallRet {
         shmflg
     );
     let task = current_task();
-    let page_aligned_size = ceil_to_page_size(size);
+    let huge = shmflg.contains(ShmGetFlags::SHM_HUGETLB);
+    let page_aligned_size = if huge {
+        // 大页段的大小向上对齐到大页
+        size.div_ceil(HUGE_PAGE_SIZE) * HUGE_PAGE_SIZE
+    } else {
+        ceil_to_page_size(size)
+    };
     if key == IPC_PRIVATE {
         // IPC_PRIVATE是一个特殊的key值, 用于创建一个新的共享内存段
-        let shmid = add_shm_segment(page_aligned_size, task.tgid(), None);
+        let shmid = add_shm_segment(page_aligned_size, task.tgid(), None, huge);
         return Ok(shmid);
     }
     // 其他key值, 需要检查是否存在
@@ -710,8 +898,7 @@ pub fn sys_shmget(key: usize, size: usize, shmflg: i32) -> SyscallRet {
     if shmid == 0 {
         if shmflg.contains(ShmGetFlags::IPC_CREAT) {
             // 创建新的共享内存段, 同时指定了key(已检查key不存在)
-            ShmSegment::new(page_aligned_size, task.tgid());
-            let shmid = add_shm_segment(page_aligned_size, task.tgid(), Some(key));
+            let shmid = add_shm_segment(page_aligned_size, task.tgid(), Some(key), huge);
             debug_assert!(shmid == key);
             return Ok(shmid);
         } else {
@@ -789,7 +976,171 @@ pub fn sys_get_mempolicy(
     Ok(0)
 }
 
-pub fn sys_mlock(_addr: usize, _len: usize) -> SyscallRet {
-    log::error!("Unimplemented sys_mlock");
+/* mlock start */
+/// mlockall: 锁定当前已有的映射
+const MCL_CURRENT: i32 = 1;
+/// mlockall: 锁定之后新建的映射
+const MCL_FUTURE: i32 = 2;
+/// mlockall: 锁定的页在第一次访问时才分配
+const MCL_ONFAULT: i32 = 4;
+/// mlock2: 锁定的页在第一次访问时才分配
+const MLOCK_ONFAULT: u32 = 1;
+
+/// 检查再锁定`nr_pages`页是否超过RLIMIT_MEMLOCK, 特权进程不受限制
+fn check_memlock_limit(task: &Task, locked_pages: usize, nr_pages: usize) -> Result<(), Errno> {
+    if task.capable(CapSet::IPC_LOCK) {
+        return Ok(());
+    }
+    let limit = task.get_rlimit(Resource::MEMLOCK).unwrap().rlim_cur;
+    if limit == 0 {
+        return Err(Errno::EPERM);
+    }
+    if limit != RLIM_INFINITY && (locked_pages + nr_pages) * PAGE_SIZE > limit {
+        log::warn!(
+            "[check_memlock_limit] locked {} pages + {} pages exceed RLIMIT_MEMLOCK {:#x}",
+            locked_pages,
+            nr_pages,
+            limit
+        );
+        return Err(Errno::ENOMEM);
+    }
+    Ok(())
+}
+
+/// 计算mlock/munlock的页范围, addr向下对齐到页
+fn mlock_range(addr: usize, len: usize) -> Result<VPNRange, Errno> {
+    let start = floor_to_page_size(addr);
+    let end = addr
+        .checked_add(len)
+        .filter(|end| *end <= USER_MAX_VA)
+        .map(ceil_to_page_size)
+        .ok_or(Errno::EINVAL)?;
+    Ok(VPNRange::new(
+        VirtAddr::from(start).floor(),
+        VirtAddr::from(end).floor(),
+    ))
+}
+
+pub fn sys_mlock(addr: usize, len: usize) -> SyscallRet {
+    sys_mlock2(addr, len, 0)
+}
+
+pub fn sys_mlock2(addr: usize, len: usize, flags: u32) -> SyscallRet {
+    log::info!(
+        "sys_mlock2: addr: {:#x}, len: {:#x}, flags: {:#x}",
+        addr,
+        len,
+        flags
+    );
+    if flags & !MLOCK_ONFAULT != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let range = mlock_range(addr, len)?;
+    if len == 0 {
+        return Ok(0);
+    }
+    let task = current_task();
+    task.op_memory_set_mut(|memory_set| {
+        if !memory_set.is_range_mapped(range) {
+            return Err(Errno::ENOMEM);
+        }
+        check_memlock_limit(
+            &task,
+            memory_set.locked_pages(),
+            memory_set.unlocked_pages_in(range),
+        )?;
+        memory_set.mlock_range(range, true);
+        if flags & MLOCK_ONFAULT == 0 {
+            memory_set.populate_locked(range);
+        }
+        Ok(0)
+    })
+}
+
+pub fn sys_munlock(addr: usize, len: usize) -> SyscallRet {
+    log::info!("sys_munlock: addr: {:#x}, len: {:#x}", addr, len);
+    let range = mlock_range(addr, len)?;
+    if len == 0 {
+        return Ok(0);
+    }
+    current_task().op_memory_set_mut(|memory_set| {
+        if !memory_set.is_range_mapped(range) {
+            return Err(Errno::ENOMEM);
+        }
+        memory_set.mlock_range(range, false);
+        Ok(0)
+    })
+}
+
+pub fn sys_mlockall(flags: i32) -> SyscallRet {
+    log::info!("sys_mlockall: flags: {:#x}", flags);
+    if flags == 0 || flags & !(MCL_CURRENT | MCL_FUTURE | MCL_ONFAULT) != 0 || flags == MCL_ONFAULT
+    {
+        return Err(Errno::EINVAL);
+    }
+    let task = current_task();
+    task.op_memory_set_mut(|memory_set| {
+        if flags & MCL_CURRENT != 0 {
+            check_memlock_limit(&task, 0, memory_set.total_pages())?;
+            memory_set.mlock_all(true);
+            if flags & MCL_ONFAULT == 0 {
+                let all = VPNRange::new(VirtPageNum(0), VirtAddr::from(USER_MAX_VA).ceil());
+                memory_set.populate_locked(all);
+            }
+        }
+        memory_set.mlock_future = flags & MCL_FUTURE != 0;
+        memory_set.mlock_onfault = flags & MCL_FUTURE != 0 && flags & MCL_ONFAULT != 0;
+        Ok(0)
+    })
+}
+
+pub fn sys_munlockall() -> SyscallRet {
+    log::info!("sys_munlockall");
+    current_task().op_memory_set_mut(|memory_set| {
+        memory_set.mlock_all(false);
+        memory_set.mlock_future = false;
+        memory_set.mlock_onfault = false;
+    });
     Ok(0)
 }
+/* mlock end */
+
+/* userfaultfd start */
+/// 创建userfaultfd, 之后需要先UFFDIO_API再UFFDIO_REGISTER注册区域
+pub fn sys_userfaultfd(flags: i32) -> SyscallRet {
+    log::info!("[sys_userfaultfd] flags: {:#x}", flags);
+    let valid = OpenFlags::O_CLOEXEC.bits() | OpenFlags::O_NONBLOCK.bits() | UFFD_USER_MODE_ONLY;
+    if flags & !valid != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let flags = OpenFlags::from_bits_truncate(flags & !UFFD_USER_MODE_ONLY) | OpenFlags::O_RDWR;
+    let uffd = UserfaultFd::new(flags);
+    current_task()
+        .fd_table()
+        .alloc_fd(uffd, FdFlags::from(&flags))
+}
+/* userfaultfd end */
+
+/* memfd start */
+/// 创建匿名内存文件, 初始大小为0, 需要ftruncate或write扩大
+pub fn sys_memfd_create(name: *const u8, flags: u32) -> SyscallRet {
+    let name = c_str_to_string(name)?;
+    log::info!("[sys_memfd_create] name: {}, flags: {:#x}", name, flags);
+    if flags & !(MFD_CLOEXEC | MFD_ALLOW_SEALING | MFD_HUGETLB) != 0
+        || name.len() > MFD_NAME_MAX_LEN
+    {
+        return Err(Errno::EINVAL);
+    }
+    if flags & MFD_HUGETLB != 0 {
+        log::warn!("[sys_memfd_create] MFD_HUGETLB not supported");
+        return Err(Errno::EINVAL);
+    }
+    let memfd = MemFd::new(&name, flags);
+    let fd_flags = if flags & MFD_CLOEXEC != 0 {
+        FdFlags::FD_CLOEXEC
+    } else {
+        FdFlags::empty()
+    };
+    current_task().fd_table().alloc_fd(memfd, fd_flags)
+}
+/* memfd end */
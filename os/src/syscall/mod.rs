@@ -21,8 +21,9 @@ use fs::{
     sys_sync, sys_umask, sys_umount2, sys_unlinkat, sys_utimensat, sys_write, sys_writev,
 };
 use mm::{
-    sys_brk, sys_get_mempolicy, sys_madvise, sys_membarrier, sys_mlock, sys_mmap, sys_mprotect,
-    sys_munmap, sys_shmat, sys_shmctl, sys_shmdt, sys_shmget,
+    sys_brk, sys_get_mempolicy, sys_madvise, sys_membarrier, sys_memfd_create, sys_mlock,
+    sys_mlock2, sys_mlockall, sys_mmap, sys_mprotect, sys_munlock, sys_munlockall, sys_munmap,
+    sys_shmat, sys_shmctl, sys_shmdt, sys_shmget, sys_userfaultfd,
 };
 use net::{
     syscall_accept, syscall_accept4, syscall_bind, syscall_connect, syscall_getpeername,
@@ -35,15 +36,17 @@ use sched::{
     sys_sched_setscheduler,
 };
 use signal::{
-    sys_kill, sys_rt_sigaction, sys_rt_sigpending, sys_rt_sigprocmask, sys_rt_sigreturn,
-    sys_rt_sigsuspend, sys_rt_sigtimedwait, sys_tgkill, sys_tkill,
+    sys_kill, sys_pidfd_send_signal, sys_rt_sigaction, sys_rt_sigpending, sys_rt_sigprocmask,
+    sys_rt_sigreturn, sys_rt_sigsuspend, sys_rt_sigtimedwait, sys_tgkill, sys_tkill,
 };
 use task::{
-    sys_acct, sys_clock_nanosleep, sys_clone, sys_execve, sys_exit_group, sys_futex, sys_get_time,
-    sys_getegid, sys_geteuid, sys_getgid, sys_getgroups, sys_getpgid, sys_getpid, sys_getppid,
-    sys_getresgid, sys_getresuid, sys_gettid, sys_getuid, sys_nanosleep, sys_set_tid_address,
-    sys_setfsgid, sys_setfsuid, sys_setgid, sys_setgroups, sys_setpgid, sys_setregid,
-    sys_setresgid, sys_setresuid, sys_setreuid, sys_setsid, sys_setuid, sys_waitpid, sys_yield,
+    sys_acct, sys_capget, sys_capset, sys_clock_nanosleep, sys_clone, sys_clone3, sys_execve,
+    sys_exit_group, sys_futex, sys_get_time, sys_getegid, sys_geteuid, sys_getgid, sys_getgroups,
+    sys_getpgid, sys_getpid, sys_getppid, sys_getresgid, sys_getresuid, sys_gettid, sys_getuid,
+    sys_nanosleep, sys_pidfd_getfd, sys_pidfd_open, sys_prctl, sys_seccomp, sys_set_tid_address,
+    sys_setfsgid, sys_setfsuid, sys_setgid, sys_setgroups, sys_setns, sys_setpgid, sys_setregid,
+    sys_setresgid, sys_setresuid, sys_setreuid, sys_setsid, sys_setuid, sys_unshare, sys_waitid,
+    sys_waitpid, sys_yield, CapUserData, CapUserHeader,
 };
 use util::{
     sys_adjtimex, sys_clock_adjtime, sys_clock_getres, sys_clock_gettime, sys_clock_settime,
@@ -58,7 +61,7 @@ use crate::{
     futex::robust_list::{sys_get_robust_list, sys_set_robust_list},
     mm::shm::ShmId,
     signal::{SigInfo, SigSet},
-    task::rusage::RUsage,
+    task::{rusage::RUsage, seccomp::secure_computing},
     time::KernelTimex,
     timer::{ITimerVal, TimeSpec},
 };
@@ -72,7 +75,7 @@ mod net;
 mod sched;
 mod signal;
 mod task;
-mod util;
+pub mod util;
 // mod time;
 
 const SYSCALL_FGETXATTR: usize = 10;
@@ -120,9 +123,13 @@ const SYSCALL_SYNC: usize = 81;
 const SYSCALL_FSYNC: usize = 82;
 const SYSCALL_UTIMENSAT: usize = 88;
 const SYSCALL_ACCT: usize = 89;
+const SYSCALL_CAPGET: usize = 90;
+const SYSCALL_CAPSET: usize = 91;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_WAITID: usize = 95;
 const SYSCALL_SET_TID_ADDRESS: usize = 96;
+const SYSCALL_UNSHARE: usize = 97;
 const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SET_ROBUST_LIST: usize = 99;
 const SYSCALL_GET_ROBUST_LIST: usize = 100;
@@ -170,6 +177,7 @@ const SYSCALL_SETHOSTNAME: usize = 161;
 const SYSCALL_SETDOMAINNAME: usize = 162;
 const SYSCALL_GETRUSAGE: usize = 165;
 const SYSCALL_UMASK: usize = 166;
+const SYSCALL_PRCTL: usize = 167;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GITPID: usize = 172;
 const SYSCALL_GETPPID: usize = 173;
@@ -207,14 +215,22 @@ const SYSCALL_FADVISE64: usize = 223;
 const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_MSYNC: usize = 227;
 const SYSCALL_MLOCK: usize = 228;
+const SYSCALL_MUNLOCK: usize = 229;
+const SYSCALL_MLOCKALL: usize = 230;
+const SYSCALL_MUNLOCKALL: usize = 231;
 const SYSCALL_MADVISE: usize = 233;
 const SYSCALL_GET_MEMPOLICY: usize = 236;
 const SYSCALL_ACCEPT4: usize = 242;
 const SYSCALL_WAIT4: usize = 260;
 const SYSCALL_PRLIMIT: usize = 261;
+const SYSCALL_SETNS: usize = 268;
 const SYSCALL_RENAMEAT2: usize = 276;
+const SYSCALL_SECCOMP: usize = 277;
 const SYSCALL_GETRANDOM: usize = 278;
+const SYSCALL_MEMFD_CREATE: usize = 279;
+const SYSCALL_USERFAULTFD: usize = 282;
 const SYSCALL_MEMBARRIER: usize = 283;
+const SYSCALL_MLOCK2: usize = 284;
 const SYSCALL_COPY_FILE_RANGE: usize = 285;
 const SYSCALL_STATX: usize = 291;
 const SYSCALL_STRERROR: usize = 300;
@@ -223,8 +239,12 @@ const SYSCALL_PSELECT: usize = 72;
 const SYSCALL_SETSID: usize = 157;
 const SYSCALL_ADJTIMEX: usize = 171;
 const SYSCALL_CLOCKADJTIME: usize = 266;
+const SYSCALL_PIDFD_SEND_SIGNAL: usize = 424;
+const SYSCALL_PIDFD_OPEN: usize = 434;
+const SYSCALL_CLONE3: usize = 435;
 const SYSCALL_CLOSE_RANGE: usize = 436;
 const SYSCALL_OPENAT2: usize = 437;
+const SYSCALL_PIDFD_GETFD: usize = 438;
 const SYSCALL_FACCESSAT2: usize = 439;
 const SYSCALL_SHUTDOMN: usize = 666;
 
@@ -247,6 +267,10 @@ pub fn syscall(
     if !CARELESS_SYSCALLS.contains(&syscall_id) {
         log::warn!("syscall_id: {}", syscall_id);
     }
+    // seccomp过滤在分发前进行
+    if let Some(ret) = secure_computing(syscall_id, [a0, a1, a2, a3, a4, a5]) {
+        return ret;
+    }
     // if syscall_id == SYSCALL_WAIT4 {
     // log::warn!("syscall_id: {}", syscall_id);
     // }
@@ -313,6 +337,8 @@ pub fn syscall(
             sys_utimensat(a0 as i32, a1 as *const u8, a2 as *const TimeSpec, a3 as i32)
         }
         SYSCALL_ACCT => sys_acct(a0 as *const u8),
+        SYSCALL_CAPGET => sys_capget(a0 as *mut CapUserHeader, a1 as *mut CapUserData),
+        SYSCALL_CAPSET => sys_capset(a0 as *mut CapUserHeader, a1 as *const CapUserData),
         SYSCALL_EXIT => sys_exit(a0 as i32),
         SYSCALL_EXIT_GROUP => sys_exit_group(a0 as i32),
         SYSCALL_SET_TID_ADDRESS => sys_set_tid_address(a0),
@@ -361,6 +387,10 @@ pub fn syscall(
         SYSCALL_UNAME => sys_uname(a0),
         SYSCALL_GETRUSAGE => sys_getrusage(a0 as i32, a1 as *mut RUsage),
         SYSCALL_UMASK => sys_umask(a0),
+        SYSCALL_PRCTL => sys_prctl(a0 as i32, a1, a2, a3, a4),
+        SYSCALL_SECCOMP => sys_seccomp(a0 as u32, a1 as u32, a2),
+        SYSCALL_UNSHARE => sys_unshare(a0),
+        SYSCALL_SETNS => sys_setns(a0, a1 as i32),
         SYSCALL_GET_TIME => sys_get_time(a0),
         SYSCALL_GITPID => sys_getpid(),
         SYSCALL_GETPPID => sys_getppid(),
@@ -379,13 +409,22 @@ pub fn syscall(
         SYSCALL_MADVISE => sys_madvise(a0, a1, a2 as i32),
         SYSCALL_MPROTECT => sys_mprotect(a0, a1, a2 as i32),
         SYSCALL_MLOCK => sys_mlock(a0, a1),
+        SYSCALL_MUNLOCK => sys_munlock(a0, a1),
+        SYSCALL_MLOCKALL => sys_mlockall(a0 as i32),
+        SYSCALL_MUNLOCKALL => sys_munlockall(),
+        SYSCALL_MLOCK2 => sys_mlock2(a0, a1, a2 as u32),
         SYSCALL_GET_MEMPOLICY => sys_get_mempolicy(a0, a1, a2, a3, a4),
         SYSCALL_MSYNC => sys_msync(a0, a1, a2 as i32),
         SYSCALL_FORK => sys_clone(a0 as u32, a1, a2, a3, a4),
+        SYSCALL_CLONE3 => sys_clone3(a0, a1),
         SYSCALL_EXEC => sys_execve(a0 as *mut u8, a1 as *const usize, a2 as *const usize),
         SYSCALL_MMAP => sys_mmap(a0, a1, a2, a3, a4 as i32, a5),
         SYSCALL_FADVISE64 => sys_fadvise64(a0, a1, a2 as usize, a3 as i32),
         SYSCALL_WAIT4 => sys_waitpid(a0 as isize, a1, a2 as i32),
+        SYSCALL_WAITID => sys_waitid(a0 as i32, a1 as isize, a2, a3 as i32, a4),
+        SYSCALL_PIDFD_OPEN => sys_pidfd_open(a0 as isize, a1 as i32),
+        SYSCALL_PIDFD_GETFD => sys_pidfd_getfd(a0 as i32, a1 as i32, a2 as u32),
+        SYSCALL_PIDFD_SEND_SIGNAL => sys_pidfd_send_signal(a0 as i32, a1 as i32, a2, a3 as u32),
         SYSCALL_SOCKET => syscall_socket(a0, a1, a2),
         SYSCALL_BIND => syscall_bind(a0, a1, a2),
         SYSCALL_LISTEN => syscall_listen(a0, a1),
@@ -406,6 +445,8 @@ pub fn syscall(
             a4 as i32,
         ),
         SYSCALL_GETRANDOM => Ok(0),
+        SYSCALL_MEMFD_CREATE => sys_memfd_create(a0 as *const u8, a1 as u32),
+        SYSCALL_USERFAULTFD => sys_userfaultfd(a0 as i32),
         SYSCALL_MEMBARRIER => sys_membarrier(a0 as i32, a1 as i32, a2 as u32),
         SYSCALL_COPY_FILE_RANGE => sys_copy_file_range(a0, a1, a2, a3, a4, a5 as i32),
         SYSCALL_STATX => sys_statx(
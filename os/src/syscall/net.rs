@@ -21,6 +21,7 @@ use crate::{
     net::{
         addr::{from_ipendpoint_to_socketaddr, LOOP_BACK_IP},
         alg::encode,
+        packet::{sockaddr_ll_from, sockaddr_ll_to, PacketOption},
         socket::{
             check_alg, socket_address_from, socket_address_from_af_alg, socket_address_from_unix,
             socket_address_to, socket_address_tounix, ALG_Option, Domain, IpOption, Ipv6Option,
@@ -30,7 +31,7 @@ use crate::{
         socketpair::create_buffer_ends,
     },
     syscall::task::{sys_getresgid, sys_nanosleep},
-    task::{current_task, yield_current_task},
+    task::{capability::CapSet, current_task, yield_current_task},
 };
 use alloc::vec;
 use alloc::{sync::Arc, task, vec::Vec};
@@ -93,7 +94,23 @@ pub fn syscall_socket(domain: usize, sockettype: usize, protocol: usize) -> Sysc
             return Err(Errno::EINVAL);
         }
     };
-    let socket = Arc::new(Socket::new(domain, s_type));
+    //packet socket需要特权,且只支持SOCK_RAW/SOCK_DGRAM
+    if domain == Domain::AF_PACKET {
+        if !current_task().capable(CapSet::NET_RAW) {
+            return Err(Errno::EPERM);
+        }
+        if s_type != SocketType::SOCK_RAW && s_type != SocketType::SOCK_DGRAM {
+            return Err(Errno::ESOCKTNOSUPPORT);
+        }
+    }
+    //raw socket需要特权
+    if s_type == SocketType::SOCK_RAW
+        && (domain == Domain::AF_INET || domain == Domain::AF_INET6)
+        && !current_task().capable(CapSet::NET_RAW)
+    {
+        return Err(Errno::EPERM);
+    }
+    let socket = Arc::new(Socket::new(domain, s_type, protocol));
     //SOCK_NONBLOCK=0X800,按照flag设计
     socket.set_nonblocking((sockettype & SOCK_NONBLOCK) != 0);
     let task = current_task();
@@ -142,7 +159,7 @@ pub fn syscall_bind(socketfd: usize, socketaddr: usize, socketlen: usize) -> Sys
         task.egid(),
         task.euid()
     );
-    if task.egid() != 0 || task.euid() != 0 {
+    if !task.capable(CapSet::NET_BIND_SERVICE) {
         return Err(Errno::EACCES);
     }
     //如果是al_afg的套接字则需要使用对应的socket_from
@@ -154,6 +171,11 @@ pub fn syscall_bind(socketfd: usize, socketaddr: usize, socketlen: usize) -> Sys
         socket.bind_af_alg(bind_addr)?;
         return Ok(0);
     }
+    if let Some(packet_socket) = socket.as_packet() {
+        let sll = sockaddr_ll_from(socketaddr as *const u8, socketlen)?;
+        packet_socket.bind(&sll)?;
+        return Ok(0);
+    }
     if socket.domain == Domain::AF_UNIX {
         if socket.get_is_af_unix() {
             return Err(Errno::EINVAL);
@@ -191,7 +213,7 @@ pub fn syscall_bind(socketfd: usize, socketaddr: usize, socketlen: usize) -> Sys
     {
         return Err(Errno::EADDRNOTAVAIL);
     }
-    socket.bind(bind_addr);
+    socket.bind(bind_addr)?;
     Ok(0)
 }
 
@@ -521,6 +543,13 @@ pub fn syscall_send(
             return Err(Errno::EBADF);
         }
     }
+    if let Some(packet_socket) = socket.as_packet() {
+        if socketaddr == 0 {
+            return packet_socket.send(kernel_buf.as_slice());
+        }
+        let sll = sockaddr_ll_from(socketaddr as *const u8, socketlen)?;
+        return packet_socket.send_to(kernel_buf.as_slice(), &sll);
+    }
     let boundaddr = socket.name();
     log::error!("[syscall_send] sockt addr is {:?}", boundaddr);
     let addr;
@@ -583,8 +612,8 @@ pub fn syscall_recvfrom(
         socketlen
     );
     //check addr len is valid
+    let mut kernellen: Vec<i32> = vec![0; 1];
     if socketlen > 0 {
-        let mut kernellen: Vec<i32> = vec![0; 1];
         if socketlen == 0xffffffffffffffff {
             return Err(Errno::EFAULT);
         }
@@ -623,18 +652,40 @@ pub fn syscall_recvfrom(
     if flags.contains(MsgFlags::MSG_ERRQUEUE) {
         return Err(Errno::EAGAIN);
     }
+    if let Some(packet_socket) = socket.as_packet() {
+        let mut kernel_buf = vec![0u8; len];
+        let (size, frame_len, sll) = packet_socket.recv_from(&mut kernel_buf)?;
+        copy_to_user(buf, kernel_buf.as_ptr(), size)?;
+        sockaddr_ll_to(&sll, socketaddr, socketlen)?;
+        //MSG_TRUNC时返回帧的真实长度
+        if flags.contains(MsgFlags::MSG_TRUNC) {
+            return Ok(frame_len);
+        }
+        return Ok(size);
+    }
     let addr = socket.name()?;
     log::error!("[syscall_recvfrom] sockt addr is {:?}", addr);
     // let addr=unsafe { socket_address_from(socketaddr as *const u8, socket) };
     // let buf=unsafe { core::slice::from_raw_parts_mut(buf, len) };
     let mut kernel_buf = vec![0u8; len];
-    match socket.recv_from(&mut kernel_buf) {
-        Ok((size, _addr)) => {
+    match socket.recv_from_full(&mut kernel_buf) {
+        Ok((size, datagram_len, src_addr)) => {
             // if size == 0 {
             //     return Err(Errno::EINTR);
             // }
             copy_to_user(buf, kernel_buf.as_ptr(), len)?;
+            //raw socket与ping socket需要告知报文来源,ipv6套接字同样写回sockaddr_in6
+            if (socket.is_raw() || socket.domain == Domain::AF_INET6)
+                && socketaddr != 0
+                && socketlen != 0
+            {
+                socket_address_to(socket.user_addr(src_addr), socketaddr, socketlen)?;
+            }
             log::error!("[syscall_recvfrom]:recv buf len {}", size);
+            //MSG_TRUNC时返回数据报的真实长度
+            if flags.contains(MsgFlags::MSG_TRUNC) {
+                return Ok(datagram_len);
+            }
             return Ok(size);
         }
         Err(e) => Err(e),
@@ -722,29 +773,34 @@ pub fn syscall_setsocketopt(
     match level {
         //TODO setopt error
         SocketOptionLevel::IP => {
-            let option = IpOption::try_from(optname).unwrap();
+            let option = IpOption::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
             option.set(socket, kernel_opt.as_slice())
             // return Ok(0);
         }
         SocketOptionLevel::Socket => {
-            let option = SocketOption::try_from(optname).unwrap();
+            let option = SocketOption::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
             option.set(socket, kernel_opt.as_slice())
             // return Ok(0);
         }
         SocketOptionLevel::Tcp => {
-            let option = TcpSocketOption::try_from(optname).unwrap();
+            let option = TcpSocketOption::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
             option.set(socket, kernel_opt.as_slice())
             // return Ok(0);
         }
         SocketOptionLevel::IPv6 => {
-            let option = Ipv6Option::try_from(optname).unwrap();
+            let option = Ipv6Option::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
             option.set(socket, kernel_opt.as_slice())
             // return Ok(0);
         }
         SocketOptionLevel::SOL_ALG => {
-            let option = ALG_Option::try_from(optname).unwrap();
+            let option = ALG_Option::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
             option.set(socket, kernel_opt.as_slice())
         }
+        SocketOptionLevel::SOL_PACKET => {
+            let packet_socket = socket.as_packet().ok_or(Errno::ENOPROTOOPT)?;
+            let option = PacketOption::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
+            option.set(packet_socket, kernel_opt.as_slice())
+        }
     }
 }
 
@@ -801,31 +857,33 @@ pub fn syscall_getsocketopt(
             if optname == 18446744073709551615 {
                 return Err(Errno::ENOPROTOOPT);
             }
-            return Ok(0);
+            let option = IpOption::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
+            if kernel_opt_len < 4 {
+                return Err(Errno::EINVAL);
+            }
+            return option.get(socket, optval, optlen as *mut u32);
         }
         SocketOptionLevel::Socket => {
-            let option = SocketOption::try_from(optname).unwrap();
-            #[cfg(target_arch = "riscv64")]
-            option.get(socket, optval, optlen as *mut u32);
-            #[cfg(target_arch = "loongarch64")]
-            option.get(socket, optval, kernel_opt_len as *mut u32);
-            return Ok(0);
+            let option = SocketOption::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
+            option.get(socket, optval, optlen as *mut u32)
         }
         SocketOptionLevel::Tcp => {
             let option = TcpSocketOption::try_from(optname).map_err(|_err| Errno::ENOPROTOOPT)?;
-            #[cfg(target_arch = "riscv64")]
-            option.get(socket, optval, optlen as *mut u32);
-            #[cfg(target_arch = "loongarch64")]
-            option.get(socket, optval, kernel_opt_len as *mut u32);
-            return Ok(0);
+            option.get(socket, optval, optlen as *mut u32)
         }
         SocketOptionLevel::IPv6 => {
-            // let option=Ipv6Option::try_from(optname).unwrap();
-            // option.set(socket, opt);
-            return Ok(0);
+            let option = Ipv6Option::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
+            if kernel_opt_len < 4 {
+                return Err(Errno::EINVAL);
+            }
+            return option.get(socket, optval, optlen as *mut u32);
         }
-        SocketOptionLevel::SOL_ALG => {
-            unimplemented!()
+        //AF_ALG的选项都只能设置
+        SocketOptionLevel::SOL_ALG => Err(Errno::ENOPROTOOPT),
+        SocketOptionLevel::SOL_PACKET => {
+            let packet_socket = socket.as_packet().ok_or(Errno::ENOPROTOOPT)?;
+            let option = PacketOption::try_from(optname).map_err(|_| Errno::ENOPROTOOPT)?;
+            option.get(packet_socket, optval, optlen as *mut u32)
         }
     }
 }
@@ -838,13 +896,6 @@ pub fn syscall_getsockname(socketfd: usize, socketaddr: usize, socketlen: usize)
     if socketaddr == 0xffffffffffffffff || socketlen <= 1 {
         return Err(Errno::EFAULT);
     }
-    let mut len = vec![0; 1];
-    copy_from_user(socketlen as *const u8, len.as_mut_ptr(), len.len())?;
-    log::error!("[syscall_getpeername]len is {:?}", len);
-    if len[0] == 255 {
-        return Err(Errno::EINVAL);
-    }
-
     let task = current_task();
     let file = match task.fd_table().get_file(socketfd) {
         Some(f) => f,
@@ -863,8 +914,12 @@ pub fn syscall_getsockname(socketfd: usize, socketaddr: usize, socketlen: usize)
         socket_address_tounix(path.as_slice(), socketaddr, socketlen)?;
         return Ok(0);
     }
+    if let Some(packet_socket) = socket.as_packet() {
+        sockaddr_ll_to(&packet_socket.local_addr(), socketaddr, socketlen)?;
+        return Ok(0);
+    }
     //TODO sock name error
-    let addr = socket.name().unwrap();
+    let addr = socket.user_addr(socket.name().unwrap());
     log::error!("[syscall_getsockname]:addr{:?}", addr);
     socket_address_to(addr, socketaddr, socketlen)?;
     Ok(0)
@@ -879,13 +934,6 @@ pub fn syscall_getpeername(socketfd: usize, socketaddr: usize, socketlen: usize)
     if socketaddr == 0xffffffffffffffff || socketlen <= 1 {
         return Err(Errno::EFAULT);
     }
-    let mut len = vec![0; 1];
-    copy_from_user(socketlen as *const u8, len.as_mut_ptr(), len.len())?;
-    log::error!("[syscall_getpeername]len is {:?}", len);
-    if len[0] == 255 {
-        return Err(Errno::EINVAL);
-    }
-
     let task = current_task();
     let file = match task.fd_table().get_file(socketfd) {
         Some(f) => f,
@@ -897,9 +945,10 @@ pub fn syscall_getpeername(socketfd: usize, socketaddr: usize, socketlen: usize)
         None => return Err(Errno::ENOTSOCK),
     };
     //TODO peer name error
-    let addr = socket.peer_name()?;
+    let addr = socket.user_addr(socket.peer_name()?);
     log::error!("[syscall_getpeername]:addr{:?}", addr);
-    socket_address_to(addr, socketaddr, socketlen)
+    socket_address_to(addr, socketaddr, socketlen)?;
+    Ok(0)
 }
 
 //socketpair中fd在socketfds中用数组给出
@@ -997,8 +1046,8 @@ pub fn make_socketpair(
     pipe_flag: OpenFlags,
 ) -> (Arc<Socket>, Arc<Socket>) {
     let s_type = SocketType::try_from(sockettype & SOCKET_TYPE_MASK).unwrap();
-    let mut fd1 = Socket::new(domain.clone(), s_type);
-    let mut fd2 = Socket::new(domain.clone(), s_type);
+    let mut fd1 = Socket::new(domain.clone(), s_type, 0);
+    let mut fd2 = Socket::new(domain.clone(), s_type, 0);
     let (pipe1, pipe2) = create_buffer_ends(pipe_flag);
     fd1.buffer = Some(pipe1);
     fd2.buffer = Some(pipe2);
@@ -1299,7 +1348,7 @@ pub fn syscall_recvmsg(socketfd: usize, msg_ptr: usize, flag: usize) -> SyscallR
     let mut kernel_buf: Vec<u8> = vec![0; total_len];
 
     // 6. Receive data into kernel buffer
-    let (n, _addr) = match socket.recv_from(&mut kernel_buf[..]) {
+    let (n, datagram_len, _addr) = match socket.recv_from_full(&mut kernel_buf[..]) {
         Ok(sz) => sz,
         Err(e) => {
             log::error!("[syscall_recvmsg]: recv error {:?}", e);
@@ -1307,6 +1356,17 @@ pub fn syscall_recvmsg(socketfd: usize, msg_ptr: usize, flag: usize) -> SyscallR
         }
     };
     log::debug!("[syscall_recvmsg]: received {} bytes into kernel_buf", n);
+    //数据报被截断时在msg_flags中报告MSG_TRUNC
+    let msg_flags: i32 = if datagram_len > n {
+        MsgFlags::MSG_TRUNC.bits() as i32
+    } else {
+        0
+    };
+    copy_to_user(
+        unsafe { core::ptr::addr_of_mut!((*(msg_ptr as *mut MessageHeaderRaw)).flags) },
+        &msg_flags as *const i32,
+        1,
+    )?;
 
     if n == 0 {
         return Ok(0);
@@ -1347,7 +1407,10 @@ pub fn syscall_recvmsg(socketfd: usize, msg_ptr: usize, flag: usize) -> SyscallR
         buf_offset += to_copy;
         remaining -= to_copy;
     }
-    // 8. 返回接收的字节数
+    // 8. 返回接收的字节数, MSG_TRUNC时返回数据报的真实长度
+    if flags.contains(MsgFlags::MSG_TRUNC) {
+        return Ok(datagram_len);
+    }
     Ok(copied)
 }
 
@@ -1369,13 +1432,16 @@ pub fn syscall_setdomainname(domainname: *const u8, len: usize) -> SyscallRet {
         task.egid(),
         task.euid()
     );
-    if task.egid() != 0 || task.euid() != 0 {
+    if !task.capable(CapSet::SYS_ADMIN) {
         return Err(Errno::EPERM);
     }
     let mut kernel_domainname: Vec<u8> = vec![0; len];
     copy_from_user(domainname, kernel_domainname.as_mut_ptr(), len)?;
-    let file = path_openat("/etc/domainname", OpenFlags::O_CLOEXEC, -100, 0)?;
-    file.pwrite(kernel_domainname.as_slice(), 0)?;
+    // 只修改当前任务所在uts命名空间的域名
+    task.uts_ns()
+        .name
+        .lock()
+        .set_domainname(kernel_domainname.as_slice());
     Ok(0)
 }
 pub fn syscall_sethostname(hostname: *const u8, len: usize) -> SyscallRet {
@@ -1391,13 +1457,16 @@ pub fn syscall_sethostname(hostname: *const u8, len: usize) -> SyscallRet {
         return Err(Errno::EFAULT);
     }
     let task = current_task();
-    if task.egid() != 0 || task.euid() != 0 {
+    if !task.capable(CapSet::SYS_ADMIN) {
         return Err(Errno::EPERM);
     }
     let mut kernel_hostname: Vec<u8> = vec![0; len];
     copy_from_user(hostname, kernel_hostname.as_mut_ptr(), len)?;
     log::error!("[syscall_sethostname] hostname is {:?}", kernel_hostname);
-    let file = path_openat("/etc/hostname", OpenFlags::O_CLOEXEC, -100, 0)?;
-    file.pwrite(kernel_hostname.as_slice(), 0)?;
+    // 只修改当前任务所在uts命名空间的主机名
+    task.uts_ns()
+        .name
+        .lock()
+        .set_nodename(kernel_hostname.as_slice());
     Ok(0)
 }
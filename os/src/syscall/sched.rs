@@ -1,4 +1,4 @@
-use crate::{arch::mm::copy_to_user, task::{current_task, get_task, CpuMask, Tid}};
+use crate::{arch::mm::copy_to_user, task::{current_task, namespace::find_task_by_vpid, CpuMask}};
 
 use super::errno::{Errno, SyscallRet};
 
@@ -46,7 +46,7 @@ pub fn sys_sched_getaffinity(pid: isize, cpusetsize: usize, mask: usize) -> Sysc
         copy_to_user(mask as *mut CpuMask, &cpu_mask, 1)?;
         return Ok(cpu_mask.bits());
     }  else {
-        if let Some(task)  = get_task(pid as Tid) {
+        if let Some(task) = find_task_by_vpid(pid as usize) {
             let cpu_mask = task.cpu_mask();
             copy_to_user(mask as *mut CpuMask, &cpu_mask, 1)?;
         } else {
@@ -14,15 +14,16 @@ use crate::{
             TrapContext,
         },
     },
+    fs::pidfd::pidfd_task,
     signal::{
-        handle_signal, FrameFlags, SiField, Sig, SigAction, SigContext, SigFrame, SigInfo,
-        SigRTFrame, SigSet, UContext,
+        handle_signal, FrameFlags, LinuxSigInfo, SiField, Sig, SigAction, SigContext, SigFrame,
+        SigInfo, SigRTFrame, SigSet, UContext,
     },
     syscall::errno::Errno,
     task::{
-        current_task, dump_scheduler, dump_wait_queue, for_each_task, get_group,
-        get_stack_top_by_sp, get_task, wait, wait_timeout, yield_current_task, Task, INITPROC,
-        INIT_PROC_PID,
+        capability::CapSet, current_task, dump_scheduler, dump_wait_queue, for_each_task,
+        get_group, get_stack_top_by_sp, namespace::find_task_by_vpid, wait, wait_timeout,
+        yield_current_task, Task, INITPROC, INIT_PROC_PID,
     },
     timer::TimeSpec,
 };
@@ -33,6 +34,32 @@ extern "C" {
     pub fn __return_to_user() -> !;
 }
 
+// 用于检查参数权限
+fn check_kill_permission(sig: Sig, task: &Arc<Task>) -> SyscallRet {
+    // 验证信号合法性
+    if sig.raw() < 0 || sig.raw() > 64 {
+        return Err(Errno::EINVAL);
+    }
+    // 检验调用者是否有权限向目标进程发送信号
+    let caller_task = current_task();
+    let caller_uid = caller_task.uid();
+    let caller_euid = caller_task.euid();
+    if !caller_task.same_thread_group(task)
+        && !(caller_task.capable(CapSet::KILL)
+            || caller_euid == task.suid()
+            || caller_euid == task.uid()
+            || caller_uid == task.suid()
+            || caller_uid == task.uid())
+    {
+        return Err(Errno::EPERM);
+    }
+    // 针对信号值为0的情况做特殊处理
+    if sig.raw() == 0 {
+        return Ok(usize::MAX);
+    }
+    return Ok(0);
+}
+
 //  kill() 系统调用可用于向任何进程组或进程发送任何信号。
 //  如果 pid 为正数，则将信号 sig 发送给具有 pid 指定 ID 的进程。
 //  如果 pid 等于 0，则将 sig 发送给调用进程的进程组中的每个进程。
@@ -43,38 +70,12 @@ extern "C" {
 //  ToDo: 并适当设置 errno
 //  EINVAL 指定了无效信号、EPERM 调用进程无权向任何目标进程发送、ESRCH 目标进程或进程组不存在
 pub fn sys_kill(pid: isize, sig: i32) -> SyscallRet {
-    // 用于检查参数权限
-    fn check_kill_permission(sig: Sig, task: &Arc<Task>) -> SyscallRet {
-        // 验证信号合法性
-        if sig.raw() < 0 || sig.raw() > 64 {
-            return Err(Errno::EINVAL);
-        }
-        // 检验调用者是否有权限向目标进程发送信号
-        let caller_task = current_task();
-        let caller_uid = caller_task.uid();
-        let caller_euid = caller_task.euid();
-        if !caller_task.same_thread_group(task)
-            && !(caller_euid == 0
-                || caller_euid == task.suid()
-                || caller_euid == task.uid()
-                || caller_uid == task.suid()
-                || caller_uid == task.uid())
-        {
-            return Err(Errno::EPERM);
-        }
-        // 针对信号值为0的情况做特殊处理
-        if sig.raw() == 0 {
-            return Ok(usize::MAX);
-        }
-        return Ok(0);
-    }
-
     let sig = Sig::from(sig);
     log::info!("[sys_kill] pid: {} signal: {}", pid, sig.raw());
     let siginfo = SigInfo::prepare_kill(current_task().tid(), sig);
     match pid {
         pid if pid > 0 => {
-            if let Some(task) = get_task(pid as usize) {
+            if let Some(task) = find_task_by_vpid(pid as usize) {
                 let ret = check_kill_permission(sig, &task)?;
                 if ret != usize::MAX {
                     // 向线程组发送信号
@@ -105,7 +106,10 @@ pub fn sys_kill(pid: isize, sig: i32) -> SyscallRet {
             }
         }
         -1 => {
+            // 只向调用者pid命名空间中可见的进程发送
+            let pid_ns = current_task().pid_ns();
             for_each_task(|task| match check_kill_permission(sig, &task) {
+                _ if pid_ns.pid_of(task.tid()).is_none() => {}
                 Ok(ret) => {
                     if (ret != usize::MAX) && (task.tid() != INIT_PROC_PID && task.is_process()) {
                         task.receive_siginfo(siginfo, false);
@@ -115,7 +119,8 @@ pub fn sys_kill(pid: isize, sig: i32) -> SyscallRet {
             });
         }
         _ => {
-            if let Some(group) = get_group(-pid as usize) {
+            let pgid = current_task().pid_ns().tid_of(-pid as usize);
+            if let Some(group) = pgid.and_then(get_group) {
                 for task in group.iter() {
                     let target_task = task.upgrade().unwrap();
                     let ret = check_kill_permission(sig, &target_task)?;
@@ -131,6 +136,66 @@ pub fn sys_kill(pid: isize, sig: i32) -> SyscallRet {
     Ok(0)
 }
 
+/// 向pidfd引用的进程发送信号, 避免了pid被回收后向错误的进程发送信号
+/// info非空时与rt_sigqueueinfo相同, 由调用者提供siginfo
+/// EINVAL flags非0、信号非法或pidfd引用的进程不在调用者的pid命名空间中
+/// EPERM 权限被拒绝, 或者向其他进程发送si_code >= 0的siginfo
+/// ESRCH 目标进程已经退出
+pub fn sys_pidfd_send_signal(pidfd: i32, sig: i32, info: usize, flags: u32) -> SyscallRet {
+    log::info!(
+        "[sys_pidfd_send_signal] pidfd: {}, signal: {}, info: {:#x}, flags: {:#x}",
+        pidfd,
+        sig,
+        info,
+        flags
+    );
+    let sig = Sig::from(sig);
+    if flags != 0 || sig.raw() < 0 || sig.raw() > 64 {
+        return Err(Errno::EINVAL);
+    }
+    let caller = current_task();
+    let file = caller
+        .fd_table()
+        .get_file(pidfd as usize)
+        .ok_or(Errno::EBADF)?;
+    let task = pidfd_task(&file)?;
+    if task.is_zombie() {
+        return Err(Errno::ESRCH);
+    }
+    if caller.vpid_of(task.tid()) == 0 {
+        return Err(Errno::EINVAL);
+    }
+    let siginfo = if info != 0 {
+        let mut linux_info = LinuxSigInfo::default();
+        copy_from_user(
+            info as *const LinuxSigInfo,
+            &mut linux_info as *mut LinuxSigInfo,
+            1,
+        )?;
+        if linux_info.si_signo != sig.raw() {
+            return Err(Errno::EINVAL);
+        }
+        // 不允许伪造内核产生的siginfo
+        if !caller.same_thread_group(&task)
+            && (linux_info.si_code >= 0 || linux_info.si_code == SigInfo::TKILL)
+        {
+            return Err(Errno::EPERM);
+        }
+        SigInfo::new(
+            sig.raw(),
+            linux_info.si_code,
+            SiField::Kill { tid: caller.tid() },
+        )
+    } else {
+        SigInfo::prepare_kill(caller.tid(), sig)
+    };
+    let ret = check_kill_permission(sig, &task)?;
+    if ret != usize::MAX {
+        task.receive_siginfo(siginfo, false);
+    }
+    Ok(0)
+}
+
 /// tgkill() 的过时前身。它仅允许指定目标线程 ID，
 /// 如果线程终止并且其线程 ID 被回收，则可能导致向错误的线程发出信号。
 pub fn sys_tkill(tid: isize, sig: i32) -> SyscallRet {
@@ -138,7 +203,7 @@ pub fn sys_tkill(tid: isize, sig: i32) -> SyscallRet {
     if !sig.is_valid() || tid < 0 {
         return Err(Errno::EINVAL);
     }
-    let task = get_task(tid as usize).ok_or(Errno::ESRCH)?;
+    let task = find_task_by_vpid(tid as usize).ok_or(Errno::ESRCH)?;
     log::info!(
         "[sys_tkill] task{} send signal {:?} to task {}",
         current_task().tid(),
@@ -178,8 +243,8 @@ pub fn sys_tgkill(tgid: isize, tid: isize, sig: i32) -> SyscallRet {
     }
 
     let siginfo = SigInfo::prepare_kill(current_task().tid(), sig);
-    if let Some(task) = get_task(tid as usize) {
-        if task.tgid() != tgid as usize {
+    if let Some(task) = find_task_by_vpid(tid as usize) {
+        if current_task().vpid_of(task.tgid()) != tgid as usize {
             return Err(Errno::ESRCH);
         }
         let pending_size = task.op_sig_pending_mut(|pending| pending.pending.bits().count_ones());
@@ -1,22 +1,41 @@
 use core::sync::atomic::{compiler_fence, Ordering};
 use core::time;
 
-use crate::arch::config::USER_MAX;
+use crate::arch::config::{PAGE_SIZE, USER_MAX};
 use crate::arch::mm::copy_from_user;
 use crate::arch::trap::context::{dump_trap_context, get_trap_context, save_trap_context};
 use crate::dump_system_info;
 use crate::ext4::fs;
 use crate::fs::dentry::X_OK;
+use crate::fs::fdtable::{FdFlags, FdTable};
 use crate::fs::file::OpenFlags;
+use crate::fs::pidfd::{pidfd_task, PidFd, PIDFD_NONBLOCK};
+use crate::fs::proc::ns::NsFile;
+use crate::fs::proc::pid_max::pid_max;
+use crate::fs::sys::cgroup::cgroup_of_file;
 use crate::futex::do_futex;
 use crate::mm::FRAME_ALLOCATOR;
-use crate::signal::Sig;
+use crate::signal::{LinuxSigInfo, Sig, SigInfo, SigSet, MAX_SIGNUM};
 use crate::syscall::errno::Errno;
 use crate::syscall::fs::NAME_MAX;
 use crate::syscall::util::{CLOCK_MONOTONIC, CLOCK_REALTIME};
 use crate::task::{
-    add_group, dump_scheduler, get_group, get_scheduler_len, get_task, info_allocator, new_group,
-    unregister_task, wait, wait_timeout, CloneFlags, Task, INITPROC,
+    add_group,
+    binfmt::search_binary_handler,
+    capability::{
+        CapSet, LINUX_CAPABILITY_VERSION_1, LINUX_CAPABILITY_VERSION_2, LINUX_CAPABILITY_VERSION_3,
+    },
+    cgroup::Cgroup,
+    dump_scheduler, get_group, get_scheduler_len, get_task, info_allocator,
+    namespace::{find_task_by_vpid, Namespace, MAX_PID_NS_LEVEL},
+    new_group,
+    seccomp::{
+        seccomp_get_action_avail, seccomp_set_mode_filter, seccomp_set_mode_strict,
+        SECCOMP_GET_ACTION_AVAIL, SECCOMP_GET_NOTIF_SIZES, SECCOMP_MODE_FILTER,
+        SECCOMP_MODE_STRICT, SECCOMP_SET_MODE_FILTER, SECCOMP_SET_MODE_STRICT,
+    },
+    unregister_task, wait, wait_timeout, CloneFlags, Task, INITPROC, SUID_DUMP_DISABLE,
+    SUID_DUMP_USER, TASK_COMM_LEN,
 };
 use crate::timer::{TimeSpec, TimeVal};
 use crate::{
@@ -26,13 +45,13 @@ use crate::{
     fs::{namei::path_openat, AT_FDCWD},
     loader::get_app_data_by_name,
     task::{
-        add_task, current_task, kernel_exit, remove_task, schedule, yield_current_task,
+        add_task, current_task, kernel_exit, remove_task, schedule, yield_current_task, JobNotify,
         TaskContext, WaitOption,
     },
     utils::{c_str_to_string, extract_cstrings},
 };
 use alloc::task;
-use alloc::{sync::Arc, vec};
+use alloc::{sync::Arc, vec, vec::Vec};
 use bitflags::bitflags;
 
 use super::errno::SyscallRet;
@@ -47,49 +66,8 @@ pub fn sys_clone(
 ) -> SyscallRet {
     // ToDo: 更新错误检验
     log::error!("[sys_clone] flags: {:b}, stack_ptr: {:x}, parent_tid_ptr: {:x}, tls_ptr: {:x}, chilren_tid_ptr: {:x}", flags, stack_ptr, parent_tid_ptr, tls_ptr, children_tid_ptr);
-    let flags = match CloneFlags::from_bits(flags as u32) {
-        None => {
-            log::error!("clone flags is None: {}", flags);
-            return Err(Errno::EINVAL);
-        }
-        Some(flag) => flag,
-    };
-    log::error!("[sys_clone] flags: {:?}", flags);
-    let task = current_task();
-    let new_task = task.kernel_clone(&flags, stack_ptr, children_tid_ptr)?;
-    let new_task_tid = new_task.tid();
-
-    if flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
-        log::warn!("[sys_clone] handle CLONE_PARENT_SETTID");
-        let content = (new_task_tid as u64).to_le_bytes();
-        log::error!("parent_tid_ptr: {:x}", parent_tid_ptr);
-        copy_to_user(parent_tid_ptr as *mut u8, &content as *const u8, 8)?;
-    }
-    if flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
-        log::warn!("[sys_clone] handle CLONE_CHILD_CLEARTID");
-        new_task.set_tac(children_tid_ptr);
-    }
-    if flags.contains(CloneFlags::CLONE_SETTLS) {
-        log::warn!("[sys_clone] handle CLONE_SETTLS");
-        log::error!("tls_ptr: {:x}", tls_ptr);
-        let mut trap_cx = get_trap_context(&new_task);
-        trap_cx.set_tp(tls_ptr as usize);
-        save_trap_context(&new_task, trap_cx);
-    }
-    add_task(new_task);
-    if flags.contains(CloneFlags::CLONE_VFORK) {
-        log::warn!("[sys_clone] handle CLONE_VFORK");
-        // vfork的特殊处理, 需要阻塞父进程直到子进程调用execve或exit
-        wait();
-    }
-    drop(task);
-    // yield_current_task();
-    Ok(new_task_tid)
-}
-
-pub fn sys_setsid() -> SyscallRet {
-    let task = current_task();
-    Ok(task.tgid())
+    let args = legacy_clone_args(flags, stack_ptr, parent_tid_ptr, tls_ptr, children_tid_ptr)?;
+    do_clone(args)
 }
 
 #[cfg(target_arch = "loongarch64")]
@@ -102,48 +80,276 @@ pub fn sys_clone(
 ) -> SyscallRet {
     // ToDo: 更新错误检验
     log::error!("[sys_clone] flags: {:b}, stack_ptr: {:x}, parent_tid_ptr: {:x}, tls_ptr: {:x}, chilren_tid_ptr: {:x}", flags, stack_ptr, parent_tid_ptr, tls_ptr, chilren_tid_ptr);
-    let flags = match CloneFlags::from_bits(flags as u32) {
+    let args = legacy_clone_args(flags, stack_ptr, parent_tid_ptr, tls_ptr, chilren_tid_ptr)?;
+    do_clone(args)
+}
+
+/// clone的flags低8位为子进程退出时向父进程发送的信号
+const CSIGNAL: u32 = 0xff;
+/// clone3: 将子进程的信号处理函数重置为默认(忽略的信号保持忽略)
+const CLONE_CLEAR_SIGHAND: u64 = 1 << 32;
+/// clone3: 子进程加入`cgroup`指定的cgroup目录
+const CLONE_INTO_CGROUP: u64 = 1 << 33;
+/// 第一版struct clone_args的大小, 不包括set_tid, set_tid_size和cgroup
+const CLONE_ARGS_SIZE_VER0: usize = 64;
+
+/// clone3的参数, 与Linux的struct clone_args布局相同
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+pub struct CloneArgs {
+    pub flags: u64,
+    pub pidfd: u64,
+    pub child_tid: u64,
+    pub parent_tid: u64,
+    pub exit_signal: u64,
+    pub stack: u64,
+    pub stack_size: u64,
+    pub tls: u64,
+    pub set_tid: u64,
+    pub set_tid_size: u64,
+    pub cgroup: u64,
+}
+
+/// clone和clone3解析后的参数
+struct KernelCloneArgs {
+    flags: CloneFlags,
+    exit_signal: i32,
+    stack: usize,
+    parent_tid: usize,
+    child_tid: usize,
+    pidfd: usize,
+    tls: usize,
+    set_tid: Vec<usize>,
+    cgroup: Option<Arc<Cgroup>>,
+    clear_sighand: bool,
+}
+
+/// clone: pidfd与父进程的tid写入同一地址, 不能同时使用
+fn legacy_clone_args(
+    flags: u32,
+    stack: usize,
+    parent_tid: usize,
+    tls: usize,
+    child_tid: usize,
+) -> Result<KernelCloneArgs, Errno> {
+    let exit_signal = (flags & CSIGNAL) as i32;
+    let flags = match CloneFlags::from_bits(flags & !CSIGNAL) {
         None => {
             log::error!("clone flags is None: {}", flags);
             return Err(Errno::EINVAL);
         }
         Some(flag) => flag,
     };
-    log::error!("[sys_clone] flags: {:?}", flags);
+    log::error!(
+        "[sys_clone] flags: {:?}, exit_signal: {}",
+        flags,
+        exit_signal
+    );
+    if exit_signal as usize > MAX_SIGNUM {
+        return Err(Errno::EINVAL);
+    }
+    if flags.contains(CloneFlags::CLONE_PIDFD) && flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
+        return Err(Errno::EINVAL);
+    }
+    Ok(KernelCloneArgs {
+        flags,
+        exit_signal,
+        stack,
+        parent_tid,
+        child_tid,
+        pidfd: parent_tid,
+        tls,
+        set_tid: Vec::new(),
+        cgroup: None,
+        clear_sighand: false,
+    })
+}
+
+/// clone3() 提供clone()的超集功能, 参数通过struct clone_args传递, `size`为结构体的大小
+/// 与clone不同, 子进程的栈由stack和stack_size指定, 退出信号由exit_signal指定
+/// EINVAL 参数组合不合法或set_tid超过pid_max; E2BIG size过大或者用户传入了内核不支持的非0扩展字段
+/// EEXIST set_tid指定的pid已被占用; EPERM 没有权限指定pid
+pub fn sys_clone3(uargs: usize, size: usize) -> SyscallRet {
+    log::info!("[sys_clone3] uargs: {:#x}, size: {}", uargs, size);
+    if size < CLONE_ARGS_SIZE_VER0 {
+        return Err(Errno::EINVAL);
+    }
+    if size > PAGE_SIZE {
+        return Err(Errno::E2BIG);
+    }
+    let known_size = core::mem::size_of::<CloneArgs>();
+    let mut args = CloneArgs::default();
+    copy_from_user(
+        uargs as *const u8,
+        &mut args as *mut CloneArgs as *mut u8,
+        size.min(known_size),
+    )?;
+    // 更新版本的用户程序传入的扩展字段必须为0
+    if size > known_size {
+        let mut extra = vec![0u8; size - known_size];
+        copy_from_user(
+            (uargs + known_size) as *const u8,
+            extra.as_mut_ptr(),
+            extra.len(),
+        )?;
+        if extra.iter().any(|&byte| byte != 0) {
+            return Err(Errno::E2BIG);
+        }
+    }
+    log::info!("[sys_clone3] args: {:x?}", args);
+
+    if args.flags & !(u32::MAX as u64 | CLONE_CLEAR_SIGHAND | CLONE_INTO_CGROUP) != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let raw_flags = args.flags as u32;
+    // 退出信号只能由exit_signal指定
+    if raw_flags & CSIGNAL != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let flags = CloneFlags::from_bits(raw_flags).ok_or(Errno::EINVAL)?;
+    if args.exit_signal > MAX_SIGNUM as u64
+        || (args.exit_signal != 0
+            && flags.intersects(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_PARENT))
+    {
+        return Err(Errno::EINVAL);
+    }
+    if (args.stack == 0) != (args.stack_size == 0) {
+        return Err(Errno::EINVAL);
+    }
+    let clear_sighand = args.flags & CLONE_CLEAR_SIGHAND != 0;
+    if clear_sighand && flags.contains(CloneFlags::CLONE_SIGHAND) {
+        return Err(Errno::EINVAL);
+    }
+
+    // set_tid从子进程所在的pid命名空间开始, 依次指定各层命名空间中的pid
+    if args.set_tid_size > MAX_PID_NS_LEVEL as u64
+        || (args.set_tid == 0) != (args.set_tid_size == 0)
+    {
+        return Err(Errno::EINVAL);
+    }
+    let mut set_tid = vec![0i32; args.set_tid_size as usize];
+    if !set_tid.is_empty() {
+        copy_from_user(
+            args.set_tid as *const i32,
+            set_tid.as_mut_ptr(),
+            set_tid.len(),
+        )?;
+    }
+    let pid_max = pid_max();
+    if set_tid.iter().any(|&pid| pid < 1 || pid as usize > pid_max) {
+        return Err(Errno::EINVAL);
+    }
+
+    let task = current_task();
+    let cgroup = if args.flags & CLONE_INTO_CGROUP != 0 {
+        let file = task
+            .fd_table()
+            .get_file(args.cgroup as usize)
+            .ok_or(Errno::EBADF)?;
+        Some(cgroup_of_file(&file)?)
+    } else {
+        None
+    };
+
+    do_clone(KernelCloneArgs {
+        flags,
+        exit_signal: args.exit_signal as i32,
+        // 栈从stack + stack_size向低地址增长
+        stack: (args.stack + args.stack_size) as usize,
+        parent_tid: args.parent_tid as usize,
+        child_tid: args.child_tid as usize,
+        pidfd: args.pidfd as usize,
+        tls: args.tls as usize,
+        set_tid: set_tid.into_iter().map(|pid| pid as usize).collect(),
+        cgroup,
+        clear_sighand,
+    })
+}
+
+/// clone和clone3的公共部分, 返回子进程在调用者pid命名空间中的tid
+fn do_clone(args: KernelCloneArgs) -> SyscallRet {
+    let flags = &args.flags;
+    if flags.contains(CloneFlags::CLONE_PIDFD) && flags.contains(CloneFlags::CLONE_THREAD) {
+        return Err(Errno::EINVAL);
+    }
     let task = current_task();
-    let new_task = task.kernel_clone(&flags, stack_ptr, chilren_tid_ptr)?;
-    let new_task_tid = new_task.tid();
+    // 线程和CLONE_PARENT创建的子进程继承调用者的退出信号
+    let exit_signal = if flags.intersects(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_PARENT) {
+        task.exit_signal()
+    } else {
+        args.exit_signal
+    };
+    let new_task = task.kernel_clone(
+        flags,
+        args.stack,
+        args.child_tid,
+        exit_signal,
+        &args.set_tid,
+        args.cgroup,
+    )?;
+    // 父进程看到的是子进程在自己pid命名空间中的tid
+    let new_task_tid = task.vpid_of(new_task.tid());
+
+    if flags.contains(CloneFlags::CLONE_PIDFD) {
+        log::warn!("[sys_clone] handle CLONE_PIDFD");
+        clone_pidfd(&task, &new_task, args.pidfd)?;
+    }
 
     if flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
         log::warn!("[sys_clone] handle CLONE_PARENT_SETTID");
-        let content = (new_task_tid as u64).to_le_bytes();
-        log::error!("parent_tid_ptr: {:x}", parent_tid_ptr);
-        copy_to_user(parent_tid_ptr as *mut u8, &content as *const u8, 8)?;
-    }
-    if flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
-        log::warn!("[sys_clone] handle CLONE_CHILD_SETTID");
-        let content = (new_task_tid as u64).to_le_bytes();
-        log::error!("chilren_tid_ptr: {:x}", parent_tid_ptr);
-        copy_to_user(chilren_tid_ptr as *mut u8, &content as *const u8, 8)?;
-        new_task.set_tas(new_task.tid());
+        let content = (new_task_tid as i32).to_le_bytes();
+        copy_to_user(args.parent_tid as *mut u8, &content as *const u8, 4)?;
     }
     if flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
         log::warn!("[sys_clone] handle CLONE_CHILD_CLEARTID");
-        new_task.set_tac(chilren_tid_ptr);
+        new_task.set_tac(args.child_tid);
     }
     if flags.contains(CloneFlags::CLONE_SETTLS) {
         log::warn!("[sys_clone] handle CLONE_SETTLS");
-        log::error!("tls_ptr: {:x}", tls_ptr);
         let mut trap_cx = get_trap_context(&new_task);
-        trap_cx.set_tp(tls_ptr as usize);
+        trap_cx.set_tp(args.tls);
         save_trap_context(&new_task, trap_cx);
     }
-    add_task(new_task);
+    if args.clear_sighand {
+        new_task.op_sig_handler_mut(|handler| handler.reset());
+    }
+    if flags.contains(CloneFlags::CLONE_VFORK) {
+        new_task.set_vfork_parent(task.tid());
+    }
+    add_task(new_task.clone());
+    if flags.contains(CloneFlags::CLONE_VFORK) {
+        log::warn!("[sys_clone] handle CLONE_VFORK");
+        // vfork的特殊处理, 阻塞父进程直到子进程调用execve或exit
+        // 期间收到的信号保持未决, 只有SIGKILL可以提前结束等待
+        while new_task.vfork_pending() {
+            wait();
+            task.set_uninterrupted();
+            if task
+                .op_sig_pending_mut(|pending| pending.find_signal(SigSet::SIGKILL))
+                .is_some()
+            {
+                break;
+            }
+        }
+    }
     drop(task);
     // yield_current_task();
     Ok(new_task_tid)
 }
 
+/// CLONE_PIDFD: 在父进程中为子进程创建pidfd并写入`pidfd_ptr`
+fn clone_pidfd(task: &Arc<Task>, new_task: &Arc<Task>, pidfd_ptr: usize) -> SyscallRet {
+    let pidfd = PidFd::new(new_task.clone(), OpenFlags::O_RDWR);
+    let fd = task.fd_table().alloc_fd(pidfd, FdFlags::FD_CLOEXEC)?;
+    copy_to_user(pidfd_ptr as *mut i32, &(fd as i32) as *const i32, 1)?;
+    Ok(fd)
+}
+
+pub fn sys_setsid() -> SyscallRet {
+    let task = current_task();
+    Ok(task.tgid())
+}
+
 // pub const IGNOER_TEST: [&str; 40] = [
 //     /* 本身就不应该单独运行的 */
 //     "ltp/testcases/bin/add_ipv6addr",
@@ -256,8 +462,9 @@ pub fn sys_execve(path: *const u8, args: *const usize, envs: *const usize) -> Sy
             //     log::error!("[sys_execve] file {} is empty", path);
             //     return Err(Errno::ENOEXEC);
             // }
-            let absolute_path = file.get_path().dentry.absolute_path.clone();
-            task.kernel_execve_lazily(absolute_path, file, args_vec, envs_vec)?;
+            // 脚本和binfmt_misc格式改写argv, 转而执行解释器
+            let (elf_file, absolute_path) = search_binary_handler(file, &path, &mut args_vec)?;
+            task.kernel_execve_lazily(absolute_path, elf_file, args_vec, envs_vec)?;
             Ok(0)
         }
         Err(err) if err == Errno::ENOENT && !path.starts_with("/") => {
@@ -279,13 +486,15 @@ pub fn sys_execve(path: *const u8, args: *const usize, envs: *const usize) -> Sy
 }
 
 pub fn sys_gettid() -> SyscallRet {
-    log::info!("[sys_gettid] tid: {}", current_task().tid());
-    Ok(current_task().tid())
+    let task = current_task();
+    log::info!("[sys_gettid] tid: {}", task.tid());
+    Ok(task.vpid_of(task.tid()))
 }
 
 pub fn sys_getpid() -> SyscallRet {
-    log::info!("[sys_getpid] pid: {}", current_task().tgid());
-    Ok(current_task().tgid())
+    let task = current_task();
+    log::info!("[sys_getpid] pid: {}", task.tgid());
+    Ok(task.vpid_of(task.tgid()))
 }
 
 /// setpgid() 将 pid 指定的进程的 PGID 设置为 pgid。
@@ -299,12 +508,19 @@ pub fn sys_setpgid(pid: usize, pgid: usize) -> SyscallRet {
         log::error!("[sys_setpgid] pgid cannot be negative");
         return Err(Errno::EINVAL);
     }
-    let mut set_pid = pid;
-    let mut set_pgid = pgid;
     let caller_task = current_task();
-    if pid == 0 {
-        set_pid = current_task().tid();
-    }
+    // pid和pgid都是调用者pid命名空间中的值
+    let pid_ns = caller_task.pid_ns();
+    let mut set_pid = if pid == 0 {
+        current_task().tid()
+    } else {
+        pid_ns.tid_of(pid).ok_or(Errno::ESRCH)?
+    };
+    let mut set_pgid = if pgid == 0 {
+        0
+    } else {
+        pid_ns.tid_of(pgid).ok_or(Errno::EPERM)?
+    };
 
     let set_task = get_task(set_pid).ok_or(Errno::ESRCH)?;
     if !set_task.is_process() {
@@ -325,7 +541,7 @@ pub fn sys_setpgid(pid: usize, pgid: usize) -> SyscallRet {
     if pgid == 0 {
         set_pgid = set_task.tid();
     } else {
-        if get_group(pgid).is_none() {
+        if get_group(set_pgid).is_none() {
             log::error!("[sys_setpgid] pgid {} does not exist", pgid);
             return Err(Errno::EPERM);
         }
@@ -379,21 +595,21 @@ pub fn sys_getpgid(pid: usize) -> SyscallRet {
     let target_task = if pid == 0 {
         current_task().clone()
     } else {
-        get_task(pid).ok_or(Errno::ESRCH)?
+        find_task_by_vpid(pid).ok_or(Errno::ESRCH)?
     };
     log::info!(
         "[sys_getpgid] task {} get pgid: {}",
         target_task.tid(),
         target_task.pgid()
     );
-    Ok(target_task.pgid())
+    Ok(current_task().vpid_of(target_task.pgid()))
 }
 
 pub fn sys_set_tid_address(tidptr: usize) -> SyscallRet {
     let task = current_task();
     log::info!("[sys_set_tid_address] tidptr:{:#x}", tidptr);
     task.set_tac(tidptr);
-    Ok(task.tid())
+    Ok(task.vpid_of(task.tid()))
 }
 
 // 获取父进程的pid
@@ -401,7 +617,8 @@ pub fn sys_getppid() -> SyscallRet {
     let task = current_task();
     let ppid = task.op_parent(|parent| {
         if let Some(parent) = parent {
-            parent.upgrade().unwrap().tid()
+            // 父进程在其他pid命名空间中时返回0
+            task.vpid_of(parent.upgrade().unwrap().tid())
         } else {
             0 // 如果没有父进程，则返回0
         }
@@ -461,11 +678,23 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: usize, option: i32) -> SyscallRet
         exit_code_ptr,
         option,
     );
-    // option 必须合法
-    if option < 0 {
+    // option 必须合法, WEXITED/WNOWAIT只能用于waitid
+    if option < 0 || option & (WaitOption::WEXITED | WaitOption::WNOWAIT).bits() != 0 {
         return Err(Errno::EINVAL);
     }
 
+    // 将调用者pid命名空间中的pid/pgid转换为全局tid
+    let pid_ns = current_task().pid_ns();
+    let pid = match pid {
+        p if p > 0 => pid_ns
+            .tid_of(p as usize)
+            .map_or(isize::MAX, |tid| tid as isize),
+        p if p < -1 => pid_ns
+            .tid_of((-p) as usize)
+            .map_or(isize::MIN + 1, |tid| -(tid as isize)),
+        p => p,
+    };
+
     // 检查进程组是否存在（pid < 0 表示等待某个 pgid）
     if pid < 0 {
         get_group(-pid as usize).ok_or(Errno::ESRCH)?;
@@ -475,11 +704,13 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: usize, option: i32) -> SyscallRet
         log::error!("[sys_waitpid] Invalid wait option: {}", option);
         WaitOption::empty()
     });
+    // wait4总是等待退出的子进程
+    let wait_option = wait_option | WaitOption::WEXITED;
 
     let cur_task = current_task();
     loop {
         // 先检查当前进程是否存在满足目标子进程
-        let target_task = select_task(pid);
+        let target_task = select_task(pid, wait_option);
 
         log::trace!(
             "[sys_waitpid] cur_task: {}, target_task: {:?}",
@@ -494,10 +725,21 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: usize, option: i32) -> SyscallRet
 
                 // 神奇小咒语
                 log::trace!("waitpid");
+                // 子进程被停止或继续, 返回状态但不回收
+                if let Some(event @ (ChildEvent::Stopped(_) | ChildEvent::Continued)) =
+                    child_event(&child, wait_option)
+                {
+                    child.op_thread_group_mut(|tg| tg.take_job_notify());
+                    let status = event.wait_status();
+                    log::warn!("[sys_waitpid] child {} status: {:#x}", tid, status);
+                    if exit_code_ptr != 0 {
+                        copy_to_user(exit_code_ptr as *mut i32, &status as *const i32, 1)?;
+                    }
+                    return Ok(cur_task.vpid_of(tgid));
+                }
                 // 如果子进程已经退出，直接回收资源并返回
                 if child.is_zombie() {
                     cur_task.remove_child_task(tid);
-                    debug_assert_eq!(Arc::strong_count(&child), 1);
                     // 注销任务
                     unregister_task(tid);
 
@@ -514,7 +756,7 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: usize, option: i32) -> SyscallRet
                         }
                     }
 
-                    return Ok(tgid);
+                    return Ok(cur_task.vpid_of(tgid));
                 }
 
                 // 子进程未退出
@@ -545,8 +787,61 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: usize, option: i32) -> SyscallRet
     }
 }
 
+/// wait4/waitid可以获取的子进程状态变化
+#[derive(Clone, Copy)]
+enum ChildEvent {
+    /// 退出, 记录wait状态
+    Exited(i32),
+    /// 被停止, 记录停止信号
+    Stopped(i32),
+    Continued,
+}
+
+impl ChildEvent {
+    /// wait4返回的状态
+    fn wait_status(&self) -> i32 {
+        match *self {
+            ChildEvent::Exited(code) => code,
+            ChildEvent::Stopped(sig) => (sig << 8) | 0x7f,
+            ChildEvent::Continued => 0xffff,
+        }
+    }
+
+    /// waitid返回的siginfo_t中的si_code和si_status
+    fn siginfo(&self) -> (i32, i32) {
+        match *self {
+            ChildEvent::Exited(code) if code & 0x7f == 0 => {
+                (SigInfo::CLD_EXITED, (code >> 8) & 0xff)
+            }
+            ChildEvent::Exited(code) if code & 0x80 != 0 => (SigInfo::CLD_DUMPED, code & 0x7f),
+            ChildEvent::Exited(code) => (SigInfo::CLD_KILLED, code & 0x7f),
+            ChildEvent::Stopped(sig) => (SigInfo::CLD_STOPPED, sig),
+            ChildEvent::Continued => (SigInfo::CLD_CONTINUED, Sig::SIGCONT.raw()),
+        }
+    }
+}
+
+/// 子进程是否有`option`关心的状态变化
+fn child_event(child: &Arc<Task>, option: WaitOption) -> Option<ChildEvent> {
+    if child.is_zombie() {
+        return option
+            .contains(WaitOption::WEXITED)
+            .then(|| ChildEvent::Exited(child.exit_code()));
+    }
+    match child.op_thread_group(|tg| tg.job_notify()) {
+        Some(JobNotify::Stopped(sig)) if option.contains(WaitOption::WSTOPPED) => {
+            Some(ChildEvent::Stopped(sig))
+        }
+        Some(JobNotify::Continued) if option.contains(WaitOption::WCONTINUED) => {
+            Some(ChildEvent::Continued)
+        }
+        _ => None,
+    }
+}
+
 // 用于waitpid选择目标任务
-fn select_task(pid: isize) -> Option<Arc<Task>> {
+// 优先选择有状态变化的子进程, 否则选择任意一个符合条件的子进程用于阻塞等待
+fn select_task(pid: isize, option: WaitOption) -> Option<Arc<Task>> {
     let mut target_task: Option<Arc<Task>> = None;
     let cur_task = current_task();
 
@@ -560,11 +855,12 @@ fn select_task(pid: isize) -> Option<Arc<Task>> {
                 child.tgid(),
                 child.is_zombie()
             );
+            let ready = child_event(child, option).is_some();
             let matches = match pid {
-                -1 => child.is_zombie(), // 等待任意僵尸子进程
-                0 => child.is_zombie() && child.pgid() == cur_task.pgid(), // 当前进程组的僵尸子进程
-                p if p > 0 => child.tgid() == p as usize, // 等待 tid 为 pid 的子进程
-                p if p < -1 => child.pgid() == (-p) as usize, // 等待 pgid 为 -pid 的子进程
+                -1 => ready,                                           // 等待任意有状态变化的子进程
+                0 => ready && child.pgid() == cur_task.pgid(),         // 当前进程组的子进程
+                p if p > 0 => child.tgid() == p as usize,              // 等待 tid 为 pid 的子进程
+                p if p < -1 => ready && child.pgid() == (-p) as usize, // 等待 pgid 为 -pid 的子进程
                 _ => false,
             };
 
@@ -574,12 +870,13 @@ fn select_task(pid: isize) -> Option<Arc<Task>> {
             }
         }
 
-        // 如果找不到符合条件的僵尸子进程，也可以选择任意一个非僵尸子进程（作为 fallback）
-        if pid == -1 || pid == 0 {
+        // 如果找不到有状态变化的子进程，也可以选择任意一个符合条件的子进程（作为 fallback）
+        if pid <= 0 {
             for child in children.values() {
                 let fallback = match pid {
                     -1 => true,
                     0 => child.pgid() == cur_task.pgid(),
+                    p if p < -1 => child.pgid() == (-p) as usize,
                     _ => false,
                 };
                 if fallback {
@@ -593,6 +890,180 @@ fn select_task(pid: isize) -> Option<Arc<Task>> {
     target_task
 }
 
+/* waitid start */
+pub const P_ALL: i32 = 0;
+pub const P_PID: i32 = 1;
+pub const P_PGID: i32 = 2;
+pub const P_PIDFD: i32 = 3;
+
+/// 等待状态由`infop`指向的siginfo_t返回, 成功时返回0
+/// WNOHANG且没有满足条件的子进程时返回0, siginfo_t的si_pid为0
+pub fn sys_waitid(
+    idtype: i32,
+    id: isize,
+    infop: usize,
+    options: i32,
+    _rusage: usize,
+) -> SyscallRet {
+    log::warn!(
+        "[sys_waitid] idtype: {}, id: {}, infop: {:#x}, options: {:#x}",
+        idtype,
+        id,
+        infop,
+        options
+    );
+    let wait_option = WaitOption::from_bits(options).ok_or(Errno::EINVAL)?;
+    if !wait_option.intersects(WaitOption::WEXITED | WaitOption::WSTOPPED | WaitOption::WCONTINUED)
+    {
+        return Err(Errno::EINVAL);
+    }
+
+    let cur_task = current_task();
+    // 与waitpid相同, 转换为全局tid后用select_task选择子进程
+    let mut nonblock = wait_option.contains(WaitOption::WNOHANG);
+    let pid = match idtype {
+        P_ALL => -1,
+        P_PID if id > 0 => cur_task
+            .pid_ns()
+            .tid_of(id as usize)
+            .map_or(isize::MAX, |tid| tid as isize),
+        P_PGID if id == 0 => 0,
+        P_PGID if id > 0 => cur_task
+            .pid_ns()
+            .tid_of(id as usize)
+            .map_or(isize::MIN + 1, |tid| -(tid as isize)),
+        P_PIDFD if id >= 0 => {
+            let file = cur_task
+                .fd_table()
+                .get_file(id as usize)
+                .ok_or(Errno::EBADF)?;
+            let pidfd = file.as_any().downcast_ref::<PidFd>().ok_or(Errno::EBADF)?;
+            nonblock |= pidfd.nonblock();
+            pidfd.task().tgid() as isize
+        }
+        _ => return Err(Errno::EINVAL),
+    };
+
+    loop {
+        let Some(child) = select_task(pid, wait_option) else {
+            return Err(Errno::ECHILD);
+        };
+        if let Some(event) = child_event(&child, wait_option) {
+            let tid = child.tid();
+            // WNOWAIT只获取状态, 子进程保持可等待
+            if !wait_option.contains(WaitOption::WNOWAIT) {
+                match event {
+                    ChildEvent::Exited(_) => {
+                        cur_task.remove_child_task(tid);
+                        unregister_task(tid);
+                    }
+                    _ => {
+                        child.op_thread_group_mut(|tg| tg.take_job_notify());
+                    }
+                }
+            }
+            let (code, status) = event.siginfo();
+            log::warn!(
+                "[sys_waitid] child {} changed state, code: {}, status: {}",
+                tid,
+                code,
+                status
+            );
+            if infop != 0 {
+                let info = LinuxSigInfo::new_chld(
+                    code,
+                    cur_task.vpid_of(child.tgid()) as i32,
+                    child.uid(),
+                    status,
+                );
+                copy_to_user(infop as *mut LinuxSigInfo, &info as *const LinuxSigInfo, 1)?;
+            }
+            return Ok(0);
+        }
+        if nonblock {
+            if idtype == P_PIDFD && !wait_option.contains(WaitOption::WNOHANG) {
+                // O_NONBLOCK的pidfd
+                return Err(Errno::EAGAIN);
+            }
+            if infop != 0 {
+                let info = LinuxSigInfo::default();
+                copy_to_user(infop as *mut LinuxSigInfo, &info as *const LinuxSigInfo, 1)?;
+            }
+            return Ok(0);
+        }
+        if wait() == -1 {
+            // 与waitpid相同, 因为SIGCHLD被唤醒时继续检查
+            if cur_task
+                .op_sig_pending_mut(|pending| pending.find_signal(Sig::SIGCHLD.into()))
+                .is_some()
+            {
+                cur_task.set_uninterrupted();
+                continue;
+            }
+            return Err(Errno::EINTR);
+        }
+    }
+}
+/* waitid end */
+
+/* pidfd start */
+/// 为线程组的主线程创建pidfd, 总是设置FD_CLOEXEC
+pub fn sys_pidfd_open(pid: isize, flags: i32) -> SyscallRet {
+    log::info!("[sys_pidfd_open] pid: {}, flags: {:#x}", pid, flags);
+    if flags & !PIDFD_NONBLOCK != 0 || pid <= 0 {
+        return Err(Errno::EINVAL);
+    }
+    let task = find_task_by_vpid(pid as usize).ok_or(Errno::ESRCH)?;
+    if !task.is_process() {
+        return Err(Errno::EINVAL);
+    }
+    let flags = OpenFlags::from_bits_truncate(flags) | OpenFlags::O_RDWR;
+    current_task()
+        .fd_table()
+        .alloc_fd(PidFd::new(task, flags), FdFlags::FD_CLOEXEC)
+}
+
+/// 复制pidfd引用的进程的文件描述符`targetfd`, 需要有ptrace该进程的权限
+pub fn sys_pidfd_getfd(pidfd: i32, targetfd: i32, flags: u32) -> SyscallRet {
+    log::info!(
+        "[sys_pidfd_getfd] pidfd: {}, targetfd: {}, flags: {:#x}",
+        pidfd,
+        targetfd,
+        flags
+    );
+    if flags != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let task = current_task();
+    let file = task
+        .fd_table()
+        .get_file(pidfd as usize)
+        .ok_or(Errno::EBADF)?;
+    let target = pidfd_task(&file)?;
+    if target.is_zombie() {
+        return Err(Errno::ESRCH);
+    }
+    // PTRACE_MODE_ATTACH_REALCREDS: 凭证完全相同且目标可以dump, 或者拥有CAP_SYS_PTRACE
+    let same_creds = task.uid() == target.uid()
+        && task.uid() == target.euid()
+        && task.uid() == target.suid()
+        && task.gid() == target.gid()
+        && task.gid() == target.egid()
+        && task.gid() == target.sgid();
+    let may_access = task.same_thread_group(&target)
+        || task.capable(CapSet::SYS_PTRACE)
+        || (same_creds && target.dumpable() == SUID_DUMP_USER);
+    if !may_access {
+        return Err(Errno::EPERM);
+    }
+    let target_file = target
+        .fd_table()
+        .get_file(targetfd as usize)
+        .ok_or(Errno::EBADF)?;
+    task.fd_table().alloc_fd(target_file, FdFlags::FD_CLOEXEC)
+}
+/* pidfd end */
+
 pub fn sys_futex(
     uaddr: usize,
     futex_op: i32,
@@ -773,7 +1244,8 @@ pub fn sys_acct(pathname: *const u8) -> SyscallRet {
 // Todo: 命名空间
 pub fn sys_setuid(uid: u32) -> SyscallRet {
     let task = current_task();
-    if task.euid() == 0 {
+    let origin_ids = (task.uid(), task.euid(), task.suid());
+    if task.capable(CapSet::SETUID) {
         log::warn!(
             "[sys_setuid] task{} is root, set uid to {}",
             task.tid(),
@@ -797,6 +1269,7 @@ pub fn sys_setuid(uid: u32) -> SyscallRet {
             task.set_fsuid(uid);
         }
     }
+    task.fix_setuid_caps(origin_ids);
     Ok(0)
 }
 
@@ -804,7 +1277,7 @@ pub fn sys_setuid(uid: u32) -> SyscallRet {
 /// 如果调用进程拥有特权则还会设置实际 GID 和保存的设置组 ID。
 pub fn sys_setgid(gid: u32) -> SyscallRet {
     let task = current_task();
-    if task.euid() == 0 {
+    if task.capable(CapSet::SETGID) {
         log::warn!(
             "[sys_setgid] task{} is root, set gid to {}",
             task.gid(),
@@ -839,10 +1312,11 @@ pub fn sys_setgid(gid: u32) -> SyscallRet {
 pub fn sys_setreuid(ruid: i32, euid: i32) -> SyscallRet {
     log::info!("[sys_setreuid] ruid: {}, euid: {}", ruid, euid);
     let task = current_task();
+    let origin_ids = (task.uid(), task.euid(), task.suid());
     let origin_uid = task.uid() as i32;
     let origin_euid = task.euid() as i32;
     let origin_suid = task.suid() as i32;
-    if task.euid() == 0 {
+    if task.capable(CapSet::SETUID) {
         log::warn!(
             "[sys_setreuid] task{} is root, set ruid: {}, euid: {}",
             task.tid(),
@@ -885,6 +1359,7 @@ pub fn sys_setreuid(ruid: i32, euid: i32) -> SyscallRet {
     if ruid != -1 || (euid != -1 && euid != origin_uid as i32) {
         task.set_suid(task.euid() as u32);
     }
+    task.fix_setuid_caps(origin_ids);
     Ok(0)
 }
 
@@ -906,7 +1381,7 @@ pub fn sys_setregid(rgid: i32, egid: i32) -> SyscallRet {
         origin_gid,
         origin_sgid
     );
-    if task.euid() == 0 {
+    if task.capable(CapSet::SETGID) {
         log::warn!(
             "[sys_setregid] task{} is root, set rgid: {}, egid: {}",
             task.tid(),
@@ -963,10 +1438,11 @@ pub fn sys_setresuid(ruid: i32, euid: i32, suid: i32) -> SyscallRet {
         suid
     );
     let task = current_task();
+    let origin_ids = (task.uid(), task.euid(), task.suid());
     let origin_uid = task.uid() as i32;
     let origin_euid = task.euid() as i32;
     let origin_suid = task.suid() as i32;
-    if task.euid() == 0 {
+    if task.capable(CapSet::SETUID) {
         log::warn!(
             "[sys_setreuid] task{} is root, set ruid: {}, euid: {}",
             task.tid(),
@@ -1022,6 +1498,7 @@ pub fn sys_setresuid(ruid: i32, euid: i32, suid: i32) -> SyscallRet {
             task.set_suid(suid as u32);
         }
     }
+    task.fix_setuid_caps(origin_ids);
     Ok(0)
 }
 
@@ -1037,7 +1514,7 @@ pub fn sys_setresgid(rgid: i32, egid: i32, sgid: i32) -> SyscallRet {
     let origin_gid = task.gid() as i32;
     let origin_egid = task.egid() as i32;
     let origin_sgid = task.sgid() as i32;
-    if task.euid() == 0 {
+    if task.capable(CapSet::SETGID) {
         log::warn!(
             "[sys_setregid] task{} is root, set rgid: {}, egid: {}",
             task.tid(),
@@ -1159,8 +1636,8 @@ pub fn sys_setgroups(size: usize, list: usize) -> SyscallRet {
     if size > NGROUPS_MAX {
         return Err(Errno::EINVAL);
     }
-    if task.euid() != 0 {
-        // 只有root用户可以设置补充组
+    if !task.capable(CapSet::SETGID) {
+        // 需要CAP_SETGID才能设置补充组
         return Err(Errno::EPERM);
     }
     let mut groups = vec![0u32; size as usize];
@@ -1210,7 +1687,7 @@ pub fn sys_setfsuid(fsuid: i32) -> SyscallRet {
     log::info!("[sys_setfsuid] fsuid: {}", fsuid);
     let task = current_task();
     let origin_fsuid = task.fsuid() as i32;
-    if task.euid() == 0 {
+    if task.capable(CapSet::SETUID) {
         log::warn!(
             "[sys_setfsuid] task{} is root, set fsuid to {}",
             task.tid(),
@@ -1238,6 +1715,7 @@ pub fn sys_setfsuid(fsuid: i32) -> SyscallRet {
             }
         }
     }
+    task.op_caps_mut(|caps| caps.fix_setfsuid(origin_fsuid as u32, task.fsuid()));
     Ok(origin_fsuid as usize)
 }
 
@@ -1247,7 +1725,7 @@ pub fn sys_setfsgid(fsgid: i32) -> SyscallRet {
     log::info!("[sys_setfsgid] fsgid: {}", fsgid);
     let task = current_task();
     let origin_fsgid = task.fsgid() as i32;
-    if task.euid() == 0 {
+    if task.capable(CapSet::SETGID) {
         if fsgid != -1 {
             log::warn!(
                 "[sys_setfsgid] task{} is root, set fsgid to {}",
@@ -1277,3 +1755,360 @@ pub fn sys_setfsgid(fsgid: i32) -> SyscallRet {
     }
     Ok(origin_fsgid as usize)
 }
+
+/* capability start */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapUserHeader {
+    pub version: u32,
+    pub pid: i32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapUserData {
+    pub effective: u32,
+    pub permitted: u32,
+    pub inheritable: u32,
+}
+
+/// 检查版本号, 返回用户数据数组的长度; 未知版本写回当前版本并返回EINVAL
+fn cap_validate_header(hdrp: *mut CapUserHeader) -> Result<(CapUserHeader, usize), Errno> {
+    let mut header = CapUserHeader::default();
+    copy_from_user(hdrp as *const CapUserHeader, &mut header, 1)?;
+    match header.version {
+        LINUX_CAPABILITY_VERSION_1 => Ok((header, 1)),
+        LINUX_CAPABILITY_VERSION_2 | LINUX_CAPABILITY_VERSION_3 => Ok((header, 2)),
+        _ => {
+            // version是头部的第一个字段
+            let version = LINUX_CAPABILITY_VERSION_3;
+            copy_to_user(hdrp as *mut u32, &version, 1)?;
+            Err(Errno::EINVAL)
+        }
+    }
+}
+
+/// capget() 获取线程的effective, permitted, inheritable能力集
+pub fn sys_capget(hdrp: *mut CapUserHeader, datap: *mut CapUserData) -> SyscallRet {
+    let (header, count) = cap_validate_header(hdrp)?;
+    log::info!(
+        "[sys_capget] version: {:#x}, pid: {}",
+        header.version,
+        header.pid
+    );
+    if header.pid < 0 {
+        return Err(Errno::EINVAL);
+    }
+    let task = if header.pid == 0 {
+        current_task()
+    } else {
+        find_task_by_vpid(header.pid as usize).ok_or(Errno::ESRCH)?
+    };
+    if datap.is_null() {
+        return Ok(0);
+    }
+    let caps = task.caps();
+    let mut data = [CapUserData::default(); 2];
+    for (i, item) in data.iter_mut().enumerate() {
+        let shift = i * 32;
+        item.effective = (caps.effective.bits() >> shift) as u32;
+        item.permitted = (caps.permitted.bits() >> shift) as u32;
+        item.inheritable = (caps.inheritable.bits() >> shift) as u32;
+    }
+    copy_to_user(datap, data.as_ptr(), count)?;
+    Ok(0)
+}
+
+/// capset() 设置当前线程的能力集, 只能修改自身
+pub fn sys_capset(hdrp: *mut CapUserHeader, datap: *const CapUserData) -> SyscallRet {
+    let (header, count) = cap_validate_header(hdrp)?;
+    log::info!(
+        "[sys_capset] version: {:#x}, pid: {}",
+        header.version,
+        header.pid
+    );
+    let task = current_task();
+    if header.pid != 0 && header.pid as usize != task.tid() {
+        return Err(Errno::EPERM);
+    }
+    let mut data = [CapUserData::default(); 2];
+    copy_from_user(datap, data.as_mut_ptr(), count)?;
+    let (mut effective, mut permitted, mut inheritable) = (0u64, 0u64, 0u64);
+    for (i, item) in data.iter().enumerate() {
+        let shift = i * 32;
+        effective |= (item.effective as u64) << shift;
+        permitted |= (item.permitted as u64) << shift;
+        inheritable |= (item.inheritable as u64) << shift;
+    }
+    task.op_caps_mut(|caps| {
+        caps.set(
+            CapSet::from_bits_truncate(effective),
+            CapSet::from_bits_truncate(permitted),
+            CapSet::from_bits_truncate(inheritable),
+        )
+    })?;
+    Ok(0)
+}
+/* capability end */
+
+/* prctl start */
+pub const PR_SET_PDEATHSIG: i32 = 1;
+pub const PR_GET_PDEATHSIG: i32 = 2;
+pub const PR_GET_DUMPABLE: i32 = 3;
+pub const PR_SET_DUMPABLE: i32 = 4;
+pub const PR_GET_KEEPCAPS: i32 = 7;
+pub const PR_SET_KEEPCAPS: i32 = 8;
+pub const PR_SET_NAME: i32 = 15;
+pub const PR_GET_NAME: i32 = 16;
+pub const PR_GET_SECCOMP: i32 = 21;
+pub const PR_SET_SECCOMP: i32 = 22;
+pub const PR_CAPBSET_READ: i32 = 23;
+pub const PR_CAPBSET_DROP: i32 = 24;
+pub const PR_SET_CHILD_SUBREAPER: i32 = 36;
+pub const PR_GET_CHILD_SUBREAPER: i32 = 37;
+pub const PR_SET_NO_NEW_PRIVS: i32 = 38;
+pub const PR_GET_NO_NEW_PRIVS: i32 = 39;
+pub const PR_CAP_AMBIENT: i32 = 47;
+
+pub const PR_CAP_AMBIENT_IS_SET: usize = 1;
+pub const PR_CAP_AMBIENT_RAISE: usize = 2;
+pub const PR_CAP_AMBIENT_LOWER: usize = 3;
+pub const PR_CAP_AMBIENT_CLEAR_ALL: usize = 4;
+
+pub fn sys_prctl(option: i32, arg2: usize, arg3: usize, arg4: usize, arg5: usize) -> SyscallRet {
+    log::info!(
+        "[sys_prctl] option: {}, arg2: {:#x}, arg3: {:#x}, arg4: {:#x}, arg5: {:#x}",
+        option,
+        arg2,
+        arg3,
+        arg4,
+        arg5
+    );
+    let task = current_task();
+    match option {
+        PR_SET_PDEATHSIG => {
+            let sig = Sig::from(arg2 as i32);
+            if arg2 != 0 && !sig.is_valid() {
+                return Err(Errno::EINVAL);
+            }
+            task.set_pdeathsig(sig.raw());
+            Ok(0)
+        }
+        PR_GET_PDEATHSIG => {
+            let sig = task.pdeathsig();
+            copy_to_user(arg2 as *mut i32, &sig, 1)?;
+            Ok(0)
+        }
+        PR_GET_DUMPABLE => Ok(task.dumpable() as usize),
+        PR_SET_DUMPABLE => {
+            if arg2 != SUID_DUMP_DISABLE as usize && arg2 != SUID_DUMP_USER as usize {
+                return Err(Errno::EINVAL);
+            }
+            task.set_dumpable(arg2 as u32);
+            Ok(0)
+        }
+        PR_SET_NAME => {
+            // 超过15字节的名字会被截断
+            let name = c_str_to_string(arg2 as *const u8)?;
+            task.set_comm(&name);
+            Ok(0)
+        }
+        PR_GET_NAME => {
+            let mut name = [0u8; TASK_COMM_LEN];
+            let comm = task.comm();
+            let len = comm.len().min(TASK_COMM_LEN - 1);
+            name[..len].copy_from_slice(&comm.as_bytes()[..len]);
+            copy_to_user(arg2 as *mut u8, name.as_ptr(), TASK_COMM_LEN)?;
+            Ok(0)
+        }
+        PR_SET_CHILD_SUBREAPER => {
+            task.set_child_subreaper(arg2 != 0);
+            Ok(0)
+        }
+        PR_GET_CHILD_SUBREAPER => {
+            let subreaper = task.is_child_subreaper() as i32;
+            copy_to_user(arg2 as *mut i32, &subreaper, 1)?;
+            Ok(0)
+        }
+        PR_SET_NO_NEW_PRIVS => {
+            if arg2 != 1 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(Errno::EINVAL);
+            }
+            task.set_no_new_privs();
+            Ok(0)
+        }
+        PR_GET_NO_NEW_PRIVS => {
+            if arg2 != 0 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(Errno::EINVAL);
+            }
+            Ok(task.no_new_privs() as usize)
+        }
+        PR_GET_SECCOMP => Ok(task.seccomp().mode as usize),
+        PR_SET_SECCOMP => match arg2 as u32 {
+            SECCOMP_MODE_STRICT => seccomp_set_mode_strict(),
+            SECCOMP_MODE_FILTER => seccomp_set_mode_filter(0, arg3),
+            _ => Err(Errno::EINVAL),
+        },
+        PR_GET_KEEPCAPS => Ok(task.caps().keep_caps as usize),
+        PR_SET_KEEPCAPS => {
+            if arg2 > 1 {
+                return Err(Errno::EINVAL);
+            }
+            task.op_caps_mut(|caps| caps.keep_caps = arg2 == 1);
+            Ok(0)
+        }
+        PR_CAPBSET_READ => {
+            let cap = CapSet::from_cap(arg2)?;
+            Ok(task.caps().bounding.contains(cap) as usize)
+        }
+        PR_CAPBSET_DROP => {
+            let cap = CapSet::from_cap(arg2)?;
+            if !task.capable(CapSet::SETPCAP) {
+                return Err(Errno::EPERM);
+            }
+            task.op_caps_mut(|caps| caps.bounding.remove(cap));
+            Ok(0)
+        }
+        PR_CAP_AMBIENT => {
+            if arg4 != 0 || arg5 != 0 {
+                return Err(Errno::EINVAL);
+            }
+            if arg2 == PR_CAP_AMBIENT_CLEAR_ALL {
+                if arg3 != 0 {
+                    return Err(Errno::EINVAL);
+                }
+                task.op_caps_mut(|caps| caps.ambient = CapSet::empty());
+                return Ok(0);
+            }
+            let cap = CapSet::from_cap(arg3)?;
+            task.op_caps_mut(|caps| match arg2 {
+                PR_CAP_AMBIENT_IS_SET => Ok(caps.ambient.contains(cap) as usize),
+                PR_CAP_AMBIENT_RAISE => {
+                    // 只能提升同时在permitted和inheritable中的能力
+                    if !caps.permitted.contains(cap) || !caps.inheritable.contains(cap) {
+                        return Err(Errno::EPERM);
+                    }
+                    caps.ambient.insert(cap);
+                    Ok(0)
+                }
+                PR_CAP_AMBIENT_LOWER => {
+                    caps.ambient.remove(cap);
+                    Ok(0)
+                }
+                _ => Err(Errno::EINVAL),
+            })
+        }
+        _ => {
+            log::warn!("[sys_prctl] unsupported option: {}", option);
+            Err(Errno::EINVAL)
+        }
+    }
+}
+/* prctl end */
+
+/* seccomp start */
+pub fn sys_seccomp(op: u32, flags: u32, uargs: usize) -> SyscallRet {
+    log::info!(
+        "[sys_seccomp] op: {}, flags: {:#x}, uargs: {:#x}",
+        op,
+        flags,
+        uargs
+    );
+    match op {
+        SECCOMP_SET_MODE_STRICT => {
+            if flags != 0 || uargs != 0 {
+                return Err(Errno::EINVAL);
+            }
+            seccomp_set_mode_strict()
+        }
+        SECCOMP_SET_MODE_FILTER => seccomp_set_mode_filter(flags, uargs),
+        SECCOMP_GET_ACTION_AVAIL => {
+            if flags != 0 {
+                return Err(Errno::EINVAL);
+            }
+            seccomp_get_action_avail(uargs)
+        }
+        // 不支持user notification
+        SECCOMP_GET_NOTIF_SIZES => Err(Errno::EOPNOTSUPP),
+        _ => Err(Errno::EINVAL),
+    }
+}
+/* seccomp end */
+
+/* namespace start */
+/// unshare() 使调用进程与其他进程解除共享部分执行上下文
+/// CLONE_NEWPID不改变调用者自身所属的pid命名空间, 只影响之后创建的子进程
+pub fn sys_unshare(flags: usize) -> SyscallRet {
+    log::info!("[sys_unshare] flags: {:#x}", flags);
+    // 不支持CLONE_NEWNS: mount/umount没有接入各命名空间的mount树
+    let new_ns_flags =
+        (CloneFlags::CLONE_NEWUTS | CloneFlags::CLONE_NEWIPC | CloneFlags::CLONE_NEWPID).bits();
+    let supported = new_ns_flags
+        | (CloneFlags::CLONE_FILES
+            | CloneFlags::CLONE_FS
+            | CloneFlags::CLONE_THREAD
+            | CloneFlags::CLONE_SIGHAND
+            | CloneFlags::CLONE_VM
+            | CloneFlags::CLONE_SYSVSEM)
+            .bits();
+    if flags as u32 & !supported != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let flags = CloneFlags::from_bits(flags as u32).ok_or(Errno::EINVAL)?;
+    let task = current_task();
+    // 线程组中还有其他线程时不能解除共享线程组, 信号处理和地址空间
+    if flags.intersects(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_SIGHAND | CloneFlags::CLONE_VM)
+        && task.op_thread_group(|tg| tg.len()) > 1
+    {
+        return Err(Errno::EINVAL);
+    }
+    if flags.bits() & new_ns_flags != 0 && !task.capable(CapSet::SYS_ADMIN) {
+        return Err(Errno::EPERM);
+    }
+    let nsproxy = task.nsproxy().copy(&flags)?;
+    task.op_nsproxy_mut(|ns| *ns = nsproxy);
+    if flags.contains(CloneFlags::CLONE_FILES) {
+        task.set_fd_table(FdTable::from_existed_user(&task.fd_table()));
+    }
+    // Todo: CLONE_FS, root/pwd/umask目前不在任务间共享
+    Ok(0)
+}
+
+/// setns() 将调用者加入fd指向的命名空间, fd由打开/proc/<pid>/ns/*得到
+/// nstype为0时不检查命名空间类型
+pub fn sys_setns(fd: usize, nstype: i32) -> SyscallRet {
+    log::info!("[sys_setns] fd: {}, nstype: {:#x}", fd, nstype);
+    let task = current_task();
+    let file = task.fd_table().get_file(fd).ok_or(Errno::EBADF)?;
+    let namespace = file
+        .as_any()
+        .downcast_ref::<NsFile>()
+        .ok_or(Errno::EINVAL)?
+        .namespace();
+    if nstype != 0 && nstype as u32 != namespace.clone_flag().bits() {
+        return Err(Errno::EINVAL);
+    }
+    if !task.capable(CapSet::SYS_ADMIN) {
+        return Err(Errno::EPERM);
+    }
+    match namespace {
+        Namespace::Mnt(mnt_ns) => {
+            // 多线程进程不能切换mount命名空间
+            if task.op_thread_group(|tg| tg.len()) > 1 {
+                return Err(Errno::EINVAL);
+            }
+            task.op_nsproxy_mut(|nsproxy| nsproxy.mnt_ns = mnt_ns);
+        }
+        Namespace::Uts(uts_ns) => task.op_nsproxy_mut(|nsproxy| nsproxy.uts_ns = uts_ns),
+        Namespace::Ipc(ipc_ns) => task.op_nsproxy_mut(|nsproxy| nsproxy.ipc_ns = ipc_ns),
+        Namespace::Pid(pid_ns) => {
+            // 只能进入自身pid命名空间或其后代命名空间
+            if !task.pid_ns().is_ancestor_of(&pid_ns) {
+                return Err(Errno::EINVAL);
+            }
+            task.op_nsproxy_mut(|nsproxy| nsproxy.pid_ns_for_children = pid_ns);
+        }
+    }
+    Ok(0)
+}
+/* namespace end */
@@ -15,8 +15,8 @@ use crate::{
     },
     syscall::errno::Errno,
     task::{
-        add_real_timer, current_task, get_task, remove_timer, rusage::RUsage, update_real_timer,
-        ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL,
+        add_real_timer, capability::CapSet, current_task, namespace::find_task_by_vpid, remove_timer,
+        rusage::RUsage, update_real_timer, ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL,
     },
     time::{config::ClockIdFlags, do_adjtimex, KernelTimex, LAST_TIMEX},
     timer::{ITimerVal, TimeSpec, TimeVal},
@@ -94,28 +94,12 @@ impl Default for Tms {
     }
 }
 
-/// fake uname  
-///
-/// Todo?:
+/// 返回当前任务uts命名空间中的系统信息
 pub fn sys_uname(uts: usize) -> SyscallRet {
     log::info!("[sys_uname] uts: {:#x}", uts);
     let uts = uts as *mut Utsname;
-    //Todo!: check validarity
-    let mut utsname = Utsname::default();
-    //todo:还差其他的
-    let hostnamefile = path_openat("/etc/hostname", OpenFlags::O_CLOEXEC, -100, 0)?;
-    let nodename = hostnamefile.read_all();
-    log::error!("[sys_uname] nodename is {:?}", nodename);
-    if nodename.len() > 0 {
-        utsname.set_nodename(nodename.as_slice());
-    }
-    let domainnamefile = path_openat("/etc/domainname", OpenFlags::O_CLOEXEC, -100, 0)?;
-    let domainname = domainnamefile.read_all();
-    log::error!("[sys_uname] domainname is {:?}", domainname);
-    if domainname.len() > 0 {
-        utsname.set_domainname(domainname.as_slice());
-    }
-    copy_to_user(uts, &utsname as *const Utsname, 1).unwrap();
+    let utsname = *current_task().uts_ns().name.lock();
+    copy_to_user(uts, &utsname as *const Utsname, 1)?;
     Ok(0)
 }
 
@@ -200,30 +184,51 @@ pub fn sys_prlimit64(
     old_limit: *mut RLimit,
 ) -> SyscallRet {
     // 根据tid获取操作的进程
+    let caller = current_task();
     let task = if pid == 0 {
-        current_task()
+        caller.clone()
     } else {
-        get_task(pid).expect("[sys_prlimit64]: invalid pid")
+        find_task_by_vpid(pid).ok_or(Errno::ESRCH)?
     };
-    let resource = Resource::try_from(resource).unwrap();
-    log::error!(
-        "resource: {:?}, new_limit: {:#x}, old_limit: {:#x}",
+    let resource = Resource::try_from(resource).map_err(|_| Errno::EINVAL)?;
+    log::info!(
+        "[sys_prlimit64] pid: {}, resource: {:?}, new_limit: {:#x}, old_limit: {:#x}",
+        pid,
         resource,
         new_limit as usize,
         old_limit as usize
     );
+    // 访问其他进程的限制需要调用者的uid与目标的真实/有效/保存uid(gid同理)都相同, 或者拥有CAP_SYS_RESOURCE
+    if !caller.same_thread_group(&task) {
+        let same_creds = caller.uid() == task.uid()
+            && caller.uid() == task.euid()
+            && caller.uid() == task.suid()
+            && caller.gid() == task.gid()
+            && caller.gid() == task.egid()
+            && caller.gid() == task.sgid();
+        if !same_creds && !caller.capable(CapSet::SYS_RESOURCE) {
+            return Err(Errno::EPERM);
+        }
+    }
+    let cur_rlimit = task.get_rlimit(resource).map_err(|_| Errno::EINVAL)?;
+    // 先检查新的限制, 失败时不写old_limit
+    let mut limit_buf = RLimit::default();
+    if !new_limit.is_null() {
+        copy_from_user(new_limit, &mut limit_buf as *mut RLimit, 1)?;
+        if limit_buf.rlim_cur > limit_buf.rlim_max {
+            return Err(Errno::EINVAL);
+        }
+        // 提高硬限制需要CAP_SYS_RESOURCE
+        if limit_buf.rlim_max > cur_rlimit.rlim_max && !caller.capable(CapSet::SYS_RESOURCE) {
+            return Err(Errno::EPERM);
+        }
+    }
     // 如果old_limit不为NULL, 则将当前的rlimit写入old_limit
     if !old_limit.is_null() {
-        let old_rlimit = task
-            .get_rlimit(resource)
-            .expect("[sys_prlimit64] get rlimit failed");
-        // 这里需要copy_to_user
-        copy_to_user(old_limit, &old_rlimit as *const RLimit, 1).unwrap();
+        copy_to_user(old_limit, &cur_rlimit as *const RLimit, 1)?;
     }
     // 如果new_limit不为NULL, 则将new_limit写入当前的rlimit
     if !new_limit.is_null() {
-        let mut limit_buf = RLimit::default();
-        copy_from_user(new_limit, &mut limit_buf as *mut RLimit, 1)?;
         return task.set_rlimit(resource, &limit_buf);
     }
     Ok(0)
@@ -288,6 +293,9 @@ pub fn sys_clock_settime(clock_id: usize, timespec: *const TimeSpec) -> SyscallR
     copy_from_user(timespec, &mut time as *mut TimeSpec, 1)?;
     match clock_id {
         CLOCK_REALTIME | CLOCK_REALTIME_COARSE => {
+            if !current_task().capable(CapSet::SYS_TIME) {
+                return Err(Errno::EPERM);
+            }
             return Ok(0);
         }
         CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW => {
@@ -465,7 +473,7 @@ pub fn sys_adjtimex(user_timex: *mut KernelTimex) -> SyscallRet {
         return Ok(0);
     }
     //非只读模式下必须root权限
-    if kernel_timex.modes != 0 && task.euid() != 0 {
+    if kernel_timex.modes != 0 && !task.capable(CapSet::SYS_TIME) {
         return Err(Errno::EPERM);
     }
     //保存非0设置的kernel_timex并在只读中返回回去
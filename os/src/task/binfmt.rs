@@ -0,0 +1,396 @@
+//! execve的可执行文件格式
+//!
+//! ELF由`MemorySet::from_elf_lazily`直接加载. `#!`开头的脚本和binfmt_misc注册的格式
+//! 会改写argv后转而执行解释器, 解释器本身也可以是脚本或其他格式, 最多嵌套`BINPRM_MAX_RECURSION`层
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use spin::RwLock;
+
+use crate::{
+    fs::{file::FileOp, file::OpenFlags, namei::path_openat, AT_FDCWD},
+    syscall::errno::Errno,
+};
+
+/// 识别格式时读取的文件头长度
+pub const BINPRM_BUF_SIZE: usize = 256;
+/// 解释器的最大嵌套层数, 超过时返回ELOOP
+pub const BINPRM_MAX_RECURSION: usize = 4;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+/// 没有`#!`的.sh脚本(测试用例中常见)交给busybox sh执行
+const DEFAULT_SHELL: [&str; 2] = ["/musl/busybox", "sh"];
+
+/// binfmt_misc总开关, /proc/sys/fs/binfmt_misc/status
+static BINFMT_MISC_ENABLED: AtomicBool = AtomicBool::new(true);
+/// 注册的格式, 后注册的优先匹配
+static BINFMT_MISC: RwLock<Vec<Arc<BinfmtMisc>>> = RwLock::new(Vec::new());
+
+pub enum BinfmtMatch {
+    /// 按文件头offset处的魔数匹配, 比较前文件内容先与mask按位与
+    Magic {
+        offset: usize,
+        magic: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    /// 按文件名的扩展名匹配(不含'.')
+    Extension(String),
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct BinfmtMiscFlags: u8 {
+        /// P: 保留原来的argv[0]
+        const PRESERVE_ARGV0 = 1 << 0;
+        /// O: 通过AT_EXECFD传递已打开的文件, 未实现
+        const OPEN_BINARY = 1 << 1;
+        /// C: 按被执行文件而非解释器计算凭证, 未实现
+        const CREDENTIALS = 1 << 2;
+        /// F: 注册时就打开解释器
+        const FIX_BINARY = 1 << 3;
+    }
+}
+
+pub struct BinfmtMisc {
+    pub name: String,
+    pub matcher: BinfmtMatch,
+    pub interpreter: String,
+    pub flags: BinfmtMiscFlags,
+    pub enabled: AtomicBool,
+    /// F标志时注册时打开的解释器
+    interp_file: Option<Arc<dyn FileOp>>,
+}
+
+impl BinfmtMisc {
+    fn matches(&self, buf: &[u8], filename: &str) -> bool {
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                let Some(head) = buf.get(*offset..*offset + magic.len()) else {
+                    return false;
+                };
+                head.iter().enumerate().all(|(i, byte)| {
+                    let byte = mask.as_ref().map_or(*byte, |mask| byte & mask[i]);
+                    byte == magic[i]
+                })
+            }
+            BinfmtMatch::Extension(ext) => filename
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.rsplit_once('.'))
+                .is_some_and(|(_, file_ext)| file_ext == ext),
+        }
+    }
+    /// /proc/sys/fs/binfmt_misc/<name>的内容
+    pub fn describe(&self) -> String {
+        let mut info = String::new();
+        info += if self.enabled.load(Ordering::Relaxed) {
+            "enabled\n"
+        } else {
+            "disabled\n"
+        };
+        info += &format!("interpreter {}\n", self.interpreter);
+        info += "flags: ";
+        for (flag, c) in [
+            (BinfmtMiscFlags::PRESERVE_ARGV0, 'P'),
+            (BinfmtMiscFlags::OPEN_BINARY, 'O'),
+            (BinfmtMiscFlags::CREDENTIALS, 'C'),
+            (BinfmtMiscFlags::FIX_BINARY, 'F'),
+        ] {
+            if self.flags.contains(flag) {
+                info.push(c);
+            }
+        }
+        info.push('\n');
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                info += &format!("offset {}\nmagic {}\n", offset, hex(magic));
+                if let Some(mask) = mask {
+                    info += &format!("mask {}\n", hex(mask));
+                }
+            }
+            BinfmtMatch::Extension(ext) => info += &format!("extension .{}\n", ext),
+        }
+        info
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+/// 解析魔数和掩码中的\xHH转义
+fn unescape(field: &str) -> Result<Vec<u8>, Errno> {
+    let bytes = field.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && bytes.get(i + 1) == Some(&b'x') {
+            let hex = field.get(i + 2..i + 4).ok_or(Errno::EINVAL)?;
+            result.push(u8::from_str_radix(hex, 16).map_err(|_| Errno::EINVAL)?);
+            i += 4;
+        } else if bytes[i] == b'\\' && bytes.get(i + 1) == Some(&b'\\') {
+            result.push(b'\\');
+            i += 2;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(result)
+}
+
+/// 解析写入register的一行`:name:type:offset:magic:mask:interpreter:flags`,
+/// 第一个字符是分隔符, 返回注册的名字
+pub fn binfmt_misc_register(line: &str) -> Result<String, Errno> {
+    let line = line.trim_end_matches('\n');
+    let mut chars = line.chars();
+    let delim = chars.next().ok_or(Errno::EINVAL)?;
+    let fields: Vec<&str> = chars.as_str().split(delim).collect();
+    if fields.len() < 6 || fields.len() > 7 {
+        return Err(Errno::EINVAL);
+    }
+    let name = fields[0];
+    if name.is_empty() || name.contains('/') || matches!(name, "." | ".." | "register" | "status") {
+        return Err(Errno::EINVAL);
+    }
+    let matcher = match fields[1] {
+        "M" => {
+            let offset = if fields[2].is_empty() {
+                0
+            } else {
+                fields[2].parse::<usize>().map_err(|_| Errno::EINVAL)?
+            };
+            let magic = unescape(fields[3])?;
+            let mask = if fields[4].is_empty() {
+                None
+            } else {
+                Some(unescape(fields[4])?)
+            };
+            if magic.is_empty()
+                || offset + magic.len() > BINPRM_BUF_SIZE
+                || mask.as_ref().is_some_and(|mask| mask.len() != magic.len())
+            {
+                return Err(Errno::EINVAL);
+            }
+            // 与Linux一致, 魔数先与掩码按位与, 匹配时只比较掩码内的位
+            let magic = match &mask {
+                Some(mask) => magic.iter().zip(mask).map(|(m, k)| m & k).collect(),
+                None => magic,
+            };
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            }
+        }
+        "E" => {
+            let ext = fields[3];
+            if !fields[2].is_empty() || !fields[4].is_empty() || ext.is_empty() || ext.contains('/')
+            {
+                return Err(Errno::EINVAL);
+            }
+            BinfmtMatch::Extension(ext.to_string())
+        }
+        _ => return Err(Errno::EINVAL),
+    };
+    let interpreter = fields[5];
+    if interpreter.is_empty() {
+        return Err(Errno::EINVAL);
+    }
+    let mut flags = BinfmtMiscFlags::empty();
+    for c in fields.get(6).unwrap_or(&"").chars() {
+        flags |= match c {
+            'P' => BinfmtMiscFlags::PRESERVE_ARGV0,
+            'O' => BinfmtMiscFlags::OPEN_BINARY,
+            'C' => BinfmtMiscFlags::CREDENTIALS | BinfmtMiscFlags::OPEN_BINARY,
+            'F' => BinfmtMiscFlags::FIX_BINARY,
+            _ => return Err(Errno::EINVAL),
+        };
+    }
+    if flags.intersects(BinfmtMiscFlags::OPEN_BINARY | BinfmtMiscFlags::CREDENTIALS) {
+        log::warn!("[binfmt_misc] flags O and C are not supported, ignored");
+    }
+    let interp_file = if flags.contains(BinfmtMiscFlags::FIX_BINARY) {
+        Some(path_openat(interpreter, OpenFlags::empty(), AT_FDCWD, 0)?)
+    } else {
+        None
+    };
+
+    let mut entries = BINFMT_MISC.write();
+    if entries.iter().any(|entry| entry.name == name) {
+        return Err(Errno::EEXIST);
+    }
+    log::info!(
+        "[binfmt_misc] register {}, interpreter: {}",
+        name,
+        interpreter
+    );
+    entries.push(Arc::new(BinfmtMisc {
+        name: name.to_string(),
+        matcher,
+        interpreter: interpreter.to_string(),
+        flags,
+        enabled: AtomicBool::new(true),
+        interp_file,
+    }));
+    Ok(name.to_string())
+}
+
+pub fn binfmt_misc_lookup(name: &str) -> Option<Arc<BinfmtMisc>> {
+    BINFMT_MISC
+        .read()
+        .iter()
+        .find(|entry| entry.name == name)
+        .cloned()
+}
+
+/// 删除一个格式, 返回是否存在
+pub fn binfmt_misc_unregister(name: &str) -> bool {
+    let mut entries = BINFMT_MISC.write();
+    let len = entries.len();
+    entries.retain(|entry| entry.name != name);
+    entries.len() != len
+}
+
+/// 删除所有格式, 返回被删除的名字
+pub fn binfmt_misc_clear() -> Vec<String> {
+    BINFMT_MISC
+        .write()
+        .drain(..)
+        .map(|entry| entry.name.clone())
+        .collect()
+}
+
+pub fn binfmt_misc_enabled() -> bool {
+    BINFMT_MISC_ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn binfmt_misc_set_enabled(enabled: bool) {
+    BINFMT_MISC_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// 解析`#!interpreter [arg]`, 返回解释器和可选的一个参数, 参数中的空白不再拆分
+fn parse_shebang(buf: &[u8]) -> Result<Option<(String, Option<String>)>, Errno> {
+    if !buf.starts_with(b"#!") {
+        return Ok(None);
+    }
+    let line = &buf[2..];
+    let line = match line.iter().position(|byte| *byte == b'\n') {
+        Some(end) => &line[..end],
+        None => line,
+    };
+    let line = core::str::from_utf8(line).map_err(|_| Errno::ENOEXEC)?;
+    let line = line.trim_matches(|c| c == ' ' || c == '\t' || c == '\r');
+    let (interp, arg) = match line.split_once([' ', '\t']) {
+        Some((interp, arg)) => (interp, Some(arg.trim_matches([' ', '\t']))),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(Errno::ENOEXEC);
+    }
+    Ok(Some((
+        interp.to_string(),
+        arg.filter(|arg| !arg.is_empty()).map(|arg| arg.to_string()),
+    )))
+}
+
+/// 确定execve实际加载的ELF文件, 按脚本和binfmt_misc的规则改写`argv`
+/// `filename`是传给execve的路径, 返回ELF文件和其绝对路径(/proc/self/exe)
+pub fn search_binary_handler(
+    mut file: Arc<dyn FileOp>,
+    filename: &str,
+    argv: &mut Vec<String>,
+) -> Result<(Arc<dyn FileOp>, String), Errno> {
+    let mut filename = filename.to_string();
+    for _ in 0..=BINPRM_MAX_RECURSION {
+        let mut buf = vec![0u8; BINPRM_BUF_SIZE];
+        let len = file.pread(&mut buf, 0)?;
+        buf.truncate(len);
+        if buf.starts_with(&ELF_MAGIC) {
+            let exe_path = file.get_path().dentry.absolute_path.clone();
+            return Ok((file, exe_path));
+        }
+
+        // 新的argv = 解释器 [参数] 文件名 原来的argv[1..]
+        let mut prepend = Vec::new();
+        let interp_file;
+        if let Some((interp, arg)) = parse_shebang(&buf)? {
+            log::info!("[binfmt_script] {}: #!{} {:?}", filename, interp, arg);
+            interp_file = Some(path_openat(&interp, OpenFlags::empty(), AT_FDCWD, 0)?);
+            prepend.push(interp.clone());
+            prepend.extend(arg);
+            prepend.push(filename.clone());
+            if !argv.is_empty() {
+                argv.remove(0);
+            }
+            filename = interp;
+        } else if let Some(entry) = binfmt_misc_enabled()
+            .then(|| {
+                BINFMT_MISC
+                    .read()
+                    .iter()
+                    .rev()
+                    .find(|entry| {
+                        entry.enabled.load(Ordering::Relaxed) && entry.matches(&buf, &filename)
+                    })
+                    .cloned()
+            })
+            .flatten()
+        {
+            log::info!(
+                "[binfmt_misc] {}: handled by {}, interpreter: {}",
+                filename,
+                entry.name,
+                entry.interpreter
+            );
+            prepend.push(entry.interpreter.clone());
+            prepend.push(filename.clone());
+            if !entry.flags.contains(BinfmtMiscFlags::PRESERVE_ARGV0) && !argv.is_empty() {
+                argv.remove(0);
+            }
+            interp_file = entry.interp_file.clone();
+            filename = entry.interpreter.clone();
+        } else if filename.ends_with(".sh") {
+            // 兼容没有#!的测试脚本
+            log::warn!("[execve] {} has no #!, run with busybox sh", filename);
+            prepend.extend(DEFAULT_SHELL.iter().map(|s| s.to_string()));
+            prepend.push(filename.clone());
+            if !argv.is_empty() {
+                argv.remove(0);
+            }
+            interp_file = None;
+            filename = DEFAULT_SHELL[0].to_string();
+        } else {
+            log::error!("[execve] {}: unknown executable format", filename);
+            return Err(Errno::ENOEXEC);
+        }
+        argv.splice(0..0, prepend);
+        file = match interp_file {
+            Some(interp_file) => interp_file,
+            None => path_openat(&filename, OpenFlags::empty(), AT_FDCWD, 0)?,
+        };
+    }
+    log::error!("[execve] too many levels of interpreters");
+    Err(Errno::ELOOP)
+}
@@ -0,0 +1,192 @@
+//! 进程能力(capabilities)
+//!
+//! 每个任务持有permitted/effective/inheritable/bounding/ambient五个能力集,
+//! 特权操作检查具体的能力位而不是euid是否为0.
+//! 能力集在execve和setuid系列调用时按照Linux的规则变换(root的传统语义由此模拟)
+use bitflags::bitflags;
+
+use crate::syscall::errno::Errno;
+
+/// 当前支持的最大能力编号
+pub const CAP_LAST_CAP: u32 = 40;
+
+/// capget/capset的版本号
+pub const LINUX_CAPABILITY_VERSION_1: u32 = 0x19980330;
+pub const LINUX_CAPABILITY_VERSION_2: u32 = 0x20071026;
+pub const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CapSet: u64 {
+        const CHOWN = 1 << 0;
+        const DAC_OVERRIDE = 1 << 1;
+        const DAC_READ_SEARCH = 1 << 2;
+        const FOWNER = 1 << 3;
+        const FSETID = 1 << 4;
+        const KILL = 1 << 5;
+        const SETGID = 1 << 6;
+        const SETUID = 1 << 7;
+        const SETPCAP = 1 << 8;
+        const LINUX_IMMUTABLE = 1 << 9;
+        const NET_BIND_SERVICE = 1 << 10;
+        const NET_BROADCAST = 1 << 11;
+        const NET_ADMIN = 1 << 12;
+        const NET_RAW = 1 << 13;
+        const IPC_LOCK = 1 << 14;
+        const IPC_OWNER = 1 << 15;
+        const SYS_MODULE = 1 << 16;
+        const SYS_RAWIO = 1 << 17;
+        const SYS_CHROOT = 1 << 18;
+        const SYS_PTRACE = 1 << 19;
+        const SYS_PACCT = 1 << 20;
+        const SYS_ADMIN = 1 << 21;
+        const SYS_BOOT = 1 << 22;
+        const SYS_NICE = 1 << 23;
+        const SYS_RESOURCE = 1 << 24;
+        const SYS_TIME = 1 << 25;
+        const SYS_TTY_CONFIG = 1 << 26;
+        const MKNOD = 1 << 27;
+        const LEASE = 1 << 28;
+        const AUDIT_WRITE = 1 << 29;
+        const AUDIT_CONTROL = 1 << 30;
+        const SETFCAP = 1 << 31;
+        const MAC_OVERRIDE = 1 << 32;
+        const MAC_ADMIN = 1 << 33;
+        const SYSLOG = 1 << 34;
+        const WAKE_ALARM = 1 << 35;
+        const BLOCK_SUSPEND = 1 << 36;
+        const AUDIT_READ = 1 << 37;
+        const PERFMON = 1 << 38;
+        const BPF = 1 << 39;
+        const CHECKPOINT_RESTORE = 1 << 40;
+    }
+}
+
+impl CapSet {
+    /// fsuid在0与非0之间切换时受影响的能力
+    pub const FS_MASK: CapSet = CapSet::CHOWN
+        .union(CapSet::DAC_OVERRIDE)
+        .union(CapSet::DAC_READ_SEARCH)
+        .union(CapSet::FOWNER)
+        .union(CapSet::FSETID)
+        .union(CapSet::LINUX_IMMUTABLE)
+        .union(CapSet::MKNOD)
+        .union(CapSet::MAC_OVERRIDE);
+
+    /// 将能力编号转换为能力集
+    pub fn from_cap(cap: usize) -> Result<CapSet, Errno> {
+        if cap > CAP_LAST_CAP as usize {
+            return Err(Errno::EINVAL);
+        }
+        Ok(CapSet::from_bits_truncate(1 << cap))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    pub permitted: CapSet,
+    pub effective: CapSet,
+    pub inheritable: CapSet,
+    pub bounding: CapSet,
+    pub ambient: CapSet,
+    /// PR_SET_KEEPCAPS, setuid离开root时保留permitted
+    pub keep_caps: bool,
+}
+
+impl Capabilities {
+    /// root进程的初始能力
+    pub fn root() -> Self {
+        Capabilities {
+            permitted: CapSet::all(),
+            effective: CapSet::all(),
+            inheritable: CapSet::empty(),
+            bounding: CapSet::all(),
+            ambient: CapSet::empty(),
+            keep_caps: false,
+        }
+    }
+
+    /// setuid/setreuid/setresuid之后的能力变换(cap_emulate_setxuid)
+    /// old/new为(ruid, euid, suid)
+    pub fn fix_setuid(&mut self, old: (u32, u32, u32), new: (u32, u32, u32)) {
+        let old_root = old.0 == 0 || old.1 == 0 || old.2 == 0;
+        let new_root = new.0 == 0 || new.1 == 0 || new.2 == 0;
+        // 所有uid都离开了0
+        if old_root && !new_root && !self.keep_caps {
+            self.permitted = CapSet::empty();
+            self.effective = CapSet::empty();
+            self.ambient = CapSet::empty();
+        }
+        if old.1 == 0 && new.1 != 0 {
+            self.effective = CapSet::empty();
+        }
+        if old.1 != 0 && new.1 == 0 {
+            self.effective = self.permitted;
+        }
+    }
+
+    /// setfsuid之后的能力变换
+    pub fn fix_setfsuid(&mut self, old_fsuid: u32, new_fsuid: u32) {
+        if old_fsuid == 0 && new_fsuid != 0 {
+            self.effective.remove(CapSet::FS_MASK);
+        }
+        if old_fsuid != 0 && new_fsuid == 0 {
+            self.effective
+                .insert(self.permitted.intersection(CapSet::FS_MASK));
+        }
+    }
+
+    /// execve时的能力变换, ruid/euid为execve后的值
+    /// 没有文件能力, root执行任何文件都视为拥有全部的文件能力
+    pub fn fix_execve(&mut self, ruid: u32, euid: u32, id_changed: bool) {
+        if id_changed {
+            // set-user-ID/set-group-ID程序清除ambient
+            self.ambient = CapSet::empty();
+        }
+        if ruid == 0 || euid == 0 {
+            self.permitted = self.bounding.union(self.inheritable);
+        } else {
+            self.permitted = self.ambient;
+        }
+        self.effective = if euid == 0 {
+            self.permitted
+        } else {
+            self.ambient
+        };
+        self.ambient &= self.permitted & self.inheritable;
+        self.keep_caps = false;
+    }
+
+    /// capset的合法性检查, 通过后更新三个能力集
+    pub fn set(
+        &mut self,
+        effective: CapSet,
+        permitted: CapSet,
+        inheritable: CapSet,
+    ) -> Result<(), Errno> {
+        // 新的inheritable只能来自原有的inheritable和permitted, 除非拥有CAP_SETPCAP
+        if !self.effective.contains(CapSet::SETPCAP)
+            && !(self.inheritable | self.permitted).contains(inheritable)
+        {
+            return Err(Errno::EPERM);
+        }
+        // inheritable不能超出bounding的限制
+        if !(self.inheritable | self.bounding).contains(inheritable) {
+            return Err(Errno::EPERM);
+        }
+        // permitted只能减少
+        if !self.permitted.contains(permitted) {
+            return Err(Errno::EPERM);
+        }
+        // effective必须是permitted的子集
+        if !permitted.contains(effective) {
+            return Err(Errno::EPERM);
+        }
+        self.effective = effective;
+        self.permitted = permitted;
+        self.inheritable = inheritable;
+        // ambient必须同时在permitted和inheritable中
+        self.ambient &= permitted & inheritable;
+        Ok(())
+    }
+}
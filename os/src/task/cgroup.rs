@@ -0,0 +1,356 @@
+//! cgroup v2
+//!
+//! 只有一个层级, 所有控制器总是启用. memory控制器按页帧计费, 超过memory.max时在cgroup内
+//! 触发OOM; pids控制器在`kernel_clone`时计数; cpu控制器按cpu.weight在cgroup之间分配CPU时间,
+//! cpu.max的配额在一个周期内用完后, 该cgroup及其后代的任务不再被调度直到下一个周期
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+use alloc::{
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+
+use crate::{mm::oom::mem_cgroup_out_of_memory, mutex::SpinNoIrqLock, syscall::errno::Errno};
+
+use super::Tid;
+
+/// 可用的控制器, 顺序与cgroup.controllers中一致
+pub const CGROUP_CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+
+/// memory.max, pids.max和cpu.max中的"max"
+pub const CGROUP_MAX: usize = usize::MAX;
+
+pub const CPU_WEIGHT_MIN: usize = 1;
+pub const CPU_WEIGHT_DFL: usize = 100;
+pub const CPU_WEIGHT_MAX: usize = 10000;
+/// cpu.max的默认周期(us)
+pub const CPU_PERIOD_DFL: usize = 100_000;
+pub const CPU_PERIOD_MIN: usize = 1_000;
+pub const CPU_PERIOD_MAX: usize = 1_000_000;
+pub const CPU_QUOTA_MIN: usize = 1_000;
+
+/// 重新变为可运行的cgroup的vruntime最多落后min_vruntime的时间(us), 避免长时间睡眠后独占CPU
+const SCHED_LATENCY_US: usize = 6_000;
+
+lazy_static! {
+    pub static ref CGROUP_ROOT: Arc<Cgroup> = Arc::new(Cgroup::new(String::new(), None));
+}
+
+/// 根cgroup以外的cgroup数量, 为0时调度器和内存计费走快速路径
+static NR_CGROUPS: AtomicUsize = AtomicUsize::new(0);
+/// 被调度的cgroup中最小的vruntime, 单调递增
+static MIN_VRUNTIME: AtomicUsize = AtomicUsize::new(0);
+
+pub fn nr_cgroups() -> usize {
+    NR_CGROUPS.load(Ordering::Relaxed)
+}
+
+pub struct Cgroup {
+    name: String,
+    parent: Option<Arc<Cgroup>>,
+    children: SpinNoIrqLock<BTreeMap<String, Arc<Cgroup>>>,
+    /// 直接属于该cgroup的任务
+    tasks: SpinNoIrqLock<BTreeSet<Tid>>,
+    /// 已经被rmdir, 剩余的引用来自还没有释放的页帧
+    removed: AtomicBool,
+    /// cgroup.subtree_control, 按位对应CGROUP_CONTROLLERS
+    subtree_control: AtomicU8,
+    pub memory: MemoryCounter,
+    pub pids: PidsCounter,
+    cpu: SpinNoIrqLock<CpuState>,
+}
+
+/// 单位都是页
+pub struct MemoryCounter {
+    pub max: AtomicUsize,
+    pub current: AtomicUsize,
+    pub peak: AtomicUsize,
+    /// memory.events: 用量达到memory.max的次数
+    pub events_max: AtomicUsize,
+    /// memory.events: 触发OOM的次数
+    pub events_oom: AtomicUsize,
+    /// memory.events: 因OOM被杀死的进程数
+    pub events_oom_kill: AtomicUsize,
+}
+
+pub struct PidsCounter {
+    pub max: AtomicUsize,
+    pub current: AtomicUsize,
+    /// pids.events: 因超过pids.max而fork失败的次数
+    pub events_max: AtomicUsize,
+}
+
+pub struct CpuState {
+    pub weight: usize,
+    /// 每个周期内可用的CPU时间(us), CGROUP_MAX表示不限制
+    pub quota: usize,
+    pub period: usize,
+    pub usage_usec: usize,
+    /// 当前周期的开始时间和已使用的时间(us)
+    period_start: usize,
+    period_usage: usize,
+    throttled_in_period: bool,
+    pub nr_periods: usize,
+    pub nr_throttled: usize,
+    /// 按权重缩放后的CPU时间, 调度器优先选择vruntime最小的cgroup中的任务
+    vruntime: usize,
+}
+
+impl Cgroup {
+    fn new(name: String, parent: Option<Arc<Cgroup>>) -> Self {
+        Self {
+            name,
+            parent,
+            children: SpinNoIrqLock::new(BTreeMap::new()),
+            tasks: SpinNoIrqLock::new(BTreeSet::new()),
+            removed: AtomicBool::new(false),
+            subtree_control: AtomicU8::new(0),
+            memory: MemoryCounter {
+                max: AtomicUsize::new(CGROUP_MAX),
+                current: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                events_max: AtomicUsize::new(0),
+                events_oom: AtomicUsize::new(0),
+                events_oom_kill: AtomicUsize::new(0),
+            },
+            pids: PidsCounter {
+                max: AtomicUsize::new(CGROUP_MAX),
+                current: AtomicUsize::new(0),
+                events_max: AtomicUsize::new(0),
+            },
+            cpu: SpinNoIrqLock::new(CpuState {
+                weight: CPU_WEIGHT_DFL,
+                quota: CGROUP_MAX,
+                period: CPU_PERIOD_DFL,
+                usage_usec: 0,
+                period_start: 0,
+                period_usage: 0,
+                throttled_in_period: false,
+                nr_periods: 0,
+                nr_throttled: 0,
+                // 新建的cgroup从当前的min_vruntime开始, 不会因为之前没有运行而独占CPU
+                vruntime: MIN_VRUNTIME.load(Ordering::Relaxed),
+            }),
+        }
+    }
+    pub fn is_root(&self) -> bool {
+        self.parent.is_none()
+    }
+    /// 相对于cgroup根的路径, 根cgroup为"/"
+    pub fn path(&self) -> String {
+        match &self.parent {
+            None => "/".to_string(),
+            Some(parent) if parent.is_root() => format!("/{}", self.name),
+            Some(parent) => format!("{}/{}", parent.path(), self.name),
+        }
+    }
+    /// 按相对路径查找后代cgroup, 空路径返回自身
+    pub fn lookup(self: &Arc<Self>, path: &str) -> Option<Arc<Cgroup>> {
+        let mut cgroup = self.clone();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let child = cgroup.children.lock().get(name).cloned()?;
+            cgroup = child;
+        }
+        Some(cgroup)
+    }
+    pub fn mkdir(self: &Arc<Self>, name: &str) -> Result<Arc<Cgroup>, Errno> {
+        let mut children = self.children.lock();
+        if children.contains_key(name) {
+            return Err(Errno::EEXIST);
+        }
+        let cgroup = Arc::new(Cgroup::new(name.to_string(), Some(self.clone())));
+        children.insert(name.to_string(), cgroup.clone());
+        NR_CGROUPS.fetch_add(1, Ordering::Relaxed);
+        Ok(cgroup)
+    }
+    /// 还有任务或子cgroup时返回EBUSY
+    pub fn rmdir(&self) -> Result<(), Errno> {
+        let Some(parent) = &self.parent else {
+            return Err(Errno::EBUSY);
+        };
+        if !self.tasks.lock().is_empty() || !self.children.lock().is_empty() {
+            return Err(Errno::EBUSY);
+        }
+        parent.children.lock().remove(&self.name);
+        self.removed.store(true, Ordering::Relaxed);
+        NR_CGROUPS.fetch_sub(1, Ordering::Relaxed);
+        Ok(())
+    }
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Relaxed)
+    }
+    /// 直接属于该cgroup的任务
+    pub fn tasks(&self) -> Vec<Tid> {
+        self.tasks.lock().iter().copied().collect()
+    }
+    /// 该cgroup及其后代中的任务
+    pub fn subtree_tasks(&self) -> Vec<Tid> {
+        let mut tasks = self.tasks();
+        let children: Vec<Arc<Cgroup>> = self.children.lock().values().cloned().collect();
+        for child in children {
+            tasks.extend(child.subtree_tasks());
+        }
+        tasks
+    }
+    pub fn attach(&self, tid: Tid) {
+        self.tasks.lock().insert(tid);
+    }
+    pub fn detach(&self, tid: Tid) {
+        self.tasks.lock().remove(&tid);
+    }
+    pub fn subtree_control(&self) -> u8 {
+        self.subtree_control.load(Ordering::Relaxed)
+    }
+    pub fn set_subtree_control(&self, mask: u8) {
+        self.subtree_control.store(mask, Ordering::Relaxed);
+    }
+}
+
+/*********************************** pids ***********************************/
+impl Cgroup {
+    /// fork前由`kernel_clone`调用, 任意一级超过pids.max时返回EAGAIN
+    pub fn try_charge_pids(self: &Arc<Self>) -> Result<(), Errno> {
+        let mut cgroup = Some(self);
+        while let Some(cg) = cgroup {
+            if cg.pids.current.fetch_add(1, Ordering::Relaxed)
+                >= cg.pids.max.load(Ordering::Relaxed)
+            {
+                cg.pids.events_max.fetch_add(1, Ordering::Relaxed);
+                // 回滚已经计数的各级
+                let mut charged = Some(self);
+                while let Some(c) = charged {
+                    c.pids.current.fetch_sub(1, Ordering::Relaxed);
+                    if Arc::ptr_eq(c, cg) {
+                        break;
+                    }
+                    charged = c.parent.as_ref();
+                }
+                return Err(Errno::EAGAIN);
+            }
+            cgroup = cg.parent.as_ref();
+        }
+        Ok(())
+    }
+    /// 迁移任务时不检查pids.max
+    pub fn charge_pids(self: &Arc<Self>) {
+        let mut cgroup = Some(self);
+        while let Some(cg) = cgroup {
+            cg.pids.current.fetch_add(1, Ordering::Relaxed);
+            cgroup = cg.parent.as_ref();
+        }
+    }
+    pub fn uncharge_pids(self: &Arc<Self>) {
+        let mut cgroup = Some(self);
+        while let Some(cg) = cgroup {
+            cg.pids.current.fetch_sub(1, Ordering::Relaxed);
+            cgroup = cg.parent.as_ref();
+        }
+    }
+}
+
+/*********************************** memory ***********************************/
+impl Cgroup {
+    /// 分配页帧后计费, 任意一级超过memory.max时在该cgroup内触发OOM
+    /// 分配总是成功, 超出的部分由被杀死的进程退出后归还
+    pub fn charge_memory(self: &Arc<Self>, nr_pages: usize) {
+        let mut over_limit = None;
+        let mut cgroup = Some(self);
+        while let Some(cg) = cgroup {
+            let current = cg.memory.current.fetch_add(nr_pages, Ordering::Relaxed) + nr_pages;
+            cg.memory.peak.fetch_max(current, Ordering::Relaxed);
+            if over_limit.is_none() && current > cg.memory.max.load(Ordering::Relaxed) {
+                over_limit = Some(cg.clone());
+            }
+            cgroup = cg.parent.as_ref();
+        }
+        if let Some(memcg) = over_limit {
+            memcg.memory.events_max.fetch_add(1, Ordering::Relaxed);
+            memcg.memory.events_oom.fetch_add(1, Ordering::Relaxed);
+            if mem_cgroup_out_of_memory(&memcg, nr_pages) {
+                memcg.memory.events_oom_kill.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+    pub fn uncharge_memory(self: &Arc<Self>, nr_pages: usize) {
+        let mut cgroup = Some(self);
+        while let Some(cg) = cgroup {
+            cg.memory.current.fetch_sub(nr_pages, Ordering::Relaxed);
+            cgroup = cg.parent.as_ref();
+        }
+    }
+}
+
+/*********************************** cpu ***********************************/
+impl CpuState {
+    /// 周期结束时开始新的周期
+    fn refresh_period(&mut self, now: usize) {
+        if now.saturating_sub(self.period_start) >= self.period {
+            if self.quota != CGROUP_MAX {
+                self.nr_periods += 1;
+            }
+            self.period_start = now;
+            self.period_usage = 0;
+            self.throttled_in_period = false;
+        }
+    }
+}
+
+impl Cgroup {
+    pub fn op_cpu<T>(&self, f: impl FnOnce(&mut CpuState) -> T) -> T {
+        f(&mut self.cpu.lock())
+    }
+    /// 任务被换下CPU时记录其运行的时间(us)
+    pub fn charge_cpu(self: &Arc<Self>, delta_us: usize, now: usize) {
+        {
+            let mut cpu = self.cpu.lock();
+            cpu.vruntime += delta_us * CPU_WEIGHT_DFL / cpu.weight;
+        }
+        let mut cgroup = Some(self);
+        while let Some(cg) = cgroup {
+            let mut cpu = cg.cpu.lock();
+            cpu.usage_usec += delta_us;
+            cpu.refresh_period(now);
+            cpu.period_usage += delta_us;
+            drop(cpu);
+            cgroup = cg.parent.as_ref();
+        }
+    }
+    /// 该cgroup或任意祖先在当前周期内用完了cpu.max的配额
+    pub fn cpu_throttled(self: &Arc<Self>, now: usize) -> bool {
+        let mut cgroup = Some(self);
+        while let Some(cg) = cgroup {
+            let mut cpu = cg.cpu.lock();
+            if cpu.quota != CGROUP_MAX {
+                cpu.refresh_period(now);
+                if cpu.period_usage >= cpu.quota {
+                    if !cpu.throttled_in_period {
+                        cpu.throttled_in_period = true;
+                        cpu.nr_throttled += 1;
+                    }
+                    return true;
+                }
+            }
+            drop(cpu);
+            cgroup = cg.parent.as_ref();
+        }
+        false
+    }
+    /// 调度时使用的vruntime, 落后min_vruntime太多时先追上
+    pub fn sched_vruntime(&self) -> usize {
+        let floor = MIN_VRUNTIME
+            .load(Ordering::Relaxed)
+            .saturating_sub(SCHED_LATENCY_US);
+        let mut cpu = self.cpu.lock();
+        cpu.vruntime = cpu.vruntime.max(floor);
+        cpu.vruntime
+    }
+}
+
+/// 调度器选中vruntime为`vruntime`的cgroup时更新min_vruntime
+pub fn update_min_vruntime(vruntime: usize) {
+    MIN_VRUNTIME.fetch_max(vruntime, Ordering::Relaxed);
+}
@@ -32,6 +32,15 @@ impl TaskContext {
             satp: satp,
         }
     }
+    /// 内核线程的初始TaskContext, 第一次被调度时`__switch`直接返回到entry
+    pub fn kthread_init_task_context(entry: usize, tp: usize, satp: usize) -> Self {
+        Self {
+            ra: entry,
+            tp: tp,
+            s: [0; 12],
+            satp: satp,
+        }
+    }
 }
 
 #[cfg(target_arch = "riscv64")]
@@ -73,6 +82,15 @@ impl TaskContext {
             pgdl: pgdl_ppn << PAGE_SIZE_BITS,
         }
     }
+    /// 内核线程的初始TaskContext, 第一次被调度时`__switch`直接返回到entry
+    pub fn kthread_init_task_context(entry: usize, tp: usize, pgdl_ppn: usize) -> Self {
+        Self {
+            ra: entry,
+            tp: tp,
+            s: [0; 12],
+            pgdl: pgdl_ppn << PAGE_SIZE_BITS,
+        }
+    }
 }
 
 // 向指定任务内核栈中保存当前task_cx
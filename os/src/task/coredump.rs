@@ -0,0 +1,538 @@
+//! 进程因默认动作为core的信号终止时, 生成ELF格式的core文件
+//!
+//! 文件布局: ELF头, 程序头(PT_NOTE + 每个区域一个PT_LOAD), notes, 页对齐的段数据
+//! 不在内存中的页(lazy allocation)留作文件空洞
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+use spin::RwLock;
+
+use super::{aux::AuxHeader, Task, SUID_DUMP_DISABLE};
+use crate::{
+    arch::{
+        config::{ELF_MACHINE, PAGE_SIZE, PAGE_SIZE_BITS},
+        trap::context::{get_trap_context, ELF_NGREG},
+    },
+    ext4::inode::{S_IFMT, S_IFREG},
+    fs::{
+        file::{File, FileOp, OpenFlags},
+        namei::path_openat,
+        uapi::Resource,
+        AT_FDCWD,
+    },
+    mm::{MapAdvice, MapPermission, MapType, PhysAddr, VirtAddr},
+    signal::Sig,
+    timer::{TimeSpec, TimeVal},
+};
+
+/// core_pattern的最大长度
+pub const CORENAME_MAX_SIZE: usize = 128;
+
+lazy_static! {
+    /// /proc/sys/kernel/core_pattern
+    pub static ref CORE_PATTERN: RwLock<String> = RwLock::new(String::from("core"));
+}
+/// /proc/sys/kernel/core_uses_pid, 非0且core_pattern中没有%p时在文件名后追加".pid"
+pub static CORE_USES_PID: AtomicUsize = AtomicUsize::new(0);
+
+const ET_CORE: u16 = 4;
+const EV_CURRENT: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x4649_4c45;
+
+#[repr(C)]
+struct Elf64Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[repr(C)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+/// struct elf_prstatus, 填充字节显式写出, 保证转为字节时没有未初始化内存
+#[repr(C)]
+struct ElfPrstatus {
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+    pr_cursig: i16,
+    _pad0: u16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    /// utime, stime, cutime, cstime, 每个都是struct timeval
+    pr_times: [[usize; 2]; 4],
+    pr_reg: [usize; ELF_NGREG],
+    pr_fpvalid: i32,
+    _pad1: u32,
+}
+
+/// struct elf_prpsinfo
+#[repr(C)]
+struct ElfPrpsinfo {
+    pr_state: u8,
+    pr_sname: u8,
+    pr_zomb: u8,
+    pr_nice: i8,
+    _pad0: u32,
+    pr_flag: u64,
+    pr_uid: u32,
+    pr_gid: u32,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_fname: [u8; 16],
+    pr_psargs: [u8; 80],
+}
+
+/// 一个PT_LOAD段
+struct CoreSegment {
+    start: usize,
+    end: usize,
+    flags: u32,
+    /// 只读的文件映射可以从文件恢复, 只记录在NT_FILE中, 不转储内容
+    dump: bool,
+}
+
+/// 在其他线程退出前准备好的core dump
+pub struct CoreDump {
+    corename: String,
+    limit: usize,
+    /// 各线程的NT_PRSTATUS, 触发信号的线程在前
+    prstatus: Vec<ElfPrstatus>,
+}
+
+fn as_bytes<T>(val: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(val as *const T as *const u8, size_of::<T>()) }
+}
+
+fn timeval(tv: TimeVal) -> [usize; 2] {
+    [tv.sec, tv.usec]
+}
+
+fn push_note(notes: &mut Vec<u8>, n_type: u32, desc: &[u8]) {
+    // name为"CORE\0", 按4字节对齐
+    notes.extend_from_slice(&5u32.to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&n_type.to_le_bytes());
+    notes.extend_from_slice(b"CORE\0\0\0\0");
+    notes.extend_from_slice(desc);
+    notes.resize(notes.len().next_multiple_of(4), 0);
+}
+
+fn ppid_of(task: &Arc<Task>) -> usize {
+    task.op_parent(|parent| {
+        parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(0, |parent| task.vpid_of(parent.tgid()))
+    })
+}
+
+/// 按照core_pattern生成文件名, 支持%p %P %i %I %u %g %d %s %t %h %e %E %c %%
+fn format_corename(task: &Arc<Task>, sig: Sig, limit: usize) -> String {
+    let pattern = CORE_PATTERN.read().clone();
+    let mut corename = String::new();
+    let mut pid_in_pattern = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            corename.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => corename.push('%'),
+            Some('p') => {
+                pid_in_pattern = true;
+                corename += &task.vpid_of(task.tgid()).to_string();
+            }
+            Some('P') => corename += &task.tgid().to_string(),
+            Some('i') => corename += &task.vpid_of(task.tid()).to_string(),
+            Some('I') => corename += &task.tid().to_string(),
+            Some('u') => corename += &task.uid().to_string(),
+            Some('g') => corename += &task.gid().to_string(),
+            Some('d') => corename += &task.dumpable().to_string(),
+            Some('s') => corename += &sig.raw().to_string(),
+            Some('t') => corename += &TimeSpec::new_wall_time().sec.to_string(),
+            Some('c') => corename += &limit.to_string(),
+            Some('h') => {
+                let nodename = task.uts_ns().name.lock().nodename;
+                let len = nodename
+                    .iter()
+                    .position(|&b| b == 0)
+                    .unwrap_or(nodename.len());
+                corename += &String::from_utf8_lossy(&nodename[..len]).replace('/', "!");
+            }
+            Some('e') => corename += &task.comm().replace('/', "!"),
+            Some('E') => corename += &task.exe_path().replace('/', "!"),
+            // 未知的格式说明符被丢弃
+            _ => {}
+        }
+    }
+    if CORE_USES_PID.load(Ordering::Relaxed) != 0 && !pid_in_pattern {
+        corename += &format!(".{}", task.vpid_of(task.tgid()));
+    }
+    corename
+}
+
+fn fill_prstatus(task: &Arc<Task>, thread: &Arc<Task>, sig: Sig) -> ElfPrstatus {
+    let (pending, mask) =
+        thread.op_sig_pending_mut(|pending| (pending.pending.bits(), pending.mask.bits()));
+    let (utime, stime) = thread.time_stat().thread_us_time();
+    let (cutime, cstime) = thread.time_stat().child_user_system_time();
+    ElfPrstatus {
+        si_signo: sig.raw(),
+        si_code: 0,
+        si_errno: 0,
+        pr_cursig: sig.raw() as i16,
+        _pad0: 0,
+        pr_sigpend: pending,
+        pr_sighold: mask,
+        pr_pid: task.vpid_of(thread.tid()) as i32,
+        pr_ppid: ppid_of(task) as i32,
+        pr_pgrp: task.pgid() as i32,
+        // 未实现会话, 与setsid的返回值保持一致
+        pr_sid: task.vpid_of(task.tgid()) as i32,
+        pr_times: [
+            timeval(utime),
+            timeval(stime),
+            timeval(cutime),
+            timeval(cstime),
+        ],
+        pr_reg: get_trap_context(thread).elf_gregs(),
+        pr_fpvalid: 0,
+        _pad1: 0,
+    }
+}
+
+fn fill_prpsinfo(task: &Arc<Task>) -> ElfPrpsinfo {
+    let mut pr_fname = [0u8; 16];
+    let comm = task.comm();
+    let len = comm.len().min(pr_fname.len() - 1);
+    pr_fname[..len].copy_from_slice(&comm.as_bytes()[..len]);
+    // 没有保存命令行参数, 使用可执行文件路径
+    let mut pr_psargs = [0u8; 80];
+    let exe_path = task.exe_path();
+    let len = exe_path.len().min(pr_psargs.len() - 1);
+    pr_psargs[..len].copy_from_slice(&exe_path.as_bytes()[..len]);
+    ElfPrpsinfo {
+        pr_state: 0,
+        pr_sname: b'R',
+        pr_zomb: 0,
+        pr_nice: 0,
+        _pad0: 0,
+        pr_flag: 0,
+        pr_uid: task.uid(),
+        pr_gid: task.gid(),
+        pr_pid: task.vpid_of(task.tgid()) as i32,
+        pr_ppid: ppid_of(task) as i32,
+        pr_pgrp: task.pgid() as i32,
+        pr_sid: task.vpid_of(task.tgid()) as i32,
+        pr_fname,
+        pr_psargs,
+    }
+}
+
+/// 返回PT_LOAD段, 以及NT_FILE的内容
+fn collect_segments(task: &Arc<Task>) -> (Vec<CoreSegment>, Vec<u8>) {
+    task.op_memory_set(|memory_set| {
+        let mut segments = Vec::new();
+        let mut files: Vec<(usize, usize, usize, String)> = Vec::new();
+        for area in memory_set.areas.values() {
+            if area.map_type == MapType::Linear {
+                // loongarch的trampoline
+                continue;
+            }
+            let start = area.vpn_range.get_start().0 << PAGE_SIZE_BITS;
+            let end = area.vpn_range.get_end().0 << PAGE_SIZE_BITS;
+            let perm = area.map_perm;
+            let mut flags = 0;
+            if perm.contains(MapPermission::R) {
+                flags |= PF_R;
+            }
+            if perm.contains(MapPermission::W) {
+                flags |= PF_W;
+            }
+            if perm.contains(MapPermission::X) {
+                flags |= PF_X;
+            }
+            // MADV_DONTDUMP的区域只保留程序头, 不转储内容
+            let mut dump =
+                perm.contains(MapPermission::R) && !area.advice.contains(MapAdvice::DONTDUMP);
+            // 非普通文件(如设备)的映射没有路径
+            if let Some(file) = area
+                .backend_file
+                .as_ref()
+                .and_then(|file| file.as_any().downcast_ref::<File>())
+            {
+                let path = file.get_path().dentry.absolute_path.clone();
+                files.push((start, end, area.offset >> PAGE_SIZE_BITS, path));
+                if !perm.contains(MapPermission::W) {
+                    dump = false;
+                }
+            }
+            segments.push(CoreSegment {
+                start,
+                end,
+                flags,
+                dump,
+            });
+        }
+        // NT_FILE: count, page_size, {start, end, pgoff}[count], 以'\0'结尾的文件名
+        let mut nt_file = Vec::new();
+        nt_file.extend_from_slice(&files.len().to_le_bytes());
+        nt_file.extend_from_slice(&PAGE_SIZE.to_le_bytes());
+        for (start, end, pgoff, _) in files.iter() {
+            nt_file.extend_from_slice(&start.to_le_bytes());
+            nt_file.extend_from_slice(&end.to_le_bytes());
+            nt_file.extend_from_slice(&pgoff.to_le_bytes());
+        }
+        for (_, _, _, path) in files.iter() {
+            nt_file.extend_from_slice(path.as_bytes());
+            nt_file.push(0);
+        }
+        (segments, nt_file)
+    })
+}
+
+/// 写入core文件, 总大小受RLIMIT_CORE限制
+struct CoreWriter {
+    file: Arc<dyn FileOp>,
+    pos: usize,
+    /// 实际写入的末尾, 之后的空洞需要通过truncate扩展文件大小
+    written: usize,
+    limit: usize,
+}
+
+impl CoreWriter {
+    fn emit(&mut self, buf: &[u8]) -> bool {
+        if buf.len() > self.limit - self.pos {
+            return false;
+        }
+        match self.file.pwrite(buf, self.pos) {
+            Ok(len) if len == buf.len() => {
+                self.pos += len;
+                self.written = self.pos;
+                true
+            }
+            _ => false,
+        }
+    }
+    fn skip(&mut self, len: usize) -> bool {
+        if len > self.limit - self.pos {
+            return false;
+        }
+        self.pos += len;
+        true
+    }
+    fn finish(&mut self) -> bool {
+        self.written == self.pos || self.file.truncate(self.pos).is_ok()
+    }
+}
+
+/// 检查是否需要生成core文件, 并在其他线程退出前记录各线程的寄存器
+pub fn coredump_prepare(task: &Arc<Task>, sig: Sig) -> Option<CoreDump> {
+    if task.dumpable() == SUID_DUMP_DISABLE {
+        log::warn!("[coredump] task{} is not dumpable", task.tid());
+        return None;
+    }
+    let limit = task.get_rlimit(Resource::CORE).unwrap().rlim_cur;
+    // 与linux一致, 限制小于一页时不生成core文件
+    if limit < PAGE_SIZE {
+        return None;
+    }
+    let corename = format_corename(task, sig, limit);
+    if corename.starts_with('|') {
+        log::warn!("[coredump] pipe core_pattern is unsupported: {}", corename);
+        return None;
+    }
+    let mut prstatus = vec![fill_prstatus(task, task, sig)];
+    task.op_thread_group(|tg| {
+        for thread in tg.iter().filter(|thread| thread.tid() != task.tid()) {
+            prstatus.push(fill_prstatus(task, &thread, sig));
+        }
+    });
+    Some(CoreDump {
+        corename,
+        limit,
+        prstatus,
+    })
+}
+
+impl CoreDump {
+    /// 写入core文件, 返回是否完整写入(决定退出状态中的core dump标志)
+    pub fn write(&self, task: &Arc<Task>) -> bool {
+        let file = match path_openat(
+            &self.corename,
+            OpenFlags::O_CREAT | OpenFlags::O_WRONLY | OpenFlags::O_TRUNC | OpenFlags::O_NOFOLLOW,
+            AT_FDCWD,
+            0o600,
+        ) {
+            Ok(file) => file,
+            Err(e) => {
+                log::warn!("[coredump] open {} failed: {:?}", self.corename, e);
+                return false;
+            }
+        };
+        if file.get_inode().get_mode() & S_IFMT != S_IFREG {
+            log::warn!("[coredump] {} is not a regular file", self.corename);
+            return false;
+        }
+
+        let (segments, nt_file) = collect_segments(task);
+        let mut notes = Vec::new();
+        for prstatus in self.prstatus.iter() {
+            push_note(&mut notes, NT_PRSTATUS, as_bytes(prstatus));
+        }
+        push_note(&mut notes, NT_PRPSINFO, as_bytes(&fill_prpsinfo(task)));
+        let auxv = task.op_memory_set(|memory_set| memory_set.saved_auxv.clone());
+        let auxv = unsafe {
+            core::slice::from_raw_parts(
+                auxv.as_ptr() as *const u8,
+                auxv.len() * size_of::<AuxHeader>(),
+            )
+        };
+        push_note(&mut notes, NT_AUXV, auxv);
+        push_note(&mut notes, NT_FILE, &nt_file);
+
+        let phnum = segments.len() + 1;
+        let notes_offset = size_of::<Elf64Ehdr>() + phnum * size_of::<Elf64Phdr>();
+        let data_offset = (notes_offset + notes.len()).next_multiple_of(PAGE_SIZE);
+
+        let mut headers = Vec::with_capacity(notes_offset);
+        let mut e_ident = [0u8; 16];
+        e_ident[..4].copy_from_slice(b"\x7fELF");
+        e_ident[4] = ELFCLASS64;
+        e_ident[5] = ELFDATA2LSB;
+        e_ident[6] = EV_CURRENT;
+        let ehdr = Elf64Ehdr {
+            e_ident,
+            e_type: ET_CORE,
+            e_machine: ELF_MACHINE,
+            e_version: EV_CURRENT as u32,
+            e_entry: 0,
+            e_phoff: size_of::<Elf64Ehdr>() as u64,
+            e_shoff: 0,
+            e_flags: 0,
+            e_ehsize: size_of::<Elf64Ehdr>() as u16,
+            e_phentsize: size_of::<Elf64Phdr>() as u16,
+            e_phnum: phnum as u16,
+            e_shentsize: 0,
+            e_shnum: 0,
+            e_shstrndx: 0,
+        };
+        headers.extend_from_slice(as_bytes(&ehdr));
+        let note_phdr = Elf64Phdr {
+            p_type: PT_NOTE,
+            p_flags: 0,
+            p_offset: notes_offset as u64,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: notes.len() as u64,
+            p_memsz: 0,
+            p_align: 4,
+        };
+        headers.extend_from_slice(as_bytes(&note_phdr));
+        let mut offset = data_offset;
+        for segment in segments.iter() {
+            let filesz = if segment.dump {
+                segment.end - segment.start
+            } else {
+                0
+            };
+            let load_phdr = Elf64Phdr {
+                p_type: PT_LOAD,
+                p_flags: segment.flags,
+                p_offset: offset as u64,
+                p_vaddr: segment.start as u64,
+                p_paddr: 0,
+                p_filesz: filesz as u64,
+                p_memsz: (segment.end - segment.start) as u64,
+                p_align: PAGE_SIZE as u64,
+            };
+            headers.extend_from_slice(as_bytes(&load_phdr));
+            offset += filesz;
+        }
+
+        let mut writer = CoreWriter {
+            file,
+            pos: 0,
+            written: 0,
+            limit: self.limit,
+        };
+        if !writer.emit(&headers) || !writer.emit(&notes) || !writer.skip(data_offset - writer.pos)
+        {
+            log::warn!("[coredump] {} exceeds RLIMIT_CORE", self.corename);
+            return false;
+        }
+        let mut page = vec![0u8; PAGE_SIZE];
+        for segment in segments.iter().filter(|segment| segment.dump) {
+            for va in (segment.start..segment.end).step_by(PAGE_SIZE) {
+                let present = task.op_memory_set(|memory_set| {
+                    memory_set
+                        .translate_va_to_pa(VirtAddr::from(va))
+                        .map(|pa| {
+                            page.copy_from_slice(PhysAddr::from(pa).floor().get_bytes_array())
+                        })
+                        .is_some()
+                });
+                let ok = if present {
+                    writer.emit(&page)
+                } else {
+                    writer.skip(PAGE_SIZE)
+                };
+                if !ok {
+                    log::warn!("[coredump] {} exceeds RLIMIT_CORE", self.corename);
+                    return false;
+                }
+            }
+        }
+        writer.finish()
+    }
+}
@@ -35,6 +35,11 @@ pub fn tid_alloc() -> TidHandle {
     TidHandle(TID_ALLOCATOR.lock().alloc())
 }
 
+/// 申请指定的进程（线程）号, 已被占用时返回None
+pub fn tid_alloc_specific(tid: Tid) -> Option<TidHandle> {
+    TID_ALLOCATOR.lock().alloc_specific(tid).map(TidHandle)
+}
+
 /// Generic Allocator struct
 pub struct IdAllocator {
     next: usize,
@@ -59,6 +64,18 @@ impl IdAllocator {
         }
     }
 
+    /// 分配指定的id, 已被占用时返回None
+    pub fn alloc_specific(&mut self, id: usize) -> Option<usize> {
+        if id < self.next {
+            let index = self.recycled.iter().position(|&recycled| recycled == id)?;
+            self.recycled.swap_remove(index);
+        } else {
+            self.recycled.extend(self.next..id);
+            self.next = id + 1;
+        }
+        Some(id)
+    }
+
     pub fn dealloc(&mut self, id: usize) {
         assert!(id < self.next);
         if !self.recycled.contains(&id) {
@@ -0,0 +1,208 @@
+//! 内核线程
+//!
+//! 内核线程运行在内核地址空间中, 与用户任务一样通过`scheduler`调度,
+//! 但没有用户态上下文, 也不会返回用户态。内核态下不开中断, 因此内核线程
+//! 需要主动调用`yield_current_task`/`kthread_sleep`/`kthread_parkme`让出CPU。
+use super::{
+    current_task, kstack::kstack_alloc, manager::cancel_wait_alarm, manager::delete_wait,
+    manager::prepare_wait, remove_task, schedule, unregister_task, wait_timeout, wakeup,
+    yield_current_task, Task, Tid,
+};
+use crate::{mutex::SpinNoIrqLock, timer::TimeSpec};
+use alloc::{
+    boxed::Box,
+    collections::btree_map::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// 内核线程入口, 返回值作为退出码
+pub type KThreadFn = Box<dyn FnOnce() -> i32 + Send>;
+
+/// 所有存活的内核线程
+/// 内核线程没有父进程回收, 由这里持有其`Arc<Task>`, 直到退出后被`reap_kthreads`回收
+static KTHREADS: SpinNoIrqLock<BTreeMap<Tid, Arc<Task>>> = SpinNoIrqLock::new(BTreeMap::new());
+
+/// 内核线程的控制信息, 保存在`Task`中
+pub struct KThread {
+    /// 线程名, 即/proc/<pid>/comm
+    name: String,
+    /// 入口函数, 第一次被调度时取出
+    entry: SpinNoIrqLock<Option<KThreadFn>>,
+    should_stop: AtomicBool,
+    should_park: AtomicBool,
+    parked: AtomicBool,
+    /// 串行化`kthread_parkme`的检查入队与`kthread_unpark`的清除唤醒, 避免丢失唤醒
+    park_lock: SpinNoIrqLock<()>,
+    exited: AtomicBool,
+    exit_code: AtomicI32,
+}
+
+impl KThread {
+    fn new(name: &str, entry: KThreadFn) -> Self {
+        Self {
+            name: name.to_string(),
+            entry: SpinNoIrqLock::new(Some(entry)),
+            should_stop: AtomicBool::new(false),
+            should_park: AtomicBool::new(false),
+            parked: AtomicBool::new(false),
+            park_lock: SpinNoIrqLock::new(()),
+            exited: AtomicBool::new(false),
+            exit_code: AtomicI32::new(0),
+        }
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn is_parked(&self) -> bool {
+        self.parked.load(Ordering::Acquire)
+    }
+    pub fn is_exited(&self) -> bool {
+        self.exited.load(Ordering::Acquire)
+    }
+}
+
+/// 创建一个内核线程并加入调度队列
+pub fn kthread_spawn<F>(name: &str, f: F) -> Arc<Task>
+where
+    F: FnOnce() -> i32 + Send + 'static,
+{
+    reap_kthreads();
+    let kthread = Arc::new(KThread::new(name, Box::new(f)));
+    let task = Task::new_kthread(kthread, kstack_alloc(), kthread_entry as usize);
+    log::info!("[kthread_spawn] kthread {} ({}) created", task.tid(), name);
+    KTHREADS.lock().insert(task.tid(), task.clone());
+    task
+}
+
+/// 内核线程第一次被调度时从这里开始执行
+extern "C" fn kthread_entry() -> ! {
+    let task = current_task();
+    let entry = task
+        .kthread()
+        .and_then(|kthread| kthread.entry.lock().take())
+        .expect("[kthread_entry] not a kthread");
+    drop(task);
+    let exit_code = entry();
+    kthread_exit(exit_code)
+}
+
+/// 当前内核线程退出
+pub fn kthread_exit(exit_code: i32) -> ! {
+    let task = current_task();
+    let kthread = task
+        .kthread()
+        .expect("[kthread_exit] not a kthread")
+        .clone();
+    log::warn!(
+        "[kthread_exit] kthread {} ({}) exit with {}",
+        task.tid(),
+        kthread.name(),
+        exit_code
+    );
+    delete_wait(task.tid());
+    remove_task(task.tid());
+    cancel_wait_alarm(task.tid());
+    unregister_task(task.tid());
+    task.set_exit_code(exit_code);
+    task.set_zombie();
+    kthread.exit_code.store(exit_code, Ordering::Release);
+    kthread.exited.store(true, Ordering::Release);
+    drop(kthread);
+    drop(task);
+    // KTHREADS中仍持有该任务, 切换走之后内核栈才会被回收
+    schedule();
+    unreachable!("[kthread_exit] zombie kthread scheduled");
+}
+
+/// 回收已经退出的内核线程
+fn reap_kthreads() {
+    let current = current_task().tid();
+    KTHREADS.lock().retain(|tid, task| {
+        *tid == current || task.kthread().is_some_and(|kthread| !kthread.is_exited())
+    });
+}
+
+fn current_kthread() -> Arc<KThread> {
+    current_task()
+        .kthread()
+        .expect("[kthread] called from a non-kthread task")
+        .clone()
+}
+
+/// 当前内核线程是否被要求退出
+pub fn kthread_should_stop() -> bool {
+    current_kthread().should_stop.load(Ordering::Acquire)
+}
+
+/// 当前内核线程是否被要求暂停
+pub fn kthread_should_park() -> bool {
+    current_kthread().should_park.load(Ordering::Acquire)
+}
+
+/// 内核线程在安全点调用, 如果被要求暂停则阻塞直到`kthread_unpark`或`kthread_stop`
+pub fn kthread_parkme() {
+    let kthread = current_kthread();
+    loop {
+        // 在park_lock内检查并加入阻塞队列, kthread_unpark清除should_park后的wakeup一定能找到本线程
+        let guard = kthread.park_lock.lock();
+        if !kthread.should_park.load(Ordering::Acquire)
+            || kthread.should_stop.load(Ordering::Acquire)
+        {
+            break;
+        }
+        kthread.parked.store(true, Ordering::Release);
+        prepare_wait();
+        drop(guard);
+        schedule();
+    }
+    kthread.parked.store(false, Ordering::Release);
+}
+
+/// 内核线程睡眠一段时间, `kthread_stop`/`kthread_unpark`会提前唤醒
+pub fn kthread_sleep(dur: TimeSpec) {
+    if kthread_should_stop() {
+        return;
+    }
+    wait_timeout(dur, -1);
+}
+
+/// 要求内核线程暂停, 线程在下一次调用`kthread_parkme`时阻塞
+pub fn kthread_park(task: &Arc<Task>) {
+    if let Some(kthread) = task.kthread() {
+        kthread.should_park.store(true, Ordering::Release);
+    }
+}
+
+/// 恢复被暂停的内核线程
+pub fn kthread_unpark(task: &Arc<Task>) {
+    if let Some(kthread) = task.kthread() {
+        let guard = kthread.park_lock.lock();
+        kthread.should_park.store(false, Ordering::Release);
+        drop(guard);
+        wakeup(task.tid());
+    }
+}
+
+/// 要求内核线程退出并等待其结束, 返回其退出码
+/// 不能由内核线程自身调用
+pub fn kthread_stop(task: &Arc<Task>) -> i32 {
+    let kthread = task
+        .kthread()
+        .expect("[kthread_stop] not a kthread")
+        .clone();
+    assert_ne!(
+        task.tid(),
+        current_task().tid(),
+        "[kthread_stop] kthread stop itself"
+    );
+    kthread.should_stop.store(true, Ordering::Release);
+    while !kthread.is_exited() {
+        // 唤醒睡眠或暂停中的线程, 让其检查should_stop
+        wakeup(task.tid());
+        yield_current_task();
+    }
+    reap_kthreads();
+    kthread.exit_code.load(Ordering::Acquire)
+}
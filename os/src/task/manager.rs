@@ -195,6 +195,14 @@ pub fn wait() -> isize {
     return 0;
 }
 
+/// 将当前任务加入阻塞队列但不调度, 之后由调用者调用`schedule`让出CPU
+/// 用于在锁内检查条件后入队, 使释放锁之后的`wakeup`一定能找到该任务
+pub fn prepare_wait() {
+    let task = current_task();
+    task.set_interruptable();
+    WAIT_MANAGER.add(task);
+}
+
 // 条件阻塞，只有当满足条件时才会被唤醒
 // pub fn wait_event<F>(name: &String, condition: F)
 // where
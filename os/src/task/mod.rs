@@ -1,11 +1,18 @@
 pub mod aux;
+pub mod binfmt;
+pub mod capability;
+pub mod cgroup;
 mod context;
+pub mod coredump;
 mod id;
 mod kstack;
+mod kthread;
 mod manager;
+pub mod namespace;
 mod processor;
 pub mod rusage;
 mod scheduler;
+pub mod seccomp;
 mod signal;
 mod task;
 mod wait;
@@ -30,6 +37,10 @@ use lazy_static::lazy_static;
 pub use context::TaskContext;
 pub use id::{info_allocator, IdAllocator};
 pub use kstack::get_stack_top_by_sp;
+pub use kthread::{
+    kthread_exit, kthread_park, kthread_parkme, kthread_should_park, kthread_should_stop,
+    kthread_sleep, kthread_spawn, kthread_stop, kthread_unpark, KThread,
+};
 pub use manager::{
     add_group, add_real_timer, dump_wait_queue, for_each_task, get_group, get_task, handle_timeout,
     new_group, remove_timer, unregister_task, update_real_timer, wait, wait_timeout, wakeup,
@@ -42,7 +53,10 @@ pub use scheduler::{
 };
 pub use task::kernel_exit;
 pub use task::CloneFlags;
-pub use task::{CpuMask, Task, INIT_PROC_PID};
+pub use task::{
+    CpuMask, JobNotify, Task, INIT_PROC_PID, PF_KTHREAD, SUID_DUMP_DISABLE, SUID_DUMP_USER,
+    TASK_COMM_LEN,
+};
 
 pub type Tid = usize;
 
@@ -0,0 +1,325 @@
+//! 命名空间
+//!
+//! 每个任务通过NsProxy持有mount/uts/ipc命名空间以及子进程使用的pid命名空间,
+//! clone和unshare时根据CLONE_NEW*创建新的命名空间, setns加入已有的命名空间.
+//! 任务所属的pid命名空间在创建时确定, 之后不再改变;
+//! 任务在所属pid命名空间及其所有祖先命名空间中各有一个pid, 根命名空间中的pid即全局tid
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+
+use crate::{
+    fs::mount::{MntNamespace, INIT_MNT_NS},
+    mm::shm::ShmManager,
+    mutex::SpinNoIrqLock,
+    syscall::{errno::Errno, util::Utsname},
+};
+
+use super::{current_task, get_task, CloneFlags, IdAllocator, Task, Tid};
+
+/// 初始命名空间的inode号, 与Linux保持一致
+pub const PROC_IPC_INIT_INO: u64 = 0xEFFF_FFFF;
+pub const PROC_UTS_INIT_INO: u64 = 0xEFFF_FFFE;
+pub const PROC_PID_INIT_INO: u64 = 0xEFFF_FFFC;
+pub const PROC_MNT_INIT_INO: u64 = 0xEFFF_FFF8;
+/// pid命名空间的最大嵌套层数
+pub const MAX_PID_NS_LEVEL: usize = 32;
+
+/// 新建命名空间的inode号从0xF0000000开始分配
+static NS_INUM: AtomicU64 = AtomicU64::new(0xF000_0000);
+
+pub fn ns_alloc_inum() -> u64 {
+    NS_INUM.fetch_add(1, Ordering::Relaxed)
+}
+
+lazy_static! {
+    pub static ref INIT_UTS_NS: Arc<UtsNamespace> = Arc::new(UtsNamespace {
+        inum: PROC_UTS_INIT_INO,
+        name: SpinNoIrqLock::new(Utsname::default()),
+    });
+    pub static ref INIT_IPC_NS: Arc<IpcNamespace> = Arc::new(IpcNamespace {
+        inum: PROC_IPC_INIT_INO,
+        shm: ShmManager::new(),
+    });
+    pub static ref INIT_PID_NS: Arc<PidNamespace> = Arc::new(PidNamespace {
+        inum: PROC_PID_INIT_INO,
+        level: 0,
+        parent: None,
+        inner: SpinNoIrqLock::new(PidNamespaceInner::new()),
+    });
+}
+
+/// uts命名空间, 隔离主机名和域名
+pub struct UtsNamespace {
+    inum: u64,
+    pub name: SpinNoIrqLock<Utsname>,
+}
+
+impl UtsNamespace {
+    pub fn inum(&self) -> u64 {
+        self.inum
+    }
+    /// 新命名空间的初始内容复制自原命名空间
+    fn copy(&self) -> Arc<Self> {
+        Arc::new(UtsNamespace {
+            inum: ns_alloc_inum(),
+            name: SpinNoIrqLock::new(*self.name.lock()),
+        })
+    }
+}
+
+/// ipc命名空间, 隔离System V共享内存
+pub struct IpcNamespace {
+    inum: u64,
+    pub shm: ShmManager,
+}
+
+impl IpcNamespace {
+    pub fn inum(&self) -> u64 {
+        self.inum
+    }
+    /// 新的ipc命名空间总是空的
+    fn new() -> Arc<Self> {
+        Arc::new(IpcNamespace {
+            inum: ns_alloc_inum(),
+            shm: ShmManager::new(),
+        })
+    }
+}
+
+/// pid命名空间
+pub struct PidNamespace {
+    inum: u64,
+    level: usize,
+    parent: Option<Arc<PidNamespace>>,
+    inner: SpinNoIrqLock<PidNamespaceInner>,
+}
+
+struct PidNamespaceInner {
+    /// 命名空间内的pid从1开始分配
+    pid_allocator: IdAllocator,
+    /// 命名空间内pid -> 全局tid
+    pid_map: BTreeMap<usize, Tid>,
+    /// 全局tid -> 命名空间内pid
+    tid_map: BTreeMap<Tid, usize>,
+    /// 命名空间内pid为1的进程, 收养命名空间内的孤儿进程
+    child_reaper: Option<Weak<Task>>,
+    /// init进程退出后命名空间内不能再创建进程
+    dead: bool,
+}
+
+impl PidNamespaceInner {
+    fn new() -> Self {
+        PidNamespaceInner {
+            pid_allocator: IdAllocator::new(),
+            pid_map: BTreeMap::new(),
+            tid_map: BTreeMap::new(),
+            child_reaper: None,
+            dead: false,
+        }
+    }
+}
+
+impl PidNamespace {
+    pub fn inum(&self) -> u64 {
+        self.inum
+    }
+    pub fn level(&self) -> usize {
+        self.level
+    }
+    pub fn new_child(self: &Arc<Self>) -> Result<Arc<Self>, Errno> {
+        if self.level >= MAX_PID_NS_LEVEL {
+            return Err(Errno::ENOSPC);
+        }
+        Ok(Arc::new(PidNamespace {
+            inum: ns_alloc_inum(),
+            level: self.level + 1,
+            parent: Some(self.clone()),
+            inner: SpinNoIrqLock::new(PidNamespaceInner::new()),
+        }))
+    }
+    /// 从本命名空间到根命名空间(不含)的命名空间链
+    fn iter(self: &Arc<Self>) -> impl Iterator<Item = &Arc<PidNamespace>> {
+        core::iter::successors(Some(self), |ns| ns.parent.as_ref()).filter(|ns| ns.level > 0)
+    }
+    /// 为新任务在本命名空间及所有祖先命名空间中分配pid
+    /// `set_tid`(clone3)从本命名空间开始依次指定各层命名空间中的pid, 其余层自动分配
+    pub fn alloc_pid(self: &Arc<Self>, tid: Tid, set_tid: &[usize]) -> Result<(), Errno> {
+        if self.iter().any(|ns| ns.inner.lock().dead) {
+            return Err(Errno::ENOMEM);
+        }
+        for (level, ns) in self.iter().enumerate() {
+            let mut inner = ns.inner.lock();
+            let pid = match set_tid.get(level) {
+                Some(&pid) => {
+                    // 还没有init的命名空间中只能指定pid 1
+                    if pid != 1 && inner.child_reaper.is_none() {
+                        drop(inner);
+                        self.free_pid(tid);
+                        return Err(Errno::EINVAL);
+                    }
+                    if inner.pid_allocator.alloc_specific(pid - 1).is_none() {
+                        drop(inner);
+                        self.free_pid(tid);
+                        return Err(Errno::EEXIST);
+                    }
+                    pid
+                }
+                None => inner.pid_allocator.alloc() + 1,
+            };
+            inner.pid_map.insert(pid, tid);
+            inner.tid_map.insert(tid, pid);
+        }
+        Ok(())
+    }
+    pub fn free_pid(self: &Arc<Self>, tid: Tid) {
+        for ns in self.iter() {
+            let mut inner = ns.inner.lock();
+            if let Some(pid) = inner.tid_map.remove(&tid) {
+                inner.pid_map.remove(&pid);
+                inner.pid_allocator.dealloc(pid - 1);
+            }
+        }
+    }
+    /// 全局tid在本命名空间中的pid, 不可见时返回None
+    pub fn pid_of(&self, tid: Tid) -> Option<usize> {
+        if self.level == 0 {
+            return Some(tid);
+        }
+        self.inner.lock().tid_map.get(&tid).copied()
+    }
+    /// 本命名空间中的pid对应的全局tid
+    pub fn tid_of(&self, pid: usize) -> Option<Tid> {
+        if self.level == 0 {
+            return Some(pid);
+        }
+        self.inner.lock().pid_map.get(&pid).copied()
+    }
+    /// 各层命名空间中的pid, 从根命名空间开始, 以'\t'分隔(/proc/<pid>/status中的NSpid)
+    pub fn pid_list(self: &Arc<Self>, tid: Tid) -> String {
+        let mut pids: Vec<String> = self
+            .iter()
+            .filter_map(|ns| ns.pid_of(tid))
+            .map(|pid| pid.to_string())
+            .collect();
+        pids.push(tid.to_string());
+        pids.reverse();
+        pids.join("\t")
+    }
+    /// self是否为other本身或其祖先
+    pub fn is_ancestor_of(self: &Arc<Self>, other: &Arc<PidNamespace>) -> bool {
+        core::iter::successors(Some(other), |ns| ns.parent.as_ref()).any(|ns| Arc::ptr_eq(ns, self))
+    }
+    /// 本命名空间的init进程, 根命名空间或init已退出时返回None
+    pub fn child_reaper(&self) -> Option<Arc<Task>> {
+        let inner = self.inner.lock();
+        if inner.dead {
+            return None;
+        }
+        inner
+            .child_reaper
+            .as_ref()
+            .and_then(|reaper| reaper.upgrade())
+    }
+    pub fn set_child_reaper(&self, task: &Arc<Task>) {
+        self.inner.lock().child_reaper = Some(Arc::downgrade(task));
+    }
+    pub fn parent(&self) -> Option<Arc<PidNamespace>> {
+        self.parent.clone()
+    }
+    /// init进程退出时调用, 命名空间内不能再创建进程, 返回命名空间内剩余任务的全局tid
+    pub fn mark_dead(&self) -> Vec<Tid> {
+        let mut inner = self.inner.lock();
+        inner.dead = true;
+        inner.tid_map.keys().copied().collect()
+    }
+}
+
+/// 任务持有的命名空间
+#[derive(Clone)]
+pub struct NsProxy {
+    pub mnt_ns: Arc<MntNamespace>,
+    pub uts_ns: Arc<UtsNamespace>,
+    pub ipc_ns: Arc<IpcNamespace>,
+    /// 子进程所属的pid命名空间, 与任务自身所属的pid命名空间可能不同(unshare/setns)
+    pub pid_ns_for_children: Arc<PidNamespace>,
+}
+
+impl NsProxy {
+    pub fn init() -> Self {
+        NsProxy {
+            mnt_ns: INIT_MNT_NS.clone(),
+            uts_ns: INIT_UTS_NS.clone(),
+            ipc_ns: INIT_IPC_NS.clone(),
+            pid_ns_for_children: INIT_PID_NS.clone(),
+        }
+    }
+    /// 根据CLONE_NEW*标志复制命名空间, 未指定的命名空间与原任务共享
+    pub fn copy(&self, flags: &CloneFlags) -> Result<Self, Errno> {
+        let mut nsproxy = self.clone();
+        if flags.contains(CloneFlags::CLONE_NEWUTS) {
+            nsproxy.uts_ns = self.uts_ns.copy();
+        }
+        if flags.contains(CloneFlags::CLONE_NEWIPC) {
+            nsproxy.ipc_ns = IpcNamespace::new();
+        }
+        if flags.contains(CloneFlags::CLONE_NEWPID) {
+            nsproxy.pid_ns_for_children = self.pid_ns_for_children.new_child()?;
+        }
+        Ok(nsproxy)
+    }
+}
+
+/// 可以通过/proc/<pid>/ns/*打开并传给setns的命名空间
+#[derive(Clone)]
+pub enum Namespace {
+    Mnt(Arc<MntNamespace>),
+    Uts(Arc<UtsNamespace>),
+    Ipc(Arc<IpcNamespace>),
+    Pid(Arc<PidNamespace>),
+}
+
+impl Namespace {
+    pub fn inum(&self) -> u64 {
+        match self {
+            Namespace::Mnt(ns) => ns.inum(),
+            Namespace::Uts(ns) => ns.inum(),
+            Namespace::Ipc(ns) => ns.inum(),
+            Namespace::Pid(ns) => ns.inum(),
+        }
+    }
+    /// /proc/<pid>/ns/*链接内容中的类型名
+    pub fn name(&self) -> &'static str {
+        match self {
+            Namespace::Mnt(_) => "mnt",
+            Namespace::Uts(_) => "uts",
+            Namespace::Ipc(_) => "ipc",
+            Namespace::Pid(_) => "pid",
+        }
+    }
+    /// setns中nstype对应的CLONE_NEW*标志
+    pub fn clone_flag(&self) -> CloneFlags {
+        match self {
+            Namespace::Mnt(_) => CloneFlags::CLONE_NEWNS,
+            Namespace::Uts(_) => CloneFlags::CLONE_NEWUTS,
+            Namespace::Ipc(_) => CloneFlags::CLONE_NEWIPC,
+            Namespace::Pid(_) => CloneFlags::CLONE_NEWPID,
+        }
+    }
+}
+
+/// 当前任务所见的pid, 任务不在当前pid命名空间中时返回0
+pub fn task_vpid(tid: Tid) -> usize {
+    current_task().pid_ns().pid_of(tid).unwrap_or(0)
+}
+
+/// 在当前任务的pid命名空间中按pid查找任务
+pub fn find_task_by_vpid(pid: usize) -> Option<Arc<Task>> {
+    current_task().pid_ns().tid_of(pid).and_then(get_task)
+}
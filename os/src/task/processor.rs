@@ -4,7 +4,7 @@ use lazy_static::lazy_static;
 use spin::RwLock;
 
 use super::Task;
-use crate::arch::switch;
+use crate::arch::{switch, timer::get_time_us};
 
 // 创建空闲任务
 
@@ -55,6 +55,7 @@ pub fn run_tasks() {
             next_task.set_running();
             let mut processor = PROCESSOR.write();
             processor.current = next_task.clone();
+            processor.last_account = get_time_us();
             drop(processor);
             drop(next_task);
             // 将tp寄存器指向idle_task
@@ -106,6 +107,8 @@ pub fn current_tp() -> usize {
 pub struct Processor {
     ///The task currently executing on the current processor
     current: Arc<Task>,
+    /// 上次将运行时间计入cgroup的时间(us)
+    last_account: usize,
 }
 
 impl Processor {
@@ -113,6 +116,7 @@ impl Processor {
     pub fn new() -> Self {
         Self {
             current: IDLE_TASK.clone(),
+            last_account: 0,
         }
     }
     pub fn current_task(&self) -> Arc<Task> {
@@ -122,8 +126,23 @@ impl Processor {
     }
     /// 将switch的时间算到switch_in的task
     pub fn switch_to(&mut self, task: Arc<Task>) {
+        self.update_curr();
         self.current.time_stat().record_switch_out();
         task.time_stat().record_switch_in();
         self.current = task;
     }
+    /// 将上次记账以来的运行时间计入当前任务的cgroup
+    pub fn update_curr(&mut self) {
+        let now = get_time_us();
+        let delta = now.saturating_sub(self.last_account);
+        self.last_account = now;
+        // 空闲任务的忙等时间不计入
+        if !Arc::ptr_eq(&self.current, &IDLE_TASK) {
+            self.current.cgroup().charge_cpu(delta, now);
+        }
+    }
+    /// 丢弃上次记账以来的时间, 用于等待定时器或cpu.max的下一个周期时的忙等
+    pub fn restart_account(&mut self) {
+        self.last_account = get_time_us();
+    }
 }
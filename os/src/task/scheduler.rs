@@ -1,10 +1,11 @@
 use super::{current_task, Task, Tid};
 use crate::{
-    arch::switch,
+    arch::{switch, timer::get_time_us},
     task::{
+        cgroup::{nr_cgroups, update_min_vruntime},
         dump_wait_queue, handle_timeout,
         manager::dump_time_manager,
-        processor::{current_tp, Processor},
+        processor::{current_tp, Processor, PROCESSOR},
     },
 };
 use alloc::{collections::vec_deque::VecDeque, sync::Arc};
@@ -102,8 +103,11 @@ pub fn schedule() {
         }
     } else {
         // 如果没有下一个任务, 则busyloop等待计时器超时
+        // 忙等的时间不计入当前任务的cgroup
+        PROCESSOR.write().update_curr();
         loop {
             log::trace!("[schedule] no next task, waiting for timeout");
+            PROCESSOR.write().restart_account();
             if let Some(next_task) = fetch_task() {
                 let next_task_kernel_stack = next_task.kstack();
                 {
@@ -167,6 +171,7 @@ pub fn yield_current_task() {
     // 注意下面这行日志不要删, 是loongarch64 release跑起来的神奇小咒语
     log::trace!("[yield_current_task] enter");
     let task = current_task();
+    throttle_current_task(&task);
     if let Some(next_task) = fetch_task() {
         task.set_ready();
         // 将当前任务加入就绪队列
@@ -227,7 +232,25 @@ pub fn yield_current_task() {
     // 如果没有下一个任务, 则继续执行当前任务
 }
 
+/// 当前任务所在cgroup的cpu.max配额用完时, 忙等到下一个周期或有其他可运行的任务
+fn throttle_current_task(task: &Arc<Task>) {
+    if nr_cgroups() == 0 {
+        return;
+    }
+    PROCESSOR.write().update_curr();
+    let cgroup = task.cgroup();
+    if !cgroup.cpu_throttled(get_time_us()) {
+        return;
+    }
+    let scheduler = unsafe { &*SCHEDULER.get() };
+    while cgroup.cpu_throttled(get_time_us()) && !scheduler.has_runnable() {
+        handle_timeout();
+    }
+    PROCESSOR.write().restart_account();
+}
+
 // FIFO Task scheduler
+// 存在cgroup时按cgroup的vruntime选择任务
 pub struct Scheduler {
     ready_queue: VecDeque<Arc<Task>>,
 }
@@ -244,8 +267,30 @@ impl Scheduler {
         self.ready_queue.push_back(task);
     }
     /// 取出调度器就绪队列队首任务
+    /// 存在cgroup时取出vruntime最小且没有被cpu.max限制的cgroup中最早就绪的任务
     pub fn fetch(&mut self) -> Option<Arc<Task>> {
-        self.ready_queue.pop_front()
+        if nr_cgroups() == 0 {
+            return self.ready_queue.pop_front();
+        }
+        let now = get_time_us();
+        let (vruntime, index) = self
+            .ready_queue
+            .iter()
+            .enumerate()
+            .filter_map(|(index, task)| {
+                let cgroup = task.cgroup();
+                (!cgroup.cpu_throttled(now)).then(|| (cgroup.sched_vruntime(), index))
+            })
+            .min()?;
+        update_min_vruntime(vruntime);
+        self.ready_queue.remove(index)
+    }
+    /// 就绪队列中是否有没有被cpu.max限制的任务
+    pub fn has_runnable(&self) -> bool {
+        let now = get_time_us();
+        self.ready_queue
+            .iter()
+            .any(|task| !task.cgroup().cpu_throttled(now))
     }
     /// 从调度器就绪队列中移除任务
     pub fn remove(&mut self, tid: Tid) {
@@ -266,6 +311,7 @@ impl Scheduler {
 }
 
 bitflags! {
+    #[derive(Clone, Copy)]
     pub struct WaitOption: i32 {
         /// 这个选项用于非阻塞挂起。当与 wait 或 waitpid 一起使用时，如果没有任何子进程状态改变，
         /// 这些系统调用不会阻塞父进程，而是立即返回。在 Linux 中，如果没有子进程处于可等待的状态，wait 或 waitpid 会返回 0。
@@ -274,10 +320,16 @@ bitflags! {
         /// 只有当子进程终止时，它们的结束状态才会被报告。如果子进程被某种信号（如 SIGSTOP 或 SIGTSTP）停止，
         /// 并且父进程没有设置 WUNTRACED 选项，那么父进程将不会感知到子进程的停止状态，直到子进程被继续执行或终止。
         const WUNTRACED = 1 << 1;
+        /// waitid使用, 与WUNTRACED相同
+        const WSTOPPED = 1 << 1;
+        /// waitid使用, 等待已经退出的子进程
+        const WEXITED = 1 << 2;
         /// 当子进程被停止后又继续执行时，使用这个选项。如果子进程之前被一个停止信号（如SIGSTOP 或 SIGTSTP）暂停，
         /// 然后通过继续信号（如 SIGCONT）被继续执行，那么 wait 或 waitpid 将报告这个子进程的状态，
         /// 即使它还没有终止。这允许父进程知道子进程已经从停止状态恢复。
         const WCONTINUED = 1 << 3;
+        /// waitid使用, 只获取子进程的状态, 不回收僵尸子进程也不清除停止/继续事件
+        const WNOWAIT = 0x0100_0000;
     }
 }
 impl Debug for WaitOption {
@@ -300,13 +352,29 @@ impl Debug for WaitOption {
                 write!(f, " | WUNTRACED")?;
             }
         }
+        if self.contains(WaitOption::WEXITED) {
+            if first {
+                write!(f, "WEXITED")?;
+                first = false;
+            } else {
+                write!(f, " | WEXITED")?;
+            }
+        }
         if self.contains(WaitOption::WCONTINUED) {
             if first {
                 write!(f, "WCONTINUED")?;
+                first = false;
             } else {
                 write!(f, " | WCONTINUED")?;
             }
         }
+        if self.contains(WaitOption::WNOWAIT) {
+            if first {
+                write!(f, "WNOWAIT")?;
+            } else {
+                write!(f, " | WNOWAIT")?;
+            }
+        }
         write!(f, "}}")
     }
 }
@@ -0,0 +1,336 @@
+//! seccomp 系统调用过滤
+//!
+//! 严格模式只允许read/write/exit/rt_sigreturn, 过滤模式在系统调用分发前
+//! 依次运行过滤器链(最新的在前)上的cBPF程序, 取优先级最高的动作.
+//! 过滤器链是线程级的, kernel_clone时复制, execve时保留
+use core::mem::size_of;
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    arch::{config::AUDIT_ARCH, mm::copy_from_user, trap::context::get_trap_context},
+    net::bpf::{
+        check_seccomp, copy_filter_from_user, run_filter, BpfInput, SockFilter, SockFprog, BPF_W,
+    },
+    signal::{SiField, Sig, SigAction, SigInfo, SIG_IGN},
+    syscall::errno::{Errno, SyscallRet},
+    task::{capability::CapSet, current_task, kernel_exit, schedule, Task},
+};
+
+pub const SECCOMP_MODE_DISABLED: u32 = 0;
+pub const SECCOMP_MODE_STRICT: u32 = 1;
+pub const SECCOMP_MODE_FILTER: u32 = 2;
+
+/// seccomp(2)的操作
+pub const SECCOMP_SET_MODE_STRICT: u32 = 0;
+pub const SECCOMP_SET_MODE_FILTER: u32 = 1;
+pub const SECCOMP_GET_ACTION_AVAIL: u32 = 2;
+pub const SECCOMP_GET_NOTIF_SIZES: u32 = 3;
+
+/// SECCOMP_SET_MODE_FILTER的flags
+pub const SECCOMP_FILTER_FLAG_TSYNC: u32 = 1 << 0;
+pub const SECCOMP_FILTER_FLAG_LOG: u32 = 1 << 1;
+pub const SECCOMP_FILTER_FLAG_SPEC_ALLOW: u32 = 1 << 2;
+pub const SECCOMP_FILTER_FLAG_NEW_LISTENER: u32 = 1 << 3;
+pub const SECCOMP_FILTER_FLAG_TSYNC_ESRCH: u32 = 1 << 4;
+/// 不支持user notification, 因此不接受NEW_LISTENER
+const SECCOMP_FILTER_FLAG_MASK: u32 = SECCOMP_FILTER_FLAG_TSYNC
+    | SECCOMP_FILTER_FLAG_LOG
+    | SECCOMP_FILTER_FLAG_SPEC_ALLOW
+    | SECCOMP_FILTER_FLAG_TSYNC_ESRCH;
+
+/// 过滤器返回值, 高16位为动作, 低16位为数据; 数值越小(有符号)优先级越高
+pub const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+pub const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+pub const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+pub const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+pub const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+pub const SECCOMP_RET_TRACE: u32 = 0x7ff0_0000;
+pub const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+pub const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+pub const SECCOMP_RET_ACTION_FULL: u32 = 0xffff_0000;
+pub const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+/// 过滤器链上所有程序的指令总数上限(每个过滤器额外计4条)
+const MAX_INSNS_PER_PATH: usize = 32768;
+const MAX_ERRNO: u32 = 4095;
+
+/// 严格模式下允许的系统调用: read, write, exit, rt_sigreturn
+const STRICT_SYSCALLS: [usize; 4] = [63, 64, 93, 139];
+
+/// struct seccomp_data, 过滤器读取的数据
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SeccompData {
+    pub nr: i32,
+    pub arch: u32,
+    pub instruction_pointer: u64,
+    pub args: [u64; 6],
+}
+
+impl BpfInput for SeccompData {
+    /// seccomp_data使用本机字节序, 只允许4字节对齐读取
+    fn load(&self, offset: usize, size: u16) -> Option<u32> {
+        if size != BPF_W || offset % 4 != 0 || offset + 4 > size_of::<SeccompData>() {
+            return None;
+        }
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                self as *const SeccompData as *const u8,
+                size_of::<SeccompData>(),
+            )
+        };
+        let word = &bytes[offset..offset + 4];
+        Some(u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+    }
+    fn load_ancillary(&self, _k: i32) -> Option<u32> {
+        None
+    }
+    fn data_len(&self) -> u32 {
+        size_of::<SeccompData>() as u32
+    }
+}
+
+pub struct SeccompFilter {
+    prog: Vec<SockFilter>,
+    /// SECCOMP_FILTER_FLAG_LOG, 记录除ALLOW外的动作
+    log: bool,
+    prev: Option<Arc<SeccompFilter>>,
+}
+
+impl SeccompFilter {
+    fn iter(self: &Arc<Self>) -> impl Iterator<Item = &Arc<SeccompFilter>> {
+        core::iter::successors(Some(self), |filter| filter.prev.as_ref())
+    }
+}
+
+/// 线程的seccomp状态
+#[derive(Clone, Default)]
+pub struct Seccomp {
+    pub mode: u32,
+    pub filter: Option<Arc<SeccompFilter>>,
+}
+
+impl Seccomp {
+    /// 过滤器链中的过滤器个数
+    pub fn filter_count(&self) -> usize {
+        self.filter
+            .as_ref()
+            .map_or(0, |filter| filter.iter().count())
+    }
+
+    /// 运行过滤器链, 返回优先级最高的返回值以及是否需要记录
+    fn run_filters(&self, data: &SeccompData) -> (u32, bool) {
+        let mut ret = SECCOMP_RET_ALLOW;
+        let mut log = false;
+        if let Some(filter) = &self.filter {
+            for filter in filter.iter() {
+                let cur = run_filter(&filter.prog, data);
+                if ((cur & SECCOMP_RET_ACTION_FULL) as i32)
+                    < ((ret & SECCOMP_RET_ACTION_FULL) as i32)
+                {
+                    ret = cur;
+                    log = filter.log;
+                }
+            }
+        }
+        (ret, log)
+    }
+
+    /// `filter`是否为当前过滤器链的祖先(或相同)
+    fn is_ancestor(&self, filter: &Option<Arc<SeccompFilter>>) -> bool {
+        match (&self.filter, filter) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(chain), Some(target)) => chain.iter().any(|f| Arc::ptr_eq(f, target)),
+        }
+    }
+}
+
+/// 系统调用分发前的seccomp检查, 返回Some时跳过系统调用并以其中的值作为返回值
+pub fn secure_computing(syscall_id: usize, args: [usize; 6]) -> Option<SyscallRet> {
+    let task = current_task();
+    let seccomp = task.seccomp();
+    match seccomp.mode {
+        SECCOMP_MODE_DISABLED => None,
+        SECCOMP_MODE_STRICT => {
+            if STRICT_SYSCALLS.contains(&syscall_id) {
+                return None;
+            }
+            log::warn!(
+                "[secure_computing] task{} syscall {} not allowed in strict mode",
+                task.tid(),
+                syscall_id
+            );
+            seccomp_exit_thread(task, Sig::SIGKILL)
+        }
+        _ => {
+            let data = SeccompData {
+                nr: syscall_id as i32,
+                arch: AUDIT_ARCH,
+                instruction_pointer: get_trap_context(&task).get_sepc() as u64,
+                args: args.map(|arg| arg as u64),
+            };
+            let (ret, log) = seccomp.run_filters(&data);
+            let action = ret & SECCOMP_RET_ACTION_FULL;
+            let ret_data = ret & SECCOMP_RET_DATA;
+            if log || action == SECCOMP_RET_LOG {
+                log::warn!(
+                    "[secure_computing] task{} syscall {} action {:#x}",
+                    task.tid(),
+                    syscall_id,
+                    ret
+                );
+            }
+            match action {
+                SECCOMP_RET_ALLOW | SECCOMP_RET_LOG => None,
+                SECCOMP_RET_ERRNO => {
+                    // errno可以是任意值, 直接作为负数返回值写回
+                    let errno = ret_data.min(MAX_ERRNO) as isize;
+                    Some(Ok(-errno as usize))
+                }
+                SECCOMP_RET_TRAP => {
+                    force_sigsys(&task, &data, ret_data as i32, false);
+                    // 与Linux的syscall_rollback一致, 返回值寄存器恢复为第一个参数
+                    Some(Ok(args[0]))
+                }
+                // 没有tracer和listener时系统调用失败
+                SECCOMP_RET_TRACE | SECCOMP_RET_USER_NOTIF => Some(Err(Errno::ENOSYS)),
+                SECCOMP_RET_KILL_THREAD if task.op_thread_group(|tg| tg.len()) > 1 => {
+                    seccomp_exit_thread(task, Sig::SIGSYS)
+                }
+                // KILL_PROCESS, 单线程的KILL_THREAD以及未知动作: 以SIGSYS终止进程并core dump
+                _ => {
+                    force_sigsys(&task, &data, 0, true);
+                    Some(Err(Errno::ENOSYS))
+                }
+            }
+        }
+    }
+}
+
+/// 向当前线程发送SIGSYS, 信号被屏蔽或忽略时(或`force_default`)恢复默认处理
+fn force_sigsys(task: &Arc<Task>, data: &SeccompData, errno: i32, force_default: bool) {
+    let ignored = task.op_sig_handler(|handler| handler.get(Sig::SIGSYS).sa_handler == SIG_IGN);
+    let blocked = task.op_sig_pending_mut(|pending| pending.mask.contain_signal(Sig::SIGSYS));
+    if force_default || ignored || blocked {
+        task.op_sig_handler_mut(|handler| handler.update(Sig::SIGSYS, SigAction::new(Sig::SIGSYS)));
+        task.op_sig_pending_mut(|pending| pending.mask.remove_signal(Sig::SIGSYS));
+    }
+    task.receive_siginfo(
+        SigInfo::new(
+            Sig::SIGSYS.raw(),
+            SigInfo::SYS_SECCOMP,
+            SiField::Sys {
+                call_addr: data.instruction_pointer as usize,
+                syscall: data.nr,
+                arch: data.arch,
+                errno,
+            },
+        ),
+        true,
+    );
+}
+
+/// 只终止当前线程
+fn seccomp_exit_thread(task: Arc<Task>, sig: Sig) -> ! {
+    kernel_exit(task, sig.raw() & 0x7F);
+    schedule();
+    panic!("Unreachable in seccomp_exit_thread");
+}
+
+pub fn seccomp_set_mode_strict() -> SyscallRet {
+    let task = current_task();
+    task.op_seccomp_mut(|seccomp| {
+        if seccomp.mode == SECCOMP_MODE_FILTER {
+            return Err(Errno::EINVAL);
+        }
+        seccomp.mode = SECCOMP_MODE_STRICT;
+        Ok(0)
+    })
+}
+
+pub fn seccomp_set_mode_filter(flags: u32, uargs: usize) -> SyscallRet {
+    if flags & !SECCOMP_FILTER_FLAG_MASK != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let task = current_task();
+    // 防止非特权进程借助过滤器影响set-user-ID程序
+    if !task.no_new_privs() && !task.capable(CapSet::SYS_ADMIN) {
+        return Err(Errno::EACCES);
+    }
+    if uargs == 0 {
+        return Err(Errno::EFAULT);
+    }
+    let mut fprog = SockFprog {
+        len: 0,
+        filter: core::ptr::null(),
+    };
+    copy_from_user(uargs as *const SockFprog, &mut fprog, 1)?;
+    let prog = copy_filter_from_user(&fprog)?;
+    check_seccomp(&prog, size_of::<SeccompData>() as u32)?;
+
+    let current = task.seccomp();
+    if current.mode == SECCOMP_MODE_STRICT {
+        return Err(Errno::EINVAL);
+    }
+    let total_insns = prog.len()
+        + 4
+        + current
+            .filter
+            .as_ref()
+            .map_or(0, |filter| filter.iter().map(|f| f.prog.len() + 4).sum());
+    if total_insns > MAX_INSNS_PER_PATH {
+        return Err(Errno::ENOMEM);
+    }
+    let filter = Arc::new(SeccompFilter {
+        prog,
+        log: flags & SECCOMP_FILTER_FLAG_LOG != 0,
+        prev: current.filter.clone(),
+    });
+
+    if flags & SECCOMP_FILTER_FLAG_TSYNC != 0 {
+        // 线程组中其它线程的过滤器链必须是当前线程的祖先
+        let threads: Vec<Arc<Task>> = task.op_thread_group(|tg| tg.iter().collect());
+        for thread in threads.iter().filter(|t| t.tid() != task.tid()) {
+            let other = thread.seccomp();
+            if other.mode == SECCOMP_MODE_STRICT || !current.is_ancestor(&other.filter) {
+                if flags & SECCOMP_FILTER_FLAG_TSYNC_ESRCH != 0 {
+                    return Err(Errno::ESRCH);
+                }
+                return Ok(thread.tid());
+            }
+        }
+        for thread in threads.iter() {
+            thread.op_seccomp_mut(|seccomp| {
+                seccomp.mode = SECCOMP_MODE_FILTER;
+                seccomp.filter = Some(filter.clone());
+            });
+            if thread.tid() != task.tid() {
+                thread.set_no_new_privs();
+            }
+        }
+        return Ok(0);
+    }
+    task.op_seccomp_mut(|seccomp| {
+        seccomp.mode = SECCOMP_MODE_FILTER;
+        seccomp.filter = Some(filter);
+    });
+    Ok(0)
+}
+
+/// 检查内核是否支持某个动作
+pub fn seccomp_get_action_avail(uargs: usize) -> SyscallRet {
+    let mut action: u32 = 0;
+    copy_from_user(uargs as *const u32, &mut action, 1)?;
+    match action {
+        SECCOMP_RET_KILL_PROCESS
+        | SECCOMP_RET_KILL_THREAD
+        | SECCOMP_RET_TRAP
+        | SECCOMP_RET_ERRNO
+        | SECCOMP_RET_TRACE
+        | SECCOMP_RET_LOG
+        | SECCOMP_RET_ALLOW => Ok(0),
+        _ => Err(Errno::EOPNOTSUPP),
+    }
+}
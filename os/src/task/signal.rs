@@ -1,8 +1,8 @@
 use alloc::sync::Arc;
 
 use crate::{
-    signal::{ActionType, Sig, SigAction, SigInfo, SigSet, SIG_IGN},
-    task::{add_task, dump_scheduler, dump_wait_queue, manager::delete_wait},
+    signal::{ActionType, SiField, Sig, SigAction, SigActionFlag, SigInfo, SigSet, SIG_IGN},
+    task::{add_task, dump_scheduler, dump_wait_queue, manager::delete_wait, wait, wakeup},
 };
 
 use super::task::Task;
@@ -15,6 +15,17 @@ impl Task {
             self.tid(),
             siginfo.signo
         );
+        // 作业控制信号在产生时就生效: SIGCONT恢复线程组并丢弃未决的停止信号, 停止信号丢弃未决的SIGCONT
+        let sig = Sig::from(siginfo.signo);
+        if sig == Sig::SIGCONT {
+            self.do_signal_continue();
+        } else if sig.is_stop() {
+            self.op_thread_group(|tg| {
+                for task in tg.iter() {
+                    task.op_sig_pending_mut(|pending| pending.remove_signals(SigSet::SIGCONT));
+                }
+            });
+        }
         // 在任务收到信号时，若满足可中断条件，则触发信号中断
         match thread_level {
             // 线程级信号
@@ -53,6 +64,78 @@ impl Task {
         }
     }
 
+    /// 停止信号的默认处理: 停止整个线程组, 直到收到SIGCONT或SIGKILL
+    /// 由线程组中每个处理停止信号的线程调用, 只有第一个线程通知父进程
+    pub fn do_signal_stop(self: &Arc<Task>, sig: Sig) {
+        log::warn!("[do_signal_stop] task{} stopped by {:?}", self.tid(), sig);
+        if self.op_thread_group_mut(|tg| tg.stop(sig.raw())) {
+            self.notify_parent_cldstop(SigInfo::CLD_STOPPED, sig);
+        }
+        loop {
+            let stopped = self.op_thread_group_mut(|tg| {
+                if tg.is_stopped() {
+                    tg.add_stop_waiter(self.tid());
+                }
+                tg.is_stopped()
+            });
+            let killed = self
+                .op_sig_pending_mut(|pending| pending.find_signal(SigSet::SIGKILL))
+                .is_some();
+            if !stopped || killed {
+                break;
+            }
+            // 其他信号保持未决, 继续停止
+            wait();
+            self.set_uninterrupted();
+        }
+        self.op_thread_group_mut(|tg| tg.remove_stop_waiter(self.tid()));
+        log::warn!("[do_signal_stop] task{} continued", self.tid());
+    }
+
+    /// 收到SIGCONT时恢复被停止的线程组, 即使SIGCONT被阻塞或忽略
+    fn do_signal_continue(self: &Arc<Task>) {
+        let waiters = self.op_thread_group_mut(|tg| {
+            for task in tg.iter() {
+                task.op_sig_pending_mut(|pending| pending.remove_signals(SigSet::STOP_SIGNALS));
+            }
+            tg.resume()
+        });
+        if let Some(waiters) = waiters {
+            log::warn!(
+                "[do_signal_continue] thread group {} continued",
+                self.tgid()
+            );
+            for tid in waiters {
+                wakeup(tid);
+            }
+            self.notify_parent_cldstop(SigInfo::CLD_CONTINUED, Sig::SIGCONT);
+        }
+    }
+
+    /// 线程组停止或继续时通知父进程
+    /// 父进程设置了SA_NOCLDSTOP时不发送SIGCHLD, 但仍然可以通过wait获取状态变化
+    fn notify_parent_cldstop(&self, code: i32, sig: Sig) {
+        let Some(parent) = self.op_parent(|parent| parent.as_ref().and_then(|p| p.upgrade()))
+        else {
+            return;
+        };
+        let action = parent.op_sig_handler(|handler| handler.get(Sig::SIGCHLD));
+        if !action.flags.contains(SigActionFlag::SA_NOCLDSTOP) {
+            log::info!(
+                "[notify_parent_cldstop] task{} send SIGCHLD({}, {:?}) to parent-{}",
+                self.tid(),
+                code,
+                sig,
+                parent.tid()
+            );
+            parent.receive_siginfo(
+                SigInfo::new(Sig::SIGCHLD.raw(), code, SiField::Kill { tid: self.tgid() }),
+                false,
+            );
+        }
+        wakeup(parent.tid());
+    }
+
     pub fn is_interrupted(&self) -> bool {
         self.op_sig_pending_mut(|sig_pending| sig_pending.is_interrupted())
     }
@@ -1,28 +1,34 @@
 use super::{
     aux::{AuxHeader, AT_EXECFN, AT_NULL, AT_RANDOM},
+    capability::{CapSet, Capabilities},
+    cgroup::{Cgroup, CGROUP_ROOT},
     context::TaskContext,
     get_task,
-    id::{tid_alloc, TidAddress, TidHandle},
+    id::{tid_alloc, tid_alloc_specific, TidAddress, TidHandle},
     kstack::{get_stack_top_by_sp, kstack_alloc, KernelStack},
+    kthread::KThread,
     manager::unregister_task,
+    namespace::{IpcNamespace, NsProxy, PidNamespace, UtsNamespace, INIT_PID_NS},
     remove_task,
     rusage::TimeStat,
+    seccomp::Seccomp,
     String, Tid,
 };
 use crate::{
     arch::{
-        config::USER_STACK_SIZE,
+        config::{PAGE_SIZE, USER_STACK_SIZE},
         mm::copy_to_user,
         trap::{
             context::{get_trap_context, save_trap_context},
             TrapContext,
         },
     },
-    ext4::inode::{S_IWGRP, S_IWOTH, S_IWUSR},
+    ext4::inode::{S_ISGID, S_ISUID, S_IWGRP, S_IWOTH, S_IWUSR, S_IXGRP},
     fs::{
         fdtable::FdTable,
         file::FileOp,
         inode::InodeOp,
+        mount::MntNamespace,
         path::Path,
         uapi::{RLimit, Resource},
     },
@@ -30,7 +36,7 @@ use crate::{
         do_futex,
         flags::{FUTEX_PRIVATE_FLAG, FUTEX_WAKE},
     },
-    mm::{MapArea, MapPermission, MapType, MemorySet, VPNRange, VirtAddr, KERNEL_SPACE},
+    mm::{MapArea, MapPermission, MapType, MemorySet, VPNRange, VirtAddr, KERNEL_SATP, KERNEL_SPACE},
     mutex::SpinNoIrqLock,
     net::addr::is_unspecified,
     signal::{SiField, Sig, SigHandler, SigInfo, SigPending, SigSet, SignalStack, UContext},
@@ -60,12 +66,19 @@ use core::{
     cell::SyncUnsafeCell,
     fmt::Write,
     mem,
-    sync::atomic::{AtomicI32, AtomicU16, AtomicU32, AtomicUsize},
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU16, AtomicU32, AtomicUsize},
 };
 use spin::{Mutex, RwLock};
 
 pub const INIT_PROC_PID: usize = 0;
+/// /proc/<pid>/stat中内核线程的标志
+pub const PF_KTHREAD: u32 = 0x0020_0000;
 pub const RLIM_NLIMITS: usize = 16;
+/// 任务名的最大长度(包括结尾的'\0')
+pub const TASK_COMM_LEN: usize = 16;
+/// PR_SET_DUMPABLE
+pub const SUID_DUMP_DISABLE: u32 = 0;
+pub const SUID_DUMP_USER: u32 = 1;
 
 extern "C" {
     fn strampoline();
@@ -84,6 +97,7 @@ pub struct Task {
     // 基本变量
     tid: RwLock<TidHandle>,                                 // 线程id
     tgid: AtomicUsize,                                      // 线程组id
+    pid_ns: Arc<PidNamespace>,                              // 所属的pid命名空间
     tid_address: SpinNoIrqLock<TidAddress>,                 // 线程id地址
     status: Mutex<TaskStatus>,                              // 任务状态
     time_stat: SyncUnsafeCell<TimeStat>,                    // 任务时间统计
@@ -91,7 +105,10 @@ pub struct Task {
     children: Arc<SpinNoIrqLock<BTreeMap<Tid, Arc<Task>>>>, // 子任务
     thread_group: Arc<SpinNoIrqLock<ThreadGroup>>,          // 线程组
     exit_code: AtomicI32,                                   // 退出码
+    exit_signal: AtomicI32,                                 // 退出时向父进程发送的信号
+    vfork_parent: SpinNoIrqLock<Option<Tid>>,               // vfork时等待子任务execve或退出的父任务
     exe_path: Arc<RwLock<String>>,                          // 执行路径
+    comm: RwLock<String>,                                   // 任务名
 
     // 内存管理
     // 包括System V shm管理
@@ -103,12 +120,22 @@ pub struct Task {
     root: Arc<SpinNoIrqLock<Arc<Path>>>,
     pwd: Arc<SpinNoIrqLock<Arc<Path>>>,
     umask: AtomicU16, // 文件权限掩码
+    // 命名空间
+    nsproxy: SpinNoIrqLock<NsProxy>,
+    // 所属的cgroup
+    cgroup: SpinNoIrqLock<Arc<Cgroup>>,
     // 信号处理
     sig_pending: SpinNoIrqLock<SigPending>,      // 待处理信号
     sig_handler: Arc<SpinNoIrqLock<SigHandler>>, // 信号处理函数
     sig_stack: SpinNoIrqLock<Option<SignalStack>>, // 额外信号栈
     itimerval: Arc<RwLock<[ITimerVal; 3]>>,      // 定时器
     rlimit: Arc<RwLock<[RLimit; 16]>>,           // 资源限制
+    oom_score_adj: Arc<AtomicI32>,               // OOM评分调整值, 线程组共享
+    pdeathsig: AtomicI32,                        // 父进程退出时收到的信号
+    no_new_privs: AtomicBool,                    // execve不再提升权限
+    child_subreaper: Arc<AtomicBool>,            // 是否收割孤儿子孙进程, 线程组共享
+    dumpable: Arc<AtomicU32>,                    // 是否允许core dump, 线程组共享
+    seccomp: SpinNoIrqLock<Seccomp>,             // seccomp过滤器链
     cpu_mask: SpinNoIrqLock<CpuMask>,            // CPU掩码
     // 权限设置
     pgid: AtomicUsize, // 进程组id
@@ -121,6 +148,9 @@ pub struct Task {
     sgid: AtomicU32,   // 保存组id
     fsgid: AtomicU32,  // 文件系统组id
     sup_groups: RwLock<Vec<u32>>, // 附加组列表
+    caps: SpinNoIrqLock<Capabilities>, // 能力集
+    // 内核线程, 用户任务为None
+    kthread: Option<Arc<KThread>>,
                        // ToDo：运行时间(调度相关)
                        // ToDo: 多核启动
 }
@@ -137,6 +167,11 @@ impl core::fmt::Debug for Task {
 impl Drop for Task {
     fn drop(&mut self) {
         // log::error!("task {} dropped", self.tid());
+        // 释放各层pid命名空间中的pid
+        self.pid_ns.free_pid(self.tid());
+        let cgroup = self.cgroup.lock().clone();
+        cgroup.detach(self.tid());
+        cgroup.uncharge_pids();
     }
 }
 
@@ -147,6 +182,7 @@ impl Task {
             kstack: KernelStack(0),
             tid: RwLock::new(TidHandle(0)),
             tgid: AtomicUsize::new(0),
+            pid_ns: INIT_PID_NS.clone(),
             tid_address: SpinNoIrqLock::new(TidAddress::new()),
             status: Mutex::new(TaskStatus::Ready),
             time_stat: SyncUnsafeCell::new(TimeStat::default()),
@@ -154,18 +190,29 @@ impl Task {
             children: Arc::new(SpinNoIrqLock::new(BTreeMap::new())),
             thread_group: Arc::new(SpinNoIrqLock::new(ThreadGroup::new())),
             exit_code: AtomicI32::new(0),
+            exit_signal: AtomicI32::new(Sig::SIGCHLD.raw()),
+            vfork_parent: SpinNoIrqLock::new(None),
             exe_path: Arc::new(RwLock::new(String::new())),
+            comm: RwLock::new(String::new()),
             memory_set: RwLock::new(Arc::new(RwLock::new(MemorySet::new_bare()))),
             robust_list_head: AtomicUsize::new(0),
             fd_table: Mutex::new(FdTable::new_bare()),
             root: Arc::new(SpinNoIrqLock::new(Path::zero_init())),
             pwd: Arc::new(SpinNoIrqLock::new(Path::zero_init())),
             umask: AtomicU16::new(0),
+            nsproxy: SpinNoIrqLock::new(NsProxy::init()),
+            cgroup: SpinNoIrqLock::new(CGROUP_ROOT.clone()),
             sig_pending: SpinNoIrqLock::new(SigPending::new()),
             sig_handler: Arc::new(SpinNoIrqLock::new(SigHandler::new())),
             sig_stack: SpinNoIrqLock::new(None),
             itimerval: Arc::new(RwLock::new([ITimerVal::default(); 3])),
-            rlimit: Arc::new(RwLock::new([RLimit::default(); RLIM_NLIMITS])),
+            rlimit: Arc::new(RwLock::new(init_rlimits())),
+            oom_score_adj: Arc::new(AtomicI32::new(0)),
+            pdeathsig: AtomicI32::new(0),
+            no_new_privs: AtomicBool::new(false),
+            child_subreaper: Arc::new(AtomicBool::new(false)),
+            dumpable: Arc::new(AtomicU32::new(SUID_DUMP_USER)),
+            seccomp: SpinNoIrqLock::new(Seccomp::default()),
             cpu_mask: SpinNoIrqLock::new(CpuMask::ALL),
             pgid: AtomicUsize::new(0),
             uid: AtomicU32::new(0),
@@ -177,6 +224,8 @@ impl Task {
             sgid: AtomicU32::new(0),
             fsgid: AtomicU32::new(0),
             sup_groups: RwLock::new(Vec::new()),
+            caps: SpinNoIrqLock::new(Capabilities::root()),
+            kthread: None,
         }
     }
 
@@ -210,6 +259,7 @@ impl Task {
             kstack: KernelStack(kstack),
             tid: RwLock::new(tid),
             tgid,
+            pid_ns: INIT_PID_NS.clone(),
             tid_address: SpinNoIrqLock::new(TidAddress::new()),
             status: Mutex::new(TaskStatus::Ready),
             time_stat: SyncUnsafeCell::new(TimeStat::default()),
@@ -218,18 +268,29 @@ impl Task {
             children: Arc::new(SpinNoIrqLock::new(BTreeMap::new())),
             thread_group: Arc::new(SpinNoIrqLock::new(ThreadGroup::new())),
             exit_code: AtomicI32::new(0),
+            exit_signal: AtomicI32::new(Sig::SIGCHLD.raw()),
+            vfork_parent: SpinNoIrqLock::new(None),
             exe_path: Arc::new(RwLock::new(String::from("/initproc"))),
+            comm: RwLock::new(String::from("initproc")),
             memory_set: RwLock::new(Arc::new(RwLock::new(memory_set))),
             robust_list_head: AtomicUsize::new(0),
             fd_table: Mutex::new(FdTable::new()),
             root: Arc::new(SpinNoIrqLock::new(root_path.clone())),
             pwd: Arc::new(SpinNoIrqLock::new(root_path)),
             umask: AtomicU16::new(S_IWGRP | S_IWOTH), // 默认umask为022
+            nsproxy: SpinNoIrqLock::new(NsProxy::init()),
+            cgroup: SpinNoIrqLock::new(CGROUP_ROOT.clone()),
             sig_pending: SpinNoIrqLock::new(SigPending::new()),
             sig_handler: Arc::new(SpinNoIrqLock::new(SigHandler::new())),
             sig_stack: SpinNoIrqLock::new(None),
             itimerval: Arc::new(RwLock::new([ITimerVal::default(); 3])),
-            rlimit: Arc::new(RwLock::new([RLimit::default(); RLIM_NLIMITS])),
+            rlimit: Arc::new(RwLock::new(init_rlimits())),
+            oom_score_adj: Arc::new(AtomicI32::new(0)),
+            pdeathsig: AtomicI32::new(0),
+            no_new_privs: AtomicBool::new(false),
+            child_subreaper: Arc::new(AtomicBool::new(false)),
+            dumpable: Arc::new(AtomicU32::new(SUID_DUMP_USER)),
+            seccomp: SpinNoIrqLock::new(Seccomp::default()),
             cpu_mask: SpinNoIrqLock::new(CpuMask::ALL),
             pgid,
             uid,
@@ -241,11 +302,15 @@ impl Task {
             sgid,
             fsgid,
             sup_groups,
+            caps: SpinNoIrqLock::new(Capabilities::root()),
+            kthread: None,
         });
         // 向线程组中添加该进程
         task.thread_group
             .lock()
             .add(task.tid(), Arc::downgrade(&task));
+        CGROUP_ROOT.attach(task.tid());
+        CGROUP_ROOT.charge_pids();
         add_task(task.clone());
         register_task(&task);
         // 新建进程组
@@ -265,14 +330,92 @@ impl Task {
         task
     }
 
+    /// 创建内核线程, 使用内核页表, 第一次被调度时从entry开始执行
+    /// 内核栈中同样预留TrapContext的位置, 但内核线程不会返回用户态
+    pub fn new_kthread(kthread: Arc<KThread>, kstack_top: usize, entry: usize) -> Arc<Self> {
+        let tid = tid_alloc();
+        let tgid = AtomicUsize::new(tid.0);
+        let mut kstack = kstack_top - core::mem::size_of::<TrapContext>();
+        kstack -= core::mem::size_of::<TaskContext>();
+        let task_cx_ptr = kstack as *mut TaskContext;
+        let task = Arc::new(Task {
+            kstack: KernelStack(kstack),
+            tid: RwLock::new(tid),
+            tgid,
+            pid_ns: INIT_PID_NS.clone(),
+            tid_address: SpinNoIrqLock::new(TidAddress::new()),
+            status: Mutex::new(TaskStatus::Ready),
+            time_stat: SyncUnsafeCell::new(TimeStat::default()),
+            parent: Arc::new(SpinNoIrqLock::new(None)),
+            children: Arc::new(SpinNoIrqLock::new(BTreeMap::new())),
+            thread_group: Arc::new(SpinNoIrqLock::new(ThreadGroup::new())),
+            exit_code: AtomicI32::new(0),
+            exit_signal: AtomicI32::new(Sig::SIGCHLD.raw()),
+            vfork_parent: SpinNoIrqLock::new(None),
+            // 内核线程没有可执行文件, comm即线程名
+            exe_path: Arc::new(RwLock::new(String::from(kthread.name()))),
+            comm: RwLock::new(String::from(kthread.name())),
+            // 内核线程运行在KERNEL_SPACE上, 这里的地址空间只是占位
+            memory_set: RwLock::new(Arc::new(RwLock::new(MemorySet::new_bare()))),
+            robust_list_head: AtomicUsize::new(0),
+            fd_table: Mutex::new(FdTable::new_bare()),
+            root: Arc::new(SpinNoIrqLock::new(INITPROC.root())),
+            pwd: Arc::new(SpinNoIrqLock::new(INITPROC.root())),
+            umask: AtomicU16::new(S_IWGRP | S_IWOTH),
+            nsproxy: SpinNoIrqLock::new(NsProxy::init()),
+            cgroup: SpinNoIrqLock::new(CGROUP_ROOT.clone()),
+            sig_pending: SpinNoIrqLock::new(SigPending::new()),
+            sig_handler: Arc::new(SpinNoIrqLock::new(SigHandler::new())),
+            sig_stack: SpinNoIrqLock::new(None),
+            itimerval: Arc::new(RwLock::new([ITimerVal::default(); 3])),
+            rlimit: Arc::new(RwLock::new(init_rlimits())),
+            oom_score_adj: Arc::new(AtomicI32::new(0)),
+            pdeathsig: AtomicI32::new(0),
+            no_new_privs: AtomicBool::new(false),
+            child_subreaper: Arc::new(AtomicBool::new(false)),
+            dumpable: Arc::new(AtomicU32::new(SUID_DUMP_USER)),
+            seccomp: SpinNoIrqLock::new(Seccomp::default()),
+            cpu_mask: SpinNoIrqLock::new(CpuMask::ALL),
+            pgid: AtomicUsize::new(0),
+            uid: AtomicU32::new(0),
+            euid: AtomicU32::new(0),
+            suid: AtomicU32::new(0),
+            fsuid: AtomicU32::new(0),
+            gid: AtomicU32::new(0),
+            egid: AtomicU32::new(0),
+            sgid: AtomicU32::new(0),
+            fsgid: AtomicU32::new(0),
+            sup_groups: RwLock::new(Vec::new()),
+            caps: SpinNoIrqLock::new(Capabilities::root()),
+            kthread: Some(kthread),
+        });
+        task.thread_group
+            .lock()
+            .add(task.tid(), Arc::downgrade(&task));
+        CGROUP_ROOT.attach(task.tid());
+        CGROUP_ROOT.charge_pids();
+        let task_ptr = Arc::as_ptr(&task) as usize;
+        let task_context = TaskContext::kthread_init_task_context(entry, task_ptr, *KERNEL_SATP);
+        unsafe {
+            task_cx_ptr.write(task_context);
+        }
+        register_task(&task);
+        add_task(task.clone());
+        task
+    }
+
     // 从父进程复制子进程的核心逻辑实现
+    /// `exit_signal`为子进程退出时向父进程发送的信号(0表示不发送)
+    /// `set_tid`和`cgroup`由clone3指定, 分别为各层pid命名空间中的pid和子任务加入的cgroup
     pub fn kernel_clone(
         self: &Arc<Self>,
         flags: &CloneFlags,
         ustack_ptr: usize,
         children_tid_ptr: usize,
+        exit_signal: i32,
+        set_tid: &[usize],
+        cgroup: Option<Arc<Cgroup>>,
     ) -> Result<Arc<Self>, Errno> {
-        let tid = tid_alloc();
         let tid_address = SpinNoIrqLock::new(TidAddress::new());
         let exit_code = AtomicI32::new(0);
         let status = Mutex::new(TaskStatus::Ready);
@@ -290,6 +433,9 @@ impl Task {
         let sig_pending;
         let sig_stack;
         let rlimit;
+        let oom_score_adj;
+        let child_subreaper;
+        let dumpable;
         let cpu_mask;
         let pgid;
         let uid;
@@ -301,8 +447,54 @@ impl Task {
         let sgid;
         let fsgid;
         let sup_groups;
+        let caps;
         log::info!("[kernel_clone] task{} ready to clone ...", self.tid());
 
+        // 创建新的命名空间需要CAP_SYS_ADMIN
+        // mount/umount 没有接入各命名空间的 mount 树, 新的 mount 命名空间无法提供隔离
+        if flags.contains(CloneFlags::CLONE_NEWNS) {
+            return Err(Errno::EINVAL);
+        }
+        let new_ns_flags = CloneFlags::CLONE_NEWUTS
+            | CloneFlags::CLONE_NEWIPC
+            | CloneFlags::CLONE_NEWPID;
+        if flags.intersects(new_ns_flags) && !self.capable(CapSet::SYS_ADMIN) {
+            return Err(Errno::EPERM);
+        }
+        if flags.contains(CloneFlags::CLONE_NEWIPC) && flags.contains(CloneFlags::CLONE_SYSVSEM) {
+            return Err(Errno::EINVAL);
+        }
+        let nsproxy = self.nsproxy().copy(flags)?;
+        // 线程必须与线程组在同一个pid命名空间中
+        let pid_ns = if flags.contains(CloneFlags::CLONE_THREAD) {
+            if flags.contains(CloneFlags::CLONE_NEWPID)
+                || !Arc::ptr_eq(&nsproxy.pid_ns_for_children, &self.pid_ns)
+            {
+                return Err(Errno::EINVAL);
+            }
+            self.pid_ns.clone()
+        } else {
+            nsproxy.pid_ns_for_children.clone()
+        };
+        // 指定pid需要CAP_SYS_ADMIN, 且不能多于pid命名空间的层数
+        if set_tid.len() > pid_ns.level() + 1 {
+            return Err(Errno::EINVAL);
+        }
+        if !set_tid.is_empty() && !self.capable(CapSet::SYS_ADMIN) {
+            return Err(Errno::EPERM);
+        }
+        // 全局tid即根命名空间中的pid
+        let tid = match set_tid.get(pid_ns.level()) {
+            Some(&tid) => tid_alloc_specific(tid).ok_or(Errno::EEXIST)?,
+            None => tid_alloc(),
+        };
+        let cgroup = cgroup.unwrap_or_else(|| self.cgroup());
+        cgroup.try_charge_pids()?;
+        if let Err(e) = pid_ns.alloc_pid(tid.0, set_tid) {
+            cgroup.uncharge_pids();
+            return Err(e);
+        }
+
         // 是否与父进程共享信号处理器
         if flags.contains(CloneFlags::CLONE_SIGHAND) {
             log::warn!("[kernel_clone] handle CLONE_SIGHAND");
@@ -316,8 +508,13 @@ impl Task {
         // 为了写到子空间，此处直接写入父空间再复制到子空间
         if flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
             log::warn!("[sys_clone] handle CLONE_CHILD_SETTID");
-            let content = (tid.0 as u64).to_le_bytes();
-            copy_to_user(children_tid_ptr as *mut u8, &content as *const u8, 8)?;
+            // 子任务看到的是自己所在pid命名空间中的tid
+            let content = (pid_ns.pid_of(tid.0).unwrap() as i32).to_le_bytes();
+            if let Err(e) = copy_to_user(children_tid_ptr as *mut u8, &content as *const u8, 4) {
+                pid_ns.free_pid(tid.0);
+                cgroup.uncharge_pids();
+                return Err(e);
+            }
         }
 
         // 继承父进程
@@ -331,6 +528,7 @@ impl Task {
         sgid = AtomicU32::new(self.sgid());
         fsgid = AtomicU32::new(self.fsgid());
         sup_groups = RwLock::new(self.op_sup_groups_mut(|groups| groups.clone()));
+        caps = SpinNoIrqLock::new(self.caps());
 
         // 创建线程
         if flags.contains(CloneFlags::CLONE_THREAD) {
@@ -352,6 +550,9 @@ impl Task {
             thread_group = self.thread_group.clone();
             itimerval = self.itimerval.clone();
             rlimit = self.rlimit.clone();
+            oom_score_adj = self.oom_score_adj.clone();
+            child_subreaper = self.child_subreaper.clone();
+            dumpable = self.dumpable.clone();
         }
         // 创建进程
         else {
@@ -361,7 +562,12 @@ impl Task {
             children = Arc::new(SpinNoIrqLock::new(BTreeMap::new()));
             thread_group = Arc::new(SpinNoIrqLock::new(ThreadGroup::new()));
             itimerval = Arc::new(RwLock::new([ITimerVal::default(); 3]));
-            rlimit = Arc::new(RwLock::new([RLimit::default(); RLIM_NLIMITS]));
+            // 子进程继承父进程的资源限制
+            rlimit = Arc::new(RwLock::new(self.op_rlimit(|rlimit| *rlimit)));
+            oom_score_adj = Arc::new(AtomicI32::new(self.oom_score_adj()));
+            // 子进程不继承subreaper属性
+            child_subreaper = Arc::new(AtomicBool::new(false));
+            dumpable = Arc::new(AtomicU32::new(self.dumpable()));
         }
 
         if flags.contains(CloneFlags::CLONE_PARENT) {
@@ -415,13 +621,17 @@ impl Task {
             kstack,
             tid,
             tgid,
+            pid_ns,
             tid_address,
             status,
             time_stat,
             parent,
             children,
             exit_code,
+            exit_signal: AtomicI32::new(exit_signal),
+            vfork_parent: SpinNoIrqLock::new(None),
             exe_path,
+            comm: RwLock::new(self.comm()),
             thread_group,
             memory_set,
             robust_list_head,
@@ -429,11 +639,21 @@ impl Task {
             root,
             pwd,
             umask,
+            nsproxy: SpinNoIrqLock::new(nsproxy),
+            cgroup: SpinNoIrqLock::new(cgroup.clone()),
             sig_handler,
             sig_pending,
             sig_stack,
             itimerval,
             rlimit,
+            oom_score_adj,
+            // 子任务不继承pdeathsig
+            pdeathsig: AtomicI32::new(0),
+            no_new_privs: AtomicBool::new(self.no_new_privs()),
+            child_subreaper,
+            dumpable,
+            // 过滤器链由子任务继承
+            seccomp: SpinNoIrqLock::new(self.seccomp()),
             cpu_mask,
             pgid,
             uid,
@@ -445,6 +665,8 @@ impl Task {
             sgid,
             fsgid,
             sup_groups,
+            caps,
+            kthread: None,
         });
         log::trace!("[kernel_clone] child task{} created", task.tid());
 
@@ -452,6 +674,14 @@ impl Task {
             task.set_tas(task.tid());
         }
 
+        // 新pid命名空间中的第一个进程成为该命名空间的init
+        if task.pid_ns.pid_of(task.tid()) == Some(1) {
+            task.pid_ns.set_child_reaper(&task);
+        }
+
+        // 子任务加入父任务所在的cgroup
+        cgroup.attach(task.tid());
+
         // 向任务管理器注册新任务（不是调度器）
         register_task(&task);
 
@@ -539,7 +769,7 @@ impl Task {
         // 初始化用户栈, 压入args和envs
         let argc = args_vec.len();
         let (argv_base, envp_base, auxv_base, ustack_top) =
-            init_user_stack(&memory_set, &args_vec, &envs_vec, aux_vec, ustack_top);
+            init_user_stack(&mut memory_set, &args_vec, &envs_vec, aux_vec, ustack_top);
         log::info!(
             "[kernel_execve] entry_point: {:x}, user_sp: {:x}, page_table: {:x}",
             entry_point,
@@ -557,6 +787,13 @@ impl Task {
 
         // 更新地址空间
         *self.memory_set.write() = Arc::new(RwLock::new(memory_set));
+        // vfork的父任务可以继续运行
+        self.vfork_done();
+        // 内核中加载的应用程序没有set-user-ID位, 只变换能力集
+        let (uid, euid) = (self.uid(), self.euid());
+        self.op_caps_mut(|caps| caps.fix_execve(uid, euid, false));
+        self.set_comm(args_vec[0].rsplit('/').next().unwrap_or(""));
+        self.set_dumpable(SUID_DUMP_USER);
 
         // 更新trap_cx
         let mut trap_cx = TrapContext::app_init_trap_context(
@@ -611,13 +848,15 @@ impl Task {
         envs_vec: Vec<String>,
     ) -> SyscallRet {
         log::info!("[kernel_execve] task{} do execve ...", self.tid());
+        let elf_inode = elf_file.get_inode();
         // 创建地址空间
         let (mut memory_set, _satp, ustack_top, entry_point, aux_vec) =
-            MemorySet::from_elf_lazily(elf_file, &mut args_vec)?;
+            MemorySet::from_elf_lazily(elf_file)?;
         // 更新页表
         memory_set.activate();
         // 更新exe_path
         *self.exe_path.write() = exe_path.clone();
+        self.set_comm(exe_path.rsplit('/').next().unwrap_or(""));
         // 6.11 Debug
         log::error!("[kernel_execve] task{} exe_path: {}", self.tid(), exe_path);
 
@@ -645,7 +884,7 @@ impl Task {
             args_vec.push(String::from(exe_path));
         }
         let (argv_base, envp_base, auxv_base, ustack_top) =
-            init_user_stack(&memory_set, &args_vec, &envs_vec, aux_vec, ustack_top);
+            init_user_stack(&mut memory_set, &args_vec, &envs_vec, aux_vec, ustack_top);
         log::info!(
             "[kernel_execve] entry_point: {:x}, user_sp: {:x}, page_table: {:x}",
             entry_point,
@@ -663,6 +902,10 @@ impl Task {
 
         // 更新地址空间
         *self.memory_set.write() = Arc::new(RwLock::new(memory_set));
+        // vfork的父任务可以继续运行
+        self.vfork_done();
+        // 更新凭证与能力集
+        self.exec_credentials(&elf_inode);
 
         // 更新trap_cx
         let mut trap_cx = TrapContext::app_init_trap_context(
@@ -706,6 +949,40 @@ impl Task {
         Ok(0)
     }
 
+    /// execve时根据可执行文件的S_ISUID/S_ISGID更新凭证, 并变换能力集
+    fn exec_credentials(&self, elf_inode: &Arc<dyn InodeOp>) {
+        // no_new_privs时忽略set-user-ID/set-group-ID位
+        let i_mode = if self.no_new_privs() {
+            elf_inode.get_mode() & !(S_ISUID | S_ISGID)
+        } else {
+            elf_inode.get_mode()
+        };
+        let mut id_changed = false;
+        if i_mode & S_ISUID != 0 && elf_inode.get_uid() != self.euid() {
+            self.set_euid(elf_inode.get_uid());
+            id_changed = true;
+        }
+        // 没有组执行权限时S_ISGID表示强制锁, 不改变egid
+        if i_mode & S_ISGID != 0 && i_mode & S_IXGRP != 0 && elf_inode.get_gid() != self.egid() {
+            self.set_egid(elf_inode.get_gid());
+            id_changed = true;
+        }
+        // 保存的set-user-ID和文件系统ID取自新的有效ID
+        self.set_suid(self.euid());
+        self.set_fsuid(self.euid());
+        self.set_sgid(self.egid());
+        self.set_fsgid(self.egid());
+        let (uid, euid) = (self.uid(), self.euid());
+        self.op_caps_mut(|caps| caps.fix_execve(uid, euid, id_changed));
+        // 凭证改变时清除pdeathsig并禁止core dump
+        if id_changed {
+            self.set_pdeathsig(0);
+            self.set_dumpable(SUID_DUMP_DISABLE);
+        } else {
+            self.set_dumpable(SUID_DUMP_USER);
+        }
+    }
+
     // 判断当前任务是否为进程
     pub fn is_process(&self) -> bool {
         self.tid() == self.tgid()
@@ -845,8 +1122,8 @@ impl Task {
     }
 
     pub fn compare_permision(&self, task: &Arc<Task>) -> SyscallRet {
-        // 如果是root用户，直接返回
-        if self.euid() == 0 {
+        // 拥有CAP_KILL，直接返回
+        if self.capable(CapSet::KILL) {
             return Ok(0);
         }
 
@@ -888,6 +1165,12 @@ impl Task {
 
     /*********************************** getter *************************************/
 
+    pub fn kthread(&self) -> Option<&Arc<KThread>> {
+        self.kthread.as_ref()
+    }
+    pub fn is_kthread(&self) -> bool {
+        self.kthread.is_some()
+    }
     pub fn kstack(&self) -> usize {
         self.kstack.0
     }
@@ -915,6 +1198,33 @@ impl Task {
     pub fn exit_code(&self) -> i32 {
         self.exit_code.load(core::sync::atomic::Ordering::SeqCst)
     }
+    pub fn oom_score_adj(&self) -> i32 {
+        self.oom_score_adj
+            .load(core::sync::atomic::Ordering::SeqCst)
+    }
+    /// 任务名, 即/proc/<pid>/comm, 用户任务默认取执行路径的文件名部分, 可由PR_SET_NAME修改
+    pub fn comm(&self) -> String {
+        match &self.kthread {
+            Some(kthread) => kthread.name().to_string(),
+            None => self.comm.read().clone(),
+        }
+    }
+    pub fn exit_signal(&self) -> i32 {
+        self.exit_signal.load(core::sync::atomic::Ordering::SeqCst)
+    }
+    pub fn pdeathsig(&self) -> i32 {
+        self.pdeathsig.load(core::sync::atomic::Ordering::SeqCst)
+    }
+    pub fn no_new_privs(&self) -> bool {
+        self.no_new_privs.load(core::sync::atomic::Ordering::SeqCst)
+    }
+    pub fn is_child_subreaper(&self) -> bool {
+        self.child_subreaper
+            .load(core::sync::atomic::Ordering::SeqCst)
+    }
+    pub fn dumpable(&self) -> u32 {
+        self.dumpable.load(core::sync::atomic::Ordering::SeqCst)
+    }
     pub fn exe_path(&self) -> String {
         self.exe_path.read().clone()
     }
@@ -985,6 +1295,9 @@ impl Task {
     pub fn set_pwd(&self, pwd: Arc<Path>) {
         *self.pwd.lock() = pwd;
     }
+    pub fn set_fd_table(&self, fd_table: Arc<FdTable>) {
+        *self.fd_table.lock() = fd_table;
+    }
     pub fn set_umask(&self, umask: u16) {
         self.umask
             .store(umask, core::sync::atomic::Ordering::Relaxed);
@@ -993,6 +1306,44 @@ impl Task {
         self.exit_code
             .store(exit_code, core::sync::atomic::Ordering::SeqCst);
     }
+    /// 设置任务名, 超过TASK_COMM_LEN - 1字节的部分被截断
+    pub fn set_comm(&self, name: &str) {
+        let mut end = name.len().min(TASK_COMM_LEN - 1);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        *self.comm.write() = name[..end].to_string();
+    }
+    pub fn set_pdeathsig(&self, sig: i32) {
+        self.pdeathsig
+            .store(sig, core::sync::atomic::Ordering::SeqCst);
+    }
+    /// vfork: 父任务阻塞直到子任务execve或退出
+    pub fn set_vfork_parent(&self, parent: Tid) {
+        *self.vfork_parent.lock() = Some(parent);
+    }
+    pub fn vfork_pending(&self) -> bool {
+        self.vfork_parent.lock().is_some()
+    }
+    /// 子任务execve或退出时唤醒vfork的父任务
+    pub fn vfork_done(&self) {
+        if let Some(parent) = self.vfork_parent.lock().take() {
+            wakeup(parent);
+        }
+    }
+    /// no_new_privs一经设置不能清除
+    pub fn set_no_new_privs(&self) {
+        self.no_new_privs
+            .store(true, core::sync::atomic::Ordering::SeqCst);
+    }
+    pub fn set_child_subreaper(&self, subreaper: bool) {
+        self.child_subreaper
+            .store(subreaper, core::sync::atomic::Ordering::SeqCst);
+    }
+    pub fn set_dumpable(&self, dumpable: u32) {
+        self.dumpable
+            .store(dumpable, core::sync::atomic::Ordering::SeqCst);
+    }
     pub fn set_parent(&self, parent: Arc<Task>) {
         *self.parent.lock() = Some(Arc::downgrade(&parent));
     }
@@ -1017,6 +1368,10 @@ impl Task {
     pub fn set_uid(&self, uid: u32) {
         self.uid.store(uid, core::sync::atomic::Ordering::SeqCst);
     }
+    pub fn set_oom_score_adj(&self, oom_score_adj: i32) {
+        self.oom_score_adj
+            .store(oom_score_adj, core::sync::atomic::Ordering::SeqCst);
+    }
     pub fn set_euid(&self, euid: u32) {
         self.euid.store(euid, core::sync::atomic::Ordering::SeqCst);
     }
@@ -1086,6 +1441,67 @@ impl Task {
     pub fn op_sup_groups_mut<T>(&self, f: impl FnOnce(&mut Vec<u32>) -> T) -> T {
         f(&mut self.sup_groups.write())
     }
+    pub fn caps(&self) -> Capabilities {
+        *self.caps.lock()
+    }
+    pub fn seccomp(&self) -> Seccomp {
+        self.seccomp.lock().clone()
+    }
+    pub fn op_seccomp_mut<T>(&self, f: impl FnOnce(&mut Seccomp) -> T) -> T {
+        f(&mut self.seccomp.lock())
+    }
+    pub fn op_caps_mut<T>(&self, f: impl FnOnce(&mut Capabilities) -> T) -> T {
+        f(&mut self.caps.lock())
+    }
+    /// setuid系列调用后变换能力集, old为调用前的(ruid, euid, suid)
+    pub fn fix_setuid_caps(&self, old: (u32, u32, u32)) {
+        let new = (self.uid(), self.euid(), self.suid());
+        self.op_caps_mut(|caps| caps.fix_setuid(old, new));
+        // 有效用户改变时清除pdeathsig并禁止core dump
+        if old.1 != new.1 {
+            self.set_pdeathsig(0);
+            self.set_dumpable(SUID_DUMP_DISABLE);
+        }
+    }
+    /// 检查effective能力集中是否拥有`cap`
+    pub fn capable(&self, cap: CapSet) -> bool {
+        self.caps.lock().effective.contains(cap)
+    }
+    pub fn cgroup(&self) -> Arc<Cgroup> {
+        self.cgroup.lock().clone()
+    }
+    /// 迁移到另一个cgroup, pids计数随之迁移, 已经计费的内存仍属于原cgroup
+    pub fn set_cgroup(&self, cgroup: Arc<Cgroup>) {
+        let tid = self.tid();
+        let mut guard = self.cgroup.lock();
+        guard.detach(tid);
+        guard.uncharge_pids();
+        cgroup.attach(tid);
+        cgroup.charge_pids();
+        *guard = cgroup;
+    }
+    pub fn pid_ns(&self) -> Arc<PidNamespace> {
+        self.pid_ns.clone()
+    }
+    pub fn nsproxy(&self) -> NsProxy {
+        self.nsproxy.lock().clone()
+    }
+    pub fn op_nsproxy_mut<T>(&self, f: impl FnOnce(&mut NsProxy) -> T) -> T {
+        f(&mut self.nsproxy.lock())
+    }
+    pub fn mnt_ns(&self) -> Arc<MntNamespace> {
+        self.nsproxy.lock().mnt_ns.clone()
+    }
+    pub fn uts_ns(&self) -> Arc<UtsNamespace> {
+        self.nsproxy.lock().uts_ns.clone()
+    }
+    pub fn ipc_ns(&self) -> Arc<IpcNamespace> {
+        self.nsproxy.lock().ipc_ns.clone()
+    }
+    /// 全局tid在本任务所属pid命名空间中的pid, 不可见时返回0
+    pub fn vpid_of(&self, tid: Tid) -> usize {
+        self.pid_ns.pid_of(tid).unwrap_or(0)
+    }
     /******************************** 任务状态判断 **************************************/
     pub fn is_ready(&self) -> bool {
         self.status() == TaskStatus::Ready
@@ -1118,23 +1534,26 @@ impl Task {
 
     pub fn info(&self) -> String {
         let mut info = String::new();
-        // 名称：这里我们用执行路径的文件名部分作为任务名（类似 bash）
-        let name = self.exe_path.read();
-        let name = name.rsplit('/').next().unwrap_or("unknown");
+        // 名称：即comm, 默认为执行路径的文件名部分（类似 bash）
+        let name = self.comm();
         let umask = 0o022; // 默认umask为022(fake)
         let status = self.status.lock();
+        let stopped = self.op_thread_group(|tg| tg.is_stopped());
         let state_str = match *status {
+            TaskStatus::Interruptable if stopped => "T (stopped)",
             TaskStatus::Running | TaskStatus::Ready => "R (running)",
             TaskStatus::Interruptable => "S (sleeping)",
             TaskStatus::UnInterruptable => "D (Uninterruptible sleep)",
             TaskStatus::Zombie => "Z (zombie)",
         };
-        let tgid = self.tgid();
+        // 以读取者所在pid命名空间中的值显示
+        let viewer = current_task();
+        let tgid = viewer.vpid_of(self.tgid());
         let ngid = 0; // NUMA 组 ID（如果没有则为 0）
-        let pid = self.tid();
+        let pid = viewer.vpid_of(self.tid());
         let ppid = self.op_parent(|parent| {
             if let Some(parent) = parent {
-                parent.upgrade().map_or(0, |p| p.tid())
+                parent.upgrade().map_or(0, |p| viewer.vpid_of(p.tid()))
             } else {
                 0
             }
@@ -1155,19 +1574,20 @@ impl Task {
                 groups.push_str(&format!("{} ", group));
             }
         });
-        let nstgid = self.tgid(); // pid 所属的每个 PID 命名空间中的线程组 ID
-        let nstpid = self.tid(); // pid 所属的每个 PID 命名空间中的线程 ID
-        let nspgid = self.pgid(); // pid 所属的每个 PID 命名空间中的进程组 ID
-        let nssid = self.tgid(); // pid 所属的每个 PID 命名空间中的会话 ID
+        let nstgid = self.pid_ns.pid_list(self.tgid()); // pid 所属的每个 PID 命名空间中的线程组 ID
+        let nstpid = self.pid_ns.pid_list(self.tid()); // pid 所属的每个 PID 命名空间中的线程 ID
+        let nspgid = self.pid_ns.pid_list(self.pgid()); // pid 所属的每个 PID 命名空间中的进程组 ID
+        let nssid = self.pid_ns.pid_list(self.tgid()); // pid 所属的每个 PID 命名空间中的会话 ID
         let vmpeak = 3356; // 虚拟内存峰值（fake）需要遍历统计
         let vmsize = 3356; // 虚拟内存大小（fake）
-        let vmlck = 0; // 锁定的虚拟内存大小（fake）
+        let vmlck = self.op_memory_set(|ms| ms.locked_pages()) * PAGE_SIZE / 1024; // 锁定的虚拟内存大小
         let vmpin = 0; // 锁定的物理内存大小（fake）
         let vmhwm = 1076; // 常驻内存峰值（fake）
-        let vmrss = 1076; // 常驻内存大小（fake）请注意，此处的值是 RssAnon、RssFile 和 RssShmem 的总和
-        let rssanon = 92; // 匿名内存（fake）
-        let rssfile = 984; // 文件映射的常驻内存（fake）
-        let rssshmem = 0; // 共享内存的常驻内存（fake）
+        let (rssanon, rssfile, rssshmem) = self.op_memory_set(|ms| ms.rss_counters());
+        let rssanon = rssanon * PAGE_SIZE / 1024; // 匿名内存
+        let rssfile = rssfile * PAGE_SIZE / 1024; // 文件映射的常驻内存
+        let rssshmem = rssshmem * PAGE_SIZE / 1024; // 共享内存的常驻内存
+        let vmrss = rssanon + rssfile + rssshmem; // 常驻内存大小, 是 RssAnon、RssFile 和 RssShmem 的总和
         let vmdata = 3840; // 数据段大小（fake）
         let vmstk = 2570; // 栈大小（fake）
         let vmexe = 378; // 可执行文件大小（fake）
@@ -1184,14 +1604,16 @@ impl Task {
         let sigblk = 0; // 阻塞的信号掩码（fake）
         let sigign = 0; // 忽略的信号掩码（fake）
         let sigcatch = 0; // 捕获的信号掩码（fake）
-        let cap_inheritable = 0; // 可继承的能力（fake）
-        let cap_permitted = 0; // 允许的能力（fake）
-        let cap_effective = 0; // 有效的能力（fake）
-        let cap_bounding = 0x000001ffffffffff as i64; // 边界能力（fake）
-        let cap_ambient = 0; // 环境能力（fake）
-        let no_new_privs = 0; // 是否设置了 no_new_privs（fake）
-        let seccomp = 0; // seccomp 状态（fake）
-        let seccomp_filter = 0; // seccomp 过滤器（fake）
+        let caps = self.caps();
+        let cap_inheritable = caps.inheritable.bits(); // 可继承的能力
+        let cap_permitted = caps.permitted.bits(); // 允许的能力
+        let cap_effective = caps.effective.bits(); // 有效的能力
+        let cap_bounding = caps.bounding.bits(); // 边界能力
+        let cap_ambient = caps.ambient.bits(); // 环境能力
+        let no_new_privs = self.no_new_privs() as usize; // 是否设置了 no_new_privs
+        let seccomp_state = self.seccomp();
+        let seccomp = seccomp_state.mode; // seccomp 模式
+        let seccomp_filter = seccomp_state.filter_count(); // seccomp 过滤器个数
         let speculation_store_bypass = "thread vulnerable".to_string();
         let speculation_indirect_branch = "conditional enabled".to_string();
         let cpus_allowed = 1; // 允许的 CPU 掩码（fake）
@@ -1334,12 +1756,12 @@ impl Task {
     // Todo: 记录程序地址等信息，根据需要完善
     pub fn stat(&self) -> String {
         let tid = self.tid();
-        let name = self.exe_path.read();
-        let name = name.rsplit('/').next().unwrap_or("unknown");
-        let comm = format!("({})", name);
+        let comm = format!("({})", self.comm());
 
         let status = self.status.lock();
+        let stopped = self.op_thread_group(|tg| tg.is_stopped());
         let state_char = match *status {
+            TaskStatus::Interruptable if stopped => 'T',
             TaskStatus::Running | TaskStatus::Ready => 'R',
             TaskStatus::Interruptable => 'S',
             TaskStatus::UnInterruptable => 'D',
@@ -1357,7 +1779,7 @@ impl Task {
         let session = self.tgid(); // 简化为 session = tgid
         let tty_nr = 0; // 没有终端设备支持
         let tpgid = 0; // 暂无前台进程组
-        let flags = 0; // 先设为 0
+        let flags = if self.is_kthread() { PF_KTHREAD } else { 0 };
 
         // 缺页统计等暂设为 0
         let minflt = 0;
@@ -1379,7 +1801,7 @@ impl Task {
         let itrealvalue = 0; // 自 Linux 2.6.17 起，此字段不再维护，并被硬编码为 0。
         let starttime = 0; // 系统启动后进程的启动时间，暂设为 0
         let vsize = 114514; // 虚拟内存（fake）
-        let rss = 242; // 驻留集大小：进程在实际内存中拥有的页面数（fake）
+        let rss = self.op_memory_set(|ms| ms.rss_pages()); // 驻留集大小：进程在实际内存中拥有的页面数
         let rsslim = self.rlimit.read()[5].rlim_cur; // 进程 rss 的当前软限制（以字节为单位）
         let startcode = 0; // 程序文本可运行的地址
         let endcode = 0; // 程序文本可运行的地址
@@ -1430,12 +1852,38 @@ impl Task {
 
 /****************************** 辅助函数 ****************************************/
 
+/// 寻找孤儿进程的新父进程: 最近的仍存活且设置了PR_SET_CHILD_SUBREAPER的祖先, 没有则为initproc
+fn find_child_reaper(task: &Arc<Task>) -> Arc<Task> {
+    // subreaper只在同一pid命名空间内查找
+    let mut ancestor = task.op_parent(|parent| parent.as_ref().and_then(|p| p.upgrade()));
+    while let Some(candidate) = ancestor {
+        if candidate.tid() == INIT_PROC_PID || !Arc::ptr_eq(&candidate.pid_ns, &task.pid_ns) {
+            break;
+        }
+        if candidate.is_child_subreaper() && candidate.op_thread_group(|tg| tg.len()) > 0 {
+            return candidate;
+        }
+        ancestor = candidate.op_parent(|parent| parent.as_ref().and_then(|p| p.upgrade()));
+    }
+    // 其次交给所在pid命名空间的init, init自身退出时交给上层命名空间的init
+    let mut pid_ns = Some(task.pid_ns());
+    while let Some(ns) = pid_ns {
+        if let Some(reaper) = ns.child_reaper() {
+            if !Arc::ptr_eq(&reaper, task) {
+                return reaper;
+            }
+        }
+        pid_ns = ns.parent();
+    }
+    INITPROC.clone()
+}
+
 /// 任务退出
 /// 参数：task 指定任务，exit_code 退出码
 /// 1. 从线程组中移除指定任务
 /// 2. 修改task_status为Zombie
 /// 3. 修改exit_code
-/// 4. 托孤给initproc
+/// 4. 托孤给最近的subreaper祖先或initproc
 /// 5. 将当前进程的fd_table清空, memory_set回收, children清空, sigpending清空
 /// 6. 向父进程发送SIGCHLD
 /// 注：现在改为调用者负责提前设置正确退出码（ 如进行操作(exit_code & 0xff)<<8 ）
@@ -1470,6 +1918,8 @@ pub fn kernel_exit(task: Arc<Task>, exit_code: i32) {
     delete_wait(task.tid());
     remove_task(task.tid());
     cancel_wait_alarm(task.tid());
+    // vfork的父任务可以继续运行
+    task.vfork_done();
 
     // 由于线程不通过waitpid，因此将线程直接从父进程中移除
     if !task.is_process() {
@@ -1499,14 +1949,61 @@ pub fn kernel_exit(task: Arc<Task>, exit_code: i32) {
         task.tid(),
         task.exit_code()
     );
-    // 托孤
-    task.op_children_mut(|children| {
-        for child in children.values() {
-            child.set_parent(INITPROC.clone());
-            INITPROC.add_child(child.clone())
+    // pid命名空间的init退出时, 杀死命名空间内的所有其他进程
+    if task.op_thread_group(|tg| tg.len()) == 0
+        && task.pid_ns.level() > 0
+        && task
+            .pid_ns
+            .child_reaper()
+            .is_some_and(|reaper| Arc::ptr_eq(&reaper, &task))
+    {
+        for tid in task.pid_ns.mark_dead() {
+            if tid == task.tid() {
+                continue;
+            }
+            if let Some(victim) = get_task(tid) {
+                victim.receive_siginfo(
+                    SigInfo::new(
+                        Sig::SIGKILL.raw(),
+                        SigInfo::KERNEL,
+                        SiField::Kill { tid: 0 },
+                    ),
+                    false,
+                );
+            }
         }
-        children.clear();
-    });
+    }
+    // 托孤: 线程组中最后一个线程退出时, 子进程交给最近的subreaper祖先, 没有则交给所在pid命名空间的init
+    if task.op_thread_group(|tg| tg.len()) == 0 {
+        let reaper = find_child_reaper(&task);
+        task.op_children_mut(|children| {
+            for child in children.values() {
+                child.set_parent(reaper.clone());
+                reaper.add_child(child.clone());
+                // 向设置了pdeathsig的子进程发送信号
+                let pdeathsig = child.pdeathsig();
+                if pdeathsig != 0 {
+                    child.receive_siginfo(
+                        SigInfo::new(pdeathsig, SigInfo::USER, SiField::Kill { tid: task.tid() }),
+                        false,
+                    );
+                }
+                // 已经退出的子进程需要由新的父进程回收
+                if child.is_zombie() && reaper.tid() != INIT_PROC_PID {
+                    reaper.receive_siginfo(
+                        SigInfo::new(
+                            Sig::SIGCHLD.raw(),
+                            SigInfo::CLD_EXITED,
+                            SiField::Kill { tid: child.tid() },
+                        ),
+                        false,
+                    );
+                    wakeup(reaper.tid());
+                }
+            }
+            children.clear();
+        });
+    }
     // // 回收地址空间
     // if Arc::strong_count(&task.memory_set()) == 1 {
     //     log::warn!("[kernel_exit] Task{} memory_set recycle", task.tid());
@@ -1541,16 +2038,20 @@ pub fn kernel_exit(task: Arc<Task>, exit_code: i32) {
             if let Some(parent) = parent {
                 log::debug!("[kernel_exit] Task{} send SIGCHILD to parent", task.tid());
                 let parent = parent.upgrade().unwrap();
-                parent.receive_siginfo(
-                    SigInfo {
-                        signo: Sig::SIGCHLD.raw(),
-                        code: SigInfo::CLD_EXITED,
-                        fields: SiField::Kill {
-                            tid: current_task().tid(),
+                // clone时指定的退出信号, 为0时不发送
+                let exit_signal = task.exit_signal();
+                if exit_signal != 0 {
+                    parent.receive_siginfo(
+                        SigInfo {
+                            signo: exit_signal,
+                            code: SigInfo::CLD_EXITED,
+                            fields: SiField::Kill {
+                                tid: current_task().tid(),
+                            },
                         },
-                    },
-                    false,
-                );
+                        false,
+                    );
+                }
                 log::debug!(
                     "[kernel_exit] Task{} wakeup parent-{}",
                     task.tid(),
@@ -1560,6 +2061,10 @@ pub fn kernel_exit(task: Arc<Task>, exit_code: i32) {
             }
         });
         remove_group(&task);
+        // 唤醒在pidfd上poll的任务
+        for tid in task.op_thread_group_mut(|tg| tg.take_pidfd_waiters()) {
+            wakeup(tid);
+        }
     }
     log::error!("[kernel_exit] Task{} clear the resource", task.tid());
     log::error!(
@@ -1577,11 +2082,19 @@ fn clone_path(old_path: &Arc<SpinNoIrqLock<Arc<Path>>>) -> Arc<SpinNoIrqLock<Arc
     Arc::new(SpinNoIrqLock::new(new_path))
 }
 
+/// 初始进程的资源限制, 除RLIMIT_CORE外都不限制(不是linux的INIT_RLIMITS)
+/// RLIMIT_CORE的软限制为0, 即默认不生成core文件, 需要时由用户通过setrlimit打开
+fn init_rlimits() -> [RLimit; RLIM_NLIMITS] {
+    let mut rlimit = [RLimit::default(); RLIM_NLIMITS];
+    rlimit[Resource::CORE as usize].rlim_cur = 0;
+    rlimit
+}
+
 // 把参数, 环境变量, 辅助信息压入用户栈
 // argv_base, envp_base, auxv_base, user_sp
 // 压入顺序从高地址到低地址: envp, argv, platform, random bytes, auxs, envp[], argv[], argc
 fn init_user_stack(
-    memory_set: &MemorySet,
+    memory_set: &mut MemorySet,
     args_vec: &[String],
     envs_vec: &[String],
     mut auxs_vec: Vec<AuxHeader>,
@@ -1725,6 +2238,7 @@ fn init_user_stack(
     unsafe {
         *(user_sp_pa as *mut usize) = args_vec.len();
     }
+    memory_set.saved_auxv = auxs_vec;
 
     (argv_base, envp_base, auxv_base, user_sp)
 }
@@ -1734,12 +2248,32 @@ fn init_user_stack(
 /// 线程组结构
 pub struct ThreadGroup {
     member: BTreeMap<Tid, Weak<Task>>,
+    /// 在pidfd上poll的任务, 线程组全部退出时唤醒
+    pidfd_waiters: Vec<Tid>,
+    /// 作业控制: 线程组是否被停止信号停止
+    stopped: bool,
+    /// 因线程组停止而阻塞的线程, 收到SIGCONT时唤醒
+    stop_waiters: Vec<Tid>,
+    /// 尚未被父进程通过wait获取的停止/继续事件
+    job_notify: Option<JobNotify>,
+}
+
+/// 线程组的作业控制状态变化, 由wait4(WUNTRACED/WCONTINUED)和waitid获取
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum JobNotify {
+    /// 被信号停止, 记录停止信号
+    Stopped(i32),
+    Continued,
 }
 
 impl ThreadGroup {
     pub fn new() -> Self {
         Self {
             member: BTreeMap::new(),
+            pidfd_waiters: Vec::new(),
+            stopped: false,
+            stop_waiters: Vec::new(),
+            job_notify: None,
         }
     }
 
@@ -1758,6 +2292,56 @@ impl ThreadGroup {
     pub fn iter(&self) -> impl Iterator<Item = Arc<Task>> + '_ {
         self.member.values().map(|task| task.upgrade().unwrap())
     }
+
+    pub fn add_pidfd_waiter(&mut self, tid: Tid) {
+        self.pidfd_waiters.push(tid);
+    }
+
+    pub fn take_pidfd_waiters(&mut self) -> Vec<Tid> {
+        core::mem::take(&mut self.pidfd_waiters)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// 停止线程组, 返回是否由运行态转为停止态(需要通知父进程)
+    pub fn stop(&mut self, sig: i32) -> bool {
+        if self.stopped {
+            return false;
+        }
+        self.stopped = true;
+        self.job_notify = Some(JobNotify::Stopped(sig));
+        true
+    }
+
+    /// 恢复被停止的线程组, 返回需要唤醒的线程; 线程组没有被停止时返回None
+    pub fn resume(&mut self) -> Option<Vec<Tid>> {
+        if !self.stopped {
+            return None;
+        }
+        self.stopped = false;
+        self.job_notify = Some(JobNotify::Continued);
+        Some(core::mem::take(&mut self.stop_waiters))
+    }
+
+    pub fn add_stop_waiter(&mut self, tid: Tid) {
+        if !self.stop_waiters.contains(&tid) {
+            self.stop_waiters.push(tid);
+        }
+    }
+
+    pub fn remove_stop_waiter(&mut self, tid: Tid) {
+        self.stop_waiters.retain(|&waiter| waiter != tid);
+    }
+
+    pub fn job_notify(&self) -> Option<JobNotify> {
+        self.job_notify
+    }
+
+    pub fn take_job_notify(&mut self) -> Option<JobNotify> {
+        self.job_notify.take()
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]